@@ -0,0 +1,53 @@
+//! Wall-clock benchmarks over the longer-running scripts in `test-files/`.
+//!
+//! `cargo bench --bench scripts [name]` runs each script a few times on a
+//! fresh runtime and reports the fastest run. The scripts are far too slow
+//! for the libtest harness's repeated sampling, hence `harness = false`.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use tcvm::env::{LuaString, Table, Value};
+use tcvm::{Executor, Lua};
+
+/// Name, path relative to the crate root, and the script's `arg` list.
+const SCRIPTS: &[(&str, &str, &[&str])] = &[
+    ("nbody", "test-files/nbody.lua", &["500000"]),
+    ("mandel", "test-files/mandel_bench.lua", &[]),
+];
+
+const RUNS: usize = 3;
+
+fn run(source: &str, args: &[&str]) -> Duration {
+    let mut lua = Lua::new();
+    lua.load_all();
+    let ex = lua.enter(|ctx| {
+        let arg = Table::new(ctx);
+        for (i, a) in args.iter().enumerate() {
+            let v = LuaString::new(ctx, a.as_bytes());
+            arg.raw_set(ctx, Value::small_integer(i as i64 + 1), Value::string(v));
+        }
+        let key = LuaString::new(ctx, b"arg");
+        ctx.globals()
+            .raw_set(ctx, Value::string(key), Value::table(arg));
+        let chunk = ctx.load(source, Some("bench")).expect("load");
+        ctx.stash(Executor::start(ctx, chunk, ()))
+    });
+    let start = Instant::now();
+    lua.finish(&ex).expect("run");
+    start.elapsed()
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; anything else narrows the selection.
+    let filter = std::env::args().skip(1).find(|a| !a.starts_with('-'));
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    for &(name, path, args) in SCRIPTS {
+        if filter.as_deref().is_some_and(|f| !name.contains(f)) {
+            continue;
+        }
+        let source = std::fs::read_to_string(root.join(path)).expect("read script");
+        let best = (0..RUNS).map(|_| run(&source, args)).min().unwrap();
+        eprintln!("{name:<8} {best:>10.2?}  (best of {RUNS})");
+    }
+}
@@ -52,10 +52,10 @@ fn main() {
     lua.enter(|ctx| {
         let arg_tbl = Table::new(ctx);
         let path_str = LuaString::new(ctx, &file_path);
-        arg_tbl.raw_set(ctx, Value::integer(0), Value::string(path_str));
+        arg_tbl.raw_set(ctx, Value::small_integer(0), Value::string(path_str));
         for (i, s) in script_args.iter().enumerate() {
             let v = LuaString::new(ctx, s.as_bytes());
-            arg_tbl.raw_set(ctx, Value::integer((i + 1) as i64), Value::string(v));
+            arg_tbl.raw_set(ctx, Value::small_integer((i + 1) as i64), Value::string(v));
         }
         let key = LuaString::new(ctx, b"arg");
         ctx.globals()
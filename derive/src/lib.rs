@@ -38,12 +38,13 @@ fn collect_derive(s: synstructure::Structure) -> TokenStream {
 
     let mut mode = None;
     let mut override_bound = None;
+    let mut kind = None;
     let mut gc_lifetime = None;
     let mut internal = false;
 
     fn usage_error(meta: &syn::meta::ParseNestedMeta, msg: &str) -> syn::parse::Error {
         meta.error(format_args!(
-            "{msg}. `#[collect(...)]` requires one mode (`require_static`, `no_drop`, or `unsafe_drop`) and optionally `bound = \"...\"`."
+            "{msg}. `#[collect(...)]` requires one mode (`require_static`, `no_drop`, or `unsafe_drop`) and optionally `bound = \"...\"` and `kind = \"...\"`."
         ))
     }
 
@@ -59,6 +60,16 @@ fn collect_derive(s: synstructure::Structure) -> TokenStream {
                 return Ok(());
             }
 
+            if meta.path.is_ident("kind") {
+                if kind.is_some() {
+                    return Err(usage_error(&meta, "multiple kinds specified"));
+                }
+
+                let lit: syn::LitStr = meta.value()?.parse()?;
+                kind = Some(lit);
+                return Ok(());
+            }
+
             if meta.path.is_ident("gc_lifetime") {
                 if gc_lifetime.is_some() {
                     return Err(usage_error(&meta, "multiple `'gc` lifetimes specified"));
@@ -122,6 +133,17 @@ fn collect_derive(s: synstructure::Structure) -> TokenStream {
             .unwrap_or_else(|| quote!())
     };
 
+    // `#[collect(kind = "...")]` sets `Collect::KIND` to the given expression.
+    let kind_item = kind
+        .as_ref()
+        .map(|x| {
+            let expr: TokenStream = x
+                .parse()
+                .expect("`#[collect]` failed to parse allocation kind expression");
+            quote!(const KIND: #base::metrics::AllocationKind = #expr;)
+        })
+        .unwrap_or_else(|| quote!());
+
     let mut errors = vec![];
 
     let collect_impl = if mode == Mode::RequireStatic {
@@ -130,6 +152,7 @@ fn collect_derive(s: synstructure::Structure) -> TokenStream {
         impl_struct.gen_impl(quote! {
             gen unsafe impl<'gc> #base::Collect<'gc> for @Self #where_clause {
                 const NEEDS_TRACE: bool = false;
+                #kind_item
             }
         })
     } else {
@@ -254,6 +277,7 @@ fn collect_derive(s: synstructure::Structure) -> TokenStream {
             impl_struct.gen_impl(quote! {
                 gen unsafe impl #base::Collect<#gc_lifetime> for @Self #where_clause {
                     const NEEDS_TRACE: bool = #needs_trace_expr;
+                    #kind_item
 
                     #[inline]
                     fn trace<Trace: #base::collect::Trace<#gc_lifetime>>(&self, cc: &mut Trace) {
@@ -265,6 +289,7 @@ fn collect_derive(s: synstructure::Structure) -> TokenStream {
             impl_struct.gen_impl(quote! {
                 gen unsafe impl<'gc> #base::Collect<'gc> for @Self #where_clause {
                     const NEEDS_TRACE: bool = #needs_trace_expr;
+                    #kind_item
 
                     #[inline]
                     fn trace<Trace: #base::collect::Trace<'gc>>(&self, cc: &mut Trace) {
@@ -323,6 +348,10 @@ decl_derive! {
     ///   to be the `'gc` lifetime. In the very unusual case that there are two or more lifetime
     ///   parameters, you must specify *which* lifetime should be used as the `'gc` lifetime.
     ///
+    /// - `#[collect(kind = "<expr>")]` - Sets `Collect::KIND`, the `AllocationKind` that `Gc`
+    ///   allocations of the type are accounted to in the arena's metrics, to the given constant
+    ///   expression, e.g., `#[collect(no_drop, kind = "MemoryKind::Table.id()")]`.
+    ///
     /// Options may be passed to the `collect` attribute together, e.g.,
     /// `#[collect(no_drop, bound = "")]`.
     ///
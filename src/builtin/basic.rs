@@ -1,9 +1,14 @@
 use std::io::Write;
+use std::pin::Pin;
 
 use crate::Context;
+use crate::builtin::coroutine::PCallSequence;
 use crate::builtin::util;
-use crate::env::{Error, Function, LuaString, NativeContext, NativeFn, Stack, Value};
-use crate::vm::sequence::CallbackAction;
+use crate::dmm::{Collect, Trace};
+use crate::env::{Error, Function, LuaString, NativeContext, NativeFn, Stack, Table, Value};
+use crate::lua::gc::GcRequest;
+use crate::lua::{MemoryKind, MemoryUsage};
+use crate::vm::sequence::{BoxSequence, CallbackAction, Execution, Sequence, SequencePoll};
 
 // TODO(#27): _G, _VERSION
 
@@ -35,7 +40,7 @@ pub fn load<'gc>(ctx: Context<'gc>) {
     ];
 
     for &(name, handler) in fns {
-        let handler = Function::new_native(ctx.mutation(), handler, Box::new([]));
+        let handler = Function::new_native(ctx.mutation(), handler, []);
         let key = Value::string(LuaString::new(ctx, name.as_bytes()));
 
         ctx.globals().raw_set(ctx, key, Value::function(handler));
@@ -67,30 +72,132 @@ fn lua_assert<'gc>(
     }
 }
 
-/// `collectgarbage([opt [, arg]])` — light stand-in until the GC exposes the
-/// control/introspection API this needs. Recognized options return
-/// plausible values; collection requests are accepted as no-ops.
+/// `collectgarbage([opt [, ...]])` — control the collector. `"collect"`
+/// and `"step"` can't collect from inside the mutation context, so they
+/// leave a request for the host and suspend on a [`GcRequestSequence`]
+/// until `Lua::enter` has carried it out.
 fn lua_collectgarbage<'gc>(
     nctx: NativeContext<'gc, '_>,
     mut stack: Stack<'gc, '_>,
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
+    let ctx = nctx.ctx;
+    let control = ctx.gc_control();
+    let metrics = ctx.mutation().metrics();
     let opt = stack.get(0);
     let opt = opt.get_string().map_or(&b"collect"[..], |s| s.as_bytes());
     match opt {
+        b"collect" => {
+            control.request(GcRequest::Collect);
+            let seq = BoxSequence::new(ctx.mutation(), GcRequestSequence { step: false });
+            return Ok(CallbackAction::Sequence(seq));
+        }
+        b"step" => {
+            let kb = gc_param(ctx, stack.get(1), 2)? as usize;
+            let work = if kb == 0 {
+                control.step_size()
+            } else {
+                kb * 1024
+            };
+            control.request(GcRequest::Step(work));
+            let seq = BoxSequence::new(ctx.mutation(), GcRequestSequence { step: true });
+            return Ok(CallbackAction::Sequence(seq));
+        }
         // Lua returns a single value: total memory in use, in Kbytes. (The
         // absolute figure differs from PUC-Lua — different allocator — but the
-        // shape/units match; full GC accounting tracked in #62.)
+        // shape/units match.)
         b"count" => {
-            let kb = nctx.ctx.mutation().metrics().total_allocation() as f64 / 1024.0;
+            let kb = metrics.total_allocation() as f64 / 1024.0;
             stack.replace(&[Value::float(kb)]);
         }
-        b"isrunning" => stack.replace(&[Value::boolean(true)]),
-        b"step" => stack.replace(&[Value::boolean(false)]),
-        _ => stack.replace(&[Value::integer(0)]),
+        // Live objects and bytes by kind, as a table of `{count =, bytes =}`
+        // records keyed by kind name. Not in PUC-Lua.
+        b"stats" => {
+            let usage = MemoryKind::ALL.map(|kind| (kind, MemoryUsage::of(metrics, kind)));
+            let stats = Table::new(ctx);
+            let count = Value::string(LuaString::new(ctx, b"count"));
+            let bytes = Value::string(LuaString::new(ctx, b"bytes"));
+            for (kind, usage) in usage {
+                let entry = Table::new(ctx);
+                entry.raw_set(ctx, count, Value::integer(nctx.ctx, usage.count as i64));
+                entry.raw_set(ctx, bytes, Value::integer(nctx.ctx, usage.bytes as i64));
+                let name = Value::string(LuaString::new(ctx, kind.name().as_bytes()));
+                stats.raw_set(ctx, name, Value::table(entry));
+            }
+            stack.replace(&[Value::table(stats)]);
+        }
+        b"stop" | b"restart" => {
+            control.set_running(opt == b"restart");
+            stack.replace(&[Value::small_integer(0)]);
+        }
+        b"isrunning" => stack.replace(&[Value::boolean(control.is_running())]),
+        b"incremental" => {
+            let pause = gc_param(ctx, stack.get(1), 2)?;
+            let stepmul = gc_param(ctx, stack.get(2), 3)?;
+            let stepsize = gc_param(ctx, stack.get(3), 4)?;
+            let prev = control.set_incremental(metrics, pause, stepmul, stepsize);
+            let name = LuaString::new(ctx, prev.name().as_bytes());
+            stack.replace(&[Value::string(name)]);
+        }
+        b"generational" => {
+            let minormul = gc_param(ctx, stack.get(1), 2)?;
+            let majormul = gc_param(ctx, stack.get(2), 3)?;
+            let prev = control.set_generational(metrics, minormul, majormul);
+            let name = LuaString::new(ctx, prev.name().as_bytes());
+            stack.replace(&[Value::string(name)]);
+        }
+        _ => {
+            let opt = String::from_utf8_lossy(opt);
+            return Err(Error::from_str(
+                ctx,
+                &format!("bad argument #1 to 'collectgarbage' (invalid option '{opt}')"),
+            ));
+        }
     }
     Ok(CallbackAction::Return)
 }
 
+/// Optional non-negative integer argument to `collectgarbage`; absent
+/// means 0, which the tuning options read as "leave unchanged".
+fn gc_param<'gc>(ctx: Context<'gc>, v: Value<'gc>, n: usize) -> Result<u32, Error<'gc>> {
+    if v.is_nil() {
+        return Ok(0);
+    }
+    let i = util::check_integer(ctx, v, "collectgarbage", n)?;
+    Ok(i.clamp(0, u32::MAX as i64) as u32)
+}
+
+/// Waits for the host to service a `collectgarbage` request, then returns
+/// its result: `0` for `"collect"`, whether a cycle finished for `"step"`.
+struct GcRequestSequence {
+    step: bool,
+}
+
+unsafe impl<'gc> Collect<'gc> for GcRequestSequence {
+    const NEEDS_TRACE: bool = false;
+}
+
+impl<'gc> Sequence<'gc> for GcRequestSequence {
+    fn trace_pointers(&self, _cc: &mut dyn Trace<'gc>) {}
+
+    fn poll(
+        self: Pin<&mut Self>,
+        ctx: Context<'gc>,
+        _exec: Execution<'gc, '_>,
+        mut stack: Stack<'gc, '_>,
+    ) -> Result<SequencePoll<'gc>, Error<'gc>> {
+        let control = ctx.gc_control();
+        if control.is_requested() {
+            return Ok(SequencePoll::Pending);
+        }
+        if self.step {
+            stack.replace(&[Value::boolean(control.step_finished())]);
+        } else {
+            stack.replace(&[Value::small_integer(0)]);
+        }
+        Ok(SequencePoll::Return)
+    }
+}
+
 fn lua_dofile<'gc>(
     _ctx: NativeContext<'gc, '_>,
     _stack: Stack<'gc, '_>,
@@ -98,16 +205,24 @@ fn lua_dofile<'gc>(
     todo!()
 }
 
-/// `error(message [, level])` — raise `message` as a Lua error. The `level`
-/// argument selects which call frame's position is prepended to a string
-/// message; we don't yet have native access to caller line info, so the value
-/// is raised verbatim (equivalent to `level == 0`). TODO(#27): prepend
-/// `"source:line:"` for `level >= 1` once reachable from a native frame.
+/// `error(message [, level])` — raise `message` as a Lua error. A string
+/// message is prefixed with `"chunkname:line:"` of the call `level` frames up
+/// (default 1, the function calling `error`); level 0, a non-string message
+/// or a frame that isn't running Lua code leave it untouched.
 fn lua_error<'gc>(
-    _nctx: NativeContext<'gc, '_>,
+    nctx: NativeContext<'gc, '_>,
     stack: Stack<'gc, '_>,
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
-    Err(Error::new(stack.get(0)))
+    let message = stack.get(0);
+    let level = stack.get(1).get_integer().unwrap_or(1);
+    let location = usize::try_from(level)
+        .ok()
+        .and_then(|level| nctx.exec.location(level));
+    let err = Error::new(message);
+    Err(match location {
+        Some(location) => err.with_location(nctx.ctx, &location),
+        None => err,
+    })
 }
 
 fn lua_getmetatable<'gc>(
@@ -154,8 +269,8 @@ fn lua_ipairs<'gc>(
         ));
     }
     let t = stack.get(0);
-    let iter = Function::new_native(nctx.ctx.mutation(), ipairs_aux, Box::new([]));
-    stack.replace(&[Value::function(iter), t, Value::integer(0)]);
+    let iter = Function::new_native(nctx.ctx.mutation(), ipairs_aux, []);
+    stack.replace(&[Value::function(iter), t, Value::small_integer(0)]);
     Ok(CallbackAction::Return)
 }
 
@@ -172,11 +287,11 @@ fn ipairs_aux<'gc>(
         )
     })?;
     let i = stack.get(1).get_integer().unwrap_or(0) + 1;
-    let v = t.raw_get(Value::integer(i));
+    let v = t.raw_get(Value::integer(nctx.ctx, i));
     if v.is_nil() {
         stack.replace(&[Value::nil()]);
     } else {
-        stack.replace(&[Value::integer(i), v]);
+        stack.replace(&[Value::integer(nctx.ctx, i), v]);
     }
     Ok(CallbackAction::Return)
 }
@@ -209,11 +324,28 @@ fn lua_pairs<'gc>(
     todo!()
 }
 
+/// `pcall(f, ...)` — call `f` with the remaining arguments in protected
+/// mode: `true` followed by its results, or `false` and the error value if
+/// it raised one. `f` may be anything Lua can call; a value that can't be
+/// called is reported the same way.
 fn lua_pcall<'gc>(
-    _ctx: NativeContext<'gc, '_>,
-    _stack: Stack<'gc, '_>,
+    nctx: NativeContext<'gc, '_>,
+    mut stack: Stack<'gc, '_>,
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
-    todo!()
+    if stack.is_empty() {
+        return Err(Error::from_str(
+            nctx.ctx,
+            "bad argument #1 to 'pcall' (value expected)",
+        ));
+    }
+    let function = stack.get(0);
+    let args: Vec<Value<'gc>> = stack.as_slice()[1..].to_vec();
+    stack.replace(&args);
+    let then = BoxSequence::new(nctx.ctx.mutation(), PCallSequence);
+    Ok(CallbackAction::Call {
+        function,
+        then: Some(then),
+    })
 }
 
 /// `print(...)` — write each argument's `tostring` form to stdout, separated
@@ -287,7 +419,7 @@ fn lua_rawlen<'gc>(
             &format!("bad argument #1 to 'rawlen' (table or string expected, got {got})"),
         ));
     };
-    stack.replace(&[Value::integer(len)]);
+    stack.replace(&[Value::integer(nctx.ctx, len)]);
     Ok(CallbackAction::Return)
 }
 
@@ -304,7 +436,7 @@ fn lua_rawset<'gc>(
             "bad argument #1 to 'rawset' (table expected)",
         ));
     };
-    t.raw_set(nctx.ctx, key, value);
+    t.try_raw_set(nctx.ctx, key, value)?;
     stack.replace(&[Value::table(t)]);
     Ok(CallbackAction::Return)
 }
@@ -321,7 +453,7 @@ fn lua_select<'gc>(
     if let Some(s) = sel.get_string()
         && s.as_bytes() == b"#"
     {
-        stack.replace(&[Value::integer(m as i64)]);
+        stack.replace(&[Value::small_integer(m as i64)]);
         return Ok(CallbackAction::Return);
     }
     let i = util::check_integer(nctx.ctx, sel, "select", 1)?;
@@ -412,11 +544,12 @@ fn lua_tonumber<'gc>(
                 "bad argument #2 to 'tonumber' (base out of range)",
             ));
         }
-        util::str_to_int_base(s.as_bytes(), base as u32).map_or(Value::nil(), Value::integer)
+        util::str_to_int_base(s.as_bytes(), base as u32)
+            .map_or(Value::nil(), |i| Value::integer(nctx.ctx, i))
     } else if v.get_integer().is_some() || v.get_float().is_some() {
         v
     } else if let Some(s) = v.get_string() {
-        util::str_to_number(s.as_bytes()).unwrap_or(Value::nil())
+        util::str_to_number(s.as_bytes()).map_or(Value::nil(), |n| n.into_value(nctx.ctx))
     } else {
         Value::nil()
     };
@@ -6,6 +6,7 @@ use crate::env::thread::{Frame, ThreadStatus};
 use crate::env::{
     Error, Function, LuaString, NativeContext, NativeFn, Stack, Table, Thread, Value,
 };
+use crate::vm::close::{self, CloseSequence};
 use crate::vm::sequence::{BoxSequence, CallbackAction, Execution, Sequence, SequencePoll};
 
 pub fn load<'gc>(ctx: Context<'gc>) {
@@ -22,7 +23,7 @@ pub fn load<'gc>(ctx: Context<'gc>) {
 
     let lib = Table::new(ctx);
     for &(name, handler) in fns {
-        let handler = Function::new_native(ctx.mutation(), handler, Box::new([]));
+        let handler = Function::new_native(ctx.mutation(), handler, []);
         let key = Value::string(LuaString::new(ctx, name.as_bytes()));
         lib.raw_set(ctx, key, Value::function(handler));
     }
@@ -40,7 +41,7 @@ fn lua_create<'gc>(
     let f = stack.get(0).get_function().ok_or_else(|| {
         Error::from_str(nctx.ctx, "bad argument #1 to 'create' (function expected)")
     })?;
-    let thread = Thread::new(nctx.ctx.mutation());
+    let thread = Thread::new(nctx.ctx);
     {
         let mc = nctx.ctx.mutation();
         let mut ts = thread.borrow_mut(mc);
@@ -174,7 +175,7 @@ fn lua_wrap<'gc>(
     let f = stack.get(0).get_function().ok_or_else(|| {
         Error::from_str(nctx.ctx, "bad argument #1 to 'wrap' (function expected)")
     })?;
-    let thread = Thread::new(nctx.ctx.mutation());
+    let thread = Thread::new(nctx.ctx);
     {
         let mc = nctx.ctx.mutation();
         let mut ts = thread.borrow_mut(mc);
@@ -194,9 +195,9 @@ fn lua_wrap<'gc>(
 /// corrupting executor invariants.
 ///
 /// The running-self case has special "does not return" semantics in the
-/// reference, which depend on `__close` machinery we haven't built yet —
-/// we reject it for now via the same path. Does NOT yet invoke `__close`
-/// metamethods on TBC variables; that ships with the broader TBC work.
+/// reference — we reject it via the same path. A suspended coroutine's
+/// pending to-be-closed variables are closed through a [`CloseSequence`],
+/// which reports `false, err` if one of their `__close` methods raises.
 fn lua_close<'gc>(
     nctx: NativeContext<'gc, '_>,
     mut stack: Stack<'gc, '_>,
@@ -218,13 +219,18 @@ fn lua_close<'gc>(
         ThreadStatus::Suspended | ThreadStatus::Stopped | ThreadStatus::Result { .. } => {
             let mc = nctx.ctx.mutation();
             let mut ts = co.borrow_mut(mc);
+            let pending = close::take_pending(&mut ts, 0);
             ts.stack.clear();
-            ts.frames.clear();
+            ts.clear_frames();
             ts.open_upvalues.clear();
             ts.tbc_slots.clear();
             ts.pending_action = None;
             ts.yield_bottom = None;
             ts.status = ThreadStatus::Stopped;
+            if !pending.is_empty() {
+                let seq = BoxSequence::new(mc, CloseSequence::reporting(pending));
+                return Ok(CallbackAction::Sequence(seq));
+            }
             stack.replace(&[Value::boolean(true)]);
             Ok(CallbackAction::Return)
         }
@@ -260,9 +266,9 @@ fn wrap_callback<'gc>(
 // Sequences
 // ---------------------------------------------------------------------------
 
-/// Wraps a coroutine resume: prepends `true` to the inner thread's
+/// Wraps a coroutine resume or a `pcall`: prepends `true` to the
 /// returned/yielded values; on a thrown error, returns `(false, msg)`.
-struct PCallSequence;
+pub(crate) struct PCallSequence;
 
 unsafe impl<'gc> Collect<'gc> for PCallSequence {
     const NEEDS_TRACE: bool = false;
@@ -277,8 +283,8 @@ impl<'gc> Sequence<'gc> for PCallSequence {
         _exec: Execution<'gc, '_>,
         mut stack: Stack<'gc, '_>,
     ) -> Result<SequencePoll<'gc>, Error<'gc>> {
-        // Inner thread completed/yielded; values are at stack[..]. Prepend
-        // `true` and return.
+        // Callee returned (or inner thread completed/yielded); values are
+        // at stack[..]. Prepend `true` and return.
         let mut vals: Vec<Value<'gc>> = Vec::with_capacity(stack.len() + 1);
         vals.push(Value::boolean(true));
         vals.extend_from_slice(stack.as_slice());
@@ -1,6 +1,12 @@
+use std::pin::Pin;
+
 use crate::Context;
-use crate::env::{Error, Function, LuaString, NativeContext, NativeFn, Stack, Table, Value};
-use crate::vm::sequence::CallbackAction;
+use crate::builtin::util;
+use crate::dmm::{Collect, Trace};
+use crate::env::{
+    Error, Function, Hook, HookMask, LuaString, NativeContext, NativeFn, Stack, Table, Value,
+};
+use crate::vm::sequence::{BoxSequence, CallbackAction, Execution, Sequence, SequencePoll};
 
 pub fn load<'gc>(ctx: Context<'gc>) {
     let fns: &[(&str, NativeFn)] = &[
@@ -24,7 +30,7 @@ pub fn load<'gc>(ctx: Context<'gc>) {
 
     let lib = Table::new(ctx);
     for &(name, handler) in fns {
-        let handler = Function::new_native(ctx.mutation(), handler, Box::new([]));
+        let handler = Function::new_native(ctx.mutation(), handler, []);
         let key = Value::string(LuaString::new(ctx, name.as_bytes()));
         lib.raw_set(ctx, key, Value::function(handler));
     }
@@ -40,11 +46,36 @@ fn lua_debug<'gc>(
     todo!()
 }
 
+/// `debug.gethook([thread])` — the hook function, mask and count of
+/// `thread` (default: the running one), or nil without a hook.
 fn lua_gethook<'gc>(
-    _ctx: NativeContext<'gc, '_>,
-    _stack: Stack<'gc, '_>,
+    nctx: NativeContext<'gc, '_>,
+    mut stack: Stack<'gc, '_>,
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
-    todo!()
+    let hook = match stack.get(0).get_thread() {
+        Some(thread) if !thread.ptr_eq(nctx.exec.current_thread()) => thread.hook(),
+        _ => nctx.exec.hook(),
+    };
+    let Some(hook) = hook else {
+        stack.replace(&[Value::nil()]);
+        return Ok(CallbackAction::Return);
+    };
+    let mut mask = Vec::new();
+    for (flag, c) in [
+        (HookMask::CALL, b'c'),
+        (HookMask::RETURN, b'r'),
+        (HookMask::LINE, b'l'),
+    ] {
+        if hook.mask.contains(flag) {
+            mask.push(c);
+        }
+    }
+    stack.replace(&[
+        Value::function(hook.function),
+        Value::string(LuaString::new(nctx.ctx, &mask)),
+        Value::integer(nctx.ctx, hook.count as i64),
+    ]);
+    Ok(CallbackAction::Return)
 }
 
 fn lua_getinfo<'gc>(
@@ -89,11 +120,97 @@ fn lua_getuservalue<'gc>(
     todo!()
 }
 
+/// `debug.sethook([thread,] hook, mask [, count])` — install `hook` on
+/// `thread` (default: the running one) for the events in `mask` (`c`all,
+/// `r`eturn, `l`ine) and every `count` instructions. Without a hook
+/// function, removes the thread's hook.
 fn lua_sethook<'gc>(
-    _ctx: NativeContext<'gc, '_>,
-    _stack: Stack<'gc, '_>,
+    nctx: NativeContext<'gc, '_>,
+    mut stack: Stack<'gc, '_>,
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
-    todo!()
+    let ctx = nctx.ctx;
+    let (thread, arg) = match stack.get(0).get_thread() {
+        Some(thread) => (thread, 1),
+        None => (nctx.exec.current_thread(), 0),
+    };
+    let function = stack.get(arg);
+    let hook = if function.is_nil() {
+        None
+    } else {
+        let function = function.get_function().ok_or_else(|| {
+            Error::from_str(
+                ctx,
+                &format!(
+                    "bad argument #{} to 'sethook' (function expected, got {})",
+                    arg + 1,
+                    function.type_name()
+                ),
+            )
+        })?;
+        let mask_arg = stack.get(arg + 1);
+        let mask = mask_arg.get_string().ok_or_else(|| {
+            Error::from_str(
+                ctx,
+                &format!(
+                    "bad argument #{} to 'sethook' (string expected, got {})",
+                    arg + 2,
+                    mask_arg.type_name()
+                ),
+            )
+        })?;
+        let mut flags = HookMask::empty();
+        for &c in mask.as_bytes() {
+            match c {
+                b'c' => flags |= HookMask::CALL,
+                b'r' => flags |= HookMask::RETURN,
+                b'l' => flags |= HookMask::LINE,
+                _ => {}
+            }
+        }
+        let count = match stack.get(arg + 2) {
+            v if v.is_nil() => 0,
+            v => util::check_integer(ctx, v, "sethook", arg + 3)?.clamp(0, u32::MAX as i64) as u32,
+        };
+        Some(Hook {
+            function,
+            mask: flags,
+            count,
+        })
+    };
+    stack.clear();
+
+    if !thread.ptr_eq(nctx.exec.current_thread()) {
+        thread.set_hook(ctx.mutation(), hook);
+        return Ok(CallbackAction::Return);
+    }
+    nctx.exec.set_hook(ctx.mutation(), hook);
+    // The interpreter picks its dispatch table (hooked or not) on entry,
+    // so go back through the executor for it to see the new hook.
+    Ok(CallbackAction::Sequence(BoxSequence::new(
+        ctx.mutation(),
+        ReenterSequence,
+    )))
+}
+
+/// Returns nothing as soon as it's polled. Returned by natives that need
+/// the interpreter to be re-entered before the calling code continues.
+struct ReenterSequence;
+
+unsafe impl<'gc> Collect<'gc> for ReenterSequence {
+    const NEEDS_TRACE: bool = false;
+}
+
+impl<'gc> Sequence<'gc> for ReenterSequence {
+    fn trace_pointers(&self, _cc: &mut dyn Trace<'gc>) {}
+
+    fn poll(
+        self: Pin<&mut Self>,
+        _ctx: Context<'gc>,
+        _exec: Execution<'gc, '_>,
+        _stack: Stack<'gc, '_>,
+    ) -> Result<SequencePoll<'gc>, Error<'gc>> {
+        Ok(SequencePoll::Return)
+    }
 }
 
 fn lua_setlocal<'gc>(
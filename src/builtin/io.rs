@@ -4,7 +4,7 @@
 //!
 //! A file handle is a `Userdata` whose payload is a [`LuaFile`] and whose
 //! metatable is the single shared file metatable (`__index` → the methods
-//! table, plus `__name`/`__tostring`). Method dispatch (`f:write(...)`)
+//! table, plus `__name`/`__tostring`/`__close`). Method dispatch (`f:write(...)`)
 //! reaches the methods through that metatable's `__index`, which the VM
 //! resolves for userdata receivers. The metatable, the methods table, and
 //! the current default input/output handles live in an internal "io-state"
@@ -12,7 +12,8 @@
 //!
 //! The OS file descriptor is owned by the `std::fs::File` inside the
 //! handle; it is released either by an explicit `close`/`io.close` (which
-//! drops the stream) or by the GC dropping the userdata (the collector
+//! drops the stream), by a `<close>` variable holding it going out of
+//! scope, or by the GC dropping the userdata (the collector
 //! runs drop glue). Standard streams hold no owned descriptor, so dropping
 //! a `stdin`/`stdout`/`stderr` handle never closes fd 0/1/2.
 
@@ -134,6 +135,11 @@ pub fn load<'gc>(ctx: Context<'gc>) {
         str_val(ctx, b"__tostring"),
         Value::function(native(lua_file_tostring)),
     );
+    mt.raw_set(
+        ctx,
+        str_val(ctx, b"__close"),
+        Value::function(native(lua_file_autoclose)),
+    );
     io_state.raw_set(ctx, str_val(ctx, b"mt"), Value::table(mt));
 
     // Predefined handles.
@@ -180,7 +186,7 @@ fn str_val<'gc>(ctx: Context<'gc>, s: &[u8]) -> Value<'gc> {
 
 fn new_handle<'gc>(ctx: Context<'gc>, mt: Table<'gc>, file: LuaFile) -> Userdata<'gc> {
     let u = Userdata::new(ctx.mutation(), file, 0);
-    u.set_metatable(ctx.mutation(), Some(mt));
+    u.set_metatable(ctx, Some(mt));
     u
 }
 
@@ -463,8 +469,8 @@ fn read_number<R: BufRead>(r: &mut R) -> std::io::Result<ReadOne> {
         r.consume(1);
     }
     Ok(match util::str_to_number(&tok) {
-        Some(v) if v.get_integer().is_some() => ReadOne::Int(v.get_integer().unwrap()),
-        Some(v) => ReadOne::Float(v.get_float().unwrap()),
+        Some(util::Number::Int(i)) => ReadOne::Int(i),
+        Some(util::Number::Float(f)) => ReadOne::Float(f),
         None => ReadOne::Nil,
     })
 }
@@ -549,15 +555,16 @@ fn do_read<'gc>(
         Dispatch::Done(Err(_)) => return Ok(vec![Value::nil()]),
         Dispatch::Done(Ok(v)) => v,
     };
-    Ok(raw
-        .into_iter()
-        .map(|r| match r {
-            ReadOne::Nil => Value::nil(),
-            ReadOne::Bytes(b) => Value::string(LuaString::new(ctx, &b)),
-            ReadOne::Int(i) => Value::integer(i),
-            ReadOne::Float(f) => Value::float(f),
+    raw.into_iter()
+        .map(|r| {
+            Ok(match r {
+                ReadOne::Nil => Value::nil(),
+                ReadOne::Bytes(b) => Value::string(util::new_string(ctx, &b)?),
+                ReadOne::Int(i) => Value::integer(ctx, i),
+                ReadOne::Float(f) => Value::float(f),
+            })
         })
-        .collect())
+        .collect()
 }
 
 /// One read format from a Lua value: a string spec (`"l"`,`"L"`,`"n"`,`"a"`,
@@ -687,7 +694,7 @@ fn io_fail<'gc>(ctx: Context<'gc>, fname: Option<&str>, e: &std::io::Error) -> [
     [
         Value::nil(),
         Value::string(LuaString::new(ctx, text.as_bytes())),
-        Value::integer(e.raw_os_error().unwrap_or(0) as i64),
+        Value::integer(ctx, e.raw_os_error().unwrap_or(0) as i64),
     ]
 }
 
@@ -695,7 +702,7 @@ fn io_fail<'gc>(ctx: Context<'gc>, fname: Option<&str>, e: &std::io::Error) -> [
 /// Lua appends as a 4th value (`g_write`).
 fn write_fail<'gc>(ctx: Context<'gc>, e: &std::io::Error, written: u64) -> [Value<'gc>; 4] {
     let [a, b, c] = io_fail(ctx, None, e);
-    [a, b, c, Value::integer(written as i64)]
+    [a, b, c, Value::small_integer(written as i64)]
 }
 
 // ---------------------------------------------------------------------------
@@ -1026,7 +1033,7 @@ fn lua_file_seek<'gc>(
         None => SeekOutcome::Io(std::io::Error::from_raw_os_error(22)), // EINVAL
     };
     match outcome {
-        SeekOutcome::Pos(n) => stack.replace(&[Value::integer(n as i64)]),
+        SeekOutcome::Pos(n) => stack.replace(&[Value::integer(nctx.ctx, n as i64)]),
         SeekOutcome::Closed => return Err(closed_file_error(nctx.ctx)),
         SeekOutcome::Io(e) => stack.replace(&io_fail(nctx.ctx, None, &e)),
     }
@@ -1096,6 +1103,32 @@ fn lua_file_setvbuf<'gc>(
     Ok(CallbackAction::Return)
 }
 
+/// `__close` — close the handle when a `<close>` variable holding it goes
+/// out of scope (Lua's `f_gc`). Unlike `file:close()` it never raises: an
+/// already-closed file or a standard stream is left as is.
+fn lua_file_autoclose<'gc>(
+    nctx: NativeContext<'gc, '_>,
+    mut stack: Stack<'gc, '_>,
+) -> Result<CallbackAction<'gc>, Error<'gc>> {
+    if let Some(u) = as_file(&nctx, stack.get(0)) {
+        u.with_data::<LuaFile, _>(|lf| {
+            let mut fs = lf.state.borrow_mut();
+            if !matches!(
+                *fs,
+                FileState::Closed
+                    | FileState::Open {
+                        stream: Stream::Stdin | Stream::Stdout | Stream::Stderr,
+                        ..
+                    }
+            ) {
+                *fs = FileState::Closed;
+            }
+        });
+    }
+    stack.clear();
+    Ok(CallbackAction::Return)
+}
+
 /// `__tostring` — `"file (0x..)"` / `"file (closed)"`. Set on the metatable
 /// for forward-compat; `tostring`/`print` don't dispatch `__tostring` yet (#27).
 fn lua_file_tostring<'gc>(
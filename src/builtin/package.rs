@@ -9,7 +9,7 @@ pub fn load<'gc>(ctx: Context<'gc>) {
 
     let lib = Table::new(ctx);
     for &(name, handler) in fns {
-        let handler = Function::new_native(ctx.mutation(), handler, Box::new([]));
+        let handler = Function::new_native(ctx.mutation(), handler, []);
         let key = Value::string(LuaString::new(ctx, name.as_bytes()));
         lib.raw_set(ctx, key, Value::function(handler));
     }
@@ -17,7 +17,7 @@ pub fn load<'gc>(ctx: Context<'gc>) {
     let lib_name = Value::string(LuaString::new(ctx, b"package"));
     ctx.globals().raw_set(ctx, lib_name, Value::table(lib));
 
-    let require = Function::new_native(ctx.mutation(), lua_require, Box::new([]));
+    let require = Function::new_native(ctx.mutation(), lua_require, []);
     let require_key = Value::string(LuaString::new(ctx, b"require"));
     ctx.globals()
         .raw_set(ctx, require_key, Value::function(require));
@@ -77,7 +77,7 @@ pub fn load<'gc>(ctx: Context<'gc>) {
 
     let lib = Table::new(ctx);
     for &(name, handler) in fns {
-        let handler = Function::new_native(ctx.mutation(), handler, Box::new([]));
+        let handler = Function::new_native(ctx.mutation(), handler, []);
         let key = Value::string(LuaString::new(ctx, name.as_bytes()));
         lib.raw_set(ctx, key, Value::function(handler));
     }
@@ -112,7 +112,7 @@ fn lua_byte<'gc>(
     let mut out = Vec::new();
     let mut k = start;
     while k <= end {
-        out.push(Value::integer(bytes[(k - 1) as usize] as i64));
+        out.push(Value::small_integer(bytes[(k - 1) as usize] as i64));
         k += 1;
     }
     stack.replace(&out);
@@ -136,7 +136,7 @@ fn lua_char<'gc>(
         }
         out.push(c as u8);
     }
-    let r = LuaString::new(nctx.ctx, &out);
+    let r = util::new_string(nctx.ctx, &out)?;
     stack.replace(&[Value::string(r)]);
     Ok(CallbackAction::Return)
 }
@@ -159,7 +159,7 @@ fn pat_err<'gc>(ctx: Context<'gc>, e: PatError) -> Error<'gc> {
 fn cap_to_value<'gc>(ctx: Context<'gc>, src: &[u8], cv: CapValue) -> Value<'gc> {
     match cv {
         CapValue::Str { start, end } => Value::string(LuaString::new(ctx, &src[start..end])),
-        CapValue::Pos(n) => Value::integer(n),
+        CapValue::Pos(n) => Value::integer(ctx, n),
     }
 }
 
@@ -212,8 +212,8 @@ fn lua_find<'gc>(
             Some(off) => {
                 let start = init + off;
                 stack.replace(&[
-                    Value::integer(start as i64 + 1),
-                    Value::integer((start + pat.len()) as i64),
+                    Value::small_integer(start as i64 + 1),
+                    Value::small_integer((start + pat.len()) as i64),
                 ]);
             }
             None => stack.replace(&[Value::nil()]),
@@ -228,7 +228,10 @@ fn lua_find<'gc>(
     loop {
         if let Some(e) = ms.match_at(s1).map_err(|e| pat_err(ctx, e))? {
             // start, end, then the explicit captures (no whole-match fallback).
-            let mut out = vec![Value::integer(s1 as i64 + 1), Value::integer(e as i64)];
+            let mut out = vec![
+                Value::small_integer(s1 as i64 + 1),
+                Value::small_integer(e as i64),
+            ];
             for i in 0..ms.num_captures(false) {
                 let cv = ms.get_onecapture(i, s1, e).map_err(|e| pat_err(ctx, e))?;
                 out.push(cap_to_value(ctx, src, cv));
@@ -293,7 +296,7 @@ fn lua_format<'gc>(
         format_one(ctx.ctx, &mut out, &spec, arg, arg_idx)?;
     }
 
-    let s = LuaString::new(ctx.ctx, &out);
+    let s = util::new_string(ctx.ctx, &out)?;
     stack.replace(&[Value::string(s)]);
     Ok(CallbackAction::Return)
 }
@@ -991,12 +994,9 @@ fn apply_width(out: &mut Vec<u8>, spec: &FmtSpec, sign: &[u8], prefix: &[u8], bo
     }
 }
 
-/// Iterator state for `gmatch`, owned by the closure's userdata upvalue. The
-/// subject and pattern bytes are copied in so the closure needn't keep the
-/// argument strings rooted (mirroring PUC's `lua_settop` + userdata).
+/// Iterator state for `gmatch`, owned by the closure's userdata upvalue.
+/// The subject and pattern strings are the closure's other two upvalues.
 struct GmatchState {
-    src: Vec<u8>,
-    pat: Vec<u8>,
     /// Next subject byte to try matching from.
     pos: usize,
     /// End of the previous match — empty matches at this exact spot are
@@ -1024,13 +1024,15 @@ fn lua_gmatch<'gc>(
     // `init > len` clamps to len+1 (iterate nothing) rather than erroring.
     let pos = init_pos(init_raw, s.len()).unwrap_or(s.len() + 1);
     let state = GmatchState {
-        src: s.as_bytes().to_vec(),
-        pat: p.as_bytes().to_vec(),
         pos,
         lastmatch: None,
     };
     let ud = Userdata::new(ctx.mutation(), RefCell::new(state), 0);
-    let iter = Function::new_native(ctx.mutation(), gmatch_aux, Box::new([Value::userdata(ud)]));
+    let iter = Function::new_native(
+        ctx.mutation(),
+        gmatch_aux,
+        [Value::userdata(ud), Value::string(s), Value::string(p)],
+    );
     stack.replace(&[Value::function(iter)]);
     Ok(CallbackAction::Return)
 }
@@ -1045,14 +1047,19 @@ fn gmatch_aux<'gc>(
     let ud = nctx.upvalues[0]
         .get_userdata()
         .expect("gmatch iterator upvalue must be a userdata");
+    let (Some(src), Some(pat)) = (nctx.upvalues[1].get_string(), nctx.upvalues[2].get_string())
+    else {
+        unreachable!("gmatch iterator upvalues must be its subject and pattern");
+    };
+    let (src, pat) = (src.as_bytes(), pat.as_bytes());
     let result = ud
         .with_data::<RefCell<GmatchState>, _>(|cell| {
             let mut st = cell.borrow_mut();
             let st = &mut *st;
-            let mut ms = MatchState::new(&st.src, &st.pat);
+            let mut ms = MatchState::new(src, pat);
             let mut src_pos = st.pos;
             loop {
-                if src_pos > st.src.len() {
+                if src_pos > src.len() {
                     return Ok(None);
                 }
                 match ms.match_at(src_pos)? {
@@ -1061,10 +1068,8 @@ fn gmatch_aux<'gc>(
                         let mut caps = Vec::with_capacity(n);
                         for i in 0..n {
                             let cv = ms.get_onecapture(i, src_pos, e)?;
-                            caps.push(cap_to_value(ctx, &st.src, cv));
+                            caps.push(cap_to_value(ctx, src, cv));
                         }
-                        // `ms` is unused past here, so its borrow of `st.src`/
-                        // `st.pat` ends and these field writes are allowed.
                         st.pos = e;
                         st.lastmatch = Some(e);
                         return Ok(Some(caps));
@@ -1118,7 +1123,7 @@ fn lua_gsub<'gc>(
     // Fast path: string/number replacement template, fully synchronous.
     if let Some(template) = repl_template(ctx, repl) {
         let (result, count) = gsub_string(ctx, s.as_bytes(), p.as_bytes(), &template, max_n)?;
-        stack.replace(&[result, Value::integer(count)]);
+        stack.replace(&[result, Value::integer(nctx.ctx, count)]);
         return Ok(CallbackAction::Return);
     }
 
@@ -1147,9 +1152,9 @@ fn lua_gsub<'gc>(
         async move {
             let mut seq = seq;
             let (result, count) = gsub_run(&mut seq, src, pat, repl, max_n).await?;
-            seq.enter(|_ctx, locals, _exec, mut stack| {
+            seq.enter(|ctx, locals, _exec, mut stack| {
                 let result = locals.fetch(&result);
-                stack.replace(&[result, Value::integer(count)]);
+                stack.replace(&[result, Value::integer(ctx, count)]);
             });
             Ok(SequenceReturn::Return)
         }
@@ -1210,7 +1215,7 @@ fn gsub_string<'gc>(
         }
     }
     out.extend_from_slice(&src[pos..]);
-    Ok((Value::string(LuaString::new(ctx, &out)), count))
+    Ok((Value::string(util::new_string(ctx, &out)?), count))
 }
 
 /// Expand a replacement template (`add_s`) for the match `src[s..e]` into
@@ -1273,7 +1278,7 @@ impl OwnedCap {
     fn to_value<'gc>(&self, ctx: Context<'gc>) -> Value<'gc> {
         match self {
             OwnedCap::Bytes(b) => Value::string(LuaString::new(ctx, b)),
-            OwnedCap::Pos(n) => Value::integer(*n),
+            OwnedCap::Pos(n) => Value::integer(ctx, *n),
         }
     }
 }
@@ -1381,9 +1386,10 @@ async fn gsub_run(
     }
     out.extend_from_slice(&src[pos..]);
 
-    let result = seq.enter(|ctx, locals, _exec, _stack| {
-        locals.stash(ctx.mutation(), Value::string(LuaString::new(ctx, &out)))
-    });
+    let result = seq.try_enter(|ctx, locals, _exec, _stack| {
+        let s = util::new_string(ctx, &out)?;
+        Ok(locals.stash(ctx.mutation(), Value::string(s)))
+    })?;
     Ok((result, count))
 }
 
@@ -1491,7 +1497,7 @@ fn lua_len<'gc>(
     mut stack: Stack<'gc, '_>,
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
     let s = check_str(nctx.ctx, stack.get(0), "len", 1)?;
-    stack.replace(&[Value::integer(s.len() as i64)]);
+    stack.replace(&[Value::small_integer(s.len() as i64)]);
     Ok(CallbackAction::Return)
 }
 
@@ -1502,7 +1508,7 @@ fn lua_lower<'gc>(
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
     let s = check_str(nctx.ctx, stack.get(0), "lower", 1)?;
     let lowered: Vec<u8> = s.as_bytes().iter().map(u8::to_ascii_lowercase).collect();
-    stack.replace(&[Value::string(LuaString::new(nctx.ctx, &lowered))]);
+    stack.replace(&[Value::string(util::new_string(nctx.ctx, &lowered)?)]);
     Ok(CallbackAction::Return)
 }
 
@@ -1587,6 +1593,7 @@ fn lua_rep<'gc>(
         let Some(total) = total else {
             return Err(Error::from_str(nctx.ctx, "resulting string too large"));
         };
+        util::check_memory(nctx.ctx, total)?;
         let mut out = Vec::with_capacity(total);
         for k in 0..n {
             if k > 0 {
@@ -1608,7 +1615,7 @@ fn lua_reverse<'gc>(
     let s = check_str(nctx.ctx, stack.get(0), "reverse", 1)?;
     let mut bytes = s.as_bytes().to_vec();
     bytes.reverse();
-    stack.replace(&[Value::string(LuaString::new(nctx.ctx, &bytes))]);
+    stack.replace(&[Value::string(util::new_string(nctx.ctx, &bytes)?)]);
     Ok(CallbackAction::Return)
 }
 
@@ -1651,6 +1658,6 @@ fn lua_upper<'gc>(
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
     let s = check_str(nctx.ctx, stack.get(0), "upper", 1)?;
     let uppered: Vec<u8> = s.as_bytes().iter().map(u8::to_ascii_uppercase).collect();
-    stack.replace(&[Value::string(LuaString::new(nctx.ctx, &uppered))]);
+    stack.replace(&[Value::string(util::new_string(nctx.ctx, &uppered)?)]);
     Ok(CallbackAction::Return)
 }
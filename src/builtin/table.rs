@@ -3,6 +3,7 @@ use crate::builtin::util;
 use crate::env::{Error, Function, LuaString, NativeContext, NativeFn, Stack, Table, Value};
 use crate::lua::{StashedError, StashedFunction, StashedTable};
 use crate::vm::async_sequence::{AsyncSequence, SequenceReturn, async_sequence};
+use crate::vm::num;
 use crate::vm::sequence::CallbackAction;
 
 /// Fetch argument 1 as a table or raise the standard bad-argument error.
@@ -36,7 +37,7 @@ pub fn load<'gc>(ctx: Context<'gc>) {
 
     let lib = Table::new(ctx);
     for &(name, handler) in fns {
-        let handler = Function::new_native(ctx.mutation(), handler, Box::new([]));
+        let handler = Function::new_native(ctx.mutation(), handler, []);
         let key = Value::string(LuaString::new(ctx, name.as_bytes()));
         lib.raw_set(ctx, key, Value::function(handler));
     }
@@ -82,17 +83,13 @@ fn lua_concat<'gc>(
         util::check_integer(nctx.ctx, j_arg, "concat", 4)?
     };
 
-    let mut out = Vec::new();
+    // Size the result before building it: it's assembled in `out` and then
+    // copied into the string, so both have to fit under the memory limit.
+    let mut len = 0usize;
     let mut k = i;
     while k <= j {
-        let v = t.raw_get(Value::integer(k));
-        if let Some(s) = v.get_string() {
-            out.extend_from_slice(s.as_bytes());
-        } else if let Some(n) = v.get_integer() {
-            util::push_int(&mut out, n);
-        } else if let Some(f) = v.get_float() {
-            util::push_float(&mut out, f);
-        } else {
+        let v = t.raw_get(Value::integer(nctx.ctx, k));
+        let Some(n) = num::coerced_len(v) else {
             return Err(Error::from_str(
                 nctx.ctx,
                 &format!(
@@ -100,6 +97,25 @@ fn lua_concat<'gc>(
                     v.type_name()
                 ),
             ));
+        };
+        len = len.saturating_add(n);
+        if k != j {
+            len = len.saturating_add(sep.len());
+        }
+        k += 1;
+    }
+    util::check_memory(nctx.ctx, len.saturating_mul(2))?;
+
+    let mut out = Vec::with_capacity(len);
+    let mut k = i;
+    while k <= j {
+        let v = t.raw_get(Value::integer(nctx.ctx, k));
+        if let Some(s) = v.get_string() {
+            out.extend_from_slice(s.as_bytes());
+        } else if let Some(n) = v.get_integer() {
+            util::push_int(&mut out, n);
+        } else if let Some(f) = v.get_float() {
+            util::push_float(&mut out, f);
         }
         if k != j {
             out.extend_from_slice(&sep);
@@ -150,7 +166,7 @@ fn lua_insert<'gc>(
     let n = t.raw_len() as i64;
     match stack.len() {
         2 => {
-            t.raw_set(nctx.ctx, Value::integer(n + 1), stack.get(1));
+            t.try_raw_set(nctx.ctx, Value::integer(nctx.ctx, n + 1), stack.get(1))?;
         }
         3 => {
             let pos = util::check_integer(nctx.ctx, stack.get(1), "insert", 2)?;
@@ -162,11 +178,11 @@ fn lua_insert<'gc>(
             }
             let mut k = n;
             while k >= pos {
-                let v = t.raw_get(Value::integer(k));
-                t.raw_set(nctx.ctx, Value::integer(k + 1), v);
+                let v = t.raw_get(Value::integer(nctx.ctx, k));
+                t.try_raw_set(nctx.ctx, Value::integer(nctx.ctx, k + 1), v)?;
                 k -= 1;
             }
-            t.raw_set(nctx.ctx, Value::integer(pos), stack.get(2));
+            t.raw_set(nctx.ctx, Value::integer(nctx.ctx, pos), stack.get(2));
         }
         _ => {
             return Err(Error::from_str(
@@ -219,14 +235,14 @@ fn lua_move<'gc>(
         // within the same table; the guards above keep `f + i` / `t + i` in range.
         if t > e || t <= f || !same {
             for i in 0..n {
-                let v = a1.raw_get(Value::integer(f + i));
-                a2.raw_set(nctx.ctx, Value::integer(t + i), v);
+                let v = a1.raw_get(Value::integer(nctx.ctx, f + i));
+                a2.try_raw_set(nctx.ctx, Value::integer(nctx.ctx, t + i), v)?;
             }
         } else {
             let mut i = n - 1;
             loop {
-                let v = a1.raw_get(Value::integer(f + i));
-                a2.raw_set(nctx.ctx, Value::integer(t + i), v);
+                let v = a1.raw_get(Value::integer(nctx.ctx, f + i));
+                a2.try_raw_set(nctx.ctx, Value::integer(nctx.ctx, t + i), v)?;
                 if i == 0 {
                     break;
                 }
@@ -247,12 +263,12 @@ fn lua_pack<'gc>(
     let n = stack.len();
     let t = Table::new(nctx.ctx);
     for i in 0..n {
-        t.raw_set(nctx.ctx, Value::integer(i as i64 + 1), stack.get(i));
+        t.raw_set(nctx.ctx, Value::small_integer(i as i64 + 1), stack.get(i));
     }
     t.raw_set(
         nctx.ctx,
         Value::string(LuaString::new(nctx.ctx, b"n")),
-        Value::integer(n as i64),
+        Value::small_integer(n as i64),
     );
     stack.replace(&[Value::table(t)]);
     Ok(CallbackAction::Return)
@@ -279,14 +295,14 @@ fn lua_remove<'gc>(
             "bad argument #2 to 'remove' (position out of bounds)",
         ));
     }
-    let result = t.raw_get(Value::integer(pos));
+    let result = t.raw_get(Value::integer(nctx.ctx, pos));
     let mut k = pos;
     while k < n {
-        let v = t.raw_get(Value::integer(k + 1));
-        t.raw_set(nctx.ctx, Value::integer(k), v);
+        let v = t.raw_get(Value::integer(nctx.ctx, k + 1));
+        t.raw_set(nctx.ctx, Value::integer(nctx.ctx, k), v);
         k += 1;
     }
-    t.raw_set(nctx.ctx, Value::integer(pos.max(n)), Value::nil());
+    t.raw_set(nctx.ctx, Value::integer(nctx.ctx, pos.max(n)), Value::nil());
     stack.replace(&[result]);
     Ok(CallbackAction::Return)
 }
@@ -437,8 +453,8 @@ async fn sort_less(
     }
     let plan = seq.try_enter(|ctx, locals, _exec, mut stack| {
         let tbl = locals.fetch(t);
-        let a = tbl.raw_get(Value::integer(xi as i64));
-        let b = tbl.raw_get(Value::integer(yi as i64));
+        let a = tbl.raw_get(Value::small_integer(xi as i64));
+        let b = tbl.raw_get(Value::small_integer(yi as i64));
         if comp.is_some() {
             stack.replace(&[a, b]);
             return Ok(Plan::CallComp);
@@ -485,8 +501,8 @@ async fn sort_less(
 fn sort_swap(seq: &mut AsyncSequence, t: &StashedTable, x: usize, y: usize) {
     seq.enter(|ctx, locals, _exec, _stack| {
         let tbl = locals.fetch(t);
-        let kx = Value::integer(x as i64);
-        let ky = Value::integer(y as i64);
+        let kx = Value::small_integer(x as i64);
+        let ky = Value::small_integer(y as i64);
         let vx = tbl.raw_get(kx);
         let vy = tbl.raw_get(ky);
         tbl.raw_set(ctx, kx, vy);
@@ -561,10 +577,10 @@ fn lua_unpack<'gc>(
     // past i64::MAX when `j == i64::MAX`.
     let mut k = i;
     while k < j {
-        out.push(t.raw_get(Value::integer(k)));
+        out.push(t.raw_get(Value::integer(nctx.ctx, k)));
         k += 1;
     }
-    out.push(t.raw_get(Value::integer(j)));
+    out.push(t.raw_get(Value::integer(nctx.ctx, j)));
     stack.replace(&out);
     Ok(CallbackAction::Return)
 }
@@ -18,7 +18,7 @@ pub fn load<'gc>(ctx: Context<'gc>) {
 
     let lib = Table::new(ctx);
     for &(name, handler) in fns {
-        let handler = Function::new_native(ctx.mutation(), handler, Box::new([]));
+        let handler = Function::new_native(ctx.mutation(), handler, []);
         let key = Value::string(LuaString::new(ctx, name.as_bytes()));
         lib.raw_set(ctx, key, Value::function(handler));
     }
@@ -149,7 +149,9 @@ fn lua_char<'gc>(
         }
         encode(c as u32, &mut out);
     }
-    stack.replace(&[Value::string(LuaString::new(nctx.ctx, &out))]);
+    stack.replace(&[Value::string(crate::builtin::util::new_string(
+        nctx.ctx, &out,
+    )?)]);
     Ok(CallbackAction::Return)
 }
 
@@ -195,7 +197,7 @@ fn lua_codepoint<'gc>(
     while pos < end {
         match decode(bytes, pos, strict) {
             Some((code, next)) => {
-                out.push(Value::integer(code as i64));
+                out.push(Value::small_integer(code as i64));
                 pos = next;
             }
             None => return Err(Error::from_str(nctx.ctx, "invalid UTF-8 code")),
@@ -250,12 +252,12 @@ fn lua_len<'gc>(
                 posi = next as i64 + 1;
             }
             None => {
-                stack.replace(&[Value::nil(), Value::integer(posi)]);
+                stack.replace(&[Value::nil(), Value::integer(nctx.ctx, posi)]);
                 return Ok(CallbackAction::Return);
             }
         }
     }
-    stack.replace(&[Value::integer(count)]);
+    stack.replace(&[Value::integer(nctx.ctx, count)]);
     Ok(CallbackAction::Return)
 }
 
@@ -330,7 +332,7 @@ fn lua_offset<'gc>(
                 }
                 e
             };
-            stack.replace(&[Value::integer(p), Value::integer(end)]);
+            stack.replace(&[Value::integer(nctx.ctx, p), Value::integer(nctx.ctx, end)]);
         }
         None => stack.replace(&[Value::nil()]),
     }
@@ -344,8 +346,12 @@ fn lua_codes<'gc>(
     mut stack: Stack<'gc, '_>,
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
     let s = check_str(nctx.ctx, stack.get(0), "codes", 1)?;
-    let iter = Function::new_native(nctx.ctx.mutation(), codes_aux, Box::new([]));
-    stack.replace(&[Value::function(iter), Value::string(s), Value::integer(0)]);
+    let iter = Function::new_native(nctx.ctx.mutation(), codes_aux, []);
+    stack.replace(&[
+        Value::function(iter),
+        Value::string(s),
+        Value::small_integer(0),
+    ]);
     Ok(CallbackAction::Return)
 }
 
@@ -375,7 +381,10 @@ fn codes_aux<'gc>(
     }
     match decode(bytes, pos, true) {
         Some((code, _)) => {
-            stack.replace(&[Value::integer(pos as i64 + 1), Value::integer(code as i64)]);
+            stack.replace(&[
+                Value::small_integer(pos as i64 + 1),
+                Value::small_integer(code as i64),
+            ]);
             Ok(CallbackAction::Return)
         }
         None => Err(Error::from_str(nctx.ctx, "invalid UTF-8 code")),
@@ -29,7 +29,7 @@ pub(crate) fn compare_error_msg<'gc>(a: Value<'gc>, b: Value<'gc>) -> String {
 /// anything non-numeric — notably `"inf"`/`"nan"`, which Rust's `f64::parse`
 /// would otherwise accept but Lua rejects. Shared by `tonumber` and
 /// `math.tointeger`.
-pub(crate) fn str_to_number<'gc>(b: &[u8]) -> Option<Value<'gc>> {
+pub(crate) fn str_to_number(b: &[u8]) -> Option<Number> {
     let s = std::str::from_utf8(b)
         .ok()?
         .trim_matches(|c: char| c.is_ascii_whitespace());
@@ -48,7 +48,7 @@ pub(crate) fn str_to_number<'gc>(b: &[u8]) -> Option<Value<'gc>> {
         // `.`/`p` means a hex float; otherwise a (wrapping) hex integer.
         if hex.contains(['.', 'p', 'P']) {
             let f = crate::parser::lit::parse_hex_float(body)?;
-            return Some(Value::float(if neg { -f } else { f }));
+            return Some(Number::Float(if neg { -f } else { f }));
         }
         let mut acc: u64 = 0;
         for c in hex.bytes() {
@@ -56,7 +56,7 @@ pub(crate) fn str_to_number<'gc>(b: &[u8]) -> Option<Value<'gc>> {
             acc = acc.wrapping_mul(16).wrapping_add(d);
         }
         let i = acc as i64;
-        return Some(Value::integer(if neg { i.wrapping_neg() } else { i }));
+        return Some(Number::Int(if neg { i.wrapping_neg() } else { i }));
     }
     // Decimal. Restrict to numeric characters so `f64::parse` can't sneak in
     // `inf`/`nan`/`infinity`.
@@ -68,9 +68,25 @@ pub(crate) fn str_to_number<'gc>(b: &[u8]) -> Option<Value<'gc>> {
         return None;
     }
     if let Ok(i) = s.parse::<i64>() {
-        return Some(Value::integer(i));
+        return Some(Number::Int(i));
+    }
+    s.parse::<f64>().ok().map(Number::Float)
+}
+
+/// A number parsed by [`str_to_number`], not yet turned into a `Value`.
+#[derive(Clone, Copy)]
+pub(crate) enum Number {
+    Int(i64),
+    Float(f64),
+}
+
+impl Number {
+    pub(crate) fn into_value<'gc>(self, ctx: Context<'gc>) -> Value<'gc> {
+        match self {
+            Number::Int(i) => Value::integer(ctx, i),
+            Number::Float(f) => Value::float(f),
+        }
     }
-    s.parse::<f64>().ok().map(Value::float)
 }
 
 /// Parse `b` as an integer written in `base` (2..=36), with optional
@@ -271,6 +287,27 @@ pub(crate) fn check_integer<'gc>(
     ))
 }
 
+/// Fail with Lua's "not enough memory" error if allocating `bytes` would
+/// take the runtime over its memory limit. For natives about to make one
+/// large allocation, which would otherwise only be noticed after the fact.
+pub(crate) fn check_memory<'gc>(ctx: Context<'gc>, bytes: usize) -> Result<(), Error<'gc>> {
+    if ctx.mutation().metrics().exceeds_memory_limit(bytes) {
+        ctx.gc_control().raised_out_of_memory();
+        return Err(Error::from_str(ctx, "not enough memory"));
+    }
+    Ok(())
+}
+
+/// [`LuaString::new`] for a native's result, refused by [`check_memory`]
+/// before the string is allocated.
+pub(crate) fn new_string<'gc>(
+    ctx: Context<'gc>,
+    bytes: &[u8],
+) -> Result<LuaString<'gc>, Error<'gc>> {
+    check_memory(ctx, bytes.len())?;
+    Ok(LuaString::new(ctx, bytes))
+}
+
 pub(crate) fn to_number<'gc>(v: Value<'gc>) -> Option<f64> {
     if let Some(i) = v.get_integer() {
         Some(i as f64)
@@ -279,8 +316,10 @@ pub(crate) fn to_number<'gc>(v: Value<'gc>) -> Option<f64> {
     } else if let Some(s) = v.get_string() {
         // Via the lexer rules (`str_to_number`), not raw `f64::parse`, so
         // `"inf"`/`"nan"` are rejected as Lua's `luaL_checknumber` does.
-        let n = str_to_number(s.as_bytes())?;
-        n.get_integer().map(|i| i as f64).or_else(|| n.get_float())
+        match str_to_number(s.as_bytes())? {
+            Number::Int(i) => Some(i as f64),
+            Number::Float(f) => Some(f),
+        }
     } else {
         None
     }
@@ -297,10 +336,10 @@ pub(crate) fn to_integer<'gc>(v: Value<'gc>) -> Option<i64> {
     }
     if let Some(s) = v.get_string() {
         // Same lexer-rule coercion as `to_number`, then the int/float rule.
-        let n = str_to_number(s.as_bytes())?;
-        return n
-            .get_integer()
-            .or_else(|| n.get_float().and_then(float_to_integer));
+        return match str_to_number(s.as_bytes())? {
+            Number::Int(i) => Some(i),
+            Number::Float(f) => float_to_integer(f),
+        };
     }
     None
 }
@@ -317,9 +356,9 @@ pub(crate) fn float_to_integer(f: f64) -> Option<i64> {
 
 /// Lua's `pushnumint`: an integral float collapses to an integer when it fits
 /// in `i64`, otherwise stays a float. Used by `math.floor`/`ceil`/`modf`.
-pub(crate) fn num_to_value<'gc>(f: f64) -> Value<'gc> {
+pub(crate) fn num_to_value<'gc>(ctx: Context<'gc>, f: f64) -> Value<'gc> {
     match float_to_integer(f) {
-        Some(i) => Value::integer(i),
+        Some(i) => Value::integer(ctx, i),
         None => Value::float(f),
     }
 }
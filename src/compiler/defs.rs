@@ -1,5 +1,7 @@
+use std::cell::Cell;
+
 use crate::dmm::{Gc, Lock, Mutation};
-use crate::env::function::InlineCache;
+use crate::env::function::{self, InlineCache, LineInfo, LocalVar, Name};
 use crate::env::{LuaString, Prototype, Value};
 use crate::instruction::{Instruction, UpValueDescriptor};
 
@@ -206,9 +208,17 @@ impl ExprDesc {
 /// Mutable accumulator used during compilation of a single function.
 pub struct Chunk<'gc> {
     pub(super) tape: Vec<Instruction>,
+    /// Source line of each instruction in `tape`, kept in lockstep with it.
+    /// Run-length encoded into `Prototype::line_info` at assembly time.
+    pub(super) lines: Vec<u32>,
     pub(super) constants: Vec<Value<'gc>>,
     pub(super) prototypes: Vec<Gc<'gc, Prototype<'gc>>>,
     pub(super) upvalue_desc: Vec<UpValueDescriptor>,
+    /// Upvalue names, parallel to `upvalue_desc`.
+    pub(super) upvalue_names: Vec<Name>,
+    /// Named locals in declaration order. `end_pc` is `u32::MAX` while the
+    /// local's scope is still open.
+    pub(super) local_vars: Vec<LocalVar>,
     /// Next free register slot; cursor for temp allocation. Locals occupy
     /// `[0, nactvar)`, temps occupy `[nactvar, freereg)`. Matches Lua 5.5's
     /// `fs->freereg` semantics.
@@ -236,7 +246,7 @@ pub struct Chunk<'gc> {
     pub(super) last_target: usize,
     pub(super) source: Option<LuaString<'gc>>,
     /// Number of IC slots reserved so far. Incremented once per emitted
-    /// GETFIELD/SETFIELD/GETTABUP/SETTABUP. The final count seeds the
+    /// GETFIELD/SETFIELD/GETTABUP/SETTABUP/SELF. The final count seeds the
     /// prototype's `ic_table` length.
     pub(super) next_ic_idx: u16,
 }
@@ -245,9 +255,12 @@ impl<'gc> Chunk<'gc> {
     pub fn new() -> Self {
         Chunk {
             tape: Vec::new(),
+            lines: Vec::new(),
             constants: Vec::new(),
             prototypes: Vec::new(),
             upvalue_desc: Vec::new(),
+            upvalue_names: Vec::new(),
+            local_vars: Vec::new(),
             freereg: 0,
             nactvar: 0,
             max_stack: 0,
@@ -263,7 +276,7 @@ impl<'gc> Chunk<'gc> {
     }
 
     /// Reserve a fresh inline-cache slot. Returns the index to embed in
-    /// the GETFIELD/SETFIELD/GETTABUP/SETTABUP instruction. Slots are
+    /// the GETFIELD/SETFIELD/GETTABUP/SETTABUP/SELF instruction. Slots are
     /// **not** deduped across call sites — sharing would defeat ICs at
     /// any non-monomorphic shared use.
     pub(super) fn alloc_ic_slot(&mut self) -> u16 {
@@ -292,22 +305,30 @@ impl<'gc> Chunk<'gc> {
 
         let num_upvalues = self.upvalue_desc.len() as u8;
 
-        let ic_table =
-            vec![Lock::new(InlineCache::Empty); self.next_ic_idx as usize].into_boxed_slice();
+        let ic_table = function::proto_slice(
+            mc,
+            (0..self.next_ic_idx).map(|_| Lock::new(InlineCache::Empty)),
+        );
+
+        let despecializations = function::proto_slice(mc, self.tape.iter().map(|_| Cell::new(0)));
 
         Gc::new(
             mc,
             Prototype {
-                code: self.tape.into_boxed_slice(),
-                constants: self.constants.into_boxed_slice(),
-                prototypes: self.prototypes.into_boxed_slice(),
-                upvalue_desc: self.upvalue_desc.into_boxed_slice(),
+                code: function::proto_slice(mc, self.tape.into_iter().map(Lock::new)),
+                despecializations,
+                constants: function::proto_slice(mc, self.constants),
+                prototypes: function::proto_slice(mc, self.prototypes),
+                upvalue_desc: function::proto_slice(mc, self.upvalue_desc),
                 num_params: self.arity,
                 is_vararg: self.is_vararg,
                 needs_vararg_table: self.vararg_info.is_some_and(|i| i.needs_table()),
                 max_stack_size: self.max_stack,
                 num_upvalues,
                 source: self.source,
+                line_info: LineInfo::new(mc, &self.lines),
+                local_vars: function::proto_slice(mc, self.local_vars),
+                upvalue_names: function::proto_slice(mc, self.upvalue_names),
                 ic_table,
             },
         )
@@ -37,7 +37,7 @@ fn format_prototype_into(out: &mut String, proto: &Prototype<'_>, depth: usize)
     for (i, instr) in proto.code.iter().enumerate() {
         out.push_str(&format!(
             "{indent}{i:04}  {}\n",
-            format_instruction(instr, &proto.constants)
+            format_instruction(&instr.get(), &proto.constants)
         ));
     }
 
@@ -116,6 +116,8 @@ fn format_instruction(instr: &Instruction, constants: &[Value<'_>]) -> String {
         Instruction::SETTABLE { src, table, key } => {
             format!("SETTABLE        R{src} R{table} R{key}")
         }
+        Instruction::GETI { dst, table, idx } => format!("GETI            R{dst} R{table} {idx}"),
+        Instruction::SETI { src, table, idx } => format!("SETI            R{src} R{table} {idx}"),
         Instruction::GETFIELD {
             dst,
             table,
@@ -141,6 +143,7 @@ fn format_instruction(instr: &Instruction, constants: &[Value<'_>]) -> String {
         Instruction::SELF {
             dst,
             object,
+            ic_idx: _,
             key_idx,
         } => {
             format!(
@@ -161,6 +164,69 @@ fn format_instruction(instr: &Instruction, constants: &[Value<'_>]) -> String {
         Instruction::BXOR { dst, lhs, rhs } => format!("BXOR            R{dst} R{lhs} R{rhs}"),
         Instruction::SHL { dst, lhs, rhs } => format!("SHL             R{dst} R{lhs} R{rhs}"),
         Instruction::SHR { dst, lhs, rhs } => format!("SHR             R{dst} R{lhs} R{rhs}"),
+        Instruction::ADDI { dst, lhs, imm } => format!("ADDI            R{dst} R{lhs} {imm}"),
+        Instruction::ADDK { dst, lhs, key } => {
+            format!(
+                "ADDK            R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::SUBK { dst, lhs, key } => {
+            format!(
+                "SUBK            R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::MULK { dst, lhs, key } => {
+            format!(
+                "MULK            R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::MODK { dst, lhs, key } => {
+            format!(
+                "MODK            R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::POWK { dst, lhs, key } => {
+            format!(
+                "POWK            R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::DIVK { dst, lhs, key } => {
+            format!(
+                "DIVK            R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::IDIVK { dst, lhs, key } => {
+            format!(
+                "IDIVK           R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::BANDK { dst, lhs, key } => {
+            format!(
+                "BANDK           R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::BORK { dst, lhs, key } => {
+            format!(
+                "BORK            R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::BXORK { dst, lhs, key } => {
+            format!(
+                "BXORK           R{dst} R{lhs} K{key}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::SHRI { dst, lhs, imm } => format!("SHRI            R{dst} R{lhs} {imm}"),
+        Instruction::SHLI { dst, rhs, imm } => format!("SHLI            R{dst} {imm} R{rhs}"),
         Instruction::UNM { dst, src } => format!("UNM             R{dst} R{src}"),
         Instruction::BNOT { dst, src } => format!("BNOT            R{dst} R{src}"),
         Instruction::NOT { dst, src } => format!("NOT             R{dst} R{src}"),
@@ -180,6 +246,27 @@ fn format_instruction(instr: &Instruction, constants: &[Value<'_>]) -> String {
         Instruction::LE { lhs, rhs, inverted } => {
             format!("LE              R{lhs} R{rhs} inv={inverted}")
         }
+        Instruction::EQK { lhs, key, inverted } => {
+            format!(
+                "EQK             R{lhs} K{key} inv={inverted}{}",
+                const_comment(constants, key)
+            )
+        }
+        Instruction::EQI { lhs, imm, inverted } => {
+            format!("EQI             R{lhs} {imm} inv={inverted}")
+        }
+        Instruction::LTI { lhs, imm, inverted } => {
+            format!("LTI             R{lhs} {imm} inv={inverted}")
+        }
+        Instruction::LEI { lhs, imm, inverted } => {
+            format!("LEI             R{lhs} {imm} inv={inverted}")
+        }
+        Instruction::GTI { lhs, imm, inverted } => {
+            format!("GTI             R{lhs} {imm} inv={inverted}")
+        }
+        Instruction::GEI { lhs, imm, inverted } => {
+            format!("GEI             R{lhs} {imm} inv={inverted}")
+        }
         Instruction::TEST { src, inverted } => {
             format!("TEST            R{src} inv={inverted}")
         }
@@ -236,5 +323,32 @@ fn format_instruction(instr: &Instruction, constants: &[Value<'_>]) -> String {
         }
         Instruction::NOP => "NOP".to_string(),
         Instruction::STOP => "STOP".to_string(),
+        Instruction::ADD_II { dst, lhs, rhs } => format!("ADD_II          R{dst} R{lhs} R{rhs}"),
+        Instruction::ADD_FF { dst, lhs, rhs } => format!("ADD_FF          R{dst} R{lhs} R{rhs}"),
+        Instruction::SUB_II { dst, lhs, rhs } => format!("SUB_II          R{dst} R{lhs} R{rhs}"),
+        Instruction::SUB_FF { dst, lhs, rhs } => format!("SUB_FF          R{dst} R{lhs} R{rhs}"),
+        Instruction::MUL_II { dst, lhs, rhs } => format!("MUL_II          R{dst} R{lhs} R{rhs}"),
+        Instruction::MUL_FF { dst, lhs, rhs } => format!("MUL_FF          R{dst} R{lhs} R{rhs}"),
+        Instruction::LT_II { lhs, rhs, inverted } => {
+            format!("LT_II           R{lhs} R{rhs} inv={inverted}")
+        }
+        Instruction::LT_FF { lhs, rhs, inverted } => {
+            format!("LT_FF           R{lhs} R{rhs} inv={inverted}")
+        }
+        Instruction::LE_II { lhs, rhs, inverted } => {
+            format!("LE_II           R{lhs} R{rhs} inv={inverted}")
+        }
+        Instruction::LE_FF { lhs, rhs, inverted } => {
+            format!("LE_FF           R{lhs} R{rhs} inv={inverted}")
+        }
+        Instruction::FORLOOP_I { base, offset } => {
+            format!("FORLOOP_I       R{base} {offset:+}")
+        }
+        Instruction::FORLOOP_F { base, offset } => {
+            format!("FORLOOP_F       R{base} {offset:+}")
+        }
+        Instruction::GETTABLE_ARRAY { dst, table, key } => {
+            format!("GETTABLE_ARRAY  R{dst} R{table} R{key}")
+        }
     }
 }
@@ -10,16 +10,21 @@ use cstree::interning::TokenInterner;
 use thiserror::Error;
 
 use crate::dmm::{Collect, Gc};
-use crate::env::Prototype;
+use crate::env::{LuaString, Prototype};
 use crate::lua;
+use crate::parser::machinery::LineMap;
 use crate::parser::syntax;
 
+/// Compile a parsed chunk. `lines` maps the tree's offsets back to source
+/// lines; `source` is the chunk name recorded on every prototype.
 pub fn compile_chunk<'gc>(
     ctx: lua::Context<'gc>,
     root: &syntax::Root,
     interner: &TokenInterner,
+    lines: &LineMap,
+    source: Option<LuaString<'gc>>,
 ) -> Result<Gc<'gc, Prototype<'gc>>, CompileError> {
-    rules::compile(ctx, root, interner)
+    rules::compile(ctx, root, interner, lines, source)
 }
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Collect)]
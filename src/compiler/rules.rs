@@ -6,9 +6,11 @@ use cstree::interning::TokenInterner;
 use super::defs::{Chunk, ExprDesc, ExprKind, JumpList, Numeral, RegisterIndex, VarargInfo, Want};
 use super::{CompileError, CompileErrorKind, LineNumber};
 use crate::dmm::Gc;
+use crate::env::function::{self, LocalVar};
 use crate::env::{LuaString, Prototype, value::Value};
 use crate::instruction::{Instruction, UpValueDescriptor};
 use crate::lua;
+use crate::parser::machinery::LineMap;
 use crate::parser::syntax::{
     Assign, BinaryOp, BinaryOperator, Break, Decl, DeclModifier, Do, Expr, ForGen, ForNum, Func,
     FuncCall, FuncExpr, Global, Goto, Ident, If, Index, Label, Literal, LiteralValue, MethodCall,
@@ -47,9 +49,9 @@ fn err(kind: CompileErrorKind, line: LineNumber) -> CompileError {
 /// Convert a constant `ExprKind` to the runtime `Value` that should be
 /// stored in the constant table when discharging. Returns `None` for
 /// `Reg`/`Jump` kinds, which don't carry a constant value.
-fn const_kind_to_value<'gc>(kind: ExprKind) -> Option<Value<'gc>> {
+fn const_kind_to_value<'gc>(ctx: lua::Context<'gc>, kind: ExprKind) -> Option<Value<'gc>> {
     match kind {
-        ExprKind::Numeral(Numeral::Int(n)) => Some(Value::integer(n)),
+        ExprKind::Numeral(Numeral::Int(n)) => Some(Value::integer(ctx, n)),
         ExprKind::Numeral(Numeral::Float(f)) => Some(Value::float(f)),
         ExprKind::Bool(b) => Some(Value::boolean(b)),
         ExprKind::Nil => Some(Value::nil()),
@@ -57,9 +59,9 @@ fn const_kind_to_value<'gc>(kind: ExprKind) -> Option<Value<'gc>> {
     }
 }
 
-fn numeral_to_value<'gc>(n: Numeral) -> Value<'gc> {
+fn numeral_to_value<'gc>(ctx: lua::Context<'gc>, n: Numeral) -> Value<'gc> {
     match n {
-        Numeral::Int(i) => Value::integer(i),
+        Numeral::Int(i) => Value::integer(ctx, i),
         Numeral::Float(f) => Value::float(f),
     }
 }
@@ -92,7 +94,12 @@ fn numeral_is_zero(n: Numeral) -> bool {
 /// silently lose information (`-0.0` collapse, NaN-result float). Reuses
 /// the runtime helpers in `vm/num.rs` so folded results match runtime
 /// bit-for-bit.
-fn try_fold_binop(op: BinaryOperator, lhs: Numeral, rhs: Numeral) -> Option<Numeral> {
+fn try_fold_binop(
+    ctx: lua::Context<'_>,
+    op: BinaryOperator,
+    lhs: Numeral,
+    rhs: Numeral,
+) -> Option<Numeral> {
     use crate::vm::num::{
         Add, BAnd, BOr, BXor, Div, IDiv, Mod, Mul, Pow, Shl, Shr, Sub, op_arith, op_bit,
     };
@@ -119,22 +126,22 @@ fn try_fold_binop(op: BinaryOperator, lhs: Numeral, rhs: Numeral) -> Option<Nume
         return None;
     }
 
-    let lv = numeral_to_value(lhs);
-    let rv = numeral_to_value(rhs);
+    let lv = numeral_to_value(ctx, lhs);
+    let rv = numeral_to_value(ctx, rhs);
 
     let result: Value<'_> = match op {
-        BinaryOperator::Add => op_arith::<Add>(lv, rv),
-        BinaryOperator::Sub => op_arith::<Sub>(lv, rv),
-        BinaryOperator::Mul => op_arith::<Mul>(lv, rv),
-        BinaryOperator::Div => op_arith::<Div>(lv, rv),
-        BinaryOperator::IntDiv => op_arith::<IDiv>(lv, rv),
-        BinaryOperator::Mod => op_arith::<Mod>(lv, rv),
-        BinaryOperator::Exp => op_arith::<Pow>(lv, rv),
-        BinaryOperator::BitAnd => op_bit::<BAnd>(lv, rv),
-        BinaryOperator::BitOr => op_bit::<BOr>(lv, rv),
-        BinaryOperator::BitXor => op_bit::<BXor>(lv, rv),
-        BinaryOperator::LShift => op_bit::<Shl>(lv, rv),
-        BinaryOperator::RShift => op_bit::<Shr>(lv, rv),
+        BinaryOperator::Add => op_arith::<Add>(ctx, lv, rv),
+        BinaryOperator::Sub => op_arith::<Sub>(ctx, lv, rv),
+        BinaryOperator::Mul => op_arith::<Mul>(ctx, lv, rv),
+        BinaryOperator::Div => op_arith::<Div>(ctx, lv, rv),
+        BinaryOperator::IntDiv => op_arith::<IDiv>(ctx, lv, rv),
+        BinaryOperator::Mod => op_arith::<Mod>(ctx, lv, rv),
+        BinaryOperator::Exp => op_arith::<Pow>(ctx, lv, rv),
+        BinaryOperator::BitAnd => op_bit::<BAnd>(ctx, lv, rv),
+        BinaryOperator::BitOr => op_bit::<BOr>(ctx, lv, rv),
+        BinaryOperator::BitXor => op_bit::<BXor>(ctx, lv, rv),
+        BinaryOperator::LShift => op_bit::<Shl>(ctx, lv, rv),
+        BinaryOperator::RShift => op_bit::<Shr>(ctx, lv, rv),
         _ => return None,
     }?;
 
@@ -298,6 +305,9 @@ struct VariableData {
 struct ScopeMark {
     freereg: u8,
     nactvar: u8,
+    /// `Chunk::local_vars` length at scope entry; the locals after it go
+    /// out of scope with it.
+    local_vars: usize,
 }
 
 // ---------------------------------------------------------------------------
@@ -332,11 +342,17 @@ enum ResolvedName {
 
 struct Ctx<'gc, 'a> {
     interner: &'a TokenInterner,
+    /// Maps tree offsets back to source lines for the line table.
+    lines: &'a LineMap,
+    /// Line of the statement or expression being compiled; stamped on
+    /// every emitted instruction.
+    line: u32,
     ctx: lua::Context<'gc>,
     chunk: Chunk<'gc>,
 
-    /// Stack of break-target labels for nested loops.
-    control_end_label: Vec<u16>,
+    /// Stack of break-target labels for nested loops, each with the scope
+    /// depth at loop entry: a `break` closes what the scopes above it hold.
+    control_end_label: Vec<(u16, usize)>,
 
     /// Lexical scope stack: each frame maps variable names to register data.
     scope: Vec<HashMap<String, VariableData>>,
@@ -348,6 +364,12 @@ struct Ctx<'gc, 'a> {
 
     /// Named labels for goto/label statements (name → label index).
     goto_labels: HashMap<String, u16>,
+    /// Register level at each defined label. A backward `goto` closes the
+    /// to-be-closed variables at or above it.
+    label_levels: HashMap<String, u8>,
+    /// Highest to-be-closed register live at a forward `goto` whose label
+    /// hasn't been reached yet; the label closes from its level if needed.
+    goto_close: HashMap<String, RegisterIndex>,
 
     /// Parent function's resolver, or `None` for the main chunk. A nested
     /// function calls this to walk the lexical chain when it encounters a
@@ -370,6 +392,18 @@ struct Ctx<'gc, 'a> {
 impl<'gc, 'a> Ctx<'gc, 'a> {
     fn emit(&mut self, instruction: Instruction) {
         self.chunk.tape.push(instruction);
+        self.chunk.lines.push(self.line);
+    }
+
+    /// Make `offset` (a tree offset, if the node has one) the line stamped
+    /// on subsequent instructions, returning the previous line so the
+    /// caller can restore it.
+    fn set_line(&mut self, offset: Option<u32>) -> u32 {
+        let prev = self.line;
+        if let Some(offset) = offset {
+            self.line = self.lines.line(offset);
+        }
+        prev
     }
 
     /// Reserve a single fresh temp register at `freereg` and return it.
@@ -544,6 +578,12 @@ impl<'gc, 'a> Ctx<'gc, 'a> {
                 Instruction::EQ { .. }
                     | Instruction::LT { .. }
                     | Instruction::LE { .. }
+                    | Instruction::EQK { .. }
+                    | Instruction::EQI { .. }
+                    | Instruction::LTI { .. }
+                    | Instruction::LEI { .. }
+                    | Instruction::GTI { .. }
+                    | Instruction::GEI { .. }
                     | Instruction::TEST { .. }
                     | Instruction::TESTSET { .. }
             ),
@@ -657,6 +697,16 @@ impl<'gc, 'a> Ctx<'gc, 'a> {
                 "no-op jump elision would orphan a live jump target"
             );
             self.chunk.tape.truncate(j - 1);
+            self.chunk.lines.truncate(j - 1);
+            // Ranges ending in the elided pair end where it was; open ones
+            // stay open.
+            let end = j as u32 - 1;
+            for var in &mut self.chunk.local_vars {
+                var.start_pc = var.start_pc.min(end);
+                if var.end_pc != u32::MAX {
+                    var.end_pc = var.end_pc.min(end);
+                }
+            }
             list.jumps.remove(pos);
         }
         let target = self.next_offset();
@@ -779,6 +829,12 @@ impl<'gc, 'a> Ctx<'gc, 'a> {
             Instruction::LT { inverted, .. }
             | Instruction::LE { inverted, .. }
             | Instruction::EQ { inverted, .. }
+            | Instruction::EQK { inverted, .. }
+            | Instruction::EQI { inverted, .. }
+            | Instruction::LTI { inverted, .. }
+            | Instruction::LEI { inverted, .. }
+            | Instruction::GTI { inverted, .. }
+            | Instruction::GEI { inverted, .. }
             | Instruction::TEST { inverted, .. }
             | Instruction::TESTSET { inverted, .. } => {
                 *inverted = !*inverted;
@@ -976,7 +1032,7 @@ impl<'gc, 'a> Ctx<'gc, 'a> {
         // The boolean fixup tail (LFALSESKIP / LOAD-true) is normally
         // skipped on this path because TESTSET-controlled jumps satisfy
         // `need_value` without it.
-        if let Some(value) = const_kind_to_value(expr.kind) {
+        if let Some(value) = const_kind_to_value(self.ctx, expr.kind) {
             let idx = self.alloc_constant(value)?;
             let dst = self.dst_or_alloc(hint)?;
             self.emit(Instruction::LOAD { dst: dst.0, idx });
@@ -1095,6 +1151,7 @@ impl<'gc, 'a> Ctx<'gc, 'a> {
         self.scope_marks.push(ScopeMark {
             freereg: self.chunk.freereg,
             nactvar: self.chunk.nactvar,
+            local_vars: self.chunk.local_vars.len(),
         });
         self.scope_close.push(Vec::new());
         self.scope_globals.push(self.globals.clone());
@@ -1108,6 +1165,10 @@ impl<'gc, 'a> Ctx<'gc, 'a> {
             .ok_or_else(|| ice("missing scope register base"))?;
         self.chunk.freereg = mark.freereg;
         self.chunk.nactvar = mark.nactvar;
+        let end_pc = self.chunk.tape.len() as u32;
+        for var in &mut self.chunk.local_vars[mark.local_vars..] {
+            var.end_pc = var.end_pc.min(end_pc);
+        }
         // Drop any `global` declarations made within this block — their
         // lexical scope ends here (`manual.of:245-249`).
         self.globals = self
@@ -1119,6 +1180,21 @@ impl<'gc, 'a> Ctx<'gc, 'a> {
             .ok_or_else(|| ice("missing scope close list"))
     }
 
+    /// Lowest to-be-closed register in the scopes from `depth` up.
+    fn close_bottom(&self, depth: usize) -> Option<RegisterIndex> {
+        self.scope_close[depth..]
+            .iter()
+            .find_map(|regs| regs.first().copied())
+    }
+
+    /// Highest to-be-closed register live in this function.
+    fn close_top(&self) -> Option<RegisterIndex> {
+        self.scope_close
+            .iter()
+            .rev()
+            .find_map(|regs| regs.last().copied())
+    }
+
     fn mark_close(&mut self, register: RegisterIndex) -> Result<(), CompileError> {
         self.scope_close
             .last_mut()
@@ -1129,6 +1205,14 @@ impl<'gc, 'a> Ctx<'gc, 'a> {
 
     fn define(&mut self, name: String, data: VariableData) -> Result<(), CompileError> {
         let scope = self.scope.last_mut().ok_or_else(|| ice("missing scope"))?;
+        if !matches!(data.kind, VarKind::Global) {
+            self.chunk.local_vars.push(LocalVar {
+                name: function::name(self.ctx.mutation(), &name),
+                register: data.register.0,
+                start_pc: self.chunk.tape.len() as u32,
+                end_pc: u32::MAX,
+            });
+        }
         scope.insert(name, data);
         Ok(())
     }
@@ -1185,7 +1269,7 @@ impl<'gc, 'a> Ctx<'gc, 'a> {
 
     /// Allocate a string constant for use as a field/global key, and
     /// reserve a fresh `ic_idx` for the GETFIELD/SETFIELD/GETTABUP/
-    /// SETTABUP instruction emitted right after.
+    /// SETTABUP/SELF instruction emitted right after.
     fn alloc_field_key(&mut self, s: &[u8]) -> Result<(u16, u16), CompileError> {
         let lua_str = LuaString::new(self.ctx, s);
         let idx = self.alloc_constant(Value::string(lua_str))?;
@@ -1311,7 +1395,7 @@ where
     F: FnOnce(&mut Ctx) -> Result<(), CompileError>,
 {
     let label = ctx.new_label();
-    ctx.control_end_label.push(label);
+    ctx.control_end_label.push((label, ctx.scope_close.len()));
     compile(ctx)?;
     ctx.set_label(label, ctx.next_offset());
     ctx.control_end_label.pop();
@@ -1333,6 +1417,8 @@ pub fn compile<'gc>(
     ctx: lua::Context<'gc>,
     root: &Root,
     interner: &TokenInterner,
+    lines: &LineMap,
+    source: Option<LuaString<'gc>>,
 ) -> Result<Gc<'gc, Prototype<'gc>>, CompileError> {
     // The chunk starts with the implicit `global *` (global-by-default);
     // nested functions inherit a clone of whatever is in scope at their
@@ -1341,13 +1427,14 @@ pub fn compile<'gc>(
     let chunk = compile_function_to_chunk(
         ctx,
         interner,
+        lines,
         None, // main chunk has no enclosing function
         root.block(),
         std::iter::empty(),
         true, // main chunk is vararg
         None, // main chunk has no named vararg parameter
         0,
-        None,
+        source,
         // Pre-seed `_ENV` at upvalue 0. The runtime wiring in
         // `src/lua/context.rs` (ctx.load) sets the top-level closure's
         // upvalues directly, so the descriptor here is purely a
@@ -1364,6 +1451,7 @@ pub fn compile<'gc>(
 fn compile_function_to_chunk<'gc, 'a>(
     ctx: lua::Context<'gc>,
     interner: &'a TokenInterner,
+    lines: &'a LineMap,
     parent_capture: Option<&'a mut dyn UpvalueResolver>,
     stmts: impl Iterator<Item = Stmt>,
     params: impl Iterator<Item = Ident>,
@@ -1381,6 +1469,8 @@ fn compile_function_to_chunk<'gc, 'a>(
 
     let mut ctx = Ctx {
         interner,
+        lines,
+        line: 1,
         ctx,
         chunk,
         control_end_label: Vec::new(),
@@ -1388,6 +1478,8 @@ fn compile_function_to_chunk<'gc, 'a>(
         scope_marks: Vec::new(),
         scope_close: Vec::new(),
         goto_labels: HashMap::new(),
+        label_levels: HashMap::new(),
+        goto_close: HashMap::new(),
         capture: parent_capture,
         upvalues: initial_upvalues,
         globals,
@@ -1483,16 +1575,17 @@ fn compile_function_to_chunk<'gc, 'a>(
         });
     }
 
-    let close_regs = ctx.pop_scope()?;
-    if let Some(first) = close_regs.first() {
-        // Insert CLOSE before the final RETURN/TAILCALL
-        let return_instr = ctx.chunk.tape.pop().unwrap();
-        ctx.chunk.tape.push(Instruction::CLOSE { start: first.0 });
-        ctx.chunk.tape.push(return_instr);
-    }
-
-    // Flatten the named upvalue list into the chunk's descriptor array.
-    ctx.chunk.upvalue_desc = ctx.upvalues.into_iter().map(|(_, d)| d).collect();
+    // The function scope's to-be-closed variables need no CLOSE: RETURN
+    // closes everything the frame still holds.
+    ctx.pop_scope()?;
+
+    // Flatten the named upvalue list into the chunk's descriptor and name
+    // arrays.
+    (ctx.chunk.upvalue_names, ctx.chunk.upvalue_desc) = ctx
+        .upvalues
+        .into_iter()
+        .map(|(name, d)| (function::name(ctx.ctx.mutation(), &name), d))
+        .unzip();
     Ok(ctx.chunk)
 }
 
@@ -1501,6 +1594,7 @@ fn compile_function_to_chunk<'gc, 'a>(
 // ---------------------------------------------------------------------------
 
 fn compile_stmt(ctx: &mut Ctx, item: Stmt) -> Result<(), CompileError> {
+    ctx.set_line(item.offset());
     match item {
         Stmt::Label(item) => compile_label(ctx, item),
         Stmt::Goto(item) => compile_goto(ctx, item),
@@ -1532,14 +1626,24 @@ fn compile_label(ctx: &mut Ctx, item: Label) -> Result<(), CompileError> {
         .ok_or_else(|| ice("ident without name"))?
         .to_owned();
 
-    if let Some(&label_idx) = ctx.goto_labels.get(&name) {
+    let level = ctx.chunk.freereg;
+    let label_idx = match ctx.goto_labels.get(&name) {
         // Forward reference already allocated — resolve it now
-        ctx.set_label(label_idx, ctx.next_offset());
-    } else {
-        let label_idx = ctx.new_label();
-        ctx.set_label(label_idx, ctx.next_offset());
-        ctx.goto_labels.insert(name, label_idx);
+        Some(&label_idx) => label_idx,
+        None => {
+            let label_idx = ctx.new_label();
+            ctx.goto_labels.insert(name.clone(), label_idx);
+            label_idx
+        }
+    };
+    ctx.set_label(label_idx, ctx.next_offset());
+    // A forward goto that left the scope of a to-be-closed variable lands
+    // here; close it on arrival. Falling through is unaffected, as those
+    // variables' own scopes have already closed them by this point.
+    if ctx.goto_close.remove(&name).is_some_and(|r| r.0 >= level) {
+        ctx.emit(Instruction::CLOSE { start: level });
     }
+    ctx.label_levels.insert(name, level);
 
     Ok(())
 }
@@ -1553,13 +1657,26 @@ fn compile_goto(ctx: &mut Ctx, item: Goto) -> Result<(), CompileError> {
         .to_owned();
 
     if let Some(&label_idx) = ctx.goto_labels.get(&name) {
+        // A backward goto leaves the scope of every to-be-closed variable
+        // declared since the label.
+        if let Some(&level) = ctx.label_levels.get(&name)
+            && ctx.close_top().is_some_and(|r| r.0 >= level)
+        {
+            ctx.emit(Instruction::CLOSE { start: level });
+        }
         ctx.emit_jump(label_idx);
     } else {
         // Forward goto — allocate a label that will be resolved when ::name:: is encountered
         let label_idx = ctx.new_label();
-        ctx.goto_labels.insert(name, label_idx);
+        ctx.goto_labels.insert(name.clone(), label_idx);
         ctx.emit_jump(label_idx);
     }
+    if !ctx.label_levels.contains_key(&name)
+        && let Some(top) = ctx.close_top()
+    {
+        let pending = ctx.goto_close.entry(name).or_insert(top);
+        pending.0 = pending.0.max(top.0);
+    }
 
     Ok(())
 }
@@ -1724,6 +1841,7 @@ fn compile_decl(ctx: &mut Ctx, item: Decl) -> Result<(), CompileError> {
     }
 
     // Bind each target name to its slot and handle `<close>` / `<const>`.
+    let mut to_close = Vec::new();
     for (i, target) in targets.into_iter().enumerate() {
         let name = target
             .name()
@@ -1745,8 +1863,7 @@ fn compile_decl(ctx: &mut Ctx, item: Decl) -> Result<(), CompileError> {
         let reg = RegisterIndex(base + i as u8);
 
         if matches!(kind, VarKind::ToClose) {
-            ctx.emit(Instruction::TBC { val: reg.0 });
-            ctx.mark_close(reg)?;
+            to_close.push(reg);
         }
 
         ctx.define(
@@ -1763,6 +1880,13 @@ fn compile_decl(ctx: &mut Ctx, item: Decl) -> Result<(), CompileError> {
     // the register staying put).
     ctx.adjust_locals(num_targets as u8);
 
+    // `TBC` runs once the variable is in scope, so a non-closable value is
+    // reported under the variable's name.
+    for reg in to_close {
+        ctx.emit(Instruction::TBC { val: reg.0 });
+        ctx.mark_close(reg)?;
+    }
+
     Ok(())
 }
 
@@ -2087,6 +2211,10 @@ enum Lvalue {
         table: RegisterIndex,
         key: RegisterIndex,
     },
+    IntIndexed {
+        table: RegisterIndex,
+        idx: u16,
+    },
     Field {
         table: RegisterIndex,
         key_idx: u16,
@@ -2293,7 +2421,12 @@ fn compile_lvalue(
             let t = index.target().ok_or_else(|| ice("index without target"))?;
             let k = index.index().ok_or_else(|| ice("index without key"))?;
             let table = compile_indexed_subexpr(ctx, t, target_local_regs)?;
-            let key = compile_indexed_subexpr(ctx, k, target_local_regs)?;
+            let mut key = compile_expr(ctx, k, None)?;
+            if let Some(idx) = integer_key(&key) {
+                return Ok(Lvalue::IntIndexed { table, idx });
+            }
+            let key = ctx.discharge_to_reg_mut(&mut key, None)?;
+            let key = protect_target_local(ctx, key, target_local_regs)?;
             Ok(Lvalue::Indexed { table, key })
         }
         Expr::BinaryOp(binop) if binop.op() == Some(BinaryOperator::Property) => {
@@ -2325,6 +2458,15 @@ fn compile_indexed_subexpr(
     target_local_regs: &[u8],
 ) -> Result<RegisterIndex, CompileError> {
     let reg = compile_expr_to_reg(ctx, expr, None)?;
+    protect_target_local(ctx, reg, target_local_regs)
+}
+
+/// `reg`, or a fresh temp copy of it if it's a local that's also a target.
+fn protect_target_local(
+    ctx: &mut Ctx,
+    reg: RegisterIndex,
+    target_local_regs: &[u8],
+) -> Result<RegisterIndex, CompileError> {
     if !target_local_regs.contains(&reg.0) {
         return Ok(reg);
     }
@@ -2453,6 +2595,11 @@ fn emit_store(ctx: &mut Ctx, lv: Lvalue, src: u8) {
             table: table.0,
             key: key.0,
         }),
+        Lvalue::IntIndexed { table, idx } => ctx.emit(Instruction::SETI {
+            src,
+            table: table.0,
+            idx,
+        }),
         Lvalue::Field {
             table,
             key_idx,
@@ -2537,6 +2684,8 @@ fn compile_nested<'gc>(
 ) -> Result<Gc<'gc, Prototype<'gc>>, CompileError> {
     let lua_ctx = ctx.ctx;
     let interner = ctx.interner;
+    let lines = ctx.lines;
+    let source = ctx.chunk.source;
     // The child inherits the declarations in scope at its definition site,
     // but mutates its own copy — its `global` decls don't leak back to the
     // parent or to sibling functions (`manual.of:245-249`).
@@ -2546,13 +2695,14 @@ fn compile_nested<'gc>(
     let chunk = compile_function_to_chunk(
         lua_ctx,
         interner,
+        lines,
         Some(parent),
         stmts.into_iter(),
         params.into_iter(),
         is_vararg,
         vararg_name,
         arity,
-        None,
+        source,
         Vec::new(),
         globals,
     )?;
@@ -2574,7 +2724,10 @@ fn compile_expr(
     item: Expr,
     dst: Option<RegisterIndex>,
 ) -> Result<ExprDesc, CompileError> {
-    match item {
+    // Instructions are attributed to the innermost expression that emits
+    // them; the enclosing line resumes once this one is done.
+    let line = ctx.set_line(item.offset());
+    let desc = match item {
         Expr::PrefixOp(item) => compile_expr_prefix_op(ctx, item, dst),
         Expr::BinaryOp(item) => compile_expr_binary_op(ctx, item, dst),
         Expr::Method(item) => {
@@ -2606,7 +2759,9 @@ fn compile_expr(
         // discharge — discharging here would run before const-folding and
         // short-circuit/jump lowering and defeat them for every `(expr)`.
         Expr::Paren(inner) => compile_expr(ctx, *inner, dst),
-    }
+    };
+    ctx.line = line;
+    desc
 }
 
 fn compile_expr_to_reg(
@@ -2924,7 +3079,18 @@ fn compile_expr_table(ctx: &mut Ctx, item: Table) -> Result<RegisterIndex, Compi
                 let val_expr = r#gen
                     .value()
                     .ok_or_else(|| ice("table generic without value"))?;
-                let key = compile_expr_to_reg(ctx, key_expr, None)?;
+                let mut key = compile_expr(ctx, key_expr, None)?;
+                if let Some(idx) = integer_key(&key) {
+                    let val = compile_expr_to_reg(ctx, val_expr, None)?;
+                    ctx.emit(Instruction::SETI {
+                        src: val.0,
+                        table: dst.0,
+                        idx,
+                    });
+                    ctx.free_reg(val);
+                    continue;
+                }
+                let key = ctx.discharge_to_reg_mut(&mut key, None)?;
                 let val = compile_expr_to_reg(ctx, val_expr, None)?;
 
                 ctx.emit(Instruction::SETTABLE {
@@ -3177,15 +3343,20 @@ fn compile_expr_binary_op(
     if let (ExprKind::Numeral(l), ExprKind::Numeral(r)) = (lhs_desc.kind, rhs_desc.kind)
         && !lhs_desc.has_jumps()
         && !rhs_desc.has_jumps()
-        && let Some(folded) = try_fold_binop(op, l, r)
+        && let Some(folded) = try_fold_binop(ctx.ctx, op, l, r)
     {
         return Ok(ExprDesc::from_numeral(folded));
     }
 
+    if let Some(reg) = try_emit_arith_constant(ctx, op, &mut lhs_desc, &mut rhs_desc, dst)? {
+        return Ok(ExprDesc::from_reg(reg));
+    }
+
     // Postfix: materialise both operands, then emit. When the LHS is still a
     // lazy numeral and the RHS carries short-circuit jumps, discharge the RHS
-    // first so the LHS's `LOAD` lands *after* the RHS's jump span (tcvm has no
-    // immediate arithmetic operands, so the constant must occupy a register).
+    // first so the LHS's `LOAD` lands *after* the RHS's jump span (besides
+    // `imm << R`, no instruction encodes a constant LHS, so it must occupy a
+    // register).
     let (lhs, rhs) = if !materialise_lhs_early && rhs_desc.has_jumps() {
         let rhs = ctx.discharge_to_reg_mut(&mut rhs_desc, None)?;
         let lhs = ctx.discharge_to_reg_mut(&mut lhs_desc, None)?;
@@ -3362,6 +3533,101 @@ fn compile_expr_binary_op(
     Ok(ExprDesc::from_reg(reg))
 }
 
+/// Emit the constant or immediate form of an arithmetic / bitwise op
+/// (Lua's `codearith` K and I variants) when one operand is a numeral the
+/// instruction can hold, so the constant never occupies a register.
+/// Returns `None`, with both operands untouched, when no such form applies.
+fn try_emit_arith_constant(
+    ctx: &mut Ctx,
+    op: BinaryOperator,
+    lhs_desc: &mut ExprDesc,
+    rhs_desc: &mut ExprDesc,
+    dst: Option<RegisterIndex>,
+) -> Result<Option<RegisterIndex>, CompileError> {
+    let numeral = |desc: &ExprDesc| match desc.kind {
+        ExprKind::Numeral(n) if !desc.has_jumps() => Some(n),
+        _ => None,
+    };
+    let immediate = |n: Option<Numeral>| match n {
+        Some(Numeral::Int(n)) => i16::try_from(n).ok(),
+        _ => None,
+    };
+
+    // `imm << R` is the only form with its constant on the left.
+    if op == BinaryOperator::LShift
+        && numeral(rhs_desc).is_none()
+        && let Some(imm) = immediate(numeral(lhs_desc))
+    {
+        let rhs = ctx.discharge_to_reg_mut(rhs_desc, None)?;
+        ctx.free_reg(rhs);
+        let dst = ctx.dst_or_alloc(dst)?;
+        ctx.emit(Instruction::SHLI {
+            dst: dst.0,
+            rhs: rhs.0,
+            imm,
+        });
+        return Ok(Some(dst));
+    }
+
+    let Some(k) = numeral(rhs_desc) else {
+        return Ok(None);
+    };
+    let imm = immediate(Some(k));
+    let applies = match op {
+        BinaryOperator::Add
+        | BinaryOperator::Sub
+        | BinaryOperator::Mul
+        | BinaryOperator::Mod
+        | BinaryOperator::Exp
+        | BinaryOperator::Div
+        | BinaryOperator::IntDiv
+        | BinaryOperator::BitAnd
+        | BinaryOperator::BitOr
+        | BinaryOperator::BitXor => true,
+        BinaryOperator::RShift => imm.is_some(),
+        _ => false,
+    };
+    if !applies {
+        return Ok(None);
+    }
+
+    let lhs = ctx.discharge_to_reg_mut(lhs_desc, None)?;
+    ctx.free_reg(lhs);
+    let dst = ctx.dst_or_alloc(dst)?;
+    let (dst_idx, lhs) = (dst.0, lhs.0);
+    let instr = match (op, imm) {
+        (BinaryOperator::Add, Some(imm)) => Instruction::ADDI {
+            dst: dst_idx,
+            lhs,
+            imm,
+        },
+        (BinaryOperator::RShift, Some(imm)) => Instruction::SHRI {
+            dst: dst_idx,
+            lhs,
+            imm,
+        },
+        _ => {
+            let key = ctx.alloc_constant(numeral_to_value(ctx.ctx, k))?;
+            let dst = dst_idx;
+            match op {
+                BinaryOperator::Add => Instruction::ADDK { dst, lhs, key },
+                BinaryOperator::Sub => Instruction::SUBK { dst, lhs, key },
+                BinaryOperator::Mul => Instruction::MULK { dst, lhs, key },
+                BinaryOperator::Mod => Instruction::MODK { dst, lhs, key },
+                BinaryOperator::Exp => Instruction::POWK { dst, lhs, key },
+                BinaryOperator::Div => Instruction::DIVK { dst, lhs, key },
+                BinaryOperator::IntDiv => Instruction::IDIVK { dst, lhs, key },
+                BinaryOperator::BitAnd => Instruction::BANDK { dst, lhs, key },
+                BinaryOperator::BitOr => Instruction::BORK { dst, lhs, key },
+                BinaryOperator::BitXor => Instruction::BXORK { dst, lhs, key },
+                _ => return Err(ice("no constant form for arithmetic op")),
+            }
+        }
+    };
+    ctx.emit(instr);
+    Ok(Some(dst))
+}
+
 fn emit_arith(
     ctx: &mut Ctx,
     lhs: RegisterIndex,
@@ -3393,6 +3659,83 @@ fn emit_arith(
     Ok(dst)
 }
 
+/// An operand of a comparison, kept out of the register file while it may
+/// still be encoded in the instruction itself.
+enum CmpOperand {
+    Reg(RegisterIndex),
+    /// A numeral, boolean or nil not yet discharged.
+    Desc(ExprDesc),
+    /// A string literal's constant slot; `compile_expr` would load it eagerly.
+    Str(u16),
+}
+
+impl CmpOperand {
+    fn compile(ctx: &mut Ctx, expr: Expr) -> Result<Self, CompileError> {
+        if let Some(idx) = string_literal_constant(ctx, &expr)? {
+            return Ok(Self::Str(idx));
+        }
+        Ok(Self::Desc(compile_expr(ctx, expr, None)?))
+    }
+
+    fn is_constant(&self) -> bool {
+        match self {
+            Self::Reg(_) => false,
+            Self::Desc(desc) => {
+                !matches!(desc.kind, ExprKind::Reg(_) | ExprKind::Jump(_)) && !desc.has_jumps()
+            }
+            Self::Str(_) => true,
+        }
+    }
+
+    /// The operand as an `imm` field, if it's an integer that fits one.
+    fn immediate(&self) -> Option<i16> {
+        match self {
+            Self::Desc(desc) if !desc.has_jumps() => match desc.kind {
+                ExprKind::Numeral(Numeral::Int(n)) => i16::try_from(n).ok(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The constant slot of an operand for which `is_constant` holds.
+    fn constant(&self, ctx: &mut Ctx) -> Result<u16, CompileError> {
+        match self {
+            Self::Str(idx) => Ok(*idx),
+            Self::Desc(desc) => {
+                let value = const_kind_to_value(ctx.ctx, desc.kind)
+                    .ok_or_else(|| ice("non-constant comparison operand"))?;
+                ctx.alloc_constant(value)
+            }
+            Self::Reg(_) => Err(ice("non-constant comparison operand")),
+        }
+    }
+
+    fn into_reg(self, ctx: &mut Ctx) -> Result<RegisterIndex, CompileError> {
+        match self {
+            Self::Reg(reg) => Ok(reg),
+            Self::Desc(mut desc) => ctx.discharge_to_reg_mut(&mut desc, None),
+            Self::Str(idx) => {
+                let dst = ctx.alloc_register()?;
+                ctx.emit(Instruction::LOAD { dst: dst.0, idx });
+                Ok(dst)
+            }
+        }
+    }
+}
+
+/// The constant slot of a string literal, looking through parentheses.
+fn string_literal_constant(ctx: &mut Ctx, expr: &Expr) -> Result<Option<u16>, CompileError> {
+    match expr {
+        Expr::Paren(inner) => string_literal_constant(ctx, inner),
+        Expr::Literal(literal) => match literal.value(ctx.interner) {
+            Some(LiteralValue::String(bytes)) => Ok(Some(ctx.alloc_string_constant(&bytes)?)),
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
 /// Compile a comparison (`==`, `~=`, `<`, `>`, `<=`, `>=`) as a jump-list
 /// expression: emit just `CMP` + an unfilled `JMP` and return a `Jump`-kind
 /// `ExprDesc` whose `pending` head holds the JMP (fires on truthy).
@@ -3407,8 +3750,13 @@ fn compile_comparison_desc(
 ) -> Result<ExprDesc, CompileError> {
     let lhs_expr = item.lhs().ok_or_else(|| ice("cmp without lhs"))?;
     let rhs_expr = item.rhs().ok_or_else(|| ice("cmp without rhs"))?;
-    let lhs = compile_expr_to_reg(ctx, lhs_expr, None)?;
-    let rhs = compile_expr_to_reg(ctx, rhs_expr, None)?;
+    // Infix: a constant LHS stays lazy so it can be encoded in the
+    // instruction; anything else is settled before the RHS is compiled.
+    let mut lhs = CmpOperand::compile(ctx, lhs_expr)?;
+    if !lhs.is_constant() {
+        lhs = CmpOperand::Reg(lhs.into_reg(ctx)?);
+    }
+    let rhs = CmpOperand::compile(ctx, rhs_expr)?;
 
     // Lua 5.5 convention: emit the CMP so the paired JMP fires on the
     // TRUTHY outcome of the comparison. The VM's `op_lt` / `op_le` /
@@ -3419,44 +3767,101 @@ fn compile_comparison_desc(
     // `LFALSESKIP`-first, so fall-through naturally produces the falsy
     // boolean). NEq / opposite-comparisons carry `inverted=false` to
     // cancel the surface-level negation.
-    let instr = match op {
-        BinaryOperator::Eq => Instruction::EQ {
-            lhs: lhs.0,
-            rhs: rhs.0,
-            inverted: true,
-        },
-        BinaryOperator::NEq => Instruction::EQ {
-            lhs: lhs.0,
-            rhs: rhs.0,
-            inverted: false,
-        },
-        BinaryOperator::Lt => Instruction::LT {
-            lhs: lhs.0,
-            rhs: rhs.0,
-            inverted: true,
-        },
-        BinaryOperator::Gt => Instruction::LT {
-            lhs: rhs.0,
-            rhs: lhs.0,
-            inverted: true,
-        },
-        BinaryOperator::LEq => Instruction::LE {
-            lhs: lhs.0,
-            rhs: rhs.0,
-            inverted: true,
-        },
-        BinaryOperator::GEq => Instruction::LE {
-            lhs: rhs.0,
-            rhs: lhs.0,
-            inverted: true,
-        },
-        _ => return Err(ice("compile_comparison_desc called with non-comparison op")),
+    let inverted = op != BinaryOperator::NEq;
+
+    // A constant operand is encoded in the instruction (Lua's `EQK` / `EQI`
+    // and `LTI` family). Equality is symmetric, and an order comparison
+    // with its immediate on the left mirrors into the opposite one.
+    let (instr, reg, other) = match op {
+        BinaryOperator::Eq | BinaryOperator::NEq if rhs.is_constant() || lhs.is_constant() => {
+            let (reg, k) = if rhs.is_constant() {
+                (lhs.into_reg(ctx)?, rhs)
+            } else {
+                (rhs.into_reg(ctx)?, lhs)
+            };
+            let instr = match k.immediate() {
+                Some(imm) => Instruction::EQI {
+                    lhs: reg.0,
+                    imm,
+                    inverted,
+                },
+                None => Instruction::EQK {
+                    lhs: reg.0,
+                    key: k.constant(ctx)?,
+                    inverted,
+                },
+            };
+            (instr, reg, None)
+        }
+        _ if rhs.immediate().is_some() || lhs.immediate().is_some() => {
+            let (reg, imm, op) = match rhs.immediate() {
+                Some(imm) => (lhs.into_reg(ctx)?, imm, op),
+                None => {
+                    let imm = lhs.immediate().ok_or_else(|| ice("missing immediate"))?;
+                    let mirrored = match op {
+                        BinaryOperator::Lt => BinaryOperator::Gt,
+                        BinaryOperator::Gt => BinaryOperator::Lt,
+                        BinaryOperator::LEq => BinaryOperator::GEq,
+                        _ => BinaryOperator::LEq,
+                    };
+                    (rhs.into_reg(ctx)?, imm, mirrored)
+                }
+            };
+            let lhs = reg.0;
+            let instr = match op {
+                BinaryOperator::Lt => Instruction::LTI { lhs, imm, inverted },
+                BinaryOperator::Gt => Instruction::GTI { lhs, imm, inverted },
+                BinaryOperator::LEq => Instruction::LEI { lhs, imm, inverted },
+                BinaryOperator::GEq => Instruction::GEI { lhs, imm, inverted },
+                _ => return Err(ice("compile_comparison_desc called with non-comparison op")),
+            };
+            (instr, reg, None)
+        }
+        _ => {
+            // Constant operands the instruction can't hold take a register;
+            // a still-lazy LHS loads after the RHS so it lands past any of
+            // the RHS's short-circuit jumps.
+            let rhs = rhs.into_reg(ctx)?;
+            let lhs = lhs.into_reg(ctx)?;
+            let instr = match op {
+                BinaryOperator::Eq | BinaryOperator::NEq => Instruction::EQ {
+                    lhs: lhs.0,
+                    rhs: rhs.0,
+                    inverted,
+                },
+                BinaryOperator::Lt => Instruction::LT {
+                    lhs: lhs.0,
+                    rhs: rhs.0,
+                    inverted,
+                },
+                BinaryOperator::Gt => Instruction::LT {
+                    lhs: rhs.0,
+                    rhs: lhs.0,
+                    inverted,
+                },
+                BinaryOperator::LEq => Instruction::LE {
+                    lhs: lhs.0,
+                    rhs: rhs.0,
+                    inverted,
+                },
+                BinaryOperator::GEq => Instruction::LE {
+                    lhs: rhs.0,
+                    rhs: lhs.0,
+                    inverted,
+                },
+                _ => return Err(ice("compile_comparison_desc called with non-comparison op")),
+            };
+            (instr, lhs, Some(rhs))
+        }
     };
     ctx.emit(instr);
     let jmp = ctx.emit_unfilled_jmp();
     // CMP + JMP have captured the operands; reclaim the temps so the
     // enclosing jump-list discharge can use their slots.
-    ctx.free_regs(lhs, rhs);
+    match other {
+        Some(other) => ctx.free_regs(reg, other),
+        None => ctx.free_reg(reg),
+    }
 
     Ok(ExprDesc {
         // Pending head — fires on truthy of this expression. `goiftrue` /
@@ -3703,12 +4108,13 @@ fn emit_method_call_setup(
 
     let object = compile_expr_to_reg(ctx, object_expr, None)?;
 
-    let key_idx = ctx.alloc_string_constant(method_name.as_bytes())?;
+    let (key_idx, ic_idx) = ctx.alloc_field_key(method_name.as_bytes())?;
 
     let func = ctx.alloc_register()?;
     ctx.emit(Instruction::SELF {
         dst: func.0,
         object: object.0,
+        ic_idx,
         key_idx,
     });
 
@@ -3874,7 +4280,18 @@ fn compile_expr_index(
     }
 
     let table = compile_expr_to_reg(ctx, target_expr, None)?;
-    let key = compile_expr_to_reg(ctx, key_expr, None)?;
+    let mut key = compile_expr(ctx, key_expr, None)?;
+    if let Some(idx) = integer_key(&key) {
+        ctx.free_reg(table);
+        let dst = ctx.dst_or_alloc(dst)?;
+        ctx.emit(Instruction::GETI {
+            dst: dst.0,
+            table: table.0,
+            idx,
+        });
+        return Ok(dst);
+    }
+    let key = ctx.discharge_to_reg_mut(&mut key, None)?;
     // Both operands have been captured into the upcoming GETTABLE; free
     // their temps (higher first) so `dst` can reuse the slot.
     ctx.free_regs(table, key);
@@ -3887,6 +4304,15 @@ fn compile_expr_index(
     Ok(dst)
 }
 
+/// The `idx` field of a `GETI` / `SETI` for a key that's an integer numeral
+/// fitting one.
+fn integer_key(key: &ExprDesc) -> Option<u16> {
+    match key.kind {
+        ExprKind::Numeral(Numeral::Int(n)) if !key.has_jumps() => u16::try_from(n).ok(),
+        _ => None,
+    }
+}
+
 /// The register of the named-vararg local if `expr` names it, else `None`
 /// (the caller then falls back to a normal table access).
 fn resolve_vararg_base(ctx: &Ctx, expr: &Expr) -> Option<u8> {
@@ -3907,10 +4333,13 @@ fn resolve_vararg_base(ctx: &Ctx, expr: &Expr) -> Option<u8> {
 // ---------------------------------------------------------------------------
 
 fn compile_break(ctx: &mut Ctx, _item: Break) -> Result<(), CompileError> {
-    let label = *ctx
+    let (label, depth) = *ctx
         .control_end_label
         .last()
         .ok_or_else(|| ice("break outside of loop"))?;
+    if let Some(first) = ctx.close_bottom(depth) {
+        ctx.emit(Instruction::CLOSE { start: first.0 });
+    }
     ctx.emit_jump(label);
     Ok(())
 }
@@ -3927,7 +4356,10 @@ fn compile_return(ctx: &mut Ctx, item: Return) -> Result<(), CompileError> {
     // paren as `Expr::Paren`, which doesn't match the FuncCall/Method
     // arms here, so it falls through to `compile_return_generic` and
     // correctly returns a single value (`manual.of:1573`).
-    if exprs.len() == 1 {
+    //
+    // Inside the scope of a to-be-closed variable a call can't be a tail
+    // call: the frame has to outlive it to close the variable afterwards.
+    if exprs.len() == 1 && ctx.close_bottom(0).is_none() {
         let only = exprs.pop().unwrap();
         match only {
             Expr::FuncCall(call) => return compile_tail_func_call(ctx, call),
@@ -3958,13 +4390,11 @@ fn compile_return_generic(ctx: &mut Ctx, mut exprs: Vec<Expr>) -> Result<(), Com
     // materialisation needs a concrete dst.
     if exprs.len() == 1 {
         let only = exprs.pop().unwrap();
-        // `return ...` propagates every vararg to the caller via MULTRET.
-        if matches!(&only, Expr::VarArg) {
-            let dst = ctx.alloc_register()?;
-            ctx.emit(Instruction::VARARG {
-                dst: dst.0,
-                count: 0,
-            });
+        // `return ...` propagates every vararg to the caller via MULTRET, as
+        // does a call that couldn't be a tail call.
+        if matches!(&only, Expr::VarArg | Expr::FuncCall(_) | Expr::Method(_)) {
+            let dst = RegisterIndex(ctx.chunk.freereg);
+            compile_trailing_multires(ctx, only, dst)?;
             ctx.emit(Instruction::RETURN {
                 values: dst.0,
                 count: 0,
@@ -4072,19 +4502,23 @@ fn compile_while(ctx: &mut Ctx, item: While) -> Result<(), CompileError> {
         let cond_expr = item.cond().ok_or_else(|| ice("while without condition"))?;
         let break_list = compile_branch_cond_false(ctx, cond_expr)?;
 
-        // Compile body
-        if let Some(block) = item.block() {
-            let stmts: Vec<_> = block.stmts().map(|s| s.collect()).unwrap_or_default();
-            for stmt in stmts {
-                compile_stmt(ctx, stmt)?;
+        // Compile body in its own scope, so its to-be-closed variables are
+        // closed on every iteration.
+        scope_lexical(ctx, |ctx| {
+            if let Some(block) = item.block() {
+                let stmts: Vec<_> = block.stmts().map(|s| s.collect()).unwrap_or_default();
+                for stmt in stmts {
+                    compile_stmt(ctx, stmt)?;
+                }
             }
-        }
+            Ok(())
+        })?;
 
         // Jump back to condition check
         ctx.emit_jump(loop_start);
 
         // Patch all "condition false" jumps to the post-loop break target.
-        let break_label = *ctx
+        let (break_label, _) = *ctx
             .control_end_label
             .last()
             .ok_or_else(|| ice("missing break label"))?;
@@ -4121,7 +4555,24 @@ fn compile_repeat(ctx: &mut Ctx, item: Repeat) -> Result<(), CompileError> {
             // the loop.
             let cond_expr = item.cond().ok_or_else(|| ice("repeat without condition"))?;
             let false_list = compile_branch_cond_false(ctx, cond_expr)?;
-            ctx.patch_to(false_list, loop_start_off);
+            // The body's to-be-closed variables are closed before looping
+            // back as well as on exit (by this scope's own CLOSE).
+            let close = ctx
+                .scope_close
+                .last()
+                .and_then(|regs| regs.first().copied());
+            if let Some(first) = close {
+                let exit = ctx.new_label();
+                ctx.emit_jump(exit);
+                ctx.patch_to_here(false_list);
+                ctx.emit(Instruction::CLOSE { start: first.0 });
+                let back = ctx.new_label();
+                ctx.set_label(back, loop_start_off);
+                ctx.emit_jump(back);
+                ctx.set_label(exit, ctx.next_offset());
+            } else {
+                ctx.patch_to(false_list, loop_start_off);
+            }
 
             Ok(())
         })
@@ -4177,6 +4628,7 @@ fn compile_if_chain(ctx: &mut Ctx, item: If, end_label: u16) -> Result<(), Compi
 }
 
 fn compile_for_num(ctx: &mut Ctx, item: ForNum) -> Result<(), CompileError> {
+    let for_line = ctx.line;
     scope_lexical_break(ctx, |ctx| {
         let (counter_ident, init_expr) = item
             .counter()
@@ -4224,7 +4676,7 @@ fn compile_for_num(ctx: &mut Ctx, item: ForNum) -> Result<(), CompileError> {
             }
         } else {
             // Default step = 1
-            let one_idx = ctx.alloc_constant(Value::integer(1))?;
+            let one_idx = ctx.alloc_constant(Value::small_integer(1))?;
             ctx.emit(Instruction::LOAD {
                 dst: step_reg.0,
                 idx: one_idx,
@@ -4273,15 +4725,21 @@ fn compile_for_num(ctx: &mut Ctx, item: ForNum) -> Result<(), CompileError> {
 
         ctx.set_label(loop_body, ctx.next_offset());
 
-        // Body
-        if let Some(block) = item.block() {
-            let stmts: Vec<_> = block.stmts().map(|s| s.collect()).unwrap_or_default();
-            for stmt in stmts {
-                compile_stmt(ctx, stmt)?;
+        // Body, scoped per iteration like `while`'s.
+        scope_lexical(ctx, |ctx| {
+            if let Some(block) = item.block() {
+                let stmts: Vec<_> = block.stmts().map(|s| s.collect()).unwrap_or_default();
+                for stmt in stmts {
+                    compile_stmt(ctx, stmt)?;
+                }
             }
-        }
+            Ok(())
+        })?;
 
-        // FORLOOP: increment and jump back if still in range
+        // FORLOOP: increment and jump back if still in range. Like the
+        // reference compiler, stamp it with the `for` line rather than the
+        // body's last.
+        ctx.line = for_line;
         ctx.emit_jump_instr(
             loop_body,
             Instruction::FORLOOP {
@@ -4296,6 +4754,7 @@ fn compile_for_num(ctx: &mut Ctx, item: ForNum) -> Result<(), CompileError> {
 }
 
 fn compile_for_gen(ctx: &mut Ctx, item: ForGen) -> Result<(), CompileError> {
+    let for_line = ctx.line;
     scope_lexical_break(ctx, |ctx| {
         let values: Vec<_> = item
             .values()
@@ -4410,15 +4869,19 @@ fn compile_for_gen(ctx: &mut Ctx, item: ForGen) -> Result<(), CompileError> {
 
         ctx.set_label(loop_body, ctx.next_offset());
 
-        // Body
-        if let Some(block) = item.block() {
-            let stmts: Vec<_> = block.stmts().map(|s| s.collect()).unwrap_or_default();
-            for stmt in stmts {
-                compile_stmt(ctx, stmt)?;
+        // Body, scoped per iteration like `while`'s.
+        scope_lexical(ctx, |ctx| {
+            if let Some(block) = item.block() {
+                let stmts: Vec<_> = block.stmts().map(|s| s.collect()).unwrap_or_default();
+                for stmt in stmts {
+                    compile_stmt(ctx, stmt)?;
+                }
             }
-        }
+            Ok(())
+        })?;
 
         ctx.set_label(loop_test, ctx.next_offset());
+        ctx.line = for_line;
 
         // TFORCALL: call iterator, results go to base+3..base+2+count
         ctx.emit(Instruction::TFORCALL {
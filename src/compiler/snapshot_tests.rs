@@ -11,30 +11,32 @@ use crate::parser::{self, syntax::Root};
 
 fn compile_and_format(source: &str) -> String {
     let mut cache = NodeCache::new();
-    let (syntax_tree, reports) = parser::parse(&mut cache, source);
+    let (syntax_tree, lines, reports) = parser::parse(&mut cache, source);
     assert!(reports.is_empty(), "parse errors: {}", reports.len());
     let root = Root::new(syntax_tree).expect("not a root node");
     let interner = cache.interner();
 
     let mut lua = Lua::new();
     lua.enter(|ctx| {
-        let proto = compile_chunk(ctx, &root, interner).unwrap();
+        let proto = compile_chunk(ctx, &root, interner, &lines, None).unwrap();
         format_prototype(&proto)
     })
 }
 
 fn compile_err_and_format(source: &str) -> String {
     let mut cache = NodeCache::new();
-    let (syntax_tree, reports) = parser::parse(&mut cache, source);
+    let (syntax_tree, lines, reports) = parser::parse(&mut cache, source);
     assert!(reports.is_empty(), "parse errors: {}", reports.len());
     let root = Root::new(syntax_tree).expect("not a root node");
     let interner = cache.interner();
 
     let mut lua = Lua::new();
-    lua.enter(|ctx| match compile_chunk(ctx, &root, interner) {
-        Err(e) => format!("{e}"),
-        Ok(_) => panic!("expected compile error, got success"),
-    })
+    lua.enter(
+        |ctx| match compile_chunk(ctx, &root, interner, &lines, None) {
+            Err(e) => format!("{e}"),
+            Ok(_) => panic!("expected compile error, got success"),
+        },
+    )
 }
 
 macro_rules! test {
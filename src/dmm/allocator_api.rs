@@ -1,11 +1,87 @@
 use core::{alloc::Layout, marker::PhantomData, ptr::NonNull};
 use std::alloc::{AllocError, Allocator, Global};
+use std::rc::Rc;
 
-use crate::dmm::{collect::Collect, context::Mutation, metrics::Metrics, types::Invariant};
+use crate::dmm::{
+    collect::Collect,
+    context::Mutation,
+    metrics::{AllocationKind, Metrics},
+    types::Invariant,
+};
 
+/// The allocator an arena places its objects in, as given to [`crate::Arena::new_in`].
+///
+/// All `Gc` allocations go through it, as does every [`MetricsAlloc`] made with
+/// [`MetricsAlloc::new`] or [`MetricsAlloc::from_metrics`]. Cloning the handle shares the
+/// allocator.
 #[derive(Clone)]
-pub struct MetricsAlloc<'gc, A = Global> {
+pub struct ArenaAllocator(Rc<dyn Allocator>);
+
+impl ArenaAllocator {
+    #[inline]
+    pub fn new<A: Allocator + 'static>(allocator: A) -> Self {
+        ArenaAllocator(Rc::new(allocator))
+    }
+}
+
+impl Default for ArenaAllocator {
+    #[inline]
+    fn default() -> Self {
+        Self::new(Global)
+    }
+}
+
+unsafe impl Allocator for ArenaAllocator {
+    #[inline]
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.allocate(layout)
+    }
+
+    #[inline]
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        unsafe { self.0.deallocate(ptr, layout) }
+    }
+
+    #[inline]
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.0.allocate_zeroed(layout)
+    }
+
+    #[inline]
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        unsafe { self.0.grow(ptr, old_layout, new_layout) }
+    }
+
+    #[inline]
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        unsafe { self.0.grow_zeroed(ptr, old_layout, new_layout) }
+    }
+
+    #[inline]
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        unsafe { self.0.shrink(ptr, old_layout, new_layout) }
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsAlloc<'gc, A = ArenaAllocator> {
     metrics: Metrics,
+    kind: AllocationKind,
     allocator: A,
     _marker: Invariant<'gc>,
 }
@@ -13,7 +89,7 @@ pub struct MetricsAlloc<'gc, A = Global> {
 impl<'gc> MetricsAlloc<'gc> {
     #[inline]
     pub fn new(mc: &Mutation<'gc>) -> Self {
-        Self::new_in(mc, Global)
+        Self::new_in(mc, mc.metrics().allocator().clone())
     }
 
     /// `MetricsAlloc` is normally branded with the `'gc` branding lifetime to ensure that it is not
@@ -26,7 +102,8 @@ impl<'gc> MetricsAlloc<'gc> {
     /// NOTE: Use `MetricsAlloc::new` if at all possible, because it is harder to misuse.
     #[inline]
     pub fn from_metrics(metrics: Metrics) -> Self {
-        Self::from_metrics_in(metrics, Global)
+        let allocator = metrics.allocator().clone();
+        Self::from_metrics_in(metrics, allocator)
     }
 }
 
@@ -35,6 +112,7 @@ impl<'gc, A> MetricsAlloc<'gc, A> {
     pub fn new_in(mc: &Mutation<'gc>, allocator: A) -> Self {
         Self {
             metrics: mc.metrics().clone(),
+            kind: AllocationKind::OTHER,
             allocator,
             _marker: PhantomData,
         }
@@ -44,24 +122,35 @@ impl<'gc, A> MetricsAlloc<'gc, A> {
     pub fn from_metrics_in(metrics: Metrics, allocator: A) -> Self {
         Self {
             metrics,
+            kind: AllocationKind::OTHER,
             allocator,
             _marker: PhantomData,
         }
     }
+
+    /// Account the bytes allocated through this allocator to `kind` rather than
+    /// [`AllocationKind::OTHER`].
+    #[inline]
+    pub fn with_kind(mut self, kind: AllocationKind) -> Self {
+        self.kind = kind;
+        self
+    }
 }
 
 unsafe impl<'gc, A: Allocator> Allocator for MetricsAlloc<'gc, A> {
     #[inline]
     fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
         let ptr = self.allocator.allocate(layout)?;
-        self.metrics.mark_external_allocation(layout.size());
+        self.metrics
+            .mark_external_allocation_of(self.kind, layout.size());
         Ok(ptr)
     }
 
     #[inline]
     unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
         unsafe {
-            self.metrics.mark_external_deallocation(layout.size());
+            self.metrics
+                .mark_external_deallocation_of(self.kind, layout.size());
             self.allocator.deallocate(ptr, layout);
         }
     }
@@ -69,7 +158,8 @@ unsafe impl<'gc, A: Allocator> Allocator for MetricsAlloc<'gc, A> {
     #[inline]
     fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
         let ptr = self.allocator.allocate_zeroed(layout)?;
-        self.metrics.mark_external_allocation(layout.size());
+        self.metrics
+            .mark_external_allocation_of(self.kind, layout.size());
         Ok(ptr)
     }
 
@@ -83,7 +173,7 @@ unsafe impl<'gc, A: Allocator> Allocator for MetricsAlloc<'gc, A> {
         unsafe {
             let ptr = self.allocator.grow(ptr, old_layout, new_layout)?;
             self.metrics
-                .mark_external_allocation(new_layout.size() - old_layout.size());
+                .mark_external_allocation_of(self.kind, new_layout.size() - old_layout.size());
             Ok(ptr)
         }
     }
@@ -98,7 +188,7 @@ unsafe impl<'gc, A: Allocator> Allocator for MetricsAlloc<'gc, A> {
         unsafe {
             let ptr = self.allocator.grow_zeroed(ptr, old_layout, new_layout)?;
             self.metrics
-                .mark_external_allocation(new_layout.size() - old_layout.size());
+                .mark_external_allocation_of(self.kind, new_layout.size() - old_layout.size());
             Ok(ptr)
         }
     }
@@ -113,7 +203,7 @@ unsafe impl<'gc, A: Allocator> Allocator for MetricsAlloc<'gc, A> {
         unsafe {
             let ptr = self.allocator.shrink(ptr, old_layout, new_layout)?;
             self.metrics
-                .mark_external_deallocation(old_layout.size() - new_layout.size());
+                .mark_external_deallocation_of(self.kind, old_layout.size() - new_layout.size());
             Ok(ptr)
         }
     }
@@ -126,3 +216,7 @@ unsafe impl<'gc, A: 'static> Collect<'gc> for MetricsAlloc<'gc, A> {
 unsafe impl<'gc> Collect<'gc> for Global {
     const NEEDS_TRACE: bool = false;
 }
+
+unsafe impl<'gc> Collect<'gc> for ArenaAllocator {
+    const NEEDS_TRACE: bool = false;
+}
@@ -1,9 +1,13 @@
 use core::marker::PhantomData;
 use std::boxed::Box;
 
+#[cfg(doc)]
+use crate::dmm::metrics::CollectionMode;
 use crate::dmm::{
     Collect,
+    allocator_api::ArenaAllocator,
     context::{Context, Finalization, Mutation, Phase, RunUntil, Stop},
+    heap::HeapGraph,
     metrics::Metrics,
 };
 
@@ -136,11 +140,20 @@ where
     /// Create a new arena with the given garbage collector tuning parameters. You must provide a
     /// closure that accepts a `&Mutation<'gc>` and returns the appropriate root.
     pub fn new<F>(f: F) -> Arena<R>
+    where
+        F: for<'gc> FnOnce(&'gc Mutation<'gc>) -> Root<'gc, R>,
+    {
+        Self::new_in(ArenaAllocator::default(), f)
+    }
+
+    /// Like `new`, but places every `Gc` object, and the memory of every [`crate::MetricsAlloc`]
+    /// created from the arena, in `allocator`.
+    pub fn new_in<F>(allocator: ArenaAllocator, f: F) -> Arena<R>
     where
         F: for<'gc> FnOnce(&'gc Mutation<'gc>) -> Root<'gc, R>,
     {
         unsafe {
-            let context = Box::new(Context::new());
+            let context = Box::new(Context::new(allocator));
             // Note - we cast the `&Mutation` to a `'static` lifetime here,
             // instead of transmuting the root type returned by `f`. Transmuting the root
             // type is allowed in nightly versions of rust
@@ -160,7 +173,7 @@ where
         F: for<'gc> FnOnce(&'gc Mutation<'gc>) -> Result<Root<'gc, R>, E>,
     {
         unsafe {
-            let context = Box::new(Context::new());
+            let context = Box::new(Context::new(ArenaAllocator::default()));
             let mc: &'static Mutation<'_> = &*(context.mutation_context() as *const _);
             let root: Root<'static, R> = f(mc)?;
             Ok(Arena { context, root })
@@ -248,6 +261,13 @@ where
         self.context.metrics()
     }
 
+    /// In [`CollectionMode::Generational`] mode, make the next collection cycle a major one,
+    /// which collects the old generation too. In incremental mode every cycle already is.
+    #[inline]
+    pub fn schedule_major(&mut self) {
+        self.context.schedule_major();
+    }
+
     #[inline]
     pub fn collection_phase(&self) -> CollectionPhase {
         match self.context.phase() {
@@ -270,6 +290,18 @@ where
     R: for<'a> Rootable<'a>,
     for<'a> Root<'a, R>: Collect<'a>,
 {
+    /// Every live object in the arena and the pointers between them, or `None` during
+    /// [`CollectionPhase::Sweeping`], when unreachable objects may point to freed ones.
+    ///
+    /// Objects the collector has not found unreachable yet are included, so objects not
+    /// reachable from [`HeapGraph::roots`] are garbage.
+    pub fn heap_graph(&self) -> Option<HeapGraph> {
+        if self.context.phase() == Phase::Sweep {
+            return None;
+        }
+        Some(self.context.heap_graph(&self.root))
+    }
+
     /// Run incremental garbage collection until the allocation debt is zero.
     ///
     /// This will run through ALL phases of the collection cycle until the debt is zero, including
@@ -381,7 +413,7 @@ where
     /// callback, any mutation that resurrects a pointer or triggers a write barrier can immediately
     /// invalidate this.
     #[inline]
-    pub fn finalize<F, T>(self, f: F) -> T
+    pub fn finalize<F, T>(&self, f: F) -> T
     where
         F: for<'gc> FnOnce(&'gc Finalization<'gc>, &'gc Root<'gc, R>) -> T,
     {
@@ -418,7 +450,7 @@ where
     F: for<'gc> FnOnce(&'gc Mutation<'gc>) -> R,
 {
     unsafe {
-        let context = Context::new();
+        let context = Context::new(ArenaAllocator::default());
         f(context.mutation_context())
     }
 }
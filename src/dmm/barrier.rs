@@ -1,4 +1,10 @@
 //! Write barrier management.
+//!
+//! Every way of mutating the contents of a [`Gc`] pointer goes through a write barrier. While the
+//! collector is marking, the barrier keeps black objects from pointing at white ones. In
+//! [generational](crate::dmm::metrics::CollectionMode::Generational) mode it also fires between
+//! cycles: a black object there is an old one that minor cycles won't trace, so when it adopts a
+//! young object it is queued to be traced again by the next cycle.
 
 use core::borrow::Borrow;
 use core::mem;
@@ -1,6 +1,6 @@
 pub use tcvm_derive::Collect;
 
-use crate::dmm::{Gc, GcWeak};
+use crate::dmm::{Gc, GcWeak, metrics::AllocationKind};
 
 /// A trait for garbage collected objects that can be placed into `Gc` pointers. This trait is
 /// unsafe, because `Gc` pointers inside an Arena are assumed never to be dangling, and in order to
@@ -26,6 +26,10 @@ pub unsafe trait Collect<'gc> {
     /// `Collect::trace` must be called.
     const NEEDS_TRACE: bool = true;
 
+    /// Which [`AllocationKind`] `Gc` allocations of this type are accounted to in the arena's
+    /// [`crate::Metrics`].
+    const KIND: AllocationKind = AllocationKind::OTHER;
+
     /// *Must* call [`Trace::trace_gc`] (resp. [`Trace::trace_gc_weak`]) on all directly owned
     /// [`Gc`] (resp. [`GcWeak`]) pointers. If this type holds inner types that implement `Collect`,
     /// a valid implementation would simply call [`Trace::trace`] on all the held values to ensure
@@ -4,12 +4,17 @@ use core::{
     ops::{ControlFlow, Deref, DerefMut},
     ptr::NonNull,
 };
-use std::{boxed::Box, vec::Vec};
+use std::{
+    alloc::{Allocator, Layout, handle_alloc_error},
+    vec::Vec,
+};
 
 use crate::dmm::{
     Gc, GcWeak,
+    allocator_api::ArenaAllocator,
     collect::{Collect, Trace},
-    metrics::Metrics,
+    heap::{EdgeTracer, HeapGraph, HeapObject},
+    metrics::{CollectionMode, Metrics},
     types::{GcBox, GcBoxHeader, GcBoxInner, GcColor, Invariant},
 };
 
@@ -193,6 +198,40 @@ pub(crate) struct Context {
     // A queue of gray objects that became gray as a result
     // of a write barrier.
     gray_again: Queue<GcBox>,
+
+    // Set when the last sweep left the objects it kept black, promoting them to the old
+    // generation. Outside of `Phase::Mark`, a black object is then an old one, and write barriers
+    // stay active so old objects adopting young ones are traced again by the next cycle.
+    promote: bool,
+
+    // Whether the current cycle is a minor one, which leaves the old generation alone.
+    minor: bool,
+
+    // Forces the next cycle to be a major one. Set once the heap has outgrown the last major
+    // cycle by `CollectionMode::Generational::major_factor`, or on request.
+    major_due: bool,
+
+    // The total size of the live heap after the last major cycle.
+    major_baseline: usize,
+
+    // The first old object in `all`. Allocations are pushed to the front of the list, so every
+    // object in front of it is young.
+    old_head: Option<GcBox>,
+
+    // Where the current sweep stops: at `old_head` in a minor cycle, at the end of `all` otherwise.
+    sweep_end: Option<GcBox>,
+
+    // The first object the current sweep kept, and `old_head` once a promoting sweep finishes.
+    sweep_first_kept: Option<GcBox>,
+
+    // Young objects adopted through a `GcWeak` by a parent that may be old, outside of marking.
+    // They are traced weakly at the start of the next minor cycle, keeping their box alive for
+    // the parent even if it is not traced again.
+    weak_again: Queue<GcBox>,
+
+    // The next object a major cycle has to return to white before it can start marking. Demotion
+    // walks the old generation a step at a time, like the sweep, instead of pausing for all of it.
+    demote: Option<GcBox>,
 }
 
 impl Drop for Context {
@@ -207,14 +246,15 @@ impl Drop for Context {
                         let header = gc_box.header();
                         drop_resume.1 = header.next();
                         let gc_size = header.size_of_box();
+                        let gc_kind = header.kind();
                         // SAFETY: the context owns its GC'd objects
                         unsafe {
                             if header.is_live() {
                                 gc_box.drop_in_place();
                                 self.0.mark_gc_dropped(gc_size);
                             }
-                            gc_box.dealloc();
-                            self.0.mark_gc_freed(gc_size);
+                            gc_box.dealloc(self.0.allocator());
+                            self.0.mark_gc_freed(gc_kind, gc_size);
                         }
                     }
                 }
@@ -227,8 +267,8 @@ impl Drop for Context {
 }
 
 impl Context {
-    pub(crate) unsafe fn new() -> Context {
-        let metrics = Metrics::new();
+    pub(crate) unsafe fn new(allocator: ArenaAllocator) -> Context {
+        let metrics = Metrics::new(allocator);
         Context {
             phase: Phase::Sleep,
             metrics: metrics.clone(),
@@ -238,6 +278,15 @@ impl Context {
             root_needs_trace: true,
             gray: Queue::new(),
             gray_again: Queue::new(),
+            promote: false,
+            minor: false,
+            major_due: false,
+            major_baseline: 0,
+            old_head: None,
+            sweep_end: None,
+            sweep_first_kept: None,
+            weak_again: Queue::new(),
+            demote: None,
         }
     }
 
@@ -268,9 +317,54 @@ impl Context {
         self.phase
     }
 
+    /// Make the next cycle a major one, even if the heap has not grown enough to call for it.
+    #[inline]
+    pub(crate) fn schedule_major(&mut self) {
+        self.major_due = true;
+    }
+
+    /// Walk every live object. Only sound outside of `Phase::Sweep`: nothing is freed until the
+    /// sweep, so until then even unreachable objects only point to allocated ones.
+    pub(crate) fn heap_graph<'gc, R: Collect<'gc> + ?Sized>(&self, root: &R) -> HeapGraph {
+        debug_assert_ne!(self.phase, Phase::Sweep);
+        let mut tracer = EdgeTracer::default();
+        root.trace(&mut tracer);
+        let (roots, weak_roots) = tracer.into_ids();
+
+        let mut objects = Vec::new();
+        let mut next = self.all.get();
+        while let Some(gc_box) = next {
+            let header = gc_box.header();
+            next = header.next();
+            if !header.is_live() {
+                continue;
+            }
+            let mut tracer = EdgeTracer::default();
+            // SAFETY: the value is live, so it has not been dropped.
+            unsafe { gc_box.trace_edges(&mut tracer) };
+            let (edges, weak_edges) = tracer.into_ids();
+            objects.push(HeapObject {
+                id: gc_box.addr(),
+                type_name: header.type_name(),
+                kind: header.kind(),
+                size: header.size_of_box(),
+                edges,
+                weak_edges,
+            });
+        }
+        HeapGraph {
+            roots,
+            weak_roots,
+            objects,
+        }
+    }
+
     #[inline]
     pub(crate) fn gray_remaining(&self) -> bool {
-        !self.gray.is_empty() || !self.gray_again.is_empty() || self.root_needs_trace
+        self.demote.is_some()
+            || !self.gray.is_empty()
+            || !self.gray_again.is_empty()
+            || self.root_needs_trace
     }
 
     // Do some collection work until either we have achieved our `target` (paying off debt or
@@ -300,6 +394,7 @@ impl Context {
             match cx.phase {
                 Phase::Sleep => {
                     has_slept = true;
+                    cx.start_cycle();
                     // Immediately enter the mark phase
                     cx.switch(Phase::Mark);
                 }
@@ -315,6 +410,10 @@ impl Context {
                             // allocations during the newly-entered `Phase:Sweep` will update `all`,
                             // but will *not* be reachable from `this.sweep`.
                             cx.sweep = cx.all.get();
+                            cx.sweep_end = if cx.minor { cx.old_head } else { None };
+                            // A minor cycle must promote what it keeps even if the mode changed
+                            // since it started: the old generation it skipped is still black.
+                            cx.promote = cx.minor || cx.generational().is_some();
                         }
                     }
                 }
@@ -327,6 +426,7 @@ impl Context {
                         // We reset our debt if we have done an entire collection cycle (marking and
                         // sweeping) as a single atomic unit. This keeps inherited debt from growing
                         // without bound.
+                        cx.finish_sweep();
                         cx.metrics.finish_cycle(has_slept);
                         cx.root_needs_trace = true;
                         cx.switch(Phase::Sleep);
@@ -368,10 +468,13 @@ impl Context {
         // Make the generated code easier to optimize into `T` being constructed in place or at the
         // very least only memcpy'd once.
         // For more information, see: https://github.com/kyren/gc-arena/pull/14
+        let layout = Layout::new::<GcBoxInner<T>>();
         let (gc_box, ptr) = unsafe {
-            let mut uninitialized = Box::new(mem::MaybeUninit::<GcBoxInner<T>>::uninit());
-            core::ptr::write(uninitialized.as_mut_ptr(), GcBoxInner::new(header, t));
-            let ptr = NonNull::new_unchecked(Box::into_raw(uninitialized) as *mut GcBoxInner<T>);
+            let ptr = match self.metrics.allocator().allocate(layout) {
+                Ok(ptr) => ptr.cast::<GcBoxInner<T>>(),
+                Err(_) => handle_alloc_error(layout),
+            };
+            core::ptr::write(ptr.as_ptr(), GcBoxInner::new(header, t));
             (GcBox::erase(ptr), ptr)
         };
 
@@ -380,7 +483,7 @@ impl Context {
             self.sweep_prev.set(self.all.get());
         }
 
-        self.metrics.mark_gc_allocated(alloc_size);
+        self.metrics.mark_gc_allocated(T::KIND, alloc_size);
 
         ptr
     }
@@ -391,10 +494,13 @@ impl Context {
         // it and invalidate the invariant that black objects may not point to white objects. Turn
         // the black parent object gray to prevent this.
         //
+        // Once there is an old generation, an old (black) object adopting a young (white) one must
+        // be traced again by the next cycle, so the same applies outside of marking.
+        //
         // NOTE: This also adds the pointer to the gray_again queue even if `header.needs_trace()`
         // is false, but this is not harmful (just wasteful). There's no reason to call a barrier on
         // a pointer that can't adopt other pointers, so we skip the check.
-        if self.phase == Phase::Mark
+        if self.barriers_active()
             && parent.header().color() == GcColor::Black
             && child
                 .map(|c| matches!(c.header().color(), GcColor::White | GcColor::WhiteWeak))
@@ -412,7 +518,7 @@ impl Context {
 
     #[inline]
     fn backward_barrier_weak(&self, parent: GcBox, child: GcBox) {
-        if self.phase == Phase::Mark
+        if self.barriers_active()
             && parent.header().color() == GcColor::Black
             && child.header().color() == GcColor::White
         {
@@ -431,7 +537,9 @@ impl Context {
         // During the marking phase, if we are mutating a black object, we may add a white object
         // to it and invalidate the invariant that black objects may not point to white objects.
         // Immediately trace the child white object to turn it gray (or black) to prevent this.
-        if self.phase == Phase::Mark
+        // Outside of marking, a young child adopted by a possibly old parent is remembered for the
+        // next cycle instead.
+        if self.barriers_active()
             && parent
                 .map(|p| p.header().color() == GcColor::Black)
                 .unwrap_or(true)
@@ -440,7 +548,11 @@ impl Context {
             // often) to promote the inlining of the write barrier.
             #[cold]
             fn barrier(this: &Context, child: GcBox) {
-                this.trace(child);
+                if this.phase == Phase::Mark {
+                    this.trace(child);
+                } else {
+                    this.remember(child);
+                }
             }
             barrier(&self, child);
         }
@@ -451,7 +563,9 @@ impl Context {
         // During the marking phase, if we are mutating a black object, we may add a white object
         // to it and invalidate the invariant that black objects may not point to white objects.
         // Immediately trace the child white object to turn it gray (or black) to prevent this.
-        if self.phase == Phase::Mark
+        // Outside of marking, the child is traced weakly once the next minor cycle starts, as an
+        // old parent would not trace it again.
+        if self.barriers_active()
             && parent
                 .map(|p| p.header().color() == GcColor::Black)
                 .unwrap_or(true)
@@ -460,7 +574,11 @@ impl Context {
             // often) to promote the inlining of the write barrier.
             #[cold]
             fn barrier(this: &Context, child: GcBox) {
-                this.trace_weak(child);
+                if this.phase == Phase::Mark {
+                    this.trace_weak(child);
+                } else if child.header().color() == GcColor::White {
+                    this.weak_again.push(child);
+                }
             }
             barrier(&self, child);
         }
@@ -566,6 +684,12 @@ impl Context {
     }
 
     fn mark_one<'gc, R: Collect<'gc> + ?Sized>(&mut self, root: &R) -> ControlFlow<()> {
+        // A major cycle only starts tracing once the old generation is white again.
+        if self.demote.is_some() {
+            self.demote_one();
+            return ControlFlow::Continue(());
+        }
+
         // We look for an object first in the normal gray queue, then the "gray again" queue.
         // Processing "gray again" objects later gives them more time to be mutated again without
         // triggering another write barrier.
@@ -625,13 +749,15 @@ impl Context {
     }
 
     fn sweep_one(&mut self) -> ControlFlow<()> {
-        let Some(mut sweep) = self.sweep else {
+        // A minor sweep stops at the old generation, which holds nothing it could free.
+        let Some(mut sweep) = self.sweep.filter(|&s| Some(s) != self.sweep_end) else {
             self.sweep_prev.set(None);
             return ControlFlow::Break(());
         };
 
         let sweep_header = sweep.header();
         let sweep_size = sweep_header.size_of_box();
+        let sweep_kind = sweep_header.kind();
 
         let next_box = sweep_header.next();
         self.sweep = next_box;
@@ -659,8 +785,8 @@ impl Context {
                         sweep.drop_in_place();
                         self.metrics.mark_gc_dropped(sweep_size);
                     }
-                    sweep.dealloc();
-                    self.metrics.mark_gc_freed(sweep_size);
+                    sweep.dealloc(self.metrics.allocator());
+                    self.metrics.mark_gc_freed(sweep_kind, sweep_size);
                 }
             }
             // Keep the `GcBox` as part of the linked list if we traced a weak pointer to it. The
@@ -668,7 +794,7 @@ impl Context {
             // is still alive. We can only deallocate the `GcBox`, once there are no weak pointers
             // left.
             GcColor::WhiteWeak => {
-                self.sweep_prev.set(Some(sweep));
+                self.keep(sweep);
                 sweep_header.set_color(GcColor::White);
                 if sweep_header.is_live() {
                     sweep_header.set_live(false);
@@ -681,23 +807,121 @@ impl Context {
                 self.metrics.mark_gc_remembered(sweep_size);
             }
             // If the next object in the sweep portion of the main list is black, we
-            // need to keep it but turn it back white.
+            // need to keep it but turn it back white, unless we are promoting it to
+            // the old generation.
             GcColor::Black => {
-                self.sweep_prev.set(Some(sweep));
-                sweep_header.set_color(GcColor::White);
+                self.keep(sweep);
+                if !self.promote {
+                    sweep_header.set_color(GcColor::White);
+                }
                 self.metrics.mark_gc_remembered(sweep_size);
             }
-            // No gray objects should be in this part of the main list, they should
-            // be added to the beginning of the list before the sweep pointer, so it
-            // should not be possible for us to encounter them here.
+            // Only a promoting sweep can encounter a gray object in this part of the
+            // main list: a black object it has yet to reach, grayed by a write barrier
+            // since marking finished. It stays gray, queued for the next cycle.
             GcColor::Gray => {
-                debug_assert!(false, "unexpected gray object in sweep list")
+                debug_assert!(self.promote, "unexpected gray object in sweep list");
+                self.keep(sweep);
+                self.metrics.mark_gc_remembered(sweep_size);
             }
         }
 
         ControlFlow::Continue(())
     }
 
+    // Leave `gc_box` in the main list, as the predecessor of whatever the sweep reaches next.
+    fn keep(&mut self, gc_box: GcBox) {
+        self.sweep_prev.set(Some(gc_box));
+        if self.sweep_first_kept.is_none() {
+            self.sweep_first_kept = Some(gc_box);
+        }
+    }
+
+    // Write barriers matter while marking, and at all times once there is an old generation. They
+    // are off while a major cycle demotes the old generation: nothing is traced yet, and the
+    // objects still black are about to turn white.
+    #[inline(always)]
+    fn barriers_active(&self) -> bool {
+        (self.phase == Phase::Mark && self.demote.is_none()) || self.promote
+    }
+
+    #[inline]
+    fn generational(&self) -> Option<f64> {
+        match self.metrics.pacing().mode {
+            CollectionMode::Generational { major_factor } => Some(major_factor),
+            CollectionMode::Incremental => None,
+        }
+    }
+
+    // Outside of marking, keep a young object adopted by a possibly old parent: it is queued to be
+    // traced by the next cycle, which promotes it along with everything it points to.
+    fn remember(&self, gc_box: GcBox) {
+        let header = gc_box.header();
+        if header.color() == GcColor::White {
+            header.set_color(GcColor::Gray);
+            self.gray_again.push(gc_box);
+        }
+    }
+
+    // Decide whether the cycle about to start is a minor or a major one. A minor cycle needs an
+    // old generation to skip, so the first generational cycle is always a major one.
+    fn start_cycle(&mut self) {
+        self.minor = self.promote && self.generational().is_some() && !self.major_due;
+        if self.minor {
+            while let Some(gc_box) = self.weak_again.pop() {
+                self.trace_weak(gc_box);
+            }
+        } else {
+            if self.promote {
+                self.start_demotion();
+            }
+            self.major_due = false;
+        }
+    }
+
+    // Return every object to white ahead of a major cycle, which must trace the whole heap. Anything
+    // a write barrier queued since is found again from the root if it is still reachable. The walk
+    // itself happens in `demote_one`, as the first steps of marking.
+    fn start_demotion(&mut self) {
+        self.gray.clear();
+        self.gray_again.clear();
+        self.weak_again.clear();
+        self.promote = false;
+        self.old_head = None;
+        self.demote = self.all.get();
+    }
+
+    fn demote_one(&mut self) {
+        if let Some(gc_box) = self.demote {
+            let header = gc_box.header();
+            header.set_color(GcColor::White);
+            self.metrics.mark_gc_demoted(header.size_of_box());
+            self.demote = header.next();
+        }
+    }
+
+    // Once a promoting sweep is done, everything it kept is old, and the next cycle is a major one
+    // if the heap has outgrown the last major cycle.
+    fn finish_sweep(&mut self) {
+        let first_kept = self.sweep_first_kept.take();
+        if !self.promote {
+            self.old_head = None;
+            return;
+        }
+        self.old_head = if self.minor {
+            first_kept.or(self.old_head)
+        } else {
+            first_kept
+        };
+        let live = self.metrics.total_gc_allocation();
+        if !self.minor {
+            self.major_baseline = live;
+        }
+        if let Some(major_factor) = self.generational() {
+            self.major_due |= live as f64 > self.major_baseline as f64 * (1.0 + major_factor);
+        }
+    }
+
     // Take a black pointer and turn it gray and put it in the `gray_again` queue.
     fn make_gray_again(&self, gc_box: GcBox) {
         let header = gc_box.header();
@@ -774,4 +998,8 @@ impl<T> Queue<T> {
     fn pop(&self) -> Option<T> {
         unsafe { (*self.vec.get()).pop() }
     }
+
+    fn clear(&self) {
+        unsafe { (*self.vec.get()).clear() }
+    }
 }
@@ -6,6 +6,7 @@ use std::{
 
 use crate::dmm::{
     Gc, Mutation, Rootable,
+    allocator_api::MetricsAlloc,
     arena::Root,
     collect::{Collect, Trace},
     metrics::Metrics,
@@ -221,18 +222,10 @@ unsafe impl<'gc> Collect<'gc> for Slot<'gc> {
 }
 
 struct Slots<'gc> {
-    metrics: Metrics,
-    slots: Vec<Slot<'gc>>,
+    slots: Vec<Slot<'gc>, MetricsAlloc<'static>>,
     next_free: Index,
 }
 
-impl<'gc> Drop for Slots<'gc> {
-    fn drop(&mut self) {
-        self.metrics
-            .mark_external_deallocation(self.slots.capacity() * mem::size_of::<Slot>());
-    }
-}
-
 unsafe impl<'gc> Collect<'gc> for Slots<'gc> {
     fn trace<T: Trace<'gc>>(&self, cc: &mut T) {
         cc.trace(&self.slots);
@@ -242,8 +235,7 @@ unsafe impl<'gc> Collect<'gc> for Slots<'gc> {
 impl<'gc> Slots<'gc> {
     fn new(metrics: Metrics) -> Self {
         Self {
-            metrics,
-            slots: Vec::new(),
+            slots: Vec::new_in(MetricsAlloc::from_metrics(metrics)),
             next_free: NULL_INDEX,
         }
     }
@@ -268,21 +260,10 @@ impl<'gc> Slots<'gc> {
             idx
         } else {
             let idx = self.slots.len();
-
-            let old_capacity = self.slots.capacity();
             self.slots.push(Slot::Occupied {
                 root: p,
                 ref_count: 0,
             });
-            let new_capacity = self.slots.capacity();
-
-            debug_assert!(new_capacity >= old_capacity);
-            if new_capacity > old_capacity {
-                self.metrics.mark_external_allocation(
-                    (new_capacity - old_capacity) * mem::size_of::<Slot>(),
-                );
-            }
-
             idx
         }
     }
@@ -0,0 +1,71 @@
+//! Read-only views of an arena's object graph, for heap profiling.
+
+use crate::dmm::{Gc, GcWeak, collect::Trace, metrics::AllocationKind, types::GcBox};
+
+/// Every live object in an arena, with the pointers between them, as returned by
+/// [`crate::Arena::heap_graph`].
+///
+/// Objects are identified by the address of their allocation, which stays the same for as long as
+/// the object lives.
+#[derive(Debug, Clone, Default)]
+pub struct HeapGraph {
+    /// Objects the arena root points to directly.
+    pub roots: Vec<usize>,
+    /// Objects the arena root points to through a `GcWeak`.
+    pub weak_roots: Vec<usize>,
+    pub objects: Vec<HeapObject>,
+}
+
+/// One allocation in a [`HeapGraph`].
+#[derive(Debug, Clone)]
+pub struct HeapObject {
+    pub id: usize,
+    /// The Rust type stored in the allocation, as given by [`core::any::type_name`] (so without
+    /// lifetimes).
+    pub type_name: &'static str,
+    /// The kind the allocation is accounted to, from [`crate::Collect::KIND`].
+    pub kind: AllocationKind,
+    /// The size of the allocation itself, not counting any memory the object owns outside of it.
+    pub size: usize,
+    /// Objects this one holds a `Gc` pointer to, in tracing order.
+    pub edges: Vec<usize>,
+    /// Objects this one holds a `GcWeak` pointer to, in tracing order.
+    pub weak_edges: Vec<usize>,
+}
+
+/// A [`Trace`] implementation that records the pointers it is given rather than marking them.
+#[derive(Default)]
+pub(crate) struct EdgeTracer {
+    pub(crate) edges: Vec<GcBox>,
+    pub(crate) weak_edges: Vec<GcBox>,
+}
+
+impl<'gc> Trace<'gc> for EdgeTracer {
+    fn trace_gc(&mut self, gc: Gc<'gc, ()>) {
+        self.edges.push(unsafe { GcBox::erase(gc.ptr) });
+    }
+
+    fn trace_gc_weak(&mut self, gc: GcWeak<'gc, ()>) {
+        self.weak_edges.push(unsafe { GcBox::erase(gc.inner.ptr) });
+    }
+}
+
+impl EdgeTracer {
+    /// Object ids of the recorded edges, leaving out weak pointers to objects that have already
+    /// been dropped.
+    pub(crate) fn into_ids(self) -> (Vec<usize>, Vec<usize>) {
+        let edges = self.edges.iter().map(GcBox::addr).collect();
+        let weak_edges = self
+            .weak_edges
+            .iter()
+            .filter(|b| b.header().is_live())
+            .map(GcBox::addr)
+            .collect();
+        (edges, weak_edges)
+    }
+}
+
+/// The id the allocation behind `gc` has in a [`HeapGraph`].
+pub fn object_id<T: ?Sized>(gc: Gc<'_, T>) -> usize {
+    unsafe { GcBox::erase(gc.ptr) }.addr()
+}
@@ -11,6 +11,7 @@ use crate::dmm::{
     barrier::Unlock,
     checked_cell::{BorrowError, BorrowMutError, CheckedCell, Ref, RefMut},
     collect::{Collect, Trace},
+    metrics::AllocationKind,
 };
 
 // Helper macro to factor out the common parts of locks types.
@@ -162,6 +163,7 @@ impl<'gc, T: Copy + 'gc> Gc<'gc, Lock<T>> {
 
 unsafe impl<'gc, T: Collect<'gc> + Copy + 'gc> Collect<'gc> for Lock<T> {
     const NEEDS_TRACE: bool = T::NEEDS_TRACE;
+    const KIND: AllocationKind = T::KIND;
 
     #[inline]
     fn trace<C: Trace<'gc>>(&self, cc: &mut C) {
@@ -286,6 +288,7 @@ impl<'gc, T: ?Sized + 'gc> Gc<'gc, RefLock<T>> {
 
 unsafe impl<'gc, T: Collect<'gc> + 'gc + ?Sized> Collect<'gc> for RefLock<T> {
     const NEEDS_TRACE: bool = T::NEEDS_TRACE;
+    const KIND: AllocationKind = T::KIND;
 
     #[inline]
     fn trace<C: Trace<'gc>>(&self, cc: &mut C) {
@@ -339,6 +342,7 @@ impl<T> OnceLock<T> {
 
 unsafe impl<'gc, T: Collect<'gc>> Collect<'gc> for OnceLock<T> {
     const NEEDS_TRACE: bool = T::NEEDS_TRACE;
+    const KIND: AllocationKind = T::KIND;
 
     #[inline]
     fn trace<C: Trace<'gc>>(&self, cc: &mut C) {
@@ -1,6 +1,8 @@
 use core::cell::Cell;
 use std::rc::Rc;
 
+use crate::dmm::allocator_api::ArenaAllocator;
+
 /// Tuning parameters for a given garbage collected [`crate::Arena`].
 ///
 /// Any allocation that occurs during a collection cycle will incur "debt" that is exactly equal to
@@ -101,13 +103,24 @@ use std::rc::Rc;
 /// `mark_factor + keep_factor` work will be recorded. This is not important to remember though, it
 /// is true that when the collector elides work it may not actually record that work as performed,
 /// but this will only *speed up* collection, it can never cause the collector to stall.
+///
+/// # Generational collection
+///
+/// With [`CollectionMode::Generational`], the work factors apply unchanged, but a minor cycle only
+/// marks, traces and sweeps the young objects, so it records (and needs) far less work than a full
+/// one.
 #[derive(Debug, Copy, Clone)]
 pub struct Pacing {
+    /// Whether every cycle collects the whole heap, or most cycles only collect the objects
+    /// allocated since the previous one.
+    pub mode: CollectionMode,
+
     /// Controls the length of the [`crate::arena::CollectionPhase::Sleeping`] phase.
     ///
     /// At the start of a new GC cycle, the collector will wait until the live size reaches
     /// `<current heap size> + <previous remembered size> * sleep_factor` before starting
-    /// collection.
+    /// collection. In generational mode, the "remembered size" is the size of the whole live heap,
+    /// old generation included, as a minor cycle only remembers the young objects it kept.
     ///
     /// External memory is ***not*** included in the "remembered size" for the purposes of
     /// calculating a new cycle's sleep period.
@@ -129,7 +142,8 @@ pub struct Pacing {
     pub trace_factor: f64,
 
     /// The multiplicative factor for "work" performed per byte when a reachable `Gc` value is
-    /// iterated over during [`crate::arena::CollectionPhase::Sweeping`].
+    /// iterated over during [`crate::arena::CollectionPhase::Sweeping`], or when an old one is
+    /// returned to the young generation ahead of a major cycle.
     pub keep_factor: f64,
 
     /// The multiplicative factor for "work" performed per byte when a `Gc` value that is forgotten
@@ -143,6 +157,7 @@ pub struct Pacing {
 
 impl Pacing {
     pub const DEFAULT: Pacing = Pacing {
+        mode: CollectionMode::Incremental,
         sleep_factor: 0.5,
         min_sleep: 4096,
         mark_factor: 0.1,
@@ -160,6 +175,7 @@ impl Pacing {
     /// It is important to set the sleep factor fairly high when configuring a collector this way
     /// (close to or even somewhat larger than 1.0).
     pub const STOP_THE_WORLD: Pacing = Pacing {
+        mode: CollectionMode::Incremental,
         sleep_factor: 1.0,
         min_sleep: 4096,
         mark_factor: 0.0,
@@ -170,6 +186,20 @@ impl Pacing {
     };
 }
 
+/// How collection cycles treat the objects that survived earlier ones.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CollectionMode {
+    /// Every cycle marks and sweeps the whole heap.
+    Incremental,
+    /// Objects that survive a cycle are promoted to the old generation, which the *minor* cycles
+    /// that follow neither trace nor sweep: they only collect the young objects allocated since
+    /// the previous cycle, finding the ones old objects adopted in the meantime through write
+    /// barriers. Old garbage is only reclaimed by a *major* cycle, which collects the whole heap
+    /// and runs once the live heap has grown by `major_factor` times its size after the previous
+    /// major cycle.
+    Generational { major_factor: f64 },
+}
+
 impl Default for Pacing {
     #[inline]
     fn default() -> Pacing {
@@ -177,13 +207,68 @@ impl Default for Pacing {
     }
 }
 
-#[derive(Debug, Default)]
+/// The number of distinct [`AllocationKind`]s [`Metrics`] keeps separate statistics for.
+pub const ALLOCATION_KINDS: usize = 16;
+
+/// A category of allocations [`Metrics`] keeps separate statistics for.
+///
+/// What the kinds mean is up to the user of the arena: `Gc` allocations take theirs from
+/// [`crate::Collect::KIND`], and external allocations from the [`crate::MetricsAlloc`] that made
+/// them. Anything not given a kind is counted as [`AllocationKind::OTHER`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct AllocationKind(u8);
+
+impl AllocationKind {
+    pub const OTHER: AllocationKind = AllocationKind(0);
+
+    /// # Panics
+    ///
+    /// If `index` is not below [`ALLOCATION_KINDS`].
+    #[inline]
+    pub const fn new(index: usize) -> AllocationKind {
+        assert!(index < ALLOCATION_KINDS);
+        AllocationKind(index as u8)
+    }
+
+    #[inline]
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Live allocations of one [`AllocationKind`], as returned by [`Metrics::allocation_stats`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    /// The number of live `Gc` objects.
+    pub gc_count: usize,
+    /// The bytes allocated for those objects.
+    pub gc_bytes: usize,
+    /// The bytes marked as externally allocated.
+    pub external_bytes: usize,
+}
+
+impl AllocationStats {
+    /// `gc_bytes + external_bytes`.
+    #[inline]
+    pub fn total_bytes(&self) -> usize {
+        self.gc_bytes.saturating_add(self.external_bytes)
+    }
+}
+
+#[derive(Default)]
 struct MetricsInner {
+    allocator: ArenaAllocator,
+
     pacing: Cell<Pacing>,
 
     total_gcs: Cell<usize>,
     total_gc_bytes: Cell<usize>,
     total_external_bytes: Cell<usize>,
+    total_cycles: Cell<usize>,
+
+    memory_limit: Cell<Option<usize>>,
+
+    kinds: [Cell<AllocationStats>; ALLOCATION_KINDS],
 
     wakeup_amount: Cell<f64>,
     artificial_debt: Cell<f64>,
@@ -208,14 +293,26 @@ struct MetricsInner {
     // Statistics for reachable `Gc` pointers as they are iterated through during the sweep phase.
     remembered_gcs: Cell<usize>,
     remembered_gc_bytes: Cell<usize>,
+
+    // Old objects returned to the young generation ahead of a major cycle.
+    demoted_gc_bytes: Cell<usize>,
 }
 
 #[derive(Clone)]
 pub struct Metrics(Rc<MetricsInner>);
 
 impl Metrics {
-    pub(crate) fn new() -> Self {
-        Self(Default::default())
+    pub(crate) fn new(allocator: ArenaAllocator) -> Self {
+        Self(Rc::new(MetricsInner {
+            allocator,
+            ..Default::default()
+        }))
+    }
+
+    /// The allocator the arena's objects live in.
+    #[inline]
+    pub fn allocator(&self) -> &ArenaAllocator {
+        &self.0.allocator
     }
 
     /// Sets the pacing parameters used by the collection algorithm.
@@ -227,6 +324,12 @@ impl Metrics {
         self.0.pacing.set(pacing);
     }
 
+    /// Returns the pacing parameters currently in use.
+    #[inline]
+    pub fn pacing(&self) -> Pacing {
+        self.0.pacing.get()
+    }
+
     /// Returns the current number of `Gc`s allocated that have not yet been freed.
     #[inline]
     pub fn total_gc_count(&self) -> usize {
@@ -248,6 +351,13 @@ impl Metrics {
         self.0.total_external_bytes.get()
     }
 
+    /// Returns the number of collection cycles that have run to completion (through the end of
+    /// [`crate::arena::CollectionPhase::Sweeping`]) over the lifetime of the arena.
+    #[inline]
+    pub fn total_cycles(&self) -> usize {
+        self.0.total_cycles.get()
+    }
+
     /// Returns the sum of `Metrics::total_gc_allocation()` and
     /// `Metrics::total_external_allocation()`.
     #[inline]
@@ -258,15 +368,56 @@ impl Metrics {
             .saturating_add(self.0.total_external_bytes.get())
     }
 
+    /// Sets the number of bytes [`Metrics::total_allocation`] may reach, or removes the limit.
+    ///
+    /// The arena never refuses an allocation on its own: the limit is only a threshold that
+    /// [`Metrics::exceeds_memory_limit`] reports on, and it is up to the owner of the arena to
+    /// check it and fail gracefully.
+    #[inline]
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        self.0.memory_limit.set(limit);
+    }
+
+    /// Returns the limit set by [`Metrics::set_memory_limit`], if any.
+    #[inline]
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.0.memory_limit.get()
+    }
+
+    /// Returns whether allocating `bytes` more would take [`Metrics::total_allocation`] past the
+    /// memory limit. With `bytes == 0`, whether the limit has already been exceeded.
+    #[inline]
+    pub fn exceeds_memory_limit(&self, bytes: usize) -> bool {
+        self.0
+            .memory_limit
+            .get()
+            .is_some_and(|limit| self.total_allocation().saturating_add(bytes) > limit)
+    }
+
+    /// Returns the live allocations of the given kind.
+    #[inline]
+    pub fn allocation_stats(&self, kind: AllocationKind) -> AllocationStats {
+        self.0.kinds[kind.index()].get()
+    }
+
     /// Call to mark that bytes have been externally allocated that are owned by an arena.
     ///
     /// This affects the GC pacing, marking external bytes as allocated will trigger allocation
     /// debt.
     #[inline]
     pub fn mark_external_allocation(&self, bytes: usize) {
+        self.mark_external_allocation_of(AllocationKind::OTHER, bytes);
+    }
+
+    /// [`Metrics::mark_external_allocation`], accounting the bytes to `kind`.
+    #[inline]
+    pub fn mark_external_allocation_of(&self, kind: AllocationKind, bytes: usize) {
         self.0
             .total_external_bytes
             .update(|b| b.saturating_add(bytes));
+        self.update_kind(kind, |s| {
+            s.external_bytes = s.external_bytes.saturating_add(bytes)
+        });
     }
 
     /// Call to mark that bytes which have been marked as allocated with
@@ -280,9 +431,26 @@ impl Metrics {
     /// allocation.
     #[inline]
     pub fn mark_external_deallocation(&self, bytes: usize) {
+        self.mark_external_deallocation_of(AllocationKind::OTHER, bytes);
+    }
+
+    /// [`Metrics::mark_external_deallocation`] for bytes accounted to `kind`.
+    #[inline]
+    pub fn mark_external_deallocation_of(&self, kind: AllocationKind, bytes: usize) {
         self.0
             .total_external_bytes
             .update(|b| b.saturating_sub(bytes));
+        self.update_kind(kind, |s| {
+            s.external_bytes = s.external_bytes.saturating_sub(bytes)
+        });
+    }
+
+    #[inline]
+    fn update_kind(&self, kind: AllocationKind, f: impl FnOnce(&mut AllocationStats)) {
+        let cell = &self.0.kinds[kind.index()];
+        let mut stats = cell.get();
+        f(&mut stats);
+        cell.set(stats);
     }
 
     /// Add artificial debt equivalent to allocating the given number of bytes.
@@ -336,7 +504,8 @@ impl Metrics {
 
         let cycle_credits = self.0.marked_gc_bytes.get() as f64 * pacing.mark_factor
             + self.0.traced_gc_bytes.get() as f64 * pacing.trace_factor
-            + self.0.remembered_gc_bytes.get() as f64 * pacing.keep_factor
+            + (self.0.remembered_gc_bytes.get() + self.0.demoted_gc_bytes.get()) as f64
+                * pacing.keep_factor
             + self.0.dropped_gc_bytes.get() as f64 * pacing.drop_factor
             + self.0.freed_gc_bytes.get() as f64 * pacing.free_factor;
 
@@ -345,7 +514,10 @@ impl Metrics {
 
     pub(crate) fn finish_cycle(&self, reset_debt: bool) {
         let pacing = self.0.pacing.get();
-        let remembered_size = self.0.remembered_gc_bytes.get();
+        let remembered_size = match pacing.mode {
+            CollectionMode::Incremental => self.0.remembered_gc_bytes.get(),
+            CollectionMode::Generational { .. } => self.0.total_gc_bytes.get(),
+        };
         let wakeup_amount =
             (remembered_size as f64 * pacing.sleep_factor).max(pacing.min_sleep as f64);
 
@@ -355,6 +527,7 @@ impl Metrics {
             self.allocation_debt()
         };
 
+        self.0.total_cycles.update(|c| c + 1);
         self.0.wakeup_amount.set(wakeup_amount);
         self.0.artificial_debt.set(artificial_debt);
 
@@ -370,15 +543,20 @@ impl Metrics {
         self.0.traced_gc_bytes.set(0);
         self.0.remembered_gcs.set(0);
         self.0.remembered_gc_bytes.set(0);
+        self.0.demoted_gc_bytes.set(0);
     }
 
     #[inline]
-    pub(crate) fn mark_gc_allocated(&self, bytes: usize) {
+    pub(crate) fn mark_gc_allocated(&self, kind: AllocationKind, bytes: usize) {
         self.0.total_gcs.update(|c| c + 1);
         self.0.total_gc_bytes.update(|b| b + bytes);
         self.0
             .allocated_gc_bytes
             .update(|b| b.saturating_add(bytes));
+        self.update_kind(kind, |s| {
+            s.gc_count += 1;
+            s.gc_bytes += bytes;
+        });
     }
 
     #[inline]
@@ -387,10 +565,14 @@ impl Metrics {
     }
 
     #[inline]
-    pub(crate) fn mark_gc_freed(&self, bytes: usize) {
+    pub(crate) fn mark_gc_freed(&self, kind: AllocationKind, bytes: usize) {
         self.0.total_gcs.update(|c| c - 1);
         self.0.total_gc_bytes.update(|b| b - bytes);
         self.0.freed_gc_bytes.update(|b| b.saturating_add(bytes));
+        self.update_kind(kind, |s| {
+            s.gc_count -= 1;
+            s.gc_bytes -= bytes;
+        });
     }
 
     #[inline]
@@ -405,10 +587,12 @@ impl Metrics {
         self.0.traced_gc_bytes.update(|b| b + bytes);
     }
 
+    // Old objects re-traced because of a write barrier were never traced in the current cycle, so
+    // there may be nothing to undo.
     #[inline]
     pub(crate) fn mark_gc_untraced(&self, bytes: usize) {
-        self.0.traced_gcs.update(|c| c - 1);
-        self.0.traced_gc_bytes.update(|b| b - bytes);
+        self.0.traced_gcs.update(|c| c.saturating_sub(1));
+        self.0.traced_gc_bytes.update(|b| b.saturating_sub(bytes));
     }
 
     #[inline]
@@ -416,4 +600,9 @@ impl Metrics {
         self.0.remembered_gcs.update(|c| c + 1);
         self.0.remembered_gc_bytes.update(|b| b + bytes);
     }
+
+    #[inline]
+    pub(crate) fn mark_gc_demoted(&self, bytes: usize) {
+        self.0.demoted_gc_bytes.update(|b| b + bytes);
+    }
 }
@@ -9,6 +9,7 @@ mod context;
 pub mod dynamic_roots;
 mod gc;
 mod gc_weak;
+pub mod heap;
 pub mod lock;
 pub mod metrics;
 mod no_drop;
@@ -24,7 +25,7 @@ mod hashbrown;
 pub use tcvm_derive::__unelide_lifetimes;
 
 pub use self::{
-    allocator_api::MetricsAlloc,
+    allocator_api::{ArenaAllocator, MetricsAlloc},
     arena::{Arena, Rootable},
     checked_cell::{BorrowError, BorrowMutError, Ref, RefMut},
     collect::{Collect, Trace},
@@ -3,8 +3,12 @@ use core::cell::Cell;
 use core::marker::PhantomData;
 use core::ptr::NonNull;
 use core::{mem, ptr};
+use std::alloc::Allocator;
 
-use crate::dmm::{collect::Collect, context::Context};
+use crate::dmm::{
+    allocator_api::ArenaAllocator, collect::Collect, context::Context, heap::EdgeTracer,
+    metrics::AllocationKind,
+};
 
 /// A thin-pointer-sized box containing a type-erased GC object.
 /// Stores the metadata required by the GC algorithm inline (see `GcBoxInner`
@@ -17,7 +21,7 @@ impl GcBox {
     /// Erases a pointer to a typed GC object.
     ///
     /// **SAFETY:** The pointer must point to a valid `GcBoxInner` allocated
-    /// in a `Box`.
+    /// by the arena's allocator.
     #[inline(always)]
     pub(crate) unsafe fn erase<T: ?Sized>(ptr: NonNull<GcBoxInner<T>>) -> Self {
         // This cast is sound because `GcBoxInner` is `repr(C)`.
@@ -38,6 +42,12 @@ impl GcBox {
         }
     }
 
+    /// The address of the box, which identifies it for as long as it is allocated.
+    #[inline(always)]
+    pub(crate) fn addr(&self) -> usize {
+        self.0.as_ptr().addr()
+    }
+
     #[inline(always)]
     pub(crate) fn header(&self) -> &GcBoxHeader {
         unsafe { &self.0.as_ref().header }
@@ -51,6 +61,14 @@ impl GcBox {
         unsafe { (self.header().vtable().trace_value)(*self, cc) }
     }
 
+    /// Records the pointers held by the stored value, without marking them.
+    ///
+    /// **SAFETY**: `Self::drop_in_place` must not have been called.
+    #[inline(always)]
+    pub(crate) unsafe fn trace_edges(&self, cc: &mut EdgeTracer) {
+        unsafe { (self.header().vtable().trace_edges)(*self, cc) }
+    }
+
     /// Drops the stored value.
     ///
     /// **SAFETY**: once called, no GC pointers should access the stored value
@@ -66,12 +84,11 @@ impl GcBox {
     /// **SAFETY**: once called, this `GcBox` should never be accessed by any GC
     /// pointers again.
     #[inline(always)]
-    pub(crate) unsafe fn dealloc(self) {
+    pub(crate) unsafe fn dealloc(self, allocator: &ArenaAllocator) {
         unsafe {
             let layout = self.header().vtable().box_layout;
-            let ptr = self.0.as_ptr() as *mut u8;
-            // SAFETY: the pointer was `Box`-allocated with this layout.
-            std::alloc::dealloc(ptr, layout);
+            // SAFETY: the box was allocated by the arena's allocator with this layout.
+            allocator.deallocate(self.0.cast(), layout);
         }
     }
 }
@@ -135,6 +152,18 @@ impl GcBoxHeader {
         self.vtable().box_layout.size()
     }
 
+    /// Returns the name of the type stored in this box.
+    #[inline(always)]
+    pub(crate) fn type_name(&self) -> &'static str {
+        (self.vtable().type_name)()
+    }
+
+    /// Returns the [`AllocationKind`] of the type stored in this box.
+    #[inline(always)]
+    pub(crate) fn kind(&self) -> AllocationKind {
+        self.vtable().kind
+    }
+
     #[inline]
     pub(crate) fn color(&self) -> GcColor {
         match tagged_ptr::get::<0x3, _>(self.tagged_vtable.get()) {
@@ -196,6 +225,12 @@ struct CollectVtable {
     drop_value: unsafe fn(GcBox),
     /// Traces the value stored in the given `GcBox`.
     trace_value: unsafe fn(GcBox, &mut Context),
+    /// Records the pointers held by the value stored in the given `GcBox`.
+    trace_edges: unsafe fn(GcBox, &mut EdgeTracer),
+    /// The name of the stored type, for heap profiling.
+    type_name: fn() -> &'static str,
+    /// What the allocation is accounted to in the arena's metrics.
+    kind: AllocationKind,
 }
 
 impl CollectVtable {
@@ -213,6 +248,12 @@ impl CollectVtable {
                 let val = &*(erased.unerased_value::<T>());
                 val.trace(cc)
             },
+            trace_edges: |erased, cc| unsafe {
+                let val = &*(erased.unerased_value::<T>());
+                val.trace(cc)
+            },
+            type_name: core::any::type_name::<T>,
+            kind: T::KIND,
         }
     }
 }
@@ -26,4 +26,17 @@ impl<'gc> Error<'gc> {
         let s = LuaString::new(ctx, msg.as_bytes());
         Error(Value::string(s))
     }
+
+    /// Prefix a string message with `location` (`chunkname:line:`), as
+    /// errors raised at a known position read. Other values are unchanged.
+    pub fn with_location(self, ctx: Context<'gc>, location: &str) -> Self {
+        let Some(msg) = self.0.get_string() else {
+            return self;
+        };
+        let mut located = Vec::with_capacity(location.len() + 1 + msg.as_bytes().len());
+        located.extend_from_slice(location.as_bytes());
+        located.push(b' ');
+        located.extend_from_slice(msg.as_bytes());
+        Error(Value::string(LuaString::new(ctx, &located)))
+    }
 }
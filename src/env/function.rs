@@ -1,23 +1,39 @@
+use std::cell::Cell;
+
 use crate::Context;
-use crate::dmm::{Collect, Gc, Lock, Mutation, RefLock};
+use crate::dmm::{Collect, Gc, Lock, MetricsAlloc, Mutation, RefLock};
 use crate::env::error::Error;
 use crate::env::shape::Shape;
 use crate::env::string::LuaString;
+use crate::env::table::Table;
+use crate::env::thread::ValueStack;
 use crate::env::value::Value;
-use crate::instruction::UpValueDescriptor;
+use crate::instruction::{Instruction, UpValueDescriptor};
+use crate::lua::MemoryKind;
 use crate::vm::sequence::{CallbackAction, Execution};
 
-/// A compiled Lua function. Immutable once created.
+/// A compiled Lua function. Immutable once created, apart from its inline
+/// caches and the instructions the interpreter quickens in place.
 /// Shared by all closures created from the same function definition.
 #[derive(Collect)]
-#[collect(internal, no_drop)]
+#[collect(internal, no_drop, kind = "MemoryKind::Prototype.id()")]
 pub struct Prototype<'gc> {
+    /// The function's bytecode. The interpreter rewrites instructions
+    /// through the `Lock`s to and from their quickened forms; instructions
+    /// hold no `Gc` pointers, so this needs no write barrier. Read an
+    /// instruction as compiled with [`Prototype::instruction`].
+    #[collect(require_static)]
+    pub code: ProtoSlice<'static, Lock<Instruction>>,
+    /// How many times each instruction has fallen back from a quickened
+    /// form, parallel to `code`. Past a small limit the interpreter stops
+    /// quickening it, so a site whose operand types keep changing settles on
+    /// the generic form.
     #[collect(require_static)]
-    pub code: Box<[crate::instruction::Instruction]>,
-    pub constants: Box<[Value<'gc>]>,
-    pub prototypes: Box<[Gc<'gc, Prototype<'gc>>]>,
+    pub despecializations: ProtoSlice<'static, Cell<u8>>,
+    pub constants: ProtoSlice<'gc, Value<'gc>>,
+    pub prototypes: ProtoSlice<'gc, Gc<'gc, Prototype<'gc>>>,
     #[collect(require_static)]
-    pub upvalue_desc: Box<[UpValueDescriptor]>,
+    pub upvalue_desc: ProtoSlice<'static, UpValueDescriptor>,
     pub num_params: u8,
     pub is_vararg: bool,
     /// Lua 5.5 `PF_VATAB`: a named vararg parameter that escaped the optimized
@@ -26,21 +42,270 @@ pub struct Prototype<'gc> {
     pub needs_vararg_table: bool,
     pub max_stack_size: u8,
     pub num_upvalues: u8,
+    /// Chunk name given to `Context::load`, shared by every prototype in
+    /// the chunk. Formatted for messages by [`Prototype::chunk_id`].
     pub source: Option<LuaString<'gc>>,
+    /// Source line of each instruction.
+    #[collect(require_static)]
+    pub line_info: LineInfo,
+    /// Named locals in declaration order, with the pcs they're live over.
+    #[collect(require_static)]
+    pub local_vars: ProtoSlice<'static, LocalVar>,
+    /// Name of each upvalue, parallel to `upvalue_desc`.
+    #[collect(require_static)]
+    pub upvalue_names: ProtoSlice<'static, Name>,
     /// Inline-cache table indexed by `ic_idx` embedded in
-    /// GETTABUP/SETTABUP/GETFIELD/SETFIELD instructions. One entry
+    /// GETTABUP/SETTABUP/GETFIELD/SETFIELD/SELF instructions. One entry
     /// per cache site (call site, not instruction count). The slice
     /// lives inline in the prototype (no separate `Gc` allocation,
     /// no `RefLock`); per-slot `Lock<InlineCache>` exposes
     /// counter-free reads via `get()` and barrier-aware writes via
     /// the parent `Prototype`'s `Gc`. See `src/env/shape/mod.rs` for
     /// the IC payload.
-    pub ic_table: Box<[Lock<InlineCache<'gc>>]>,
+    pub ic_table: ProtoSlice<'gc, Lock<InlineCache<'gc>>>,
+}
+
+/// A slice owned by a [`Prototype`], allocated in the arena and accounted
+/// to [`MemoryKind::Prototype`]. Slices holding no `Gc` pointers use the
+/// `'static` brand so they can be `require_static`.
+pub type ProtoSlice<'a, T> = Box<[T], MetricsAlloc<'a>>;
+
+/// A local or upvalue name kept for debug info, allocated like a
+/// [`ProtoSlice`].
+pub type Name = Box<str, MetricsAlloc<'static>>;
+
+/// Collect `items` into a [`ProtoSlice`].
+pub(crate) fn proto_slice<'a, T>(
+    mc: &Mutation<'_>,
+    items: impl IntoIterator<Item = T>,
+) -> ProtoSlice<'a, T> {
+    let items = items.into_iter();
+    let alloc =
+        MetricsAlloc::from_metrics(mc.metrics().clone()).with_kind(MemoryKind::Prototype.id());
+    let mut v = Vec::with_capacity_in(items.size_hint().0, alloc);
+    v.extend(items);
+    v.into_boxed_slice()
+}
+
+/// Copy `name` into the arena.
+pub(crate) fn name(mc: &Mutation<'_>, name: &str) -> Name {
+    let (ptr, alloc) = Box::into_raw_with_allocator(proto_slice(mc, name.bytes()));
+    // SAFETY: the bytes were copied from a `str`, and `str` has the layout
+    // of `[u8]`.
+    unsafe { Box::from_raw_in(ptr as *mut str, alloc) }
+}
+
+/// Longest chunk id produced by [`Prototype::chunk_id`], Lua's
+/// `LUA_IDSIZE` less the terminator.
+const CHUNK_ID_SIZE: usize = 59;
+
+impl<'gc> Prototype<'gc> {
+    /// The instruction at `pc` as the compiler emitted it, whether or not
+    /// it has since been quickened.
+    pub fn instruction(&self, pc: usize) -> Instruction {
+        self.code[pc].get().generic()
+    }
+
+    /// Pointer to the first instruction, where the interpreter's `ip`
+    /// starts. `Lock` and the `Cell` in it are `repr(transparent)`.
+    #[inline(always)]
+    pub(crate) fn code_ptr(&self) -> *const Instruction {
+        self.code.as_ptr().cast()
+    }
+
+    /// Source line of the instruction at `pc`.
+    pub fn line(&self, pc: usize) -> u32 {
+        self.line_info.line(pc)
+    }
+
+    /// The chunk name as reference Lua prints it in messages
+    /// (`luaO_chunkid`): `=name` verbatim, `@file` as a possibly shortened
+    /// file name, anything else as `[string "first line..."]`.
+    pub fn chunk_id(&self) -> String {
+        let Some(source) = self.source else {
+            return "?".to_owned();
+        };
+        let source = String::from_utf8_lossy(source.as_bytes());
+        if let Some(name) = source.strip_prefix('=') {
+            name.chars().take(CHUNK_ID_SIZE).collect()
+        } else if let Some(file) = source.strip_prefix('@') {
+            let len = file.chars().count();
+            if len <= CHUNK_ID_SIZE {
+                file.to_owned()
+            } else {
+                let tail: String = file.chars().skip(len - (CHUNK_ID_SIZE - 3)).collect();
+                format!("...{tail}")
+            }
+        } else {
+            // Room left for the text after `[string "`, `..."]` and Lua's
+            // terminator.
+            const AVAILABLE: usize = CHUNK_ID_SIZE + 1 - 15;
+            let line = source.split('\n').next().unwrap_or_default();
+            if line.len() == source.len() && source.chars().count() < AVAILABLE {
+                format!("[string \"{source}\"]")
+            } else {
+                let line: String = line.chars().take(AVAILABLE).collect();
+                format!("[string \"{line}...\"]")
+            }
+        }
+    }
+
+    /// `chunkid:line:` for the instruction at `pc`, the prefix runtime
+    /// errors carry.
+    pub fn location(&self, pc: usize) -> String {
+        format!("{}:{}:", self.chunk_id(), self.line(pc))
+    }
+
+    /// Name of the local held in `register` at `pc`, if any. The innermost
+    /// declaration wins.
+    pub fn local_name(&self, register: u8, pc: usize) -> Option<&str> {
+        let pc = pc as u32;
+        self.local_vars
+            .iter()
+            .rev()
+            .find(|v| v.register == register && v.start_pc <= pc && pc < v.end_pc)
+            .map(|v| &*v.name)
+    }
+
+    /// Describe where the value in `register` came from when the
+    /// instruction at `pc` runs, as reference Lua's `getobjname` does:
+    /// `local 'x'`, `upvalue 'x'`, `global 'x'`, `field 'x'`, `method 'x'`
+    /// or `constant 'x'`. `None` for temporaries of unknown origin.
+    pub fn describe_register(&self, pc: usize, register: u8) -> Option<String> {
+        if let Some(name) = self.local_name(register, pc) {
+            return Some(format!("local '{name}'"));
+        }
+        let set_pc = self.find_set_register(pc, register)?;
+        match self.instruction(set_pc) {
+            Instruction::MOVE { dst, src } if src < dst => self.describe_register(set_pc, src),
+            Instruction::GETUPVAL { idx, .. } => Some(format!(
+                "upvalue '{}'",
+                self.upvalue_names.get(idx as usize)?
+            )),
+            Instruction::LOAD { idx, .. } => {
+                Some(format!("constant '{}'", self.string_constant(idx)?))
+            }
+            Instruction::GETTABUP { idx, key, .. } => {
+                let is_env = self
+                    .upvalue_names
+                    .get(idx as usize)
+                    .is_some_and(|n| &**n == "_ENV");
+                Some(field_kind(is_env, self.string_constant(key)?))
+            }
+            Instruction::GETFIELD { table, key_idx, .. } => {
+                let is_env = self.local_name(table, set_pc) == Some("_ENV");
+                Some(field_kind(is_env, self.string_constant(key_idx)?))
+            }
+            Instruction::GETTABLE { key, .. } => {
+                let key_pc = self.find_set_register(set_pc, key)?;
+                let Instruction::LOAD { idx, .. } = self.instruction(key_pc) else {
+                    return None;
+                };
+                Some(field_kind(false, self.string_constant(idx)?))
+            }
+            Instruction::SELF { key_idx, .. } => {
+                Some(format!("method '{}'", self.string_constant(key_idx)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Pc of the last instruction before `last_pc` that wrote `register`,
+    /// `None` if there is none or a forward jump may skip it (`findsetreg`).
+    fn find_set_register(&self, last_pc: usize, register: u8) -> Option<usize> {
+        let mut set_pc = None;
+        // Writes before the furthest jump target seen so far sit in
+        // conditional code and may not have happened.
+        let mut jump_target = 0;
+        for pc in 0..last_pc {
+            let writes = match self.instruction(pc) {
+                Instruction::CALL { func, .. } | Instruction::TAILCALL { func, .. } => {
+                    register >= func
+                }
+                Instruction::VARARG { dst, .. } => register >= dst,
+                Instruction::SELF { dst, .. } => register == dst || register == dst + 1,
+                Instruction::TFORCALL { base, .. } => register >= base + 3,
+                Instruction::FORPREP { base, .. }
+                | Instruction::FORLOOP { base, .. }
+                | Instruction::TFORPREP { base, .. }
+                | Instruction::TFORLOOP { base, .. } => register >= base,
+                Instruction::JMP { offset } => {
+                    let target = (pc as i64 + 1 + offset as i64) as usize;
+                    if pc < target && target <= last_pc {
+                        jump_target = jump_target.max(target);
+                    }
+                    false
+                }
+                other => other.dst() == Some(register),
+            };
+            if writes {
+                set_pc = (pc >= jump_target).then_some(pc);
+            }
+        }
+        set_pc
+    }
+
+    fn string_constant(&self, idx: u16) -> Option<std::borrow::Cow<'_, str>> {
+        let s = self.constants.get(idx as usize)?.get_string()?;
+        Some(String::from_utf8_lossy(s.as_bytes()))
+    }
+}
+
+/// `global 'k'` for a field of `_ENV`, `field 'k'` otherwise.
+fn field_kind(is_env: bool, key: impl std::fmt::Display) -> String {
+    if is_env {
+        format!("global '{key}'")
+    } else {
+        format!("field '{key}'")
+    }
 }
 
-/// Per-call-site monomorphic inline cache. `Empty` initially; a slow
-/// path fills it on first miss with the observed shape and slot. Future
-/// hits skip the metatable lookup entirely.
+/// Debug info of a named local: the register holding it and the range
+/// `[start_pc, end_pc)` of instructions it's in scope for.
+#[derive(Debug)]
+pub struct LocalVar {
+    pub name: Name,
+    pub register: u8,
+    pub start_pc: u32,
+    pub end_pc: u32,
+}
+
+/// Compact pc→line table: one `(first_pc, line)` entry per run of
+/// consecutive instructions on the same line.
+#[derive(Debug)]
+pub struct LineInfo {
+    runs: ProtoSlice<'static, (u32, u32)>,
+}
+
+impl LineInfo {
+    /// Build the table from one line per instruction.
+    pub fn new(mc: &Mutation<'_>, lines: &[u32]) -> Self {
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for (pc, &line) in lines.iter().enumerate() {
+            if runs.last().is_none_or(|&(_, last)| last != line) {
+                runs.push((pc as u32, line));
+            }
+        }
+        LineInfo {
+            runs: proto_slice(mc, runs),
+        }
+    }
+
+    /// Line of the instruction at `pc`, or 0 if unknown.
+    pub fn line(&self, pc: usize) -> u32 {
+        let i = self
+            .runs
+            .partition_point(|&(start, _)| start as usize <= pc);
+        i.checked_sub(1).map_or(0, |i| self.runs[i].1)
+    }
+}
+
+/// Per-call-site inline cache. `Empty` initially; a slow path fills it on
+/// each miss with the observed shape and slot, so future hits skip the
+/// metatable lookup entirely. A site that sees a second shape goes
+/// `Poly`, caching up to [`InlineCache::POLY_ENTRIES`] shapes; one that
+/// sees more goes `Mega` and is never refilled, leaving it on the slow
+/// path rather than thrashing.
 ///
 /// Metatable-mutation tracking is handled by `Shape::has_mm`, which
 /// reads the live `MtCache` bitset on the metatable. Bits are updated
@@ -52,24 +317,119 @@ pub struct Prototype<'gc> {
 pub enum InlineCache<'gc> {
     #[default]
     Empty,
-    Mono {
-        /// Shape pointer the cache was filled against.
-        shape: Shape<'gc>,
-        /// Slot index in `TableState::properties`. `u32::MAX` =
-        /// "key absent in shape" (so a get returns the metamethod
-        /// chain on this branch and a set must transition).
+    Mono(IcEntry<'gc>),
+    Poly {
+        /// Entries in use, most recently added first. Those past `len`
+        /// are filler copies of live ones.
         #[collect(require_static)]
-        slot: u32,
+        len: u8,
+        entries: [IcEntry<'gc>; InlineCache::POLY_ENTRIES],
     },
+    Mega,
+}
+
+/// One shape → slot mapping of an [`InlineCache`].
+#[derive(Clone, Copy, Collect)]
+#[collect(internal, no_drop)]
+pub struct IcEntry<'gc> {
+    /// Shape pointer the entry was filled against.
+    pub shape: Shape<'gc>,
+    /// Slot index in `TableState::properties` — of the receiver, or of
+    /// `via`'s holder when set. `u32::MAX` = "key absent in shape" (so
+    /// a get returns the metamethod chain on this branch and a set must
+    /// transition).
+    #[collect(require_static)]
+    pub slot: u32,
+    /// Set on a GETFIELD / SELF entry whose key the receiver lacks but
+    /// its metatable's `__index` table has. Set paths never match these.
+    pub via: Option<Gc<'gc, IndexLink<'gc>>>,
+}
+
+/// Where a key absent from the receiver was found one `__index` table
+/// away. The receiver's shape pins its metatable and the key's absence;
+/// the metatable's shape pins where `__index` lives, whose value must
+/// still be `holder`; `holder`'s shape pins the slot.
+#[derive(Collect)]
+#[collect(internal, no_drop)]
+pub struct IndexLink<'gc> {
+    /// Shape of the receiver's metatable when `__index` was read.
+    pub meta_shape: Shape<'gc>,
+    /// Slot of `__index` in that metatable.
+    #[collect(require_static)]
+    pub index_slot: u32,
+    /// The `__index` table the key was found in.
+    pub holder: Table<'gc>,
+    /// Shape of `holder` when the key was found.
+    pub holder_shape: Shape<'gc>,
 }
 
 impl<'gc> InlineCache<'gc> {
     pub const ABSENT_SLOT: u32 = u32::MAX;
+    /// Shapes a polymorphic site caches before going megamorphic.
+    pub const POLY_ENTRIES: usize = 4;
+
+    /// Entry cached for `shape`, if any.
+    #[inline(always)]
+    pub fn entry(&self, shape: Shape<'gc>) -> Option<IcEntry<'gc>> {
+        match self {
+            InlineCache::Mono(entry) if Shape::ptr_eq(entry.shape, shape) => Some(*entry),
+            InlineCache::Poly { len, entries } => entries[..*len as usize]
+                .iter()
+                .find(|e| Shape::ptr_eq(e.shape, shape))
+                .copied(),
+            _ => None,
+        }
+    }
+
+    /// Own-property slot cached for `shape`, if any.
+    #[inline(always)]
+    pub fn lookup(&self, shape: Shape<'gc>) -> Option<u32> {
+        self.entry(shape)
+            .filter(|e| e.via.is_none())
+            .map(|e| e.slot)
+    }
+
+    /// The cache after recording `entry`: an entry for the same shape is
+    /// replaced, a new shape widens the cache, and one shape past
+    /// `POLY_ENTRIES` makes it megamorphic.
+    pub fn with(self, entry: IcEntry<'gc>) -> Self {
+        match self {
+            InlineCache::Empty => InlineCache::Mono(entry),
+            InlineCache::Mono(old) if Shape::ptr_eq(old.shape, entry.shape) => {
+                InlineCache::Mono(entry)
+            }
+            InlineCache::Mono(old) => {
+                let mut entries = [entry; Self::POLY_ENTRIES];
+                entries[1] = old;
+                InlineCache::Poly { len: 2, entries }
+            }
+            InlineCache::Poly { len, mut entries } => {
+                let live = &mut entries[..len as usize];
+                if let Some(e) = live
+                    .iter_mut()
+                    .find(|e| Shape::ptr_eq(e.shape, entry.shape))
+                {
+                    e.slot = entry.slot;
+                    return InlineCache::Poly { len, entries };
+                }
+                if len as usize == Self::POLY_ENTRIES {
+                    return InlineCache::Mega;
+                }
+                entries.copy_within(..len as usize, 1);
+                entries[0] = entry;
+                InlineCache::Poly {
+                    len: len + 1,
+                    entries,
+                }
+            }
+            InlineCache::Mega => InlineCache::Mega,
+        }
+    }
 }
 
 /// An upvalue — open (references a stack slot) or closed (owns the value).
 #[derive(Collect)]
-#[collect(internal, no_drop)]
+#[collect(internal, no_drop, kind = "MemoryKind::Closure.id()")]
 pub enum UpvalueState<'gc> {
     Open {
         thread: crate::env::thread::Thread<'gc>,
@@ -82,10 +442,10 @@ pub type Upvalue<'gc> = Gc<'gc, RefLock<UpvalueState<'gc>>>;
 
 /// A Lua closure (bytecode + upvalues).
 #[derive(Collect)]
-#[collect(internal, no_drop)]
+#[collect(internal, no_drop, kind = "MemoryKind::Closure.id()")]
 pub struct LuaClosure<'gc> {
     pub proto: Gc<'gc, Prototype<'gc>>,
-    pub upvalues: Box<[Upvalue<'gc>]>,
+    pub upvalues: Box<[Upvalue<'gc>], MetricsAlloc<'gc>>,
 }
 
 /// A native closure (Rust function + optional upvalues).
@@ -94,7 +454,7 @@ pub struct LuaClosure<'gc> {
 pub struct NativeClosure<'gc> {
     #[collect(require_static)]
     pub function: NativeFn,
-    pub upvalues: Box<[Value<'gc>]>,
+    pub upvalues: Box<[Value<'gc>], MetricsAlloc<'gc>>,
 }
 
 /// Signature of a native callback invoked by the VM on `CALL` / `TAILCALL`.
@@ -126,13 +486,13 @@ pub struct NativeContext<'gc, 'a> {
 /// any values it leaves on the stack (via `push`, `extend`, or `replace`)
 /// become the callback's return values.
 pub struct Stack<'gc, 'a> {
-    values: &'a mut Vec<Value<'gc>>,
+    values: &'a mut ValueStack<'gc>,
     bottom: usize,
 }
 
 impl<'gc, 'a> Stack<'gc, 'a> {
     #[inline]
-    pub(crate) fn new(values: &'a mut Vec<Value<'gc>>, bottom: usize) -> Self {
+    pub(crate) fn new(values: &'a mut ValueStack<'gc>, bottom: usize) -> Self {
         debug_assert!(bottom <= values.len());
         Stack { values, bottom }
     }
@@ -140,7 +500,7 @@ impl<'gc, 'a> Stack<'gc, 'a> {
     /// Destructure the borrowed view back into its underlying parts. Used
     /// by `async_sequence` to ferry the live stack through a `SharedSlot`.
     #[inline]
-    pub(crate) fn into_parts(self) -> (&'a mut Vec<Value<'gc>>, usize) {
+    pub(crate) fn into_parts(self) -> (&'a mut ValueStack<'gc>, usize) {
         (self.values, self.bottom)
     }
 
@@ -196,6 +556,12 @@ impl<'gc, 'a> Stack<'gc, 'a> {
         self.values.extend(iter);
     }
 
+    /// Shorten the window to its first `len` values.
+    #[inline]
+    pub fn truncate(&mut self, len: usize) {
+        self.values.truncate(self.bottom + len);
+    }
+
     /// Convenience for the common "clear args, push N results" pattern.
     #[inline]
     pub fn replace(&mut self, values: &[Value<'gc>]) {
@@ -212,13 +578,26 @@ impl<'gc, 'a> std::ops::Index<usize> for Stack<'gc, 'a> {
     }
 }
 
+/// Collect a new closure's upvalues into the arena, accounted to
+/// [`MemoryKind::Closure`].
+fn upvalue_box<'gc, T>(
+    mc: &Mutation<'gc>,
+    upvalues: impl IntoIterator<Item = T>,
+) -> Box<[T], MetricsAlloc<'gc>> {
+    let upvalues = upvalues.into_iter();
+    let alloc = MetricsAlloc::new(mc).with_kind(MemoryKind::Closure.id());
+    let mut v = Vec::with_capacity_in(upvalues.size_hint().0, alloc);
+    v.extend(upvalues);
+    v.into_boxed_slice()
+}
+
 /// Copy wrapper stored in Value. Single Gc pointer for size efficiency.
 #[derive(Clone, Copy, Collect)]
 #[collect(internal, no_drop)]
 pub struct Function<'gc>(Gc<'gc, FunctionKind<'gc>>);
 
 #[derive(Collect)]
-#[collect(internal, no_drop)]
+#[collect(internal, no_drop, kind = "MemoryKind::Closure.id()")]
 pub enum FunctionKind<'gc> {
     Lua(Gc<'gc, LuaClosure<'gc>>),
     Native(NativeClosure<'gc>),
@@ -228,13 +607,19 @@ impl<'gc> Function<'gc> {
     pub fn new_lua(
         mc: &Mutation<'gc>,
         proto: Gc<'gc, Prototype<'gc>>,
-        upvalues: Box<[Upvalue<'gc>]>,
+        upvalues: impl IntoIterator<Item = Upvalue<'gc>>,
     ) -> Self {
+        let upvalues = upvalue_box(mc, upvalues);
         let closure = Gc::new(mc, LuaClosure { proto, upvalues });
         Function(Gc::new(mc, FunctionKind::Lua(closure)))
     }
 
-    pub fn new_native(mc: &Mutation<'gc>, function: NativeFn, upvalues: Box<[Value<'gc>]>) -> Self {
+    pub fn new_native(
+        mc: &Mutation<'gc>,
+        function: NativeFn,
+        upvalues: impl IntoIterator<Item = Value<'gc>>,
+    ) -> Self {
+        let upvalues = upvalue_box(mc, upvalues);
         Function(Gc::new(
             mc,
             FunctionKind::Native(NativeClosure { function, upvalues }),
@@ -47,6 +47,9 @@ macro_rules! for_each_metamethod {
             (LEN,      20, b"__len",      mm_len);
             (CALL,     21, b"__call",     mm_call);
             (TOSTRING, 22, b"__tostring", mm_tostring);
+            (MODE,     23, b"__mode",     mm_mode);
+            (GC,       24, b"__gc",       mm_gc);
+            (CLOSE,    25, b"__close",    mm_close);
         }
     };
 }
@@ -57,6 +60,6 @@ pub use shape::{MetamethodBits, MtCache, Shape};
 pub use string::LuaString;
 pub use symbols::Symbols;
 pub use table::Table;
-pub use thread::Thread;
+pub use thread::{Hook, HookMask, Thread};
 pub use userdata::Userdata;
 pub use value::{Value, ValueKind};
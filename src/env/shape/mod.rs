@@ -22,9 +22,10 @@ use hashbrown::{HashTable, hash_table};
 
 use crate::dmm::allocator_api::MetricsAlloc;
 use crate::dmm::barrier::unlock;
-use crate::dmm::{Collect, Gc, GcWeak, Lock, Mutation, RefLock};
+use crate::dmm::{Collect, Finalization, Gc, GcWeak, Lock, Mutation, RefLock};
 use crate::env::for_each_metamethod;
 use crate::env::string::LuaString;
+use crate::lua::MemoryKind;
 
 /// V8-style "Map" / hidden class. Copy wrapper over a single Gc pointer
 /// for cheap pass-by-value and pointer-equality identity checks.
@@ -111,7 +112,9 @@ pub fn metamethod_bit_of_bytes(name: &[u8]) -> Option<MetamethodBits> {
 /// `Prototype.ic_table`). Mutation goes through `Gc::write` on the
 /// owning `ShapeData` to emit the backward barrier — children adopted
 /// as `GcWeak` won't retain their targets, so a transient sub-shape
-/// can be reclaimed by GC even while its parent is alive.
+/// can be reclaimed by GC even while its parent is alive. The edges to
+/// reclaimed children are dropped before the sweep by
+/// `Shape::prune_dead_transitions`.
 #[derive(Collect)]
 #[collect(internal, no_drop)]
 pub struct TransitionTable<'gc> {
@@ -136,7 +139,7 @@ pub struct MtEdge<'gc> {
 }
 
 #[derive(Collect)]
-#[collect(internal, no_drop)]
+#[collect(internal, no_drop, kind = "MemoryKind::Shape.id()")]
 pub struct ShapeData<'gc> {
     /// Parent shape this one was derived from. `None` only at the root.
     pub parent: Option<Shape<'gc>>,
@@ -171,11 +174,11 @@ pub struct ShapeData<'gc> {
     /// Full ordered descriptor list (parent's prefix + this shape's
     /// last_key, if any). Eager rather than lazy — keeps slow paths
     /// branchless.
-    pub descriptors: Box<[Descriptor<'gc>]>,
+    pub descriptors: Box<[Descriptor<'gc>], MetricsAlloc<'gc>>,
 }
 
 #[derive(Collect)]
-#[collect(internal, no_drop)]
+#[collect(internal, no_drop, kind = "MemoryKind::Shape.id()")]
 pub struct MtCacheData<'gc> {
     #[collect(require_static)]
     pub bits: Cell<MetamethodBits>,
@@ -293,10 +296,15 @@ impl<'gc> Shape<'gc> {
                 mt_cache: None,
                 is_dict: false,
                 transitions: RefLock::new(TransitionTable {
-                    by_prop: HashTable::new_in(MetricsAlloc::new(mc)),
-                    by_mt: HashTable::new_in(MetricsAlloc::new(mc)),
+                    by_prop: HashTable::new_in(
+                        MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id()),
+                    ),
+                    by_mt: HashTable::new_in(
+                        MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id()),
+                    ),
                 }),
-                descriptors: Box::from([]),
+                descriptors: Vec::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id()))
+                    .into_boxed_slice(),
             },
         ))
     }
@@ -313,10 +321,15 @@ impl<'gc> Shape<'gc> {
                 mt_cache,
                 is_dict: true,
                 transitions: RefLock::new(TransitionTable {
-                    by_prop: HashTable::new_in(MetricsAlloc::new(mc)),
-                    by_mt: HashTable::new_in(MetricsAlloc::new(mc)),
+                    by_prop: HashTable::new_in(
+                        MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id()),
+                    ),
+                    by_mt: HashTable::new_in(
+                        MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id()),
+                    ),
                 }),
-                descriptors: Box::from([]),
+                descriptors: Vec::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id()))
+                    .into_boxed_slice(),
             },
         ))
     }
@@ -389,6 +402,37 @@ impl<'gc> Shape<'gc> {
             Some(c) => !c.get().is_empty(),
         }
     }
+
+    /// Drop every transition edge whose child the collector found dead
+    /// from the tree rooted at this shape, returning the number of live
+    /// shapes in it. Must run on the fully marked arena, right before the
+    /// sweep: a dead child's box is only freed once nothing points at it
+    /// weakly, and its key and metatable cache are held by the edge.
+    pub(crate) fn prune_dead_transitions(self, fc: &Finalization<'gc>) -> usize {
+        let mut live = 0;
+        let mut pending = vec![self.0];
+        while let Some(shape) = pending.pop() {
+            live += 1;
+            let transitions = shape.transitions.borrow();
+            let has_dead = transitions.by_prop.iter().any(|e| e.child.is_dead(fc))
+                || transitions.by_mt.iter().any(|e| e.child.is_dead(fc));
+            drop(transitions);
+            if has_dead {
+                let shape_write = Gc::write(fc, shape);
+                let mut transitions = unlock!(shape_write, ShapeData, transitions).borrow_mut();
+                transitions.by_prop.retain(|e| !e.child.is_dead(fc));
+                transitions.by_mt.retain(|e| !e.child.is_dead(fc));
+            }
+            let transitions = shape.transitions.borrow();
+            let children = transitions
+                .by_prop
+                .iter()
+                .map(|e| e.child)
+                .chain(transitions.by_mt.iter().map(|e| e.child));
+            pending.extend(children.filter_map(|child| child.upgrade(fc)));
+        }
+        live
+    }
 }
 
 /// Add a string-keyed property `key` to `parent`, returning the child
@@ -421,7 +465,11 @@ pub fn transition_add_prop<'gc>(
 
     // Slow path: allocate a child and install/replace the edge.
     let new_slot = parent.data().slot_count;
-    let mut new_descs: Vec<Descriptor<'gc>> = parent.descriptors().to_vec();
+    let mut new_descs = Vec::with_capacity_in(
+        parent.descriptors().len() + 1,
+        MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id()),
+    );
+    new_descs.extend_from_slice(parent.descriptors());
     new_descs.push(Descriptor {
         key,
         slot: new_slot,
@@ -436,8 +484,8 @@ pub fn transition_add_prop<'gc>(
             mt_cache: parent.data().mt_cache,
             is_dict: false,
             transitions: RefLock::new(TransitionTable {
-                by_prop: HashTable::new_in(MetricsAlloc::new(mc)),
-                by_mt: HashTable::new_in(MetricsAlloc::new(mc)),
+                by_prop: HashTable::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id())),
+                by_mt: HashTable::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id())),
             }),
             descriptors: new_descs.into_boxed_slice(),
         },
@@ -514,7 +562,12 @@ pub fn transition_set_metatable<'gc>(
     // still works because we keep `parent` / `last_key` pointing into
     // `parent`'s chain. Future prop additions on the result will mint
     // their own edges normally.
-    let descriptors: Box<[Descriptor<'gc>]> = parent.descriptors().to_vec().into_boxed_slice();
+    let mut descriptors = Vec::with_capacity_in(
+        parent.descriptors().len(),
+        MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id()),
+    );
+    descriptors.extend_from_slice(parent.descriptors());
+    let descriptors = descriptors.into_boxed_slice();
     let child_data = Gc::new(
         mc,
         ShapeData {
@@ -528,8 +581,8 @@ pub fn transition_set_metatable<'gc>(
             mt_cache: new_mt,
             is_dict: false,
             transitions: RefLock::new(TransitionTable {
-                by_prop: HashTable::new_in(MetricsAlloc::new(mc)),
-                by_mt: HashTable::new_in(MetricsAlloc::new(mc)),
+                by_prop: HashTable::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id())),
+                by_mt: HashTable::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::Shape.id())),
             }),
             descriptors,
         },
@@ -5,17 +5,19 @@ use std::hash::BuildHasher;
 use hashbrown::{HashTable, hash_table};
 
 use crate::dmm::allocator_api::MetricsAlloc;
-use crate::dmm::{Collect, Gc, Mutation, RefLock};
-use crate::lua::Context;
+use crate::dmm::{Collect, Finalization, Gc, GcWeak, Mutation, RefLock};
+use crate::lua::{Context, MemoryKind};
 
 #[derive(Clone, Copy, Collect)]
 #[collect(internal, no_drop)]
 pub struct LuaString<'gc>(Gc<'gc, StringData>);
 
 #[derive(Collect)]
-#[collect(internal, require_static)]
+#[collect(internal, require_static, kind = "MemoryKind::String.id()")]
 pub struct StringData {
-    bytes: Box<[u8]>,
+    /// Counted as external memory, so long strings weigh on the pacing and
+    /// the memory limit as much as the tables they'd otherwise hide in.
+    bytes: Box<[u8], MetricsAlloc<'static>>,
 }
 
 impl<'gc> LuaString<'gc> {
@@ -75,9 +77,13 @@ impl<'gc> Hash for LuaString<'gc> {
 pub struct Interner<'gc>(Gc<'gc, RefLock<InternerState<'gc>>>);
 
 #[derive(Collect)]
-#[collect(internal, no_drop)]
+#[collect(internal, no_drop, kind = "MemoryKind::String.id()")]
 struct InternerState<'gc> {
-    table: HashTable<LuaString<'gc>, MetricsAlloc<'gc>>,
+    /// Weak, so interning never keeps a string alive. `prune_dead` drops
+    /// entries before their strings are swept; every entry left is live,
+    /// which keeps one allocation per byte sequence and lets
+    /// `LuaString::eq` stay a pointer comparison.
+    table: HashTable<GcWeak<'gc, StringData>, MetricsAlloc<'gc>>,
     #[collect(require_static)]
     hasher: foldhash::fast::RandomState,
 }
@@ -85,7 +91,7 @@ struct InternerState<'gc> {
 impl<'gc> Interner<'gc> {
     pub(crate) fn new(mc: &Mutation<'gc>) -> Self {
         let state = InternerState {
-            table: HashTable::new_in(MetricsAlloc::new(mc)),
+            table: HashTable::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::String.id())),
             hasher: foldhash::fast::RandomState::default(),
         };
 
@@ -96,10 +102,15 @@ impl<'gc> Interner<'gc> {
         let mut state = self.0.borrow_mut(mc);
         let InternerState { table, hasher } = &mut *state;
 
-        let eq = |string: &LuaString| &*string.0.bytes == bytes;
-        let hash = |string: &LuaString| {
+        let live = |string: &GcWeak<'gc, StringData>| {
+            string
+                .upgrade(mc)
+                .expect("interned strings are pruned before they are swept")
+        };
+        let eq = |string: &GcWeak<'gc, StringData>| &*live(string).bytes == bytes;
+        let hash = |string: &GcWeak<'gc, StringData>| {
             let mut hasher: foldhash::fast::FoldHasher<'_> = hasher.build_hasher();
-            hasher.write(&string.0.bytes);
+            hasher.write(&live(string).bytes);
             hasher.finish()
         };
 
@@ -112,16 +123,33 @@ impl<'gc> Interner<'gc> {
         let entry = table.entry(target_hash, eq, hash);
 
         match entry {
-            hash_table::Entry::Occupied(entry) => *entry.get(),
+            hash_table::Entry::Occupied(entry) => LuaString(live(entry.get())),
             hash_table::Entry::Vacant(entry) => {
+                let mut owned = Vec::with_capacity_in(
+                    bytes.len(),
+                    MetricsAlloc::from_metrics(mc.metrics().clone())
+                        .with_kind(MemoryKind::String.id()),
+                );
+                owned.extend_from_slice(bytes);
                 let data = StringData {
-                    bytes: bytes.into(),
+                    bytes: owned.into_boxed_slice(),
                 };
 
                 let string = LuaString(Gc::new(mc, data));
-                entry.insert(string);
+                entry.insert(Gc::downgrade(string.0));
                 string
             }
         }
     }
+
+    /// Forget every interned string the collector found dead. Must run on
+    /// the fully marked arena once nothing more can be resurrected, right
+    /// before the sweep: a string dropped here and revived later would
+    /// get a second, unequal allocation the next time it is interned.
+    pub(crate) fn prune_dead(&self, fc: &Finalization<'gc>) {
+        self.0
+            .borrow_mut(fc)
+            .table
+            .retain(|string| !string.is_dead(fc));
+    }
 }
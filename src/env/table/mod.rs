@@ -2,11 +2,20 @@ use core::hash::BuildHasher;
 
 use hashbrown::{HashTable, hash_table};
 
+mod weak;
+
+pub use weak::WeakMode;
+pub(crate) use weak::WeakTables;
+
 use crate::Context;
-use crate::dmm::{Collect, Gc, Mutation, RefLock, allocator_api::MetricsAlloc};
+use crate::builtin::util;
+use crate::dmm::metrics::AllocationKind;
+use crate::dmm::{Collect, Gc, Mutation, RefLock, Trace, allocator_api::MetricsAlloc};
+use crate::env::error::Error;
 use crate::env::shape::{self, MAX_PROPERTIES_FAST, Shape};
 use crate::env::string::LuaString;
 use crate::env::value::{Value, ValueKind, value_hash};
+use crate::lua::MemoryKind;
 
 #[derive(Clone, Copy, Collect)]
 #[collect(internal, no_drop)]
@@ -34,6 +43,19 @@ impl<'gc> Table<'gc> {
         self.0.borrow_mut(ctx.mutation()).raw_set(ctx, key, value);
     }
 
+    /// [`Table::raw_set`], failing with "not enough memory" rather than
+    /// growing the table past the runtime's memory limit.
+    pub fn try_raw_set(
+        self,
+        ctx: Context<'gc>,
+        key: Value<'gc>,
+        value: Value<'gc>,
+    ) -> Result<(), Error<'gc>> {
+        self.0
+            .borrow_mut(ctx.mutation())
+            .try_raw_set(ctx, key, value)
+    }
+
     pub fn raw_len(self) -> usize {
         self.0.borrow().raw_len()
     }
@@ -47,11 +69,21 @@ impl<'gc> Table<'gc> {
     /// observe the new metatable's identity. Subsequent in-place
     /// mutations of the metatable update its shared `MtCache` bitset
     /// in place.
+    ///
+    /// The metatable's `__mode` is read here, not on every collection:
+    /// changing `__mode` on a metatable already in use has no effect on
+    /// the tables that adopted it. Likewise the table is only marked for
+    /// finalization if `mt` already has a `__gc` field.
     pub fn set_metatable(self, ctx: Context<'gc>, mt: Option<Table<'gc>>) {
         let new_cache = match mt {
             Some(t) => Some(t.ensure_mt_cache(ctx)),
             None => None,
         };
+        let weak = match mt {
+            Some(t) => WeakMode::from_metafield(t.raw_get(Value::string(ctx.symbols().mm_mode))),
+            None => WeakMode::empty(),
+        };
+        let has_gc = mt.is_some_and(|t| !t.raw_get(Value::string(ctx.symbols().mm_gc)).is_nil());
         let mut state = self.0.borrow_mut(ctx.mutation());
         state.shape = shape::transition_set_metatable(
             ctx.mutation(),
@@ -60,6 +92,21 @@ impl<'gc> Table<'gc> {
             ctx.empty_dict_sentinel(),
         );
         state.metatable = mt;
+        state.weak = weak;
+        if !weak.is_empty() && !state.weak_registered {
+            state.weak_registered = true;
+            ctx.weak_tables().register(ctx.mutation(), self);
+        }
+        if has_gc && !state.finalizer_marked {
+            state.finalizer_marked = true;
+            ctx.finalizers().mark_table(ctx.mutation(), self);
+        }
+    }
+
+    /// Which halves of this table's entries are weak, per the `__mode`
+    /// of the metatable it was last given.
+    pub fn weak_mode(self) -> WeakMode {
+        self.0.borrow().weak
     }
 
     /// Look up a metamethod by pre-interned name. The runtime keeps
@@ -113,8 +160,6 @@ impl<'gc> Table<'gc> {
     }
 }
 
-#[derive(Collect)]
-#[collect(internal, no_drop)]
 pub struct TableState<'gc> {
     /// Hidden class describing string-keyed property layout + metatable
     /// identity. In dict mode, this is a per-table sentinel shape; ICs
@@ -144,6 +189,59 @@ pub struct TableState<'gc> {
     /// write to this table; downstream shapes share this same `Gc`
     /// pointer and observe the updates without a freshness check.
     mt_cache: Option<shape::MtCache<'gc>>,
+    /// Weakness taken from the metatable's `__mode` at `set_metatable`
+    /// time. Decides how `trace` visits the entries below.
+    weak: WeakMode,
+    /// Set once the table has been pushed onto `State::weak_tables`;
+    /// cleared when the registry drops it after losing its weak mode.
+    weak_registered: bool,
+    /// Marked for finalization: the table was given a metatable with a
+    /// `__gc` field. Cleared when it is queued for its finalizer.
+    pub(crate) finalizer_marked: bool,
+}
+
+unsafe impl<'gc> Collect<'gc> for TableState<'gc> {
+    const KIND: AllocationKind = MemoryKind::Table.id();
+
+    fn trace<T: Trace<'gc>>(&self, cc: &mut T) {
+        cc.trace(&self.shape);
+        cc.trace(&self.metatable);
+        cc.trace(&self.mt_cache);
+        if self.weak.is_empty() {
+            cc.trace(&self.properties);
+            cc.trace(&self.array);
+            cc.trace(&self.misc_hash);
+            cc.trace(&self.dict);
+            return;
+        }
+
+        // Only `misc_hash` can hold collectable keys; the other parts
+        // are keyed by strings and integers, which are never weak.
+        let weak_keys = self.weak.contains(WeakMode::KEYS);
+        let weak_values = self.weak.contains(WeakMode::VALUES);
+        for v in self.properties.iter().chain(self.array.iter()) {
+            weak::trace_value(cc, *v, weak_values);
+        }
+        if let Some(dict) = &self.dict {
+            for (k, v) in dict.table.iter() {
+                cc.trace(k);
+                weak::trace_value(cc, *v, weak_values);
+            }
+        }
+        for (k, v) in self.misc_hash.iter() {
+            if weak_keys && let Some(key) = k.weak_object() {
+                cc.trace_gc_weak(Gc::downgrade(key));
+                // Ephemeron: the value is left untraced and resurrected
+                // during finalization only if the key survives.
+                if !weak_values && v.weak_object().is_some() {
+                    continue;
+                }
+            } else {
+                cc.trace(k);
+            }
+            weak::trace_value(cc, *v, weak_values);
+        }
+    }
 }
 
 /// Slow / dictionary-mode storage for string-keyed properties. Replaces
@@ -165,12 +263,15 @@ impl<'gc> TableState<'gc> {
     fn new(mc: &Mutation<'gc>, shape: Shape<'gc>) -> Self {
         Self {
             shape,
-            properties: Vec::new_in(MetricsAlloc::new(mc)),
-            array: Vec::new_in(MetricsAlloc::new(mc)),
-            misc_hash: HashTable::new_in(MetricsAlloc::new(mc)),
+            properties: Vec::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::Table.id())),
+            array: Vec::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::TableArray.id())),
+            misc_hash: HashTable::new_in(MetricsAlloc::new(mc).with_kind(MemoryKind::Table.id())),
             dict: None,
             metatable: None,
             mt_cache: None,
+            weak: WeakMode::empty(),
+            weak_registered: false,
+            finalizer_marked: false,
         }
     }
 
@@ -217,14 +318,22 @@ impl<'gc> TableState<'gc> {
             return self.get_string_key(s);
         }
         if let Some(index) = array_index(key) {
-            return match self.array.get(index - 1) {
-                Some(value) => *value,
-                None => Value::nil(),
-            };
+            return self.array_get(index);
         }
         self.misc_hash_get(key, value_hash(key))
     }
 
+    /// `raw_get` of the positive integer key `index`, which always lives
+    /// in the array part.
+    #[inline]
+    pub fn array_get(&self, index: usize) -> Value<'gc> {
+        debug_assert!(index >= 1);
+        match self.array.get(index.wrapping_sub(1)) {
+            Some(value) => *value,
+            None => Value::nil(),
+        }
+    }
+
     #[inline]
     fn get_string_key(&self, key: LuaString<'gc>) -> Value<'gc> {
         if let Some(d) = &self.dict {
@@ -272,6 +381,49 @@ impl<'gc> TableState<'gc> {
         self.misc_hash_set(key, value, value_hash(key));
     }
 
+    /// [`TableState::raw_set`], failing with "not enough memory" rather
+    /// than growing the array or hash part past the runtime's memory limit.
+    /// The properties of string keys aren't checked.
+    pub fn try_raw_set(
+        &mut self,
+        ctx: Context<'gc>,
+        key: Value<'gc>,
+        value: Value<'gc>,
+    ) -> Result<(), Error<'gc>> {
+        if key.get_string().is_none() {
+            let growth = self.growth_for(key, value);
+            if growth > 0 {
+                util::check_memory(ctx, growth)?;
+            }
+        }
+        self.raw_set(ctx, key, value);
+        Ok(())
+    }
+
+    /// Bytes storing `value` under the non-string `key` would have the
+    /// array or hash part allocate.
+    fn growth_for(&self, key: Value<'gc>, value: Value<'gc>) -> usize {
+        if let Some(index) = array_index(key) {
+            let cap = self.array.capacity();
+            if index <= cap {
+                return 0;
+            }
+            // `Vec` at least doubles, and the old buffer is only freed
+            // once the new one is filled.
+            return index.max(cap * 2).saturating_mul(size_of::<Value<'gc>>());
+        }
+        let cap = self.misc_hash.capacity();
+        if value.is_nil()
+            || self.misc_hash.len() < cap
+            || !self.misc_hash_get(key, value_hash(key)).is_nil()
+        {
+            return 0;
+        }
+        // Buckets are kept at most 7/8 full, with a control byte each.
+        let buckets = ((cap + 1) * 8 / 7).next_power_of_two();
+        buckets * (size_of::<(Value<'gc>, Value<'gc>)>() + 1)
+    }
+
     fn set_string_key(&mut self, ctx: Context<'gc>, key: LuaString<'gc>, value: Value<'gc>) {
         if self.dict.is_some() {
             self.set_string_key_dict(key, value);
@@ -344,7 +496,10 @@ impl<'gc> TableState<'gc> {
             "migrate_to_dict called on already-dict table"
         );
         let descs = self.shape.descriptors();
-        let mut table = HashTable::with_capacity_in(descs.len(), MetricsAlloc::new(ctx.mutation()));
+        let mut table = HashTable::with_capacity_in(
+            descs.len(),
+            MetricsAlloc::new(ctx.mutation()).with_kind(MemoryKind::Table.id()),
+        );
         for d in descs {
             let v = self.properties[d.slot as usize];
             if v.is_nil() {
@@ -0,0 +1,190 @@
+//! Weak tables (`__mode`). A table whose metatable carries `__mode`
+//! containing `k` and/or `v` traces the corresponding halves of its
+//! entries weakly (see `TableState`'s `Collect` impl), and is recorded
+//! in the runtime's `WeakTables` registry so entries whose key or value
+//! died can be cleared once the arena is fully marked.
+//!
+//! Weak-key tables are ephemerons: a value under a collectable key is
+//! not traced at all during marking, and is only resurrected if its key
+//! turned out to be reachable by other means. Marking and resurrection
+//! alternate until no new value is resurrected.
+
+use bitflags::bitflags;
+
+use crate::dmm::allocator_api::MetricsAlloc;
+use crate::dmm::{Collect, Finalization, Gc, GcWeak, Mutation, RefLock, Trace};
+use crate::env::string::LuaString;
+use crate::env::table::{Table, TableState};
+use crate::env::value::Value;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Collect)]
+#[collect(internal, require_static)]
+pub struct WeakMode(u8);
+
+bitflags! {
+    impl WeakMode: u8 {
+        const KEYS = 1 << 0;
+        const VALUES = 1 << 1;
+    }
+}
+
+impl WeakMode {
+    /// Parse a `__mode` metafield. Anything other than a string is
+    /// treated as no mode, as in PUC-Rio Lua.
+    pub fn from_metafield(mode: Value<'_>) -> Self {
+        let mut weak = WeakMode::empty();
+        if let Some(s) = mode.get_string() {
+            if s.as_bytes().contains(&b'k') {
+                weak |= WeakMode::KEYS;
+            }
+            if s.as_bytes().contains(&b'v') {
+                weak |= WeakMode::VALUES;
+            }
+        }
+        weak
+    }
+}
+
+/// Registry of every table that has been given a weak metatable. Held
+/// on `State`; entries are weak so registration never keeps a table
+/// alive, and dead or no-longer-weak tables are dropped from the list
+/// at the end of each cycle's finalization.
+#[derive(Clone, Copy, Collect)]
+#[collect(internal, no_drop)]
+pub struct WeakTables<'gc>(Gc<'gc, RefLock<Vec<WeakTableRef<'gc>, MetricsAlloc<'gc>>>>);
+
+type WeakTableRef<'gc> = GcWeak<'gc, RefLock<TableState<'gc>>>;
+
+impl<'gc> WeakTables<'gc> {
+    pub(crate) fn new(mc: &Mutation<'gc>) -> Self {
+        WeakTables(Gc::new(
+            mc,
+            RefLock::new(Vec::new_in(MetricsAlloc::new(mc))),
+        ))
+    }
+
+    pub(crate) fn register(self, mc: &Mutation<'gc>, table: Table<'gc>) {
+        self.0.borrow_mut(mc).push(Gc::downgrade(table.inner()));
+    }
+
+    /// Ephemeron step. For every live weak-key table, resurrect the
+    /// values whose keys survived marking. Returns `true` if anything
+    /// was resurrected, in which case marking must resume before the
+    /// next call.
+    pub(crate) fn resurrect_ephemerons(self, fc: &Finalization<'gc>) -> bool {
+        let mut resurrected = false;
+        for table in self.0.borrow().iter() {
+            if table.is_dead(fc) {
+                continue;
+            }
+            let table = table.upgrade(fc).expect("live table upgrades");
+            resurrected |= table.borrow().resurrect_ephemerons(fc);
+        }
+        resurrected
+    }
+
+    /// Clear entries whose weak value died, leaving weak keys alone.
+    /// Runs before objects with pending finalizers are resurrected, so
+    /// those disappear from weak values before their finalizer runs.
+    pub(crate) fn clear_dead_values(self, fc: &Finalization<'gc>) {
+        for table in self.0.borrow().iter() {
+            if table.is_dead(fc) {
+                continue;
+            }
+            let table = table.upgrade(fc).expect("live table upgrades");
+            if table.borrow().weak.contains(WeakMode::VALUES) {
+                table.borrow_mut(fc).clear_dead_values(fc);
+            }
+        }
+    }
+
+    /// Clear dead entries from every live weak table and forget tables
+    /// that died or lost their weak mode. Must only run once
+    /// `resurrect_ephemerons` has reached a fixed point.
+    pub(crate) fn clear_dead(self, fc: &Finalization<'gc>) {
+        self.0.borrow_mut(fc).retain(|table| {
+            if table.is_dead(fc) {
+                return false;
+            }
+            let table = table.upgrade(fc).expect("live table upgrades");
+            let mut state = table.borrow_mut(fc);
+            if state.weak.is_empty() {
+                state.weak_registered = false;
+                return false;
+            }
+            if state.weak.contains(WeakMode::KEYS) {
+                state.misc_hash.retain(|(k, _)| !is_dead(fc, *k));
+            }
+            if state.weak.contains(WeakMode::VALUES) {
+                state.clear_dead_values(fc);
+            }
+            true
+        });
+    }
+}
+
+/// Trace one table value, weakly if the table's values are weak.
+#[inline]
+pub(super) fn trace_value<'gc, T: Trace<'gc>>(cc: &mut T, value: Value<'gc>, weak: bool) {
+    match value.weak_object() {
+        Some(gc) if weak => cc.trace_gc_weak(Gc::downgrade(gc)),
+        _ => cc.trace(&value),
+    }
+}
+
+#[inline]
+fn is_dead<'gc>(fc: &Finalization<'gc>, value: Value<'gc>) -> bool {
+    value.weak_object().is_some_and(|gc| Gc::is_dead(fc, gc))
+}
+
+impl<'gc> TableState<'gc> {
+    fn resurrect_ephemerons(&self, fc: &Finalization<'gc>) -> bool {
+        // With weak values too, nothing under a live key is kept alive.
+        if self.weak != WeakMode::KEYS {
+            return false;
+        }
+        let mut resurrected = false;
+        for (k, v) in self.misc_hash.iter() {
+            if let Some(k) = k.weak_object()
+                && let Some(v) = v.weak_object()
+                && !Gc::is_dead(fc, k)
+                && Gc::is_dead(fc, v)
+            {
+                Gc::resurrect(fc, v);
+                resurrected = true;
+            }
+        }
+        resurrected
+    }
+
+    fn clear_dead_values(&mut self, fc: &Finalization<'gc>) {
+        self.misc_hash.retain(|(_, v)| !is_dead(fc, *v));
+        for v in self.array.iter_mut() {
+            if is_dead(fc, *v) {
+                *v = Value::nil();
+            }
+        }
+        // A cleared property keeps its slot, now holding nil, so the
+        // shape and any inline cache keyed on it stay valid.
+        let mut cleared: Vec<LuaString<'gc>> = Vec::new();
+        for d in self.shape.descriptors() {
+            let slot = &mut self.properties[d.slot as usize];
+            if is_dead(fc, *slot) {
+                *slot = Value::nil();
+                cleared.push(d.key);
+            }
+        }
+        if let Some(dict) = &mut self.dict {
+            dict.table.retain(|(k, v)| {
+                if is_dead(fc, *v) {
+                    cleared.push(*k);
+                    return false;
+                }
+                true
+            });
+        }
+        for key in cleared {
+            self.maybe_update_mt_bit(Value::string(key), Value::nil());
+        }
+    }
+}
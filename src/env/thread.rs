@@ -1,7 +1,15 @@
-use crate::dmm::{Collect, Gc, Mutation, Ref, RefLock, RefMut};
+use std::cell::Cell;
+
+use bitflags::bitflags;
+
+use crate::dmm::metrics::AllocationKind;
+use crate::dmm::{
+    Collect, Finalization, Gc, GcWeak, Lock, MetricsAlloc, Mutation, Ref, RefLock, RefMut, Trace,
+};
 use crate::env::error::Error;
 use crate::env::function::{Function, LuaClosure, Upvalue};
 use crate::env::value::Value;
+use crate::lua::{Context, MemoryKind};
 use crate::vm::interp::Continuation;
 use crate::vm::sequence::{BoxSequence, CallbackAction};
 
@@ -113,15 +121,162 @@ pub enum Frame<'gc> {
     Error(Error<'gc>),
 }
 
-/// The mutable state of a thread/coroutine.
-#[derive(Collect)]
+/// A thread's value stack. The buffer is accounted to
+/// [`MemoryKind::Thread`] in the arena's metrics.
+pub type ValueStack<'gc> = Vec<Value<'gc>, MetricsAlloc<'gc>>;
+
+/// Stacks are never shrunk below this many slots.
+const MIN_STACK_CAPACITY: usize = 256;
+
+/// Capacity a trimmed stack with `live` slots in use keeps, leaving room
+/// to grow again without reallocating straight away.
+fn stack_capacity_for(live: usize) -> usize {
+    (live * 2).max(MIN_STACK_CAPACITY)
+}
+
+/// The frame, open upvalue and to-be-closed lists are never shrunk below
+/// this many entries.
+const MIN_LIST_CAPACITY: usize = 16;
+
+/// Whether `list` has more than twice the capacity a trimmed list of its
+/// length keeps.
+fn list_mostly_unused<T>(list: &Vec<T, MetricsAlloc<'_>>) -> bool {
+    list.capacity() > (list.len() * 2).max(MIN_LIST_CAPACITY) * 2
+}
+
+/// Give back `list`'s memory if it is mostly unused.
+fn trim_list<T>(list: &mut Vec<T, MetricsAlloc<'_>>) {
+    if list_mostly_unused(list) {
+        list.shrink_to((list.len() * 2).max(MIN_LIST_CAPACITY));
+    }
+}
+
+/// Which events a [`Hook`] is called for, besides the instruction count.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Collect)]
+#[collect(internal, require_static)]
+pub struct HookMask(u8);
+
+bitflags! {
+    impl HookMask: u8 {
+        /// Entering a Lua function, including by tail call.
+        const CALL = 1 << 0;
+        /// Leaving a Lua function through `RETURN`.
+        const RETURN = 1 << 1;
+        /// Starting a new source line, or jumping back within one.
+        const LINE = 1 << 2;
+    }
+}
+
+/// A debug hook, as installed by `debug.sethook`. `function` is called
+/// with the event name (`"call"`, `"tail call"`, `"return"`, `"line"` or
+/// `"count"`) and, for line events, the new line. It runs on the hooked
+/// thread like any other call, so it may yield or raise; events raised
+/// while it runs are not reported. Native functions don't get frames and
+/// so don't raise call/return events.
+#[derive(Clone, Copy, Collect)]
 #[collect(internal, no_drop)]
+pub struct Hook<'gc> {
+    pub function: Function<'gc>,
+    pub mask: HookMask,
+    /// Call `function` with a `"count"` event every `count` instructions;
+    /// 0 disables count events.
+    pub count: u32,
+}
+
+impl<'gc> Hook<'gc> {
+    /// Whether the hook has any event to be called for.
+    pub fn is_active(&self) -> bool {
+        !self.mask.is_empty() || self.count > 0
+    }
+}
+
+/// A thread's installed [`Hook`]. Natives running on the thread replace it
+/// through [`Execution`](crate::vm::sequence::Execution) while the
+/// interpreter holds the thread borrowed, hence the interior mutability.
+pub struct HookSlot<'gc> {
+    hook: Lock<Option<Hook<'gc>>>,
+    /// Set when the hook is replaced; the interpreter then restarts its
+    /// [`HookCursor`] from wherever the thread is.
+    changed: Cell<bool>,
+}
+
+impl<'gc> HookSlot<'gc> {
+    fn new() -> Self {
+        HookSlot {
+            hook: Lock::new(None),
+            changed: Cell::new(false),
+        }
+    }
+
+    pub fn get(&self) -> Option<Hook<'gc>> {
+        self.hook.get()
+    }
+
+    /// Install `hook` (or remove it with `None`). `thread` must be the
+    /// thread owning this slot.
+    pub fn set(&self, mc: &Mutation<'gc>, thread: Thread<'gc>, hook: Option<Hook<'gc>>) {
+        // The hook function is adopted through the `Lock` without going
+        // through a `RefMut`, so emit the thread's barrier by hand.
+        mc.backward_barrier(Gc::erase(thread.0), None);
+        unsafe { self.hook.as_cell() }.set(hook.filter(Hook::is_active));
+        self.changed.set(true);
+    }
+
+    /// Clear and return the changed flag.
+    pub(crate) fn take_changed(&self) -> bool {
+        self.changed.replace(false)
+    }
+}
+
+unsafe impl<'gc> Collect<'gc> for HookSlot<'gc> {
+    fn trace<T: Trace<'gc>>(&self, cc: &mut T) {
+        cc.trace(&self.hook);
+    }
+}
+
+/// Where the interpreter last reported hook events, used to tell calls,
+/// returns and new lines apart. Only touched while a hook is installed.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct HookCursor {
+    /// `frames.len()` and pc of the last instruction events were computed
+    /// for.
+    pub depth: usize,
+    pub pc: usize,
+    /// That instruction was a `TAILCALL`.
+    pub tailcall: bool,
+    /// That instruction was a `RETURN`, so the frame at `depth` is gone.
+    pub returned: bool,
+    /// Instructions left until the next count event.
+    pub countdown: u32,
+    /// Events at `(depth, pc)` that haven't been reported yet.
+    pub pending: HookEvents,
+    /// `frames.len()` of the hooked frame while the hook function runs.
+    pub running: Option<usize>,
+}
+
+/// Hook events, in the order they're reported for one instruction.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub(crate) struct HookEvents(u8);
+
+bitflags! {
+    impl HookEvents: u8 {
+        const CALL = 1 << 0;
+        const TAIL_CALL = 1 << 1;
+        const COUNT = 1 << 2;
+        const LINE = 1 << 3;
+        const RETURN = 1 << 4;
+    }
+}
+
+/// The mutable state of a thread/coroutine.
 pub struct ThreadState<'gc> {
-    // See #43: custom Collect impl to avoid unused stack slots keeping values alive.
-    pub stack: Vec<Value<'gc>>,
-    pub frames: Vec<Frame<'gc>>,
-    pub open_upvalues: Vec<Upvalue<'gc>>,
-    pub tbc_slots: Vec<usize>,
+    /// Only `stack[..live_top()]` is traced; the slots above are dead
+    /// registers of frames that have returned, and are cleared at the end
+    /// of every collection by [`Threads::trim_stacks`].
+    pub stack: ValueStack<'gc>,
+    pub frames: Vec<Frame<'gc>, MetricsAlloc<'gc>>,
+    pub open_upvalues: Vec<Upvalue<'gc>, MetricsAlloc<'gc>>,
+    pub tbc_slots: Vec<usize, MetricsAlloc<'gc>>,
     pub status: ThreadStatus,
     /// Dynamic top register: written by a multires producer (`VARARG`/`CALL`/
     /// `TAILCALL` with the `0` sentinel, native multi-return) and read by the
@@ -139,8 +294,33 @@ pub struct ThreadState<'gc> {
     /// `SequencePoll::Yield`/`TailYield`). Consumed on resume to recover
     /// where the call's results should land. `None` outside of yielded
     /// state.
-    #[collect(require_static)]
     pub yield_bottom: Option<CallSite>,
+    /// The thread's debug hook. While one is installed the interpreter
+    /// dispatches through `op_hook`, which reports events to it.
+    pub hook: HookSlot<'gc>,
+    pub(crate) hook_cursor: HookCursor,
+    /// `Frame::Sequence`s on `frames`: natives waiting on a call they made.
+    pub(crate) sequences: usize,
+    /// Native depth of the thread that resumed this one, counting the
+    /// resume itself.
+    pub(crate) resume_depth: usize,
+    /// Set once the thread has raised a stack overflow, until it's back
+    /// within its [`StackLimits`](crate::lua::StackLimits); meanwhile it
+    /// may use the headroom above them.
+    pub(crate) overflowed: bool,
+}
+
+unsafe impl<'gc> Collect<'gc> for ThreadState<'gc> {
+    const KIND: AllocationKind = MemoryKind::Thread.id();
+
+    fn trace<T: Trace<'gc>>(&self, cc: &mut T) {
+        cc.trace(&self.stack[..self.live_top()]);
+        cc.trace(&self.frames);
+        cc.trace(&self.open_upvalues);
+        cc.trace(&self.thread_handle);
+        cc.trace(&self.pending_action);
+        cc.trace(&self.hook);
+    }
 }
 
 /// A native callback wants to suspend / call / yield / resume; the executor
@@ -210,24 +390,111 @@ impl<'gc> ThreadState<'gc> {
     pub fn push_lua(&mut self, lf: LuaFrame<'gc>) {
         self.frames.push(Frame::Lua(lf));
     }
+
+    /// Push a `Frame::Sequence`, counting it towards the native depth.
+    pub(crate) fn push_sequence(
+        &mut self,
+        seq: BoxSequence<'gc>,
+        call_site: CallSite,
+        pending_error: Option<Error<'gc>>,
+    ) {
+        self.sequences += 1;
+        self.frames.push(Frame::Sequence {
+            seq,
+            call_site,
+            pending_error,
+        });
+    }
+
+    /// How many natives are waiting on calls they made, on this thread and
+    /// the threads that resumed it.
+    #[inline(always)]
+    pub(crate) fn native_depth(&self) -> usize {
+        self.sequences + self.resume_depth
+    }
+
+    /// Drop every frame, as when the thread finishes or is closed.
+    pub(crate) fn clear_frames(&mut self) {
+        self.frames.clear();
+        self.sequences = 0;
+    }
+
+    /// End of the part of `stack` that may still be read. With a Lua frame
+    /// on top, that is the highest register window of any Lua frame (or a
+    /// pending multires `top` above it); everything else (native windows,
+    /// yielded or returned values) extends to the end of the stack.
+    pub fn live_top(&self) -> usize {
+        let len = self.stack.len();
+        if self.pending_action.is_some() || self.yield_bottom.is_some() {
+            return len;
+        }
+        if !matches!(self.frames.last(), Some(Frame::Lua(_))) {
+            return len;
+        }
+        let mut top = self.top;
+        for frame in &self.frames {
+            if let Frame::Lua(lf) = frame {
+                top = top.max(lf.base + lf.closure.proto.max_stack_size as usize);
+            }
+        }
+        top.min(len)
+    }
+
+    /// Whether `trim_stack` has anything to do.
+    fn needs_trim(&self) -> bool {
+        let live = self.live_top();
+        self.stack.len() > live
+            || self.stack.capacity() > stack_capacity_for(live) * 2
+            || list_mostly_unused(&self.frames)
+            || list_mostly_unused(&self.open_upvalues)
+            || list_mostly_unused(&self.tbc_slots)
+    }
+
+    /// Drop the dead slots above `live_top`, and give back the memory of
+    /// the stack and the frame lists if it is mostly unused, e.g. after
+    /// deep recursion unwound.
+    fn trim_stack(&mut self) {
+        let live = self.live_top();
+        self.stack.truncate(live);
+        let wanted = stack_capacity_for(live);
+        if self.stack.capacity() > wanted * 2 {
+            self.stack.shrink_to(wanted);
+        }
+        trim_list(&mut self.frames);
+        trim_list(&mut self.open_upvalues);
+        trim_list(&mut self.tbc_slots);
+    }
 }
 
 impl<'gc> Thread<'gc> {
-    pub fn new(mc: &Mutation<'gc>) -> Self {
+    pub fn new(ctx: Context<'gc>) -> Self {
+        Self::new_in(ctx.mutation(), ctx.threads())
+    }
+
+    /// Create a thread and record it in `threads`, before a `Context`
+    /// exists.
+    pub(crate) fn new_in(mc: &Mutation<'gc>, threads: Threads<'gc>) -> Self {
+        let alloc = MetricsAlloc::new(mc).with_kind(MemoryKind::Thread.id());
         let state = ThreadState {
-            stack: Vec::new(),
-            frames: Vec::new(),
-            open_upvalues: Vec::new(),
-            tbc_slots: Vec::new(),
+            stack: Vec::new_in(alloc.clone()),
+            frames: Vec::new_in(alloc.clone()),
+            open_upvalues: Vec::new_in(alloc.clone()),
+            tbc_slots: Vec::new_in(alloc),
             status: ThreadStatus::Stopped,
             top: 0,
             thread_handle: None,
             pending_action: None,
             yield_bottom: None,
+            hook: HookSlot::new(),
+            hook_cursor: HookCursor::default(),
+            sequences: 0,
+            resume_depth: 0,
+            overflowed: false,
         };
         let thread = Thread(Gc::new(mc, RefLock::new(state)));
         // Store the back-reference
         thread.borrow_mut(mc).thread_handle = Some(thread);
+        threads.register(mc, thread);
         thread
     }
 
@@ -239,6 +506,19 @@ impl<'gc> Thread<'gc> {
         self.0.borrow_mut(mc)
     }
 
+    /// The thread's debug hook. Panics if the thread is running; natives
+    /// reach the running thread's hook through `Execution::hook`.
+    pub fn hook(self) -> Option<Hook<'gc>> {
+        self.borrow().hook.get()
+    }
+
+    /// Install a debug hook on the thread, or remove it with `None`. A hook
+    /// whose mask is empty and count is 0 is removed. Panics if the thread
+    /// is running; see `Execution::set_hook`.
+    pub fn set_hook(self, mc: &Mutation<'gc>, hook: Option<Hook<'gc>>) {
+        self.borrow().hook.set(mc, self, hook);
+    }
+
     pub fn status(self) -> ThreadStatus {
         self.0.borrow().status
     }
@@ -256,3 +536,45 @@ impl<'gc> Thread<'gc> {
         Thread(g)
     }
 }
+
+/// Registry of every thread in the runtime, so their stacks can be
+/// trimmed once the arena is fully marked. Entries are weak; dead threads
+/// are dropped from the list as they are found.
+#[derive(Clone, Copy, Collect)]
+#[collect(internal, no_drop)]
+pub(crate) struct Threads<'gc>(Gc<'gc, RefLock<Vec<ThreadRef<'gc>, MetricsAlloc<'gc>>>>);
+
+type ThreadRef<'gc> = GcWeak<'gc, RefLock<ThreadState<'gc>>>;
+
+impl<'gc> Threads<'gc> {
+    pub(crate) fn new(mc: &Mutation<'gc>) -> Self {
+        Threads(Gc::new(
+            mc,
+            RefLock::new(Vec::new_in(
+                MetricsAlloc::new(mc).with_kind(MemoryKind::Thread.id()),
+            )),
+        ))
+    }
+
+    fn register(self, mc: &Mutation<'gc>, thread: Thread<'gc>) {
+        self.0.borrow_mut(mc).push(Gc::downgrade(thread.0));
+    }
+
+    /// Clear the slots above every live thread's `live_top`. They were not
+    /// traced, so whatever they point to may be about to be swept. Must run
+    /// after marking is complete: from then on the stacks can't change
+    /// before the sweep, so `live_top` is what it was when they were last
+    /// traced.
+    pub(crate) fn trim_stacks(self, fc: &Finalization<'gc>) {
+        self.0.borrow_mut(fc).retain(|thread| {
+            if thread.is_dead(fc) {
+                return false;
+            }
+            let thread = thread.upgrade(fc).expect("live thread upgrades");
+            if thread.borrow().needs_trim() {
+                thread.borrow_mut(fc).trim_stack();
+            }
+            true
+        });
+    }
+}
@@ -1,6 +1,8 @@
-use crate::dmm::{Collect, Gc, Mutation, RefLock};
+use crate::Context;
+use crate::dmm::{Collect, Gc, MetricsAlloc, Mutation, RefLock};
 use crate::env::table::Table;
 use crate::env::value::Value;
+use crate::lua::MemoryKind;
 
 /// Copy wrapper stored in Value.
 #[derive(Clone, Copy, Collect)]
@@ -8,20 +10,32 @@ use crate::env::value::Value;
 pub struct Userdata<'gc>(Gc<'gc, RefLock<UserdataState<'gc>>>);
 
 #[derive(Collect)]
-#[collect(internal, no_drop)]
+#[collect(internal, no_drop, kind = "MemoryKind::Userdata.id()")]
 pub struct UserdataState<'gc> {
     #[collect(require_static)]
-    data: Box<dyn std::any::Any>,
-    user_values: Vec<Value<'gc>>,
+    data: Box<dyn std::any::Any, MetricsAlloc<'static>>,
+    user_values: Vec<Value<'gc>, MetricsAlloc<'gc>>,
     metatable: Option<Table<'gc>>,
+    /// Marked for finalization: the userdata was given a metatable with
+    /// a `__gc` field. Cleared when it is queued for its finalizer.
+    pub(crate) finalizer_marked: bool,
 }
 
 impl<'gc> Userdata<'gc> {
     pub fn new<T: 'static>(mc: &Mutation<'gc>, data: T, num_user_values: usize) -> Self {
+        let kind = MemoryKind::Userdata.id();
+        let data = Box::new_in(
+            data,
+            MetricsAlloc::from_metrics(mc.metrics().clone()).with_kind(kind),
+        );
+        let mut user_values =
+            Vec::with_capacity_in(num_user_values, MetricsAlloc::new(mc).with_kind(kind));
+        user_values.resize(num_user_values, Value::nil());
         let state = UserdataState {
-            data: Box::new(data),
-            user_values: vec![Value::nil(); num_user_values],
+            data,
+            user_values,
             metatable: None,
+            finalizer_marked: false,
         };
         Userdata(Gc::new(mc, RefLock::new(state)))
     }
@@ -30,8 +44,16 @@ impl<'gc> Userdata<'gc> {
         self.0.borrow().metatable
     }
 
-    pub fn set_metatable(self, mc: &Mutation<'gc>, mt: Option<Table<'gc>>) {
-        self.0.borrow_mut(mc).metatable = mt;
+    /// Replace the metatable. As with tables, the userdata is marked for
+    /// finalization only if `mt` has a `__gc` field at this point.
+    pub fn set_metatable(self, ctx: Context<'gc>, mt: Option<Table<'gc>>) {
+        let has_gc = mt.is_some_and(|t| !t.raw_get(Value::string(ctx.symbols().mm_gc)).is_nil());
+        let mut state = self.0.borrow_mut(ctx.mutation());
+        state.metatable = mt;
+        if has_gc && !state.finalizer_marked {
+            state.finalizer_marked = true;
+            ctx.finalizers().mark_userdata(ctx.mutation(), self);
+        }
     }
 
     /// Borrow the boxed payload as `&T` for the duration of `f`, returning
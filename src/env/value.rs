@@ -1,5 +1,25 @@
+//! `Value` is a single NaN-boxed 64-bit word.
+//!
+//! Every float other than a NaN is stored as its own bit pattern. NaNs are
+//! canonicalized to one quiet NaN, which frees the negative quiet-NaN space
+//! from `0xFFF8_0000_0000_0000` upwards for everything else: the top 16 bits
+//! select a tag and the low 48 bits hold its payload.
+//!
+//! | top 16 bits | payload                                        |
+//! |-------------|------------------------------------------------|
+//! | `0xFFF8`    | `0` nil, `1` false, `2` true                   |
+//! | `0xFFF9`    | integer in `[-2^47, 2^47)`, sign-extended      |
+//! | `0xFFFA`    | `Gc<i64>` holding an integer outside that range |
+//! | `0xFFFB`    | `Gc` pointer to a string                       |
+//! | `0xFFFC`    | `Gc` pointer to a table                        |
+//! | `0xFFFD`    | `Gc` pointer to a function                     |
+//! | `0xFFFE`    | `Gc` pointer to a thread                       |
+//! | `0xFFFF`    | `Gc` pointer to a userdata                     |
+//!
+//! An integer is boxed exactly when it does not fit inline, so two integer
+//! values are equal either bitwise or, when both are boxed, by contents.
+
 use core::hash::{Hash, Hasher};
-use std::hint;
 use std::marker::PhantomData;
 
 use crate::dmm::{Collect, Gc, collect::Trace};
@@ -8,6 +28,7 @@ use crate::env::string::LuaString;
 use crate::env::table::Table;
 use crate::env::thread::Thread;
 use crate::env::userdata::Userdata;
+use crate::lua::Context;
 
 #[derive(Clone, Copy, Collect, PartialEq, Eq)]
 #[collect(internal, require_static)]
@@ -24,173 +45,273 @@ pub enum ValueKind {
     Userdata,
 }
 
-#[derive(Clone, Copy, PartialEq)]
+const TAG_SHIFT: u32 = 48;
+const PAYLOAD_MASK: u64 = (1 << TAG_SHIFT) - 1;
+
+const TAG_NIL_BOOLEAN: u64 = 0xFFF8;
+const TAG_INTEGER: u64 = 0xFFF9;
+const TAG_BOXED_INTEGER: u64 = 0xFFFA;
+const TAG_STRING: u64 = 0xFFFB;
+const TAG_TABLE: u64 = 0xFFFC;
+const TAG_FUNCTION: u64 = 0xFFFD;
+const TAG_THREAD: u64 = 0xFFFE;
+const TAG_USERDATA: u64 = 0xFFFF;
+
+/// The lowest tagged word; everything below it is a float.
+const FIRST_TAGGED: u64 = TAG_NIL_BOOLEAN << TAG_SHIFT;
+
+const NIL: u64 = FIRST_TAGGED;
+const FALSE: u64 = FIRST_TAGGED | 1;
+const TRUE: u64 = FIRST_TAGGED | 2;
+
+/// The single NaN every float NaN is stored as.
+const CANONICAL_NAN: u64 = 0x7FF8_0000_0000_0000;
+
+const INLINE_INT_MIN: i64 = -(1 << (TAG_SHIFT - 1));
+const INLINE_INT_MAX: i64 = (1 << (TAG_SHIFT - 1)) - 1;
+
+#[derive(Clone, Copy)]
+#[repr(transparent)]
 pub struct Value<'gc> {
-    kind: ValueKind,
-    data: u64,
+    bits: u64,
     _marker: PhantomData<&'gc ()>,
 }
 
 impl<'gc> Value<'gc> {
-    pub fn nil() -> Self {
+    #[inline(always)]
+    const fn from_bits(bits: u64) -> Self {
         Self {
-            kind: ValueKind::Nil,
-            data: 0,
+            bits,
             _marker: PhantomData,
         }
     }
 
+    #[inline(always)]
+    fn tag(self) -> u64 {
+        self.bits >> TAG_SHIFT
+    }
+
+    #[inline(always)]
+    fn tagged<T>(tag: u64, ptr: *const T) -> Self {
+        let addr = ptr as usize as u64;
+        debug_assert!(addr & !PAYLOAD_MASK == 0, "pointer does not fit in 48 bits");
+        Self::from_bits(tag << TAG_SHIFT | addr)
+    }
+
+    /// The pointer payload of a value known to carry `tag`.
+    #[inline(always)]
+    fn pointer<T>(self, tag: u64) -> Option<*const T> {
+        if self.tag() != tag {
+            return None;
+        }
+
+        Some((self.bits & PAYLOAD_MASK) as usize as *const T)
+    }
+
+    pub fn nil() -> Self {
+        Self::from_bits(NIL)
+    }
+
     pub fn is_nil(&self) -> bool {
-        self.kind == ValueKind::Nil
+        self.bits == NIL
+    }
+
+    /// Integer or float.
+    pub fn is_number(&self) -> bool {
+        self.bits < FIRST_TAGGED || matches!(self.tag(), TAG_INTEGER | TAG_BOXED_INTEGER)
     }
 
     pub fn boolean(v: bool) -> Self {
-        Self {
-            kind: ValueKind::Boolean,
-            data: v as u64,
-            _marker: PhantomData,
-        }
+        Self::from_bits(if v { TRUE } else { FALSE })
     }
 
     pub fn get_boolean(self) -> Option<bool> {
-        if self.kind != ValueKind::Boolean {
-            return None;
+        match self.bits {
+            FALSE => Some(false),
+            TRUE => Some(true),
+            _ => None,
         }
+    }
 
-        Some(match self.data {
-            0 => false,
-            1 => true,
-            _ => unsafe { hint::unreachable_unchecked() },
-        })
+    /// Integers outside the 48-bit inline range are boxed, which allocates.
+    #[inline(always)]
+    pub fn integer(ctx: Context<'gc>, v: i64) -> Self {
+        match Self::inline_integer(v) {
+            Some(value) => value,
+            None => Self::boxed_integer(ctx, v),
+        }
     }
 
-    pub fn integer(v: i64) -> Self {
-        Self {
-            kind: ValueKind::Integer,
-            data: v as u64,
-            _marker: PhantomData,
+    /// An integer known to fit the 48-bit inline range, such as a length, an
+    /// index or a small constant. Never allocates; panics if `v` doesn't fit,
+    /// so use [`Value::integer`] for arbitrary results.
+    #[inline(always)]
+    #[track_caller]
+    pub fn small_integer(v: i64) -> Self {
+        Self::inline_integer(v).expect("integer outside the inline range")
+    }
+
+    /// `v` as a value, if it fits without boxing.
+    #[inline(always)]
+    pub fn inline_integer(v: i64) -> Option<Self> {
+        if !(INLINE_INT_MIN..=INLINE_INT_MAX).contains(&v) {
+            return None;
         }
+
+        Some(Self::from_bits(
+            TAG_INTEGER << TAG_SHIFT | (v as u64 & PAYLOAD_MASK),
+        ))
     }
 
+    #[cold]
+    #[inline(never)]
+    fn boxed_integer(ctx: Context<'gc>, v: i64) -> Self {
+        Self::from_boxed_integer(Gc::new(ctx.mutation(), v))
+    }
+
+    pub(crate) fn from_boxed_integer(v: Gc<'gc, i64>) -> Self {
+        Self::tagged(TAG_BOXED_INTEGER, Gc::as_ptr(v))
+    }
+
+    /// The allocation behind an integer too wide to store inline.
+    pub(crate) fn get_boxed_integer(self) -> Option<Gc<'gc, i64>> {
+        self.pointer(TAG_BOXED_INTEGER)
+            .map(|ptr| unsafe { Gc::from_ptr(ptr) })
+    }
+
+    #[inline(always)]
     pub fn get_integer(self) -> Option<i64> {
-        if self.kind != ValueKind::Integer {
+        // Both integer tags in one comparison, so floats fall through fast.
+        if self.tag().wrapping_sub(TAG_INTEGER) > TAG_BOXED_INTEGER - TAG_INTEGER {
             return None;
         }
 
-        Some(self.data as i64)
+        if let Some(v) = self.get_inline_integer() {
+            return Some(v);
+        }
+
+        Some(self.unbox_integer())
     }
 
-    pub fn float(v: f64) -> Self {
-        Self {
-            kind: ValueKind::Float,
-            data: v.to_bits(),
-            _marker: PhantomData,
+    /// The integer, if it's stored inline. A single tag check, for fast
+    /// paths that leave boxed integers to a slower fallback.
+    #[inline(always)]
+    pub(crate) fn get_inline_integer(self) -> Option<i64> {
+        if self.tag() != TAG_INTEGER {
+            return None;
         }
+
+        Some(((self.bits << (64 - TAG_SHIFT)) as i64) >> (64 - TAG_SHIFT))
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn unbox_integer(self) -> i64 {
+        unsafe { *Gc::from_ptr((self.bits & PAYLOAD_MASK) as usize as *const i64) }
     }
 
+    #[inline(always)]
+    pub fn float(v: f64) -> Self {
+        // Only negative quiet NaNs reach into the tagged range; every
+        // other bit pattern, other NaNs included, is already a valid float.
+        let bits = v.to_bits();
+        Self::from_bits(if bits >= FIRST_TAGGED {
+            CANONICAL_NAN
+        } else {
+            bits
+        })
+    }
+
+    #[inline(always)]
     pub fn get_float(self) -> Option<f64> {
-        if self.kind != ValueKind::Float {
+        if self.bits >= FIRST_TAGGED {
             return None;
         }
 
-        Some(f64::from_bits(self.data))
+        Some(f64::from_bits(self.bits))
     }
 
     pub fn string(v: LuaString<'gc>) -> Self {
-        Self {
-            kind: ValueKind::String,
-            data: Gc::as_ptr(v.inner()) as usize as u64,
-            _marker: PhantomData,
-        }
+        Self::tagged(TAG_STRING, Gc::as_ptr(v.inner()))
     }
 
     pub fn get_string(self) -> Option<LuaString<'gc>> {
-        if self.kind != ValueKind::String {
-            return None;
-        }
-
-        let ptr = unsafe { Gc::from_ptr(self.data as usize as *const _) };
-        Some(LuaString::from_inner(ptr))
+        self.pointer(TAG_STRING)
+            .map(|ptr| LuaString::from_inner(unsafe { Gc::from_ptr(ptr) }))
     }
 
     pub fn table(v: Table<'gc>) -> Self {
-        Self {
-            kind: ValueKind::Table,
-            data: Gc::as_ptr(v.inner()) as usize as u64,
-            _marker: PhantomData,
-        }
+        Self::tagged(TAG_TABLE, Gc::as_ptr(v.inner()))
     }
 
     pub fn get_table(self) -> Option<Table<'gc>> {
-        if self.kind != ValueKind::Table {
-            return None;
-        }
-
-        let ptr = unsafe { Gc::from_ptr(self.data as usize as *const _) };
-        Some(Table::from_inner(ptr))
+        self.pointer(TAG_TABLE)
+            .map(|ptr| Table::from_inner(unsafe { Gc::from_ptr(ptr) }))
     }
 
     pub fn function(v: Function<'gc>) -> Self {
-        Self {
-            kind: ValueKind::Function,
-            data: Gc::as_ptr(v.inner()) as usize as u64,
-            _marker: PhantomData,
-        }
+        Self::tagged(TAG_FUNCTION, Gc::as_ptr(v.inner()))
     }
 
     pub fn get_function(self) -> Option<Function<'gc>> {
-        if self.kind != ValueKind::Function {
-            return None;
-        }
-
-        let ptr = unsafe { Gc::from_ptr(self.data as usize as *const _) };
-        Some(Function::from_inner(ptr))
+        self.pointer(TAG_FUNCTION)
+            .map(|ptr| Function::from_inner(unsafe { Gc::from_ptr(ptr) }))
     }
 
     pub fn thread(v: Thread<'gc>) -> Self {
-        Self {
-            kind: ValueKind::Thread,
-            data: Gc::as_ptr(v.inner()) as usize as u64,
-            _marker: PhantomData,
-        }
+        Self::tagged(TAG_THREAD, Gc::as_ptr(v.inner()))
     }
 
     pub fn get_thread(self) -> Option<Thread<'gc>> {
-        if self.kind != ValueKind::Thread {
-            return None;
-        }
-
-        let ptr = unsafe { Gc::from_ptr(self.data as usize as *const _) };
-        Some(Thread::from_inner(ptr))
+        self.pointer(TAG_THREAD)
+            .map(|ptr| Thread::from_inner(unsafe { Gc::from_ptr(ptr) }))
     }
 
     pub fn userdata(v: Userdata<'gc>) -> Self {
-        Self {
-            kind: ValueKind::Userdata,
-            data: Gc::as_ptr(v.inner()) as usize as u64,
-            _marker: PhantomData,
-        }
+        Self::tagged(TAG_USERDATA, Gc::as_ptr(v.inner()))
     }
 
     pub fn get_userdata(self) -> Option<Userdata<'gc>> {
-        if self.kind != ValueKind::Userdata {
-            return None;
-        }
-
-        let ptr = unsafe { Gc::from_ptr(self.data as usize as *const _) };
-        Some(Userdata::from_inner(ptr))
+        self.pointer(TAG_USERDATA)
+            .map(|ptr| Userdata::from_inner(unsafe { Gc::from_ptr(ptr) }))
     }
 
     pub fn is_falsy(&self) -> bool {
-        self.kind == ValueKind::Nil || self.get_boolean() == Some(false)
+        self.bits == NIL || self.bits == FALSE
     }
 
     pub fn kind(self) -> ValueKind {
-        self.kind
+        if self.bits < FIRST_TAGGED {
+            return ValueKind::Float;
+        }
+
+        match self.tag() {
+            TAG_NIL_BOOLEAN if self.bits == NIL => ValueKind::Nil,
+            TAG_NIL_BOOLEAN => ValueKind::Boolean,
+            TAG_INTEGER | TAG_BOXED_INTEGER => ValueKind::Integer,
+            TAG_STRING => ValueKind::String,
+            TAG_TABLE => ValueKind::Table,
+            TAG_FUNCTION => ValueKind::Function,
+            TAG_THREAD => ValueKind::Thread,
+            _ => ValueKind::Userdata,
+        }
+    }
+
+    /// The type-erased allocation behind a value that weak tables may
+    /// drop. Strings and boxed integers are excluded: Lua treats them as
+    /// values, never clearing them from weak tables.
+    pub(crate) fn weak_object(self) -> Option<Gc<'gc, ()>> {
+        match self.kind() {
+            ValueKind::Table => self.get_table().map(|t| Gc::erase(t.inner())),
+            ValueKind::Function => self.get_function().map(|f| Gc::erase(f.inner())),
+            ValueKind::Thread => self.get_thread().map(|t| Gc::erase(t.inner())),
+            ValueKind::Userdata => self.get_userdata().map(|u| Gc::erase(u.inner())),
+            _ => None,
+        }
     }
 
     pub fn type_name(&self) -> &'static str {
-        match self.kind {
+        match self.kind() {
             ValueKind::Nil => "nil",
             ValueKind::Boolean => "boolean",
             ValueKind::Integer | ValueKind::Float => "number",
@@ -203,11 +324,28 @@ impl<'gc> Value<'gc> {
     }
 }
 
+impl<'gc> PartialEq for Value<'gc> {
+    #[inline(always)]
+    fn eq(&self, other: &Self) -> bool {
+        if self.bits == other.bits {
+            return true;
+        }
+
+        match (self.get_boxed_integer(), other.get_boxed_integer()) {
+            (Some(a), Some(b)) => *a == *b,
+            _ => false,
+        }
+    }
+}
+
 impl<'gc> Eq for Value<'gc> {}
 
 impl<'gc> Hash for Value<'gc> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        state.write_u64(self.data);
+        match self.get_boxed_integer() {
+            Some(v) => state.write_u64(*v as u64),
+            None => state.write_u64(self.bits),
+        }
     }
 }
 
@@ -220,14 +358,19 @@ pub(crate) fn value_hash(v: Value<'_>) -> u64 {
 unsafe impl<'gc> Collect<'gc> for Value<'gc> {
     #[inline]
     fn trace<T: Trace<'gc>>(&self, cc: &mut T) {
+        if self.bits < FIRST_TAGGED {
+            return;
+        }
+
         unsafe {
-            match self.kind {
-                ValueKind::String => self.get_string().unwrap_unchecked().trace(cc),
-                ValueKind::Table => self.get_table().unwrap_unchecked().trace(cc),
-                ValueKind::Function => self.get_function().unwrap_unchecked().trace(cc),
-                ValueKind::Thread => self.get_thread().unwrap_unchecked().trace(cc),
-                ValueKind::Userdata => self.get_userdata().unwrap_unchecked().trace(cc),
-                _ => (),
+            match self.tag() {
+                TAG_NIL_BOOLEAN | TAG_INTEGER => (),
+                TAG_BOXED_INTEGER => self.get_boxed_integer().unwrap_unchecked().trace(cc),
+                TAG_STRING => self.get_string().unwrap_unchecked().trace(cc),
+                TAG_TABLE => self.get_table().unwrap_unchecked().trace(cc),
+                TAG_FUNCTION => self.get_function().unwrap_unchecked().trace(cc),
+                TAG_THREAD => self.get_thread().unwrap_unchecked().trace(cc),
+                _ => self.get_userdata().unwrap_unchecked().trace(cc),
             }
         }
     }
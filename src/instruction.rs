@@ -5,7 +5,7 @@ type ConstantIndex = u16;
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 #[repr(align(8))]
-#[allow(clippy::upper_case_acronyms)]
+#[allow(clippy::upper_case_acronyms, non_camel_case_types)]
 pub enum Instruction {
     MOVE {
         dst: Register,
@@ -36,7 +36,7 @@ pub enum Instruction {
         idx: UpvalueIndex,
         /// Index into the prototype's `ic_table` reserved for this
         /// call site. One IC slot is allocated per emitted GETTABUP/
-        /// SETTABUP/GETFIELD/SETFIELD; sites are not deduped.
+        /// SETTABUP/GETFIELD/SETFIELD/SELF; sites are not deduped.
         ic_idx: u16,
         key: ConstantIndex,
     },
@@ -60,6 +60,20 @@ pub enum Instruction {
         key: Register,
     },
 
+    /// `R[dst] = R[table][idx]` for an integer constant key.
+    GETI {
+        dst: Register,
+        table: Register,
+        idx: u16,
+    },
+
+    /// `R[table][idx] = R[src]` for an integer constant key.
+    SETI {
+        src: Register,
+        table: Register,
+        idx: u16,
+    },
+
     GETFIELD {
         dst: Register,
         table: Register,
@@ -75,14 +89,12 @@ pub enum Instruction {
     },
 
     /// Method-call setup: `R[dst] = R[object][K[key_idx]]; R[dst+1] = R[object]`.
-    /// Backs `obj:m(...)` codegen. Falls back to the slow path on
-    /// `__index` when the direct lookup is nil. No inline cache —
-    /// caching this site needs a wider IC payload than the current
-    /// fixed-width opcode encoding can carry; revisit when bytecode
-    /// supports variable-width instructions.
+    /// Backs `obj:m(...)` codegen. Like `GETFIELD`, the inline cache at
+    /// `ic_idx` also covers methods found one `__index` table away.
     SELF {
         dst: Register,
         object: Register,
+        ic_idx: u16,
         key_idx: ConstantIndex,
     },
 
@@ -162,6 +174,89 @@ pub enum Instruction {
         rhs: Register,
     },
 
+    /// `R[dst] = R[lhs] + imm`.
+    ADDI {
+        dst: Register,
+        lhs: Register,
+        imm: i16,
+    },
+
+    /// `R[dst] = R[lhs] + K[key]`; like the other `K` forms, `K[key]` is a
+    /// number.
+    ADDK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    SUBK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    MULK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    MODK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    POWK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    DIVK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    IDIVK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    BANDK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    BORK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    BXORK {
+        dst: Register,
+        lhs: Register,
+        key: ConstantIndex,
+    },
+
+    /// `R[dst] = R[lhs] >> imm`.
+    SHRI {
+        dst: Register,
+        lhs: Register,
+        imm: i16,
+    },
+
+    /// `R[dst] = imm << R[rhs]`; the immediate is the *left* operand.
+    SHLI {
+        dst: Register,
+        rhs: Register,
+        imm: i16,
+    },
+
     UNM {
         dst: Register,
         src: Register,
@@ -218,6 +313,48 @@ pub enum Instruction {
         inverted: bool,
     },
 
+    /// `if (R[lhs] == K[key]) != inverted then skip next instruction`.
+    EQK {
+        lhs: Register,
+        key: ConstantIndex,
+        inverted: bool,
+    },
+
+    /// `if (R[lhs] == imm) != inverted then skip next instruction`.
+    EQI {
+        lhs: Register,
+        imm: i16,
+        inverted: bool,
+    },
+
+    /// `if (R[lhs] < imm) != inverted then skip next instruction`.
+    LTI {
+        lhs: Register,
+        imm: i16,
+        inverted: bool,
+    },
+
+    /// `if (R[lhs] <= imm) != inverted then skip next instruction`.
+    LEI {
+        lhs: Register,
+        imm: i16,
+        inverted: bool,
+    },
+
+    /// `if (R[lhs] > imm) != inverted then skip next instruction`.
+    GTI {
+        lhs: Register,
+        imm: i16,
+        inverted: bool,
+    },
+
+    /// `if (R[lhs] >= imm) != inverted then skip next instruction`.
+    GEI {
+        lhs: Register,
+        imm: i16,
+        inverted: bool,
+    },
+
     TEST {
         src: Register,
         inverted: bool,
@@ -308,12 +445,173 @@ pub enum Instruction {
     NOP,
 
     STOP,
+
+    // Quickened forms. The compiler never emits these: the interpreter
+    // rewrites a generic instruction to one of them in place once it has
+    // seen the operand types it specializes for, and rewrites it back when
+    // its guard fails. See [`Instruction::generic`].
+    /// `ADD` of two inline integers. Boxed integers stay on the generic
+    /// form, so the guard is a single tag check per operand.
+    ADD_II {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+
+    /// `ADD` of two floats.
+    ADD_FF {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+
+    SUB_II {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+
+    SUB_FF {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+
+    MUL_II {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+
+    MUL_FF {
+        dst: Register,
+        lhs: Register,
+        rhs: Register,
+    },
+
+    /// `LT` of two inline integers.
+    LT_II {
+        lhs: Register,
+        rhs: Register,
+        inverted: bool,
+    },
+
+    /// `LT` of two floats.
+    LT_FF {
+        lhs: Register,
+        rhs: Register,
+        inverted: bool,
+    },
+
+    LE_II {
+        lhs: Register,
+        rhs: Register,
+        inverted: bool,
+    },
+
+    LE_FF {
+        lhs: Register,
+        rhs: Register,
+        inverted: bool,
+    },
+
+    /// `FORLOOP` whose control values are inline integers.
+    FORLOOP_I {
+        base: Register,
+        offset: i32,
+    },
+
+    /// `FORLOOP` whose control values are floats.
+    FORLOOP_F {
+        base: Register,
+        offset: i32,
+    },
+
+    /// `GETTABLE` of a table by a positive integer key, which lives in the
+    /// table's array part.
+    GETTABLE_ARRAY {
+        dst: Register,
+        table: Register,
+        key: Register,
+    },
 }
 
 impl Instruction {
     pub fn discriminant(self) -> u8 {
         unsafe { *<*const _>::from(&self).cast::<u8>() }
     }
+
+    /// The instruction a quickened form specializes, as the compiler
+    /// emitted it. Other instructions are returned unchanged.
+    pub fn generic(self) -> Instruction {
+        use Instruction::*;
+        match self {
+            ADD_II { dst, lhs, rhs } | ADD_FF { dst, lhs, rhs } => ADD { dst, lhs, rhs },
+            SUB_II { dst, lhs, rhs } | SUB_FF { dst, lhs, rhs } => SUB { dst, lhs, rhs },
+            MUL_II { dst, lhs, rhs } | MUL_FF { dst, lhs, rhs } => MUL { dst, lhs, rhs },
+            LT_II { lhs, rhs, inverted } | LT_FF { lhs, rhs, inverted } => {
+                LT { lhs, rhs, inverted }
+            }
+            LE_II { lhs, rhs, inverted } | LE_FF { lhs, rhs, inverted } => {
+                LE { lhs, rhs, inverted }
+            }
+            FORLOOP_I { base, offset } | FORLOOP_F { base, offset } => FORLOOP { base, offset },
+            GETTABLE_ARRAY { dst, table, key } => GETTABLE { dst, table, key },
+            other => other,
+        }
+    }
+
+    /// The single register this instruction writes, if it writes exactly
+    /// one. Calls, loops, `SELF` and `VARARG` write ranges and aren't
+    /// covered.
+    pub fn dst(self) -> Option<Register> {
+        use Instruction::*;
+        match self.generic() {
+            MOVE { dst, .. }
+            | LOAD { dst, .. }
+            | GETUPVAL { dst, .. }
+            | GETTABUP { dst, .. }
+            | GETTABLE { dst, .. }
+            | GETFIELD { dst, .. }
+            | GETI { dst, .. }
+            | NEWTABLE { dst }
+            | ADD { dst, .. }
+            | SUB { dst, .. }
+            | MUL { dst, .. }
+            | MOD { dst, .. }
+            | POW { dst, .. }
+            | DIV { dst, .. }
+            | IDIV { dst, .. }
+            | BAND { dst, .. }
+            | BOR { dst, .. }
+            | BXOR { dst, .. }
+            | SHL { dst, .. }
+            | SHR { dst, .. }
+            | ADDI { dst, .. }
+            | ADDK { dst, .. }
+            | SUBK { dst, .. }
+            | MULK { dst, .. }
+            | MODK { dst, .. }
+            | POWK { dst, .. }
+            | DIVK { dst, .. }
+            | IDIVK { dst, .. }
+            | BANDK { dst, .. }
+            | BORK { dst, .. }
+            | BXORK { dst, .. }
+            | SHRI { dst, .. }
+            | SHLI { dst, .. }
+            | UNM { dst, .. }
+            | BNOT { dst, .. }
+            | NOT { dst, .. }
+            | LEN { dst, .. }
+            | CONCAT { dst, .. }
+            | TESTSET { dst, .. }
+            | CLOSURE { dst, .. }
+            | VARARGGET { dst, .. } => Some(dst),
+            LFALSESKIP { src } => Some(src),
+            _ => None,
+        }
+    }
 }
 
 /// Describes how to capture an upvalue when creating a closure.
@@ -17,7 +17,8 @@ pub mod vm;
 
 pub use compiler::format::format_prototype;
 pub use lua::{
-    Context, Executor, ExecutorMode, Fetchable, FromMultiValue, FromValue, IntoMultiValue,
-    IntoValue, LoadError, Lua, RuntimeError, Stashable, StashedError, StashedExecutor,
-    StashedFunction, StashedTable, StashedThread, StashedValue, StepResult, TypeError,
+    Context, Executor, ExecutorMode, Fetchable, FromMultiValue, FromValue, HeapNode, HeapSnapshot,
+    IntoMultiValue, IntoValue, LoadError, Lua, MemoryKind, MemoryUsage, RuntimeError, StackLimits,
+    Stashable, StashedError, StashedExecutor, StashedFunction, StashedTable, StashedThread,
+    StashedValue, StepResult, TypeError,
 };
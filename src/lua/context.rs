@@ -5,9 +5,14 @@ use crate::dmm::{DynamicRootSet, Gc, Mutation, RefLock};
 use crate::env::function::{Function, UpvalueState};
 use crate::env::shape::Shape;
 use crate::env::string::Interner;
-use crate::env::{Symbols, Table, Thread, Value};
+use crate::env::table::WeakTables;
+use crate::env::thread::Threads;
+use crate::env::{LuaString, Symbols, Table, Thread, Value};
+use crate::lua::executor::Fuel;
+use crate::lua::finalizers::Finalizers;
+use crate::lua::gc::GcControl;
 use crate::lua::stash::{Fetchable, Stashable};
-use crate::lua::{LoadError, State};
+use crate::lua::{LoadError, StackLimits, State};
 use crate::parser;
 
 /// Cheap, copy handle into the arena mutation context.
@@ -61,6 +66,42 @@ impl<'gc> Context<'gc> {
         self.state.roots
     }
 
+    /// Registry of tables with a weak `__mode`, cleared of dead entries
+    /// at the end of every marking phase.
+    pub(crate) fn weak_tables(self) -> WeakTables<'gc> {
+        self.state.weak_tables
+    }
+
+    /// Registry of every thread, whose stacks are trimmed at the end of
+    /// every marking phase.
+    pub(crate) fn threads(self) -> Threads<'gc> {
+        self.state.threads
+    }
+
+    /// Objects marked for finalization and those waiting for their
+    /// `__gc` metamethod to run.
+    pub(crate) fn finalizers(self) -> Finalizers<'gc> {
+        self.state.finalizers
+    }
+
+    /// Collector settings and pending requests from `collectgarbage`.
+    #[inline(always)]
+    pub(crate) fn gc_control(self) -> &'gc GcControl {
+        &self.state.gc
+    }
+
+    /// Fuel left in the executor step in progress.
+    #[inline(always)]
+    pub(crate) fn fuel(self) -> &'gc Fuel {
+        &self.state.fuel
+    }
+
+    /// How far each thread's stack may grow.
+    #[inline(always)]
+    pub(crate) fn stack_limits(self) -> StackLimits {
+        self.state.stack_limits.get()
+    }
+
     pub(crate) fn interner(&self) -> &Interner<'gc> {
         &self.state.interner
     }
@@ -75,21 +116,27 @@ impl<'gc> Context<'gc> {
 
     /// Parse and compile `source` into a `Function`, with `_ENV` bound to the
     /// runtime's globals table.
-    pub fn load(self, source: &str, _name: Option<&str>) -> Result<Function<'gc>, LoadError> {
+    ///
+    /// `name` becomes the chunk name error messages are prefixed with, in
+    /// reference Lua's conventions: `=name` is shown as is, `@file` as a file
+    /// name and anything else as `[string "..."]`. Without one the source
+    /// text itself is used, like `luaL_loadstring`.
+    pub fn load(self, source: &str, name: Option<&str>) -> Result<Function<'gc>, LoadError> {
         let mut cache = NodeCache::new();
-        let (syntax, reports) = parser::parse(&mut cache, source);
+        let (syntax, lines, reports) = parser::parse(&mut cache, source);
         if !reports.is_empty() {
             return Err(LoadError::Parse(reports));
         }
         let root = parser::syntax::Root::new(syntax)
             .ok_or(LoadError::Internal("parser did not produce a Root node"))?;
-        let proto = compile_chunk(self, &root, cache.interner())?;
+        let chunk_name = LuaString::new(self, name.unwrap_or(source).as_bytes());
+        let proto = compile_chunk(self, &root, cache.interner(), &lines, Some(chunk_name))?;
 
         // Main chunk's upvalue 0 is _ENV. Pre-close it onto globals.
         let env_uv = Gc::new(
             self.mutation,
             RefLock::new(UpvalueState::Closed(Value::table(self.state.globals))),
         );
-        Ok(Function::new_lua(self.mutation, proto, Box::from([env_uv])))
+        Ok(Function::new_lua(self.mutation, proto, [env_uv]))
     }
 }
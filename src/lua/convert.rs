@@ -1,12 +1,14 @@
 //! Conversion traits for passing Rust values into and out of Lua calls.
 
+use std::alloc::Allocator;
+
 use crate::env::function::Function;
 use crate::env::{LuaString, Table, Thread, Value};
-use crate::lua::TypeError;
+use crate::lua::{Context, TypeError};
 
 /// A Rust value that lowers to a single `Value<'gc>`.
 pub trait IntoValue<'gc> {
-    fn into_value(self) -> Value<'gc>;
+    fn into_value(self, ctx: Context<'gc>) -> Value<'gc>;
 }
 
 /// A Rust value built from a single `Value<'gc>`.
@@ -16,7 +18,7 @@ pub trait FromValue<'gc>: Sized {
 
 /// An argument list pushed onto a Lua call's stack.
 pub trait IntoMultiValue<'gc> {
-    fn push_into(self, stack: &mut Vec<Value<'gc>>);
+    fn push_into<Al: Allocator>(self, ctx: Context<'gc>, stack: &mut Vec<Value<'gc>, Al>);
 }
 
 /// A Rust type constructed from the return-value sequence of a Lua call.
@@ -29,7 +31,7 @@ pub trait FromMultiValue<'gc>: Sized {
 // ---------------------------------------------------------------------------
 
 impl<'gc> IntoValue<'gc> for Value<'gc> {
-    fn into_value(self) -> Value<'gc> {
+    fn into_value(self, _ctx: Context<'gc>) -> Value<'gc> {
         self
     }
 }
@@ -41,7 +43,7 @@ impl<'gc> FromValue<'gc> for Value<'gc> {
 }
 
 impl<'gc> IntoValue<'gc> for bool {
-    fn into_value(self) -> Value<'gc> {
+    fn into_value(self, _ctx: Context<'gc>) -> Value<'gc> {
         Value::boolean(self)
     }
 }
@@ -56,8 +58,8 @@ impl<'gc> FromValue<'gc> for bool {
 }
 
 impl<'gc> IntoValue<'gc> for i64 {
-    fn into_value(self) -> Value<'gc> {
-        Value::integer(self)
+    fn into_value(self, ctx: Context<'gc>) -> Value<'gc> {
+        Value::integer(ctx, self)
     }
 }
 
@@ -79,7 +81,7 @@ impl<'gc> FromValue<'gc> for i64 {
 }
 
 impl<'gc> IntoValue<'gc> for f64 {
-    fn into_value(self) -> Value<'gc> {
+    fn into_value(self, _ctx: Context<'gc>) -> Value<'gc> {
         Value::float(self)
     }
 }
@@ -100,7 +102,7 @@ impl<'gc> FromValue<'gc> for f64 {
 }
 
 impl<'gc> IntoValue<'gc> for LuaString<'gc> {
-    fn into_value(self) -> Value<'gc> {
+    fn into_value(self, _ctx: Context<'gc>) -> Value<'gc> {
         Value::string(self)
     }
 }
@@ -115,7 +117,7 @@ impl<'gc> FromValue<'gc> for LuaString<'gc> {
 }
 
 impl<'gc> IntoValue<'gc> for Table<'gc> {
-    fn into_value(self) -> Value<'gc> {
+    fn into_value(self, _ctx: Context<'gc>) -> Value<'gc> {
         Value::table(self)
     }
 }
@@ -130,7 +132,7 @@ impl<'gc> FromValue<'gc> for Table<'gc> {
 }
 
 impl<'gc> IntoValue<'gc> for Function<'gc> {
-    fn into_value(self) -> Value<'gc> {
+    fn into_value(self, _ctx: Context<'gc>) -> Value<'gc> {
         Value::function(self)
     }
 }
@@ -145,7 +147,7 @@ impl<'gc> FromValue<'gc> for Function<'gc> {
 }
 
 impl<'gc> IntoValue<'gc> for Thread<'gc> {
-    fn into_value(self) -> Value<'gc> {
+    fn into_value(self, _ctx: Context<'gc>) -> Value<'gc> {
         Value::thread(self)
     }
 }
@@ -170,10 +172,10 @@ impl<'gc, T: FromValue<'gc>> FromValue<'gc> for Option<T> {
 }
 
 impl<'gc, T: IntoValue<'gc>> IntoValue<'gc> for Option<T> {
-    fn into_value(self) -> Value<'gc> {
+    fn into_value(self, ctx: Context<'gc>) -> Value<'gc> {
         match self {
             None => Value::nil(),
-            Some(t) => t.into_value(),
+            Some(t) => t.into_value(ctx),
         }
     }
 }
@@ -183,11 +185,11 @@ impl<'gc, T: IntoValue<'gc>> IntoValue<'gc> for Option<T> {
 // ---------------------------------------------------------------------------
 
 impl<'gc> IntoMultiValue<'gc> for () {
-    fn push_into(self, _stack: &mut Vec<Value<'gc>>) {}
+    fn push_into<Al: Allocator>(self, _ctx: Context<'gc>, _stack: &mut Vec<Value<'gc>, Al>) {}
 }
 
 impl<'gc> IntoMultiValue<'gc> for &[Value<'gc>] {
-    fn push_into(self, stack: &mut Vec<Value<'gc>>) {
+    fn push_into<Al: Allocator>(self, _ctx: Context<'gc>, stack: &mut Vec<Value<'gc>, Al>) {
         stack.extend_from_slice(self);
     }
 }
@@ -199,9 +201,9 @@ macro_rules! into_multi_tuple {
             $($t: IntoValue<'gc>,)+
         {
             #[allow(non_snake_case)]
-            fn push_into(self, stack: &mut Vec<Value<'gc>>) {
+            fn push_into<Al: Allocator>(self, ctx: Context<'gc>, stack: &mut Vec<Value<'gc>, Al>) {
                 let ($($t,)+) = self;
-                $(stack.push($t.into_value());)+
+                $(stack.push($t.into_value(ctx));)+
             }
         }
     };
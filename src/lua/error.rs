@@ -17,8 +17,9 @@ pub enum LoadError {
 
 #[derive(Debug, Error)]
 pub enum RuntimeError {
-    /// Internal VM error (type mismatch, missing metamethod, etc.). Carries
-    /// the offending PC for debugging.
+    /// Internal VM error. Carries the offending PC for debugging. Faults
+    /// a script can cause, such as arithmetic on nil or calling a
+    /// non-function, are raised as [`RuntimeError::Lua`] errors instead.
     #[error("vm error at pc {pc}")]
     Opcode { pc: usize },
     #[error("bad executor mode")]
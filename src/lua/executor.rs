@@ -1,13 +1,58 @@
+use std::cell::Cell;
+
 use crate::dmm::{Collect, Gc, RefLock};
 use crate::env::function::Function;
 use crate::env::thread::{CallSite, Frame, LuaFrame, PendingAction, ThreadStatus};
-use crate::env::{Thread, Value};
+use crate::env::{Error, Thread, Value};
 use crate::lua::RuntimeError;
 use crate::lua::context::Context;
 use crate::lua::convert::{FromMultiValue, IntoMultiValue};
 use crate::vm;
-use crate::vm::interp::{Continuation, ContinuationPayload};
-use crate::vm::sequence::CallbackAction;
+use crate::vm::close::CloseSequence;
+use crate::vm::interp::{CallChainError, CallTarget, Continuation, ContinuationPayload};
+use crate::vm::sequence::{BoxSequence, CallbackAction};
+
+/// Fuel a Lua call costs on top of what its body is charged.
+pub(crate) const CALL_FUEL: i64 = 1;
+/// Fuel a native call or sequence poll costs, standing in for the work it
+/// does outside the interpreter.
+pub(crate) const NATIVE_CALL_FUEL: i64 = 8;
+
+/// Instruction budget of the running [`Executor::step_with_fuel`].
+///
+/// Counting every dispatched instruction would slow the interpreter down,
+/// so fuel is charged where control can repeat: a backward jump costs the
+/// length of the loop body it closes, a call [`CALL_FUEL`] and a native
+/// call [`NATIVE_CALL_FUEL`]. Straight-line code in between is free, but is
+/// bounded by the size of the function it's in. The budget is checked at
+/// the same safe points the collector uses, so it may be overdrawn by a
+/// little.
+pub(crate) struct Fuel {
+    remaining: Cell<i64>,
+}
+
+impl Fuel {
+    pub(crate) fn new() -> Self {
+        Fuel {
+            remaining: Cell::new(i64::MAX),
+        }
+    }
+
+    fn set(&self, fuel: u64) {
+        self.remaining.set(i64::try_from(fuel).unwrap_or(i64::MAX));
+    }
+
+    #[inline(always)]
+    pub(crate) fn consume(&self, cost: i64) {
+        self.remaining
+            .set(self.remaining.get().saturating_sub(cost));
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_exhausted(&self) -> bool {
+        self.remaining.get() <= 0
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Eq, Collect)]
 #[collect(internal, require_static)]
@@ -32,8 +77,10 @@ pub enum StepResult<'gc> {
     /// [`Executor::resume`] (mode → `Normal`) and call `step` again.
     Yielded(Vec<Value<'gc>>),
     /// A `Sequence` returned `SequencePoll::Pending`, asking the host to
-    /// interleave other work / consult fuel. Mode stays `Normal`; call
-    /// `step` again to keep going.
+    /// interleave other work / consult fuel, or the driver stopped at a
+    /// safe point so the host can pay down GC debt or because the fuel
+    /// given to [`Executor::step_with_fuel`] ran out. Mode stays `Normal`;
+    /// call `step` again to keep going.
     Pending,
 }
 
@@ -78,16 +125,27 @@ impl<'gc> Executor<'gc> {
         function: Function<'gc>,
         args: A,
     ) -> Self {
-        let thread = ctx.main_thread();
+        Self::start_on(ctx, ctx.main_thread(), function, args)
+    }
+
+    /// [`Executor::start`] on `thread` instead of the main thread, leaving
+    /// whatever the main thread is running undisturbed. Any previous state
+    /// on `thread` is cleared.
+    pub fn start_on<A: IntoMultiValue<'gc>>(
+        ctx: Context<'gc>,
+        thread: Thread<'gc>,
+        function: Function<'gc>,
+        args: A,
+    ) -> Self {
         {
             let mc = ctx.mutation();
             let mut ts = thread.borrow_mut(mc);
             ts.stack.clear();
-            ts.frames.clear();
+            ts.clear_frames();
             ts.open_upvalues.clear();
             ts.tbc_slots.clear();
 
-            args.push_into(&mut ts.stack);
+            args.push_into(ctx, &mut ts.stack);
             ts.frames.push(Frame::Start(function));
             ts.status = ThreadStatus::Suspended;
         }
@@ -109,15 +167,37 @@ impl<'gc> Executor<'gc> {
     /// - [`StepResult::Done`] — the main thread completed; call `take_result`.
     /// - [`StepResult::Yielded(values)`] — the main thread yielded to the
     ///   host. Feed args back via [`Executor::resume`] then `step` again.
-    /// - [`StepResult::Pending`] — a `Sequence` returned `Pending`; the
-    ///   driver yielded so the host can interleave other work. Call
-    ///   `step` again to continue. (No fuel limit is enforced yet.)
+    /// - [`StepResult::Pending`] — a `Sequence` returned `Pending`, or the
+    ///   collector has accrued enough allocation debt that it should run
+    ///   before execution continues; the driver yielded so the host can
+    ///   interleave other work. Call `step` again to continue (from a fresh
+    ///   `Lua::enter`, so the debt gets paid).
     ///
     /// The hot path (Lua-only execution, sync natives) makes a single call
     /// into `run_thread` and exits. Coroutine resume / sequence pump cycles
     /// loop here until something terminal happens or values cross the host
     /// boundary.
     pub fn step(self, ctx: Context<'gc>) -> Result<StepResult<'gc>, RuntimeError> {
+        ctx.fuel().set(u64::MAX);
+        self.drive(ctx)
+    }
+
+    /// [`Executor::step`] with a budget of roughly `fuel` executed
+    /// instructions, after which it returns [`StepResult::Pending`] so a
+    /// host running many scripts cooperatively can't be starved by one of
+    /// them (`while true do end`). Fuel is charged at backward jumps and
+    /// calls, with an extra cost per native call, and doesn't carry over:
+    /// each call starts with a fresh budget.
+    pub fn step_with_fuel(
+        self,
+        ctx: Context<'gc>,
+        fuel: u64,
+    ) -> Result<StepResult<'gc>, RuntimeError> {
+        ctx.fuel().set(fuel);
+        self.drive(ctx)
+    }
+
+    fn drive(self, ctx: Context<'gc>) -> Result<StepResult<'gc>, RuntimeError> {
         {
             let inner = self.0.borrow();
             if inner.mode != ExecutorMode::Normal {
@@ -226,6 +306,20 @@ impl<'gc> Executor<'gc> {
                 }
             };
 
+            // A full collection on the way out of the last `enter` still
+            // left the heap over the memory limit: fail the running Lua
+            // code's allocation where `pcall` can see it. Natives refuse
+            // their own allocations up front with `check_memory`.
+            if matches!(kind, FrameKind::Lua)
+                && ctx.gc_control().take_out_of_memory()
+                && mc.metrics().exceeds_memory_limit(0)
+            {
+                ctx.gc_control().raised_out_of_memory();
+                let err = Error::from_str(ctx, "not enough memory");
+                top.borrow_mut(mc).frames.push(Frame::Error(err));
+                continue;
+            }
+
             match kind {
                 FrameKind::Lua => {
                     vm::interp::run_thread(ctx, top)
@@ -249,7 +343,7 @@ impl<'gc> Executor<'gc> {
                         _ => unreachable!(),
                     };
                     ts.stack.insert(0, Value::function(f));
-                    schedule_call_at(&mut ts, ctx, 0, f, 0)?;
+                    schedule_call_at(&mut ts, ctx, 0, 0)?;
                     if ts.frames.is_empty() && ts.pending_action.is_none() {
                         // Native entry returned `Return` synchronously;
                         // results sit at stack[0..] and the thread is
@@ -266,6 +360,16 @@ impl<'gc> Executor<'gc> {
                     unwind_error(self, ctx, top)?;
                 }
             }
+
+            // (5) Collector and fuel safe point. Garbage can only be
+            // collected outside the arena's mutation context, so once enough
+            // debt has built up, hand control back to the host (`Lua::enter`
+            // pays it down on the way out); likewise once the step's fuel is
+            // spent. The interpreter bails out of `run_thread` on the same
+            // conditions at backward jumps and calls.
+            if vm::interp::collection_due(ctx) || ctx.fuel().is_exhausted() {
+                return Ok(StepResult::Pending);
+            }
         }
     }
 
@@ -305,7 +409,7 @@ impl<'gc> Executor<'gc> {
         // them at stack[bottom..], replacing the previously-yielded
         // values.
         let mut buf: Vec<Value<'gc>> = Vec::new();
-        args.push_into(&mut buf);
+        args.push_into(ctx, &mut buf);
         {
             let mut ts = top.borrow_mut(mc);
             ts.stack.truncate(cs.bottom);
@@ -341,7 +445,7 @@ impl<'gc> Executor<'gc> {
 
         let values: Vec<Value<'gc>> = {
             let ts = thread.borrow();
-            ts.stack.clone()
+            ts.stack.to_vec()
         };
         let result = R::from_multi_value(&values).map_err(RuntimeError::from);
 
@@ -350,7 +454,7 @@ impl<'gc> Executor<'gc> {
             let mc = ctx.mutation();
             let mut ts = thread.borrow_mut(mc);
             ts.stack.clear();
-            ts.frames.clear();
+            ts.clear_frames();
             ts.open_upvalues.clear();
             ts.tbc_slots.clear();
             ts.status = ThreadStatus::Stopped;
@@ -386,11 +490,7 @@ fn apply_pending_action<'gc>(
         }
         CallbackAction::Sequence(seq) => {
             let mut ts = top.borrow_mut(mc);
-            ts.frames.push(Frame::Sequence {
-                seq,
-                call_site,
-                pending_error: None,
-            });
+            ts.push_sequence(seq, call_site, None);
         }
         CallbackAction::Call { function, then } => {
             let mut ts = top.borrow_mut(mc);
@@ -403,28 +503,20 @@ fn apply_pending_action<'gc>(
             // Frame::Error (see #2 in review.md) lands above this
             // sequence and the unwinder routes the error to it.
             if let Some(seq) = then {
-                ts.frames.push(Frame::Sequence {
-                    seq,
-                    call_site,
-                    pending_error: None,
-                });
+                ts.push_sequence(seq, call_site, None);
             }
             // Now schedule the call. The callback that produced `Call`
             // left its desired args at stack[bottom..] with no function
             // slot in front; insert the function so the layout matches
             // schedule_call_at's convention (function at slot, args
             // after).
-            ts.stack.insert(call_site.bottom, Value::function(function));
-            schedule_call_at(&mut ts, ctx, call_site.bottom, function, call_site.returns)?;
+            ts.stack.insert(call_site.bottom, function);
+            schedule_call_at(&mut ts, ctx, call_site.bottom, call_site.returns)?;
         }
         CallbackAction::Yield { then } => {
             let mut ts = top.borrow_mut(mc);
             if let Some(seq) = then {
-                ts.frames.push(Frame::Sequence {
-                    seq,
-                    call_site,
-                    pending_error: None,
-                });
+                ts.push_sequence(seq, call_site, None);
             }
             // Yielded values live at stack[bottom..]. Mark thread
             // suspended and stash where they are so the next pump can
@@ -439,11 +531,7 @@ fn apply_pending_action<'gc>(
             // Optional `then` fires when target yields/returns; install on
             // the resumer first so it's seen *after* the WaitThread frame.
             if let Some(seq) = then {
-                top.borrow_mut(mc).frames.push(Frame::Sequence {
-                    seq,
-                    call_site,
-                    pending_error: None,
-                });
+                top.borrow_mut(mc).push_sequence(seq, call_site, None);
             }
             schedule_thread_resume(exec, ctx, top, target, call_site.bottom, call_site)?;
         }
@@ -481,8 +569,10 @@ fn schedule_thread_resume<'gc>(
         let mut rs = resumer.borrow_mut(mc);
         rs.stack.drain(args_abs_bottom..).collect()
     };
+    let resume_depth = resumer.borrow().native_depth() + 1;
     {
         let mut ts = target.borrow_mut(mc);
+        ts.resume_depth = resume_depth;
         if matches!(ts.status, ThreadStatus::Suspended)
             && matches!(ts.frames.last(), Some(Frame::Start(_)))
         {
@@ -515,86 +605,119 @@ fn schedule_thread_resume<'gc>(
     Ok(())
 }
 
-/// Push a Lua/Native call frame for `function` whose function-slot is at
-/// `slot` (so args live at `slot+1..`). For Lua: a `LuaFrame` with `base =
-/// slot+1`. For Native: invoke synchronously and either land Return values
-/// at `slot..` or stash a pending action.
+/// Push a Lua/Native call frame for the value at `slot` (args live at
+/// `slot+1..`), following its `__call` chain like a Lua call would. For Lua:
+/// a `LuaFrame` with `base = slot+1`. For Native: invoke synchronously and
+/// either land Return values at `slot..` or stash a pending action. A value
+/// that isn't callable leaves a `Frame::Error`.
 fn schedule_call_at<'gc>(
     ts: &mut crate::env::thread::ThreadState<'gc>,
     ctx: Context<'gc>,
     slot: usize,
-    function: Function<'gc>,
     caller_returns: u8,
 ) -> Result<(), RuntimeError> {
-    if let Some(closure) = function.as_lua() {
-        let base = slot + 1;
-        let caller_provided = ts.stack.len().saturating_sub(base);
-        let num_params = closure.proto.num_params as usize;
-        let num_extras = if closure.proto.is_vararg {
-            caller_provided.saturating_sub(num_params) as u32
-        } else {
-            0
-        };
-        let needed = base + closure.proto.max_stack_size as usize;
-        if ts.stack.len() < needed {
-            ts.stack.resize(needed, Value::nil());
-        }
-        // Nil-fill fixed params the caller didn't supply.
-        for i in caller_provided..num_params {
-            ts.stack[base + i] = Value::nil();
-        }
-        ts.push_lua(LuaFrame {
-            closure,
-            base,
-            pc: 0,
-            num_results: caller_returns,
-            num_extras,
-            continuation: None,
-        });
-        Ok(())
-    } else {
-        // Native target. Drive synchronously; if it returns Return we land
-        // values at [slot..]; otherwise stash a new pending_action.
-        let nc = function
-            .as_native()
-            .expect("function is neither Lua nor Native");
-        let args_base = slot + 1;
-        let argc = ts.stack.len() - args_base;
-        let action = match vm::interp::invoke_native(ctx, ts, nc, args_base, argc) {
-            Ok(a) => a,
-            Err(e) => {
-                // Mirror op_call's native-error path: push Frame::Error so the
-                // executor unwinder can find the nearest Sequence catcher
-                // (e.g. a PCallSequence wrapping coroutine.resume). Returning
-                // Err here would short-circuit past any catcher pushed by
-                // apply_pending_action / pump_sequence before this call.
-                ts.frames.push(Frame::Error(e));
-                return Ok(());
-            }
-        };
-        match action {
-            CallbackAction::Return => {
-                // Move stack[args_base..] down to stack[slot..]. (Slot is
-                // where the function used to sit; the function itself is
-                // stored back in [slot] before the call by the caller.)
-                let retc = ts.stack.len() - args_base;
-                for i in 0..retc {
-                    ts.stack[slot + i] = ts.stack[args_base + i];
+    // Each `__call` hop prepends an argument, so pass a count `CALL`-style
+    // and let a large one fall back to the `thread.top` form.
+    let nargs = u8::try_from(ts.stack.len() - slot).unwrap_or(0);
+    if nargs == 0 {
+        ts.top = ts.stack.len();
+    }
+    let target = match vm::interp::resolve_call_chain(ctx, ts, slot, nargs) {
+        Ok((target, _)) => target,
+        Err(err) => {
+            let err = match err {
+                CallChainError::NotCallable => {
+                    let msg = format!("attempt to call a {} value", ts.stack[slot].type_name());
+                    Error::from_str(ctx, &msg)
                 }
-                ts.stack.truncate(slot + retc);
-                Ok(())
+                CallChainError::TooLong => vm::interp::call_chain_error(ctx),
+            };
+            ts.frames.push(Frame::Error(err));
+            return Ok(());
+        }
+    };
+    // A native calling into Lua (or another native) nests one level deeper;
+    // a Lua callee also needs room for its frame.
+    let (depth, needed) = match target {
+        CallTarget::Lua(closure) => (
+            ts.frames.len() + 1,
+            slot + 1 + closure.proto.max_stack_size as usize,
+        ),
+        CallTarget::Native(_) => (ts.frames.len(), ts.stack.len()),
+    };
+    if let Err(msg) = ctx.stack_limits().check(ts, depth, needed) {
+        ts.frames.push(Frame::Error(Error::from_str(ctx, msg)));
+        return Ok(());
+    }
+    match target {
+        CallTarget::Lua(closure) => {
+            let base = slot + 1;
+            let caller_provided = ts.stack.len().saturating_sub(base);
+            let num_params = closure.proto.num_params as usize;
+            let num_extras = if closure.proto.is_vararg {
+                caller_provided.saturating_sub(num_params) as u32
+            } else {
+                0
+            };
+            let needed = base + closure.proto.max_stack_size as usize;
+            if ts.stack.len() < needed {
+                ts.stack.resize(needed, Value::nil());
             }
-            other => {
-                ts.pending_action = Some(PendingAction {
-                    action: other,
-                    call_site: CallSite {
-                        bottom: args_base,
-                        func_idx: slot,
-                        returns: caller_returns,
-                        cont: None,
-                    },
-                });
-                Ok(())
+            // Nil-fill fixed params the caller didn't supply.
+            for i in caller_provided..num_params {
+                ts.stack[base + i] = Value::nil();
+            }
+            ts.push_lua(LuaFrame {
+                closure,
+                base,
+                pc: 0,
+                num_results: caller_returns,
+                num_extras,
+                continuation: None,
+            });
+            Ok(())
+        }
+        CallTarget::Native(nc) => {
+            // Native target. Drive synchronously; if it returns Return we land
+            // values at [slot..]; otherwise stash a new pending_action.
+            let args_base = slot + 1;
+            let argc = ts.stack.len() - args_base;
+            let action = match vm::interp::invoke_native(ctx, ts, nc, args_base, argc) {
+                Ok(a) => a,
+                Err(e) => {
+                    // Mirror op_call's native-error path: push Frame::Error so the
+                    // executor unwinder can find the nearest Sequence catcher
+                    // (e.g. a PCallSequence wrapping coroutine.resume). Returning
+                    // Err here would short-circuit past any catcher pushed by
+                    // apply_pending_action / pump_sequence before this call.
+                    ts.frames.push(Frame::Error(e));
+                    return Ok(());
+                }
+            };
+            match action {
+                CallbackAction::Return => {
+                    // Move stack[args_base..] down to stack[slot..]. (Slot is
+                    // where the function used to sit; the function itself is
+                    // stored back in [slot] before the call by the caller.)
+                    let retc = ts.stack.len() - args_base;
+                    for i in 0..retc {
+                        ts.stack[slot + i] = ts.stack[args_base + i];
+                    }
+                    ts.stack.truncate(slot + retc);
+                    Ok(())
+                }
+                other => {
+                    ts.pending_action = Some(PendingAction {
+                        action: other,
+                        call_site: CallSite {
+                            bottom: args_base,
+                            func_idx: slot,
+                            returns: caller_returns,
+                            cont: None,
+                        },
+                    });
+                    Ok(())
+                }
             }
         }
     }
@@ -623,6 +746,7 @@ fn pump_sequence<'gc>(
     // Pop the sequence frame and call poll/error.
     let (mut seq, call_site, pending_error) = {
         let mut ts = top.borrow_mut(mc);
+        ts.sequences -= 1;
         match ts.frames.pop() {
             Some(Frame::Sequence {
                 seq,
@@ -632,10 +756,12 @@ fn pump_sequence<'gc>(
             _ => unreachable!("pump_sequence: top wasn't Frame::Sequence"),
         }
     };
+    ctx.fuel().consume(NATIVE_CALL_FUEL);
     let poll_result = {
         let mut ts = top.borrow_mut(mc);
+        let ts = &mut *ts;
         let stack_view = crate::env::function::Stack::new(&mut ts.stack, call_site.bottom);
-        let exec = Execution::new(top);
+        let exec = Execution::new(top, &ts.frames, &ts.hook);
         if let Some(err) = pending_error {
             seq.error(ctx, exec, err, stack_view)
         } else {
@@ -644,11 +770,7 @@ fn pump_sequence<'gc>(
     };
     match poll_result {
         Ok(SequencePoll::Pending) => {
-            top.borrow_mut(mc).frames.push(Frame::Sequence {
-                seq,
-                call_site,
-                pending_error: None,
-            });
+            top.borrow_mut(mc).push_sequence(seq, call_site, None);
             return Ok(PumpOutcome::Pending);
         }
         Ok(SequencePoll::Return) => {
@@ -664,14 +786,10 @@ fn pump_sequence<'gc>(
             let abs_bottom = call_site.bottom + rel;
             let mut ts = top.borrow_mut(mc);
             // Re-push self to be re-polled with results at stack[bottom..].
-            ts.frames.push(Frame::Sequence {
-                seq,
-                call_site,
-                pending_error: None,
-            });
+            ts.push_sequence(seq, call_site, None);
             // Schedule the call: insert function at abs_bottom, args after.
-            ts.stack.insert(abs_bottom, Value::function(function));
-            schedule_call_at(&mut ts, ctx, abs_bottom, function, 0)?;
+            ts.stack.insert(abs_bottom, function);
+            schedule_call_at(&mut ts, ctx, abs_bottom, 0)?;
         }
         Ok(SequencePoll::TailCall(function)) => {
             // Sequence is done; the call's results must land at the
@@ -689,24 +807,14 @@ fn pump_sequence<'gc>(
                 }
                 ts.stack.truncate(new_args_base + argc);
             }
-            ts.stack[call_site.func_idx] = Value::function(function);
-            schedule_call_at(
-                &mut ts,
-                ctx,
-                call_site.func_idx,
-                function,
-                call_site.returns,
-            )?;
+            ts.stack[call_site.func_idx] = function;
+            schedule_call_at(&mut ts, ctx, call_site.func_idx, call_site.returns)?;
         }
         Ok(SequencePoll::Yield { bottom: rel }) => {
             let abs_bottom = call_site.bottom + rel;
             let mut ts = top.borrow_mut(mc);
             // Re-push self to be re-polled with resume-args at stack[bottom..].
-            ts.frames.push(Frame::Sequence {
-                seq,
-                call_site,
-                pending_error: None,
-            });
+            ts.push_sequence(seq, call_site, None);
             ts.yield_bottom = Some(CallSite {
                 bottom: abs_bottom,
                 func_idx: call_site.func_idx,
@@ -728,11 +836,7 @@ fn pump_sequence<'gc>(
             // sequence beneath the WaitThread and lands target's
             // eventual values at seq.bottom for the next poll.
             let abs_bottom = call_site.bottom + rel;
-            top.borrow_mut(mc).frames.push(Frame::Sequence {
-                seq,
-                call_site,
-                pending_error: None,
-            });
+            top.borrow_mut(mc).push_sequence(seq, call_site, None);
             schedule_thread_resume(exec, ctx, top, target, abs_bottom, call_site)?;
         }
         Ok(SequencePoll::TailResume(target)) => {
@@ -914,7 +1018,9 @@ fn apply_native_continuation<'gc>(
 }
 
 /// Walk a thread's frame stack popping Lua/Wait frames (closing upvalues
-/// at each `bottom`) until a `Sequence` frame can catch the error.
+/// at each `bottom`) until a `Sequence` frame can catch the error. A Lua
+/// frame with to-be-closed variables is replaced by a [`CloseSequence`]
+/// that receives the error first.
 ///
 /// On no-catcher: if the thread isn't the bottom of the executor's
 /// thread stack, route the error to the resumer's `Frame::WaitThread`
@@ -938,9 +1044,25 @@ fn unwind_error<'gc>(
                 Some(Frame::Lua(lf)) => {
                     let base = lf.base;
                     ts.frames.pop();
+                    let pending = vm::close::take_pending(&mut ts, base);
                     vm::interp::close_upvalues(mc, &mut ts, base);
-                    vm::interp::close_tbc_vars(mc, &mut ts, base);
                     ts.stack.truncate(base);
+                    if !pending.is_empty() {
+                        // The frame's to-be-closed variables see the error
+                        // and may replace it; unwinding resumes once the
+                        // sequence raises the final one.
+                        ts.push_sequence(
+                            BoxSequence::new(mc, CloseSequence::new(pending, 0)),
+                            CallSite {
+                                bottom: base,
+                                func_idx: base,
+                                returns: 0,
+                                cont: None,
+                            },
+                            Some(err),
+                        );
+                        return Ok(());
+                    }
                 }
                 Some(Frame::Sequence { .. }) => {
                     if let Some(Frame::Sequence { pending_error, .. }) = ts.frames.last_mut() {
@@ -0,0 +1,121 @@
+//! `__gc` bookkeeping. Tables and userdata whose metatable carried a
+//! `__gc` field when it was set are *marked for finalization* and held
+//! weakly here. When the collector finds one of them unreachable, it is
+//! resurrected and moved to the pending queue; `Lua::run_finalizers`
+//! later calls its `__gc` metamethod through an `Executor`.
+
+use crate::dmm::allocator_api::MetricsAlloc;
+use crate::dmm::{Collect, Finalization, Gc, GcWeak, Mutation, RefLock};
+use crate::env::table::TableState;
+use crate::env::userdata::UserdataState;
+use crate::env::{Table, Userdata, Value};
+
+#[derive(Clone, Copy, Collect)]
+#[collect(internal, no_drop)]
+pub(crate) struct Finalizers<'gc>(Gc<'gc, RefLock<FinalizersState<'gc>>>);
+
+#[derive(Collect)]
+#[collect(internal, no_drop)]
+struct FinalizersState<'gc> {
+    /// Objects marked for finalization, in marking order. Weak: being
+    /// marked never keeps an object alive.
+    marked: Vec<Finalizable<'gc>, MetricsAlloc<'gc>>,
+    /// Unreachable objects resurrected for their finalizer, run from the
+    /// back so the most recently marked object is finalized first.
+    pending: Vec<Value<'gc>, MetricsAlloc<'gc>>,
+}
+
+#[derive(Clone, Copy, Collect)]
+#[collect(internal, no_drop)]
+enum Finalizable<'gc> {
+    Table(GcWeak<'gc, RefLock<TableState<'gc>>>),
+    Userdata(GcWeak<'gc, RefLock<UserdataState<'gc>>>),
+}
+
+impl<'gc> Finalizable<'gc> {
+    fn is_dead(self, fc: &Finalization<'gc>) -> bool {
+        match self {
+            Finalizable::Table(t) => t.is_dead(fc),
+            Finalizable::Userdata(u) => u.is_dead(fc),
+        }
+    }
+
+    fn upgrade(self, mc: &Mutation<'gc>) -> Option<Value<'gc>> {
+        match self {
+            Finalizable::Table(t) => t.upgrade(mc).map(|t| Value::table(Table::from_inner(t))),
+            Finalizable::Userdata(u) => u
+                .upgrade(mc)
+                .map(|u| Value::userdata(Userdata::from_inner(u))),
+        }
+    }
+
+    /// Keep a dead object alive for this cycle and clear its mark, so a
+    /// finalizer that calls `setmetatable` again re-marks it.
+    fn resurrect(self, fc: &Finalization<'gc>) -> Option<Value<'gc>> {
+        match self {
+            Finalizable::Table(t) => t.resurrect(fc).map(|t| {
+                t.borrow_mut(fc).finalizer_marked = false;
+                Value::table(Table::from_inner(t))
+            }),
+            Finalizable::Userdata(u) => u.resurrect(fc).map(|u| {
+                u.borrow_mut(fc).finalizer_marked = false;
+                Value::userdata(Userdata::from_inner(u))
+            }),
+        }
+    }
+}
+
+impl<'gc> Finalizers<'gc> {
+    pub(crate) fn new(mc: &Mutation<'gc>) -> Self {
+        Finalizers(Gc::new(
+            mc,
+            RefLock::new(FinalizersState {
+                marked: Vec::new_in(MetricsAlloc::new(mc)),
+                pending: Vec::new_in(MetricsAlloc::new(mc)),
+            }),
+        ))
+    }
+
+    pub(crate) fn mark_table(self, mc: &Mutation<'gc>, table: Table<'gc>) {
+        let entry = Finalizable::Table(Gc::downgrade(table.inner()));
+        self.0.borrow_mut(mc).marked.push(entry);
+    }
+
+    pub(crate) fn mark_userdata(self, mc: &Mutation<'gc>, ud: Userdata<'gc>) {
+        let entry = Finalizable::Userdata(Gc::downgrade(ud.inner()));
+        self.0.borrow_mut(mc).marked.push(entry);
+    }
+
+    /// Resurrect every marked object the collector found unreachable and
+    /// queue it for finalization. Returns `true` if anything was queued,
+    /// in which case marking must resume before the arena is swept.
+    pub(crate) fn queue_dead(self, fc: &Finalization<'gc>) -> bool {
+        let mut state = self.0.borrow_mut(fc);
+        let FinalizersState { marked, pending } = &mut *state;
+        let before = pending.len();
+        marked.retain(|obj| {
+            if !obj.is_dead(fc) {
+                return true;
+            }
+            pending.extend(obj.resurrect(fc));
+            false
+        });
+        pending.len() != before
+    }
+
+    /// Queue every marked object regardless of reachability. Used when
+    /// the runtime is torn down, mirroring `lua_close`.
+    pub(crate) fn queue_all(self, mc: &Mutation<'gc>) {
+        let mut state = self.0.borrow_mut(mc);
+        let FinalizersState { marked, pending } = &mut *state;
+        pending.extend(marked.drain(..).filter_map(|obj| obj.upgrade(mc)));
+    }
+
+    pub(crate) fn has_pending(self) -> bool {
+        !self.0.borrow().pending.is_empty()
+    }
+
+    pub(crate) fn pop_pending(self, mc: &Mutation<'gc>) -> Option<Value<'gc>> {
+        self.0.borrow_mut(mc).pending.pop()
+    }
+}
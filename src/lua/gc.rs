@@ -0,0 +1,255 @@
+//! Collector controls shared between the host and `collectgarbage`.
+//!
+//! Lua code runs inside the arena's mutation context, where garbage can't
+//! be collected, so `collectgarbage("collect")` and `("step")` only leave a
+//! [`GcRequest`] here and suspend; `Lua::enter` services it on the way out.
+//! Everything else (`stop`, `restart`, the tuning options) takes effect
+//! immediately by changing this state or the arena's [`Pacing`].
+
+use std::cell::Cell;
+
+use crate::dmm::Collect;
+use crate::dmm::metrics::{CollectionMode, Metrics, Pacing};
+
+/// Allocation debt (in bytes of pacing "work") that must build up before
+/// the collector runs, unless `collectgarbage("incremental")` changes it.
+/// Paying off tiny amounts of debt after every `enter` would cost more
+/// than it reclaims, so `Lua::enter` and the executor's safe points both
+/// wait for at least this much.
+pub(crate) const COLLECTOR_GRANULARITY: f64 = 1024.0;
+
+/// Largest multiple of the default work factors `stepmul` may select. Any
+/// more and a byte could cost a whole unit of work, and the collector
+/// would no longer be guaranteed to outpace allocation.
+const MAX_WORK_SCALE: f64 = 1.7;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum GcMode {
+    Incremental,
+    Generational,
+}
+
+impl GcMode {
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            GcMode::Incremental => "incremental",
+            GcMode::Generational => "generational",
+        }
+    }
+}
+
+/// Collection work Lua code asked for, carried out by the host.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum GcRequest {
+    /// Run a full collection cycle.
+    Collect,
+    /// Perform this many bytes of collection work.
+    Step(usize),
+}
+
+#[derive(Collect)]
+#[collect(internal, require_static)]
+pub(crate) struct GcControl {
+    /// Cleared by `collectgarbage("stop")`: debt still accrues but is
+    /// never paid automatically.
+    running: Cell<bool>,
+    granularity: Cell<f64>,
+    mode: Cell<GcMode>,
+    /// `collectgarbage("incremental")` parameters, in Lua's units.
+    pause: Cell<u32>,
+    stepmul: Cell<u32>,
+    stepsize: Cell<u32>,
+    /// `collectgarbage("generational")` parameters, in Lua's units.
+    minormul: Cell<u32>,
+    majormul: Cell<u32>,
+    request: Cell<Option<GcRequest>>,
+    /// Whether the last serviced `Step` request finished a cycle.
+    step_finished: Cell<bool>,
+    /// Set when even a full collection couldn't bring the heap back under
+    /// the memory limit; the executor raises "not enough memory" in the
+    /// running code at its next step.
+    out_of_memory: Cell<bool>,
+    /// The heap's size after the last full collection that couldn't bring
+    /// it back under the memory limit. Another collection would fail the
+    /// same way, so being over the limit is no reason to run one until the
+    /// heap grows past this.
+    oom_floor: Cell<Option<usize>>,
+    /// Set when "not enough memory" has been raised since the last
+    /// collection for the memory limit. Once the error unwinds, what the
+    /// failed code allocated may be garbage, so that collection only
+    /// re-measures the heap instead of failing the running code again.
+    oom_raised: Cell<bool>,
+}
+
+impl GcControl {
+    pub(crate) fn new() -> Self {
+        GcControl {
+            running: Cell::new(true),
+            granularity: Cell::new(COLLECTOR_GRANULARITY),
+            mode: Cell::new(GcMode::Incremental),
+            // The values `Pacing::DEFAULT` corresponds to.
+            pause: Cell::new(150),
+            stepmul: Cell::new(100),
+            stepsize: Cell::new(10),
+            minormul: Cell::new(20),
+            majormul: Cell::new(100),
+            request: Cell::new(None),
+            step_finished: Cell::new(false),
+            out_of_memory: Cell::new(false),
+            oom_floor: Cell::new(None),
+            oom_raised: Cell::new(false),
+        }
+    }
+
+    /// Whether enough debt has built up for the host to pay it down, or
+    /// the heap has outgrown the memory limit (which calls for a
+    /// collection even while the collector is stopped).
+    #[inline(always)]
+    pub(crate) fn collection_due(&self, metrics: &Metrics) -> bool {
+        (self.running.get() && metrics.allocation_debt() > self.granularity.get())
+            || self.over_memory_limit(metrics)
+    }
+
+    /// Whether the heap is over the memory limit and has grown since a full
+    /// collection last failed to bring it back under.
+    #[inline(always)]
+    pub(crate) fn over_memory_limit(&self, metrics: &Metrics) -> bool {
+        metrics.exceeds_memory_limit(0)
+            && self.oom_floor.get().is_none_or(|floor| {
+                metrics.total_allocation() as f64 > floor as f64 + self.granularity.get()
+            })
+    }
+
+    /// Record how a full collection run for [`GcControl::over_memory_limit`]
+    /// went: if the heap is still over the limit and grew to get there, the
+    /// running code gets "not enough memory" at its next step.
+    pub(crate) fn after_limit_collection(&self, metrics: &Metrics) {
+        let oom = metrics.exceeds_memory_limit(0);
+        let raised = self.oom_raised.take();
+        self.out_of_memory.set(oom && !raised);
+        self.oom_floor.set(oom.then(|| metrics.total_allocation()));
+    }
+
+    /// Note that "not enough memory" was just raised, so the heap is
+    /// measured again by a full collection once the error has unwound.
+    pub(crate) fn raised_out_of_memory(&self) {
+        self.oom_raised.set(true);
+        self.oom_floor.set(None);
+    }
+
+    /// Forget a failed collection's floor once the heap is back under the
+    /// memory limit.
+    pub(crate) fn clear_oom_floor(&self) {
+        self.oom_floor.set(None);
+    }
+
+    pub(crate) fn is_running(&self) -> bool {
+        self.running.get()
+    }
+
+    pub(crate) fn set_running(&self, running: bool) {
+        self.running.set(running);
+    }
+
+    /// Bytes of work one basic `collectgarbage("step")` performs.
+    pub(crate) fn step_size(&self) -> usize {
+        self.granularity.get() as usize
+    }
+
+    pub(crate) fn request(&self, request: GcRequest) {
+        self.request.set(Some(request));
+    }
+
+    pub(crate) fn is_requested(&self) -> bool {
+        self.request.get().is_some()
+    }
+
+    pub(crate) fn take_request(&self) -> Option<GcRequest> {
+        self.request.take()
+    }
+
+    pub(crate) fn step_finished(&self) -> bool {
+        self.step_finished.get()
+    }
+
+    pub(crate) fn set_step_finished(&self, finished: bool) {
+        self.step_finished.set(finished);
+    }
+
+    pub(crate) fn take_out_of_memory(&self) -> bool {
+        self.out_of_memory.take()
+    }
+
+    /// Switch to incremental mode, replacing each non-zero parameter, and
+    /// return the previous mode. `pause` is the heap growth (in percent of
+    /// the live size) that starts a new cycle, `stepmul` scales how much
+    /// is collected per byte allocated, and a basic step is
+    /// `2^stepsize` bytes of work.
+    pub(crate) fn set_incremental(
+        &self,
+        metrics: &Metrics,
+        pause: u32,
+        stepmul: u32,
+        stepsize: u32,
+    ) -> GcMode {
+        set_nonzero(&self.pause, pause);
+        set_nonzero(&self.stepmul, stepmul);
+        set_nonzero(&self.stepsize, stepsize.min(40));
+        self.granularity.set(2f64.powi(self.stepsize.get() as i32));
+        let prev = self.mode.replace(GcMode::Incremental);
+        metrics.set_pacing(self.pacing());
+        prev
+    }
+
+    /// Switch to generational mode, replacing each non-zero parameter, and
+    /// return the previous mode. `minormul` is the heap growth (in percent)
+    /// that starts a minor cycle, and `majormul` the growth since the last
+    /// major cycle that makes the next one major.
+    pub(crate) fn set_generational(
+        &self,
+        metrics: &Metrics,
+        minormul: u32,
+        majormul: u32,
+    ) -> GcMode {
+        set_nonzero(&self.minormul, minormul);
+        set_nonzero(&self.majormul, majormul);
+        let prev = self.mode.replace(GcMode::Generational);
+        metrics.set_pacing(self.pacing());
+        prev
+    }
+
+    /// The [`Pacing`] the current mode and parameters map onto.
+    fn pacing(&self) -> Pacing {
+        let (mode, sleep) = match self.mode.get() {
+            GcMode::Incremental => (
+                CollectionMode::Incremental,
+                self.pause.get().saturating_sub(100),
+            ),
+            GcMode::Generational => {
+                let major_factor = self.majormul.get() as f64 / 100.0;
+                (
+                    CollectionMode::Generational { major_factor },
+                    self.minormul.get(),
+                )
+            }
+        };
+        let scale = (100.0 / self.stepmul.get() as f64).min(MAX_WORK_SCALE);
+        let d = Pacing::DEFAULT;
+        Pacing {
+            mode,
+            sleep_factor: sleep as f64 / 100.0,
+            min_sleep: d.min_sleep,
+            mark_factor: d.mark_factor * scale,
+            trace_factor: d.trace_factor * scale,
+            keep_factor: d.keep_factor * scale,
+            drop_factor: d.drop_factor * scale,
+            free_factor: d.free_factor * scale,
+        }
+    }
+}
+
+fn set_nonzero(cell: &Cell<u32>, value: u32) {
+    if value != 0 {
+        cell.set(value);
+    }
+}
@@ -0,0 +1,103 @@
+//! Per-thread stack limits.
+//!
+//! The executor is stackless, so unbounded recursion doesn't crash the
+//! host: it grows a thread's value stack and frame list until memory runs
+//! out. [`StackLimits`] bounds both, plus the nesting of native functions
+//! that call back into Lua, and going past any of them raises a Lua error
+//! the script can catch with `pcall`.
+//!
+//! Once a thread has overflowed it gets a little headroom above the limits
+//! while it handles the error (running `__close` methods, or a `pcall`
+//! handler that itself calls functions), as Lua's `ERRORSTACKSIZE` does.
+//! Overflowing that as well raises "error in error handling". The headroom
+//! is given back as soon as the thread is within its limits again.
+
+use crate::env::thread::ThreadState;
+
+/// Extra Lua calls and stack slots a thread handling a stack overflow may
+/// use (Lua's `EXTRA_STACK` margin).
+const ERROR_HEADROOM: usize = 200;
+
+/// Extra native nesting a thread handling a "C stack overflow" may use
+/// (Lua allows `LUAI_MAXCCALLS / 10`).
+const NATIVE_ERROR_HEADROOM: usize = 20;
+
+/// Limits on how far one thread may grow. Set with
+/// [`Lua::set_stack_limits`](crate::Lua::set_stack_limits).
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StackLimits {
+    /// Maximum number of active Lua calls. Tail calls don't count.
+    pub max_call_depth: usize,
+    /// Maximum number of value slots in the thread's stack: registers,
+    /// arguments and results of every active call.
+    pub max_stack_size: usize,
+    /// Maximum nesting of native functions calling into Lua or other
+    /// natives (`pcall`, `table.sort` comparators, `coroutine.resume`, ...),
+    /// counted across the chain of threads that resumed each other.
+    pub max_native_depth: usize,
+}
+
+impl StackLimits {
+    /// Lua's defaults: a million stack slots (`LUAI_MAXSTACK`) and 200
+    /// nested native calls (`LUAI_MAXCCALLS`), with room for 200,000 Lua
+    /// calls in between.
+    pub const DEFAULT: StackLimits = StackLimits {
+        max_call_depth: 200_000,
+        max_stack_size: 1_000_000,
+        max_native_depth: 200,
+    };
+
+    /// Check a thread about to hold `frames` Lua frames in `slots` stack
+    /// slots. Returns the message to raise if that's over a limit.
+    #[inline(always)]
+    pub(crate) fn check(
+        &self,
+        thread: &mut ThreadState<'_>,
+        frames: usize,
+        slots: usize,
+    ) -> Result<(), &'static str> {
+        let native = thread.native_depth();
+        if std::hint::likely(
+            frames <= self.max_call_depth
+                && slots <= self.max_stack_size
+                && native <= self.max_native_depth,
+        ) {
+            thread.overflowed = false;
+            return Ok(());
+        }
+        self.overflow(thread, frames, slots, native)
+    }
+
+    #[cold]
+    #[inline(never)]
+    fn overflow(
+        &self,
+        thread: &mut ThreadState<'_>,
+        frames: usize,
+        slots: usize,
+        native: usize,
+    ) -> Result<(), &'static str> {
+        if !thread.overflowed {
+            thread.overflowed = true;
+            return Err(if native > self.max_native_depth {
+                "C stack overflow"
+            } else {
+                "stack overflow"
+            });
+        }
+        if frames <= self.max_call_depth.saturating_add(ERROR_HEADROOM)
+            && slots <= self.max_stack_size.saturating_add(ERROR_HEADROOM)
+            && native <= self.max_native_depth.saturating_add(NATIVE_ERROR_HEADROOM)
+        {
+            Ok(())
+        } else {
+            Err("error in error handling")
+        }
+    }
+}
+
+impl Default for StackLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
@@ -5,24 +5,43 @@
 mod context;
 mod convert;
 mod error;
-mod executor;
+pub(crate) mod executor;
+mod finalizers;
+pub(crate) mod gc;
+mod limits;
+mod snapshot;
 pub(crate) mod stash;
+mod stats;
+
+use std::alloc::{Allocator, Global};
+use std::cell::Cell;
 
 pub use context::Context;
 pub use convert::{FromMultiValue, FromValue, IntoMultiValue, IntoValue};
 pub use error::{LoadError, RuntimeError, TypeError};
 pub use executor::{Executor, ExecutorMode, StepResult};
+pub use limits::StackLimits;
+pub use snapshot::{HeapNode, HeapSnapshot};
 pub use stash::{
     Fetchable, Stashable, StashedError, StashedExecutor, StashedFunction, StashedTable,
     StashedThread, StashedValue,
 };
+pub use stats::{MemoryKind, MemoryUsage};
 
 use crate::builtin;
 use crate::dmm::Rootable;
-use crate::dmm::{Arena, Collect, DynamicRootSet, Mutation};
+use crate::dmm::allocator_api::ArenaAllocator;
+use crate::dmm::arena::CollectionPhase;
+use crate::dmm::metrics::Metrics;
+use crate::dmm::{Arena, Collect, DynamicRootSet, Finalization, Mutation};
 use crate::env::shape::Shape;
 use crate::env::string::Interner;
-use crate::env::{Symbols, Table, Thread};
+use crate::env::table::WeakTables;
+use crate::env::thread::Threads;
+use crate::env::{Symbols, Table, Thread, Value};
+use crate::lua::executor::Fuel;
+use crate::lua::finalizers::Finalizers;
+use crate::lua::gc::{GcControl, GcRequest};
 
 /// Root object of the GC arena. Holds the globals table, the main thread,
 /// and the dynamic root set used to stash values across `enter` boundaries.
@@ -33,6 +52,10 @@ pub struct State<'gc> {
     /// transition tree so two tables that grow through the same key
     /// sequence converge on the same shape pointer.
     pub(crate) empty_shape: Shape<'gc>,
+    /// Shapes in the transition tree under `empty_shape` as of the last
+    /// collection.
+    #[collect(require_static)]
+    pub(crate) live_shapes: Cell<usize>,
     /// Shared dict-mode sentinel for tables migrating to dict mode
     /// while carrying no metatable. Tables with a metatable use the
     /// per-`MtCache` sentinel via `MtCache::ensure_dict_sentinel`.
@@ -44,11 +67,71 @@ pub struct State<'gc> {
     pub(crate) main_thread: Thread<'gc>,
     pub(crate) roots: DynamicRootSet<'gc>,
     pub(crate) interner: Interner<'gc>,
+    /// Every table that has been given a weak metatable, so their dead
+    /// entries can be cleared before the sweep.
+    pub(crate) weak_tables: WeakTables<'gc>,
+    /// Every thread, so the dead slots of their stacks can be cleared
+    /// before the sweep.
+    pub(crate) threads: Threads<'gc>,
+    /// Objects marked for finalization, and the queue of those found
+    /// unreachable whose `__gc` has yet to run.
+    pub(crate) finalizers: Finalizers<'gc>,
+    /// Collector settings and requests made through `collectgarbage`.
+    pub(crate) gc: GcControl,
+    /// Budget of the executor step in progress.
+    #[collect(require_static)]
+    pub(crate) fuel: Fuel,
+    #[collect(require_static)]
+    pub(crate) stack_limits: Cell<StackLimits>,
+}
+
+impl<'gc> State<'gc> {
+    /// Runs on the fully marked arena, before sweeping. Returns `false`
+    /// if it resurrected anything; marking must then resume and
+    /// `finalize` run again before the arena may be swept.
+    fn finalize(&self, fc: &Finalization<'gc>) -> bool {
+        if self.weak_tables.resurrect_ephemerons(fc) {
+            return false;
+        }
+        // Objects about to be finalized vanish from weak values before
+        // their finalizer runs, but stay as weak keys until a later cycle
+        // (they are live again once resurrected). Repeating this after
+        // marking resumes is harmless: the set of dead objects only
+        // shrinks.
+        self.weak_tables.clear_dead_values(fc);
+        if self.finalizers.queue_dead(fc) {
+            return false;
+        }
+        self.weak_tables.clear_dead(fc);
+        self.interner.prune_dead(fc);
+        self.live_shapes
+            .set(self.empty_shape.prune_dead_transitions(fc));
+        self.threads.trim_stacks(fc);
+        true
+    }
 }
 
 /// A Lua runtime instance.
+///
+/// Dropping it runs the `__gc` finalizer of every object still marked
+/// for finalization, reachable or not, as `lua_close` does.
 pub struct Lua {
     arena: Arena<Rootable![State<'_>]>,
+    /// Set while `run_finalizers` drives a finalizer, so the `finish`
+    /// calls it makes don't start running finalizers themselves.
+    running_finalizers: bool,
+}
+
+impl Drop for Lua {
+    fn drop(&mut self) {
+        // Running Lua code while unwinding risks a double panic.
+        if std::thread::panicking() {
+            return;
+        }
+        self.arena
+            .mutate(|mc, state| state.finalizers.queue_all(mc));
+        self.run_finalizers();
+    }
 }
 
 impl Default for Lua {
@@ -59,30 +142,133 @@ impl Default for Lua {
 
 impl Lua {
     pub fn new() -> Self {
-        let arena = Arena::<Rootable![State<'_>]>::new(|mc: &Mutation<'_>| {
+        Self::new_in(Global)
+    }
+
+    /// A runtime whose entire heap lives in `allocator`: every object,
+    /// table part, string, interner table, thread stack and frame list,
+    /// compiled prototype and userdata payload. Only scratch memory that
+    /// doesn't outlive a call, like the compiler's working state, comes
+    /// from the global allocator. Useful to put a VM in a region that can
+    /// be released in one go once the `Lua` is dropped.
+    pub fn new_in<A: Allocator + 'static>(allocator: A) -> Self {
+        let allocator = ArenaAllocator::new(allocator);
+        let arena = Arena::<Rootable![State<'_>]>::new_in(allocator, |mc: &Mutation<'_>| {
             let empty_shape = Shape::root_empty(mc);
             let empty_dict_sentinel = Shape::dict_sentinel(mc, None);
             let interner = Interner::new(mc);
             let symbols = Symbols::intern_all(mc, &interner);
+            let threads = Threads::new(mc);
             State {
                 empty_shape,
+                live_shapes: Cell::new(1),
                 empty_dict_sentinel,
                 symbols,
                 globals: Table::new_with_shape(mc, empty_shape),
-                main_thread: Thread::new(mc),
+                main_thread: Thread::new_in(mc, threads),
                 roots: DynamicRootSet::new(mc),
                 interner,
+                weak_tables: WeakTables::new(mc),
+                threads,
+                finalizers: Finalizers::new(mc),
+                gc: GcControl::new(),
+                fuel: Fuel::new(),
+                stack_limits: Cell::new(StackLimits::DEFAULT),
             }
         });
-        Lua { arena }
+        Lua {
+            arena,
+            running_finalizers: false,
+        }
     }
 
     /// Run `f` inside the arena's mutation context.
+    ///
+    /// Garbage can only be collected between mutations, so on the way out
+    /// this carries out any collection `collectgarbage` asked for, or else
+    /// pays down the allocation debt `f` accrued (per the arena's
+    /// [`Pacing`](crate::dmm::metrics::Pacing)) unless the collector was
+    /// stopped. `finish` / `execute` / `resume` step the executor one
+    /// `enter` at a time, which keeps the heap bounded for long-running
+    /// scripts.
     pub fn enter<F, T>(&mut self, f: F) -> T
     where
         F: for<'gc> FnOnce(Context<'gc>) -> T,
     {
-        self.arena.mutate(|mc, state| f(Context::new(mc, state)))
+        let r = self.arena.mutate(|mc, state| f(Context::new(mc, state)));
+        let (request, due) = self.arena.mutate(|mc, state| {
+            (
+                state.gc.take_request(),
+                state.gc.collection_due(mc.metrics()),
+            )
+        });
+        match request {
+            Some(GcRequest::Collect) => self.gc_collect(),
+            Some(GcRequest::Step(work)) => {
+                let finished = self.gc_step(work);
+                self.arena
+                    .mutate(|_, state| state.gc.set_step_finished(finished));
+            }
+            None if due => self.collect_debt(),
+            None => {}
+        }
+        let over_limit = self
+            .arena
+            .mutate(|mc, state| state.gc.over_memory_limit(mc.metrics()));
+        if over_limit {
+            self.full_collection();
+            self.arena
+                .mutate(|mc, state| state.gc.after_limit_collection(mc.metrics()));
+        } else if !self.arena.metrics().exceeds_memory_limit(0) {
+            self.arena.mutate(|_, state| state.gc.clear_oom_floor());
+        }
+        r
+    }
+
+    /// Cap the memory this runtime may use at `bytes`, counting both `Gc`
+    /// objects and the external memory they own (table storage, string
+    /// contents), or lift the cap with `None`.
+    ///
+    /// Anything that can grow without bound — concatenation, the strings
+    /// built by natives such as `string.rep`, `string.format` and `gsub`,
+    /// `table.concat`, growing a table's storage — checks the limit before
+    /// allocating and raises a "not enough memory" error `pcall` can catch,
+    /// so the process never holds much more than `bytes`. Going over the
+    /// limit through many small allocations instead triggers a full
+    /// collection, and if the heap is still too large afterwards the
+    /// running Lua code gets the same error at its next safe point. After
+    /// that, staying over the limit costs nothing until the heap grows
+    /// further.
+    pub fn set_memory_limit(&mut self, bytes: Option<usize>) {
+        self.arena.metrics().set_memory_limit(bytes);
+    }
+
+    /// Limit how deep every thread may recurse. A thread going past a
+    /// limit gets a "stack overflow" error (or "C stack overflow" for
+    /// nested native calls) that `pcall` can catch. The defaults are
+    /// [`StackLimits::DEFAULT`].
+    pub fn set_stack_limits(&mut self, limits: StackLimits) {
+        self.arena.mutate(|_, state| state.stack_limits.set(limits));
+    }
+
+    /// The stack limits in effect.
+    pub fn stack_limits(&self) -> StackLimits {
+        self.arena.mutate(|_, state| state.stack_limits.get())
+    }
+
+    /// Pay down allocation debt like `Arena::collect_debt`, but without
+    /// ever letting the arena go from marking to sweeping behind
+    /// `State::finalize`'s back.
+    fn collect_debt(&mut self) {
+        while self.arena.metrics().allocation_debt() > 0.0 {
+            if self.arena.collection_phase() == CollectionPhase::Sweeping {
+                self.arena.cycle_debt();
+            } else if let Some(marked) = self.arena.mark_debt()
+                && marked.finalize(|fc, root| root.finalize(fc))
+            {
+                marked.start_sweeping();
+            }
+        }
     }
 
     /// `enter` variant that threads a `Result` through.
@@ -119,6 +305,9 @@ impl Lua {
                     StepResult::Pending => Outcome::Pending,
                 })
             })?;
+            if self.finalizers_pending() {
+                self.run_finalizers();
+            }
             match outcome {
                 Outcome::Done => return Ok(()),
                 Outcome::Yielded => return Err(RuntimeError::MainYielded),
@@ -156,6 +345,123 @@ impl Lua {
         self.finish(ex)
     }
 
+    /// Run a full collection cycle, finishing the current one first if the
+    /// collector is mid-cycle. In generational mode the full cycle is a
+    /// major one.
+    pub fn gc_collect(&mut self) {
+        self.full_collection();
+        self.run_finalizers();
+    }
+
+    /// `gc_collect` without running the finalizers it queues.
+    fn full_collection(&mut self) {
+        if self.arena.collection_phase() != CollectionPhase::Sleeping {
+            self.finish_cycle();
+        }
+        self.arena.schedule_major();
+        self.finish_cycle();
+    }
+
+    /// Run the current cycle (or a fresh one, if sleeping) to completion,
+    /// finalizing the fully marked arena before it is swept.
+    fn finish_cycle(&mut self) {
+        if self.arena.collection_phase() != CollectionPhase::Sweeping {
+            loop {
+                let marked = self
+                    .arena
+                    .finish_marking()
+                    .expect("arena is fully marked outside the sweep");
+                if marked.finalize(|fc, root| root.finalize(fc)) {
+                    marked.start_sweeping();
+                    break;
+                }
+            }
+        }
+        self.arena.finish_cycle();
+    }
+
+    /// Perform `work` units of incremental collection on top of any debt
+    /// already owed. Returns `true` if a collection cycle finished during
+    /// this step.
+    pub fn gc_step(&mut self, work: usize) -> bool {
+        let before = self.arena.metrics().total_cycles();
+        self.arena.metrics().add_debt(work);
+        self.collect_debt();
+        self.arena.metrics().total_cycles() != before
+    }
+
+    /// Call the `__gc` metamethod of every object the collector has
+    /// queued for finalization, most recently marked first. `finish` and
+    /// `gc_collect` do this on their own; hosts that only drive the
+    /// runtime through `enter` can call it directly.
+    ///
+    /// Each finalizer runs to completion on a fresh thread through its own
+    /// [`Executor`]. Errors, and attempts to yield to the host, are
+    /// discarded: Lua reports them as warnings, which are off by default.
+    pub fn run_finalizers(&mut self) {
+        if self.running_finalizers {
+            return;
+        }
+        self.running_finalizers = true;
+        while let Some(ex) = self.enter(|ctx| {
+            let mc = ctx.mutation();
+            while let Some(obj) = ctx.finalizers().pop_pending(mc) {
+                let mt = match obj.get_table() {
+                    Some(t) => t.metatable(),
+                    None => obj.get_userdata().and_then(|u| u.metatable()),
+                };
+                let gc = mt.map_or(Value::nil(), |mt| {
+                    mt.raw_get(Value::string(ctx.symbols().mm_gc))
+                });
+                // A `__gc` field that isn't a function is silently ignored.
+                if let Some(f) = gc.get_function() {
+                    let ex = Executor::start_on(ctx, Thread::new(ctx), f, (obj,));
+                    return Some(ctx.stash(ex));
+                }
+            }
+            None
+        }) {
+            let _ = self.finish(&ex);
+        }
+        self.running_finalizers = false;
+    }
+
+    fn finalizers_pending(&self) -> bool {
+        self.arena.mutate(|_, state| state.finalizers.has_pending())
+    }
+
+    /// Take a [`HeapSnapshot`] of every live object, finishing the current
+    /// sweep first if one is under way.
+    pub fn heap_snapshot(&mut self) -> HeapSnapshot {
+        if self.arena.collection_phase() == CollectionPhase::Sweeping {
+            self.finish_cycle();
+        }
+        let graph = self
+            .arena
+            .heap_graph()
+            .expect("arena is not sweeping after finishing the cycle");
+        HeapSnapshot::new(graph)
+    }
+
+    /// Collector metrics and pacing for this runtime.
+    pub fn gc_metrics(&self) -> &Metrics {
+        self.arena.metrics()
+    }
+
+    /// Number of shapes in the table shape transition tree as of the last
+    /// collection. Shapes no table uses anymore are pruned from the tree
+    /// when they are collected, so this tracks the distinct key layouts
+    /// live tables have rather than every layout ever seen.
+    pub fn shape_count(&self) -> usize {
+        self.arena.mutate(|_, state| state.live_shapes.get())
+    }
+
+    /// Live objects and bytes of one kind, e.g. how much of the heap the
+    /// table shapes take up.
+    pub fn memory_usage(&self, kind: MemoryKind) -> MemoryUsage {
+        MemoryUsage::of(self.arena.metrics(), kind)
+    }
+
     pub fn load_all(&mut self) {
         self.enter(|ctx| {
             builtin::load_basic(ctx);
@@ -231,7 +537,7 @@ mod tests {
     ) -> Result<crate::vm::sequence::CallbackAction<'gc>, crate::env::Error<'gc>> {
         let (a, b) = (stack.get(0), stack.get(1));
         let sum = match (a.get_integer(), b.get_integer()) {
-            (Some(x), Some(y)) => Value::integer(x + y),
+            (Some(x), Some(y)) => Value::integer(nctx.ctx, x + y),
             _ => return Err(crate::env::Error::from_str(nctx.ctx, "bad args")),
         };
         stack.replace(&[sum]);
@@ -244,8 +550,7 @@ mod tests {
         let mut lua = Lua::new();
         let ex = lua
             .try_enter(|ctx| -> Result<_, LoadError> {
-                let add =
-                    Function::new_native(ctx.mutation(), native_add as NativeFn, Box::new([]));
+                let add = Function::new_native(ctx.mutation(), native_add as NativeFn, []);
                 let key = Value::string(LuaString::new(ctx, b"add"));
                 ctx.globals().raw_set(ctx, key, Value::function(add));
 
@@ -288,8 +593,7 @@ mod tests {
         let mut lua = Lua::new();
         let ex = lua
             .try_enter(|ctx| -> Result<_, LoadError> {
-                let add =
-                    Function::new_native(ctx.mutation(), native_add as NativeFn, Box::new([]));
+                let add = Function::new_native(ctx.mutation(), native_add as NativeFn, []);
                 let key = Value::string(LuaString::new(ctx, b"add"));
                 ctx.globals().raw_set(ctx, key, Value::function(add));
 
@@ -312,8 +616,7 @@ mod tests {
         let mut lua = Lua::new();
         let ex = lua
             .try_enter(|ctx| -> Result<_, LoadError> {
-                let add =
-                    Function::new_native(ctx.mutation(), native_add as NativeFn, Box::new([]));
+                let add = Function::new_native(ctx.mutation(), native_add as NativeFn, []);
                 let key = Value::string(LuaString::new(ctx, b"add"));
                 ctx.globals().raw_set(ctx, key, Value::function(add));
 
@@ -338,7 +641,7 @@ mod tests {
         // directly, step runs the callback and take_result reads results.
         let mut lua = Lua::new();
         let ex = lua.enter(|ctx| {
-            let add = Function::new_native(ctx.mutation(), native_add as NativeFn, Box::new([]));
+            let add = Function::new_native(ctx.mutation(), native_add as NativeFn, []);
             ctx.stash(Executor::start(ctx, add, (10i64, 32i64)))
         });
         let result: i64 = lua.execute(&ex).expect("run native entry");
@@ -351,7 +654,7 @@ mod tests {
         // with the payload stashed (native-entry path).
         let mut lua = Lua::new();
         let ex = lua.enter(|ctx| {
-            let add = Function::new_native(ctx.mutation(), native_add as NativeFn, Box::new([]));
+            let add = Function::new_native(ctx.mutation(), native_add as NativeFn, []);
             // Pass a float to trigger native_add's Err path (it requires Integer).
             ctx.stash(Executor::start(ctx, add, (1i64, 2.5f64)))
         });
@@ -388,8 +691,7 @@ mod tests {
         let mut lua = Lua::new();
         let ex = lua
             .try_enter(|ctx| -> Result<_, LoadError> {
-                let probe =
-                    Function::new_native(ctx.mutation(), native_id as NativeFn, Box::new([]));
+                let probe = Function::new_native(ctx.mutation(), native_id as NativeFn, []);
                 let key = Value::string(LuaString::new(ctx, b"probe"));
                 ctx.globals().raw_set(ctx, key, Value::function(probe));
                 let chunk = ctx.load(src, Some("test"))?;
@@ -521,7 +823,7 @@ mod tests {
     fn integer_div_mod_by_zero_raises() {
         // Lua raises on integer `//` / `%` by a zero divisor. Previously this
         // panicked the VM (`wrapping_div` / `wrapping_rem` by zero); now it
-        // surfaces as a runtime error like any other arithmetic failure.
+        // surfaces as a Lua error like any other arithmetic failure.
         for src in [
             "return 7 // 0",
             "return 0 // 0",
@@ -530,7 +832,7 @@ mod tests {
             "return (-5) % 0",
         ] {
             assert!(
-                matches!(run_expecting_error(src), RuntimeError::Opcode { .. }),
+                matches!(run_expecting_error(src), RuntimeError::Lua(_)),
                 "expected a runtime error for {src:?}",
             );
         }
@@ -818,8 +1120,7 @@ mod tests {
         let ex = lua
             .try_enter(|ctx| -> Result<_, LoadError> {
                 builtin::load_basic(ctx);
-                let probe =
-                    Function::new_native(ctx.mutation(), native_id as NativeFn, Box::new([]));
+                let probe = Function::new_native(ctx.mutation(), native_id as NativeFn, []);
                 let key = Value::string(LuaString::new(ctx, b"probe"));
                 ctx.globals().raw_set(ctx, key, Value::function(probe));
                 let chunk = ctx.load(src, Some("test"))?;
@@ -1018,10 +1319,10 @@ mod tests {
             let kx = Value::string(LuaString::new(ctx, b"x"));
             let ky = Value::string(LuaString::new(ctx, b"y"));
 
-            a.raw_set(ctx, kx, Value::integer(1));
-            a.raw_set(ctx, ky, Value::integer(2));
-            b.raw_set(ctx, kx, Value::integer(10));
-            b.raw_set(ctx, ky, Value::integer(20));
+            a.raw_set(ctx, kx, Value::small_integer(1));
+            a.raw_set(ctx, ky, Value::small_integer(2));
+            b.raw_set(ctx, kx, Value::small_integer(10));
+            b.raw_set(ctx, ky, Value::small_integer(20));
 
             assert!(
                 Shape::ptr_eq(a.shape(), b.shape()),
@@ -1030,8 +1331,8 @@ mod tests {
 
             // Different ordering -> different shape pointer.
             let c = Table::new(ctx);
-            c.raw_set(ctx, ky, Value::integer(2));
-            c.raw_set(ctx, kx, Value::integer(1));
+            c.raw_set(ctx, ky, Value::small_integer(2));
+            c.raw_set(ctx, kx, Value::small_integer(1));
             assert!(
                 !Shape::ptr_eq(a.shape(), c.shape()),
                 "tables grown through different key orders should have distinct shapes"
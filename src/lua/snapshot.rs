@@ -0,0 +1,210 @@
+//! Heap snapshots: every live object in a `Lua` with its kind, size and
+//! references, for tracking down what keeps memory alive.
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::io;
+
+use crate::dmm::heap::{self, HeapGraph};
+use crate::env::{Value, ValueKind};
+use crate::lua::MemoryKind;
+
+/// One live object in a [`HeapSnapshot`].
+#[derive(Debug, Clone)]
+pub struct HeapNode {
+    /// Stable for as long as the object lives; see [`HeapSnapshot::id_of`].
+    pub id: usize,
+    /// What the object is, as accounted in [`crate::Lua::memory_usage`].
+    pub kind: MemoryKind,
+    /// The Rust type backing the object.
+    pub type_name: &'static str,
+    /// Size of the object's own allocation in bytes. Memory it owns
+    /// separately, such as a table's array part, isn't included.
+    pub size: usize,
+    /// Objects this one keeps alive.
+    pub edges: Vec<usize>,
+    /// Objects this one refers to without keeping them alive (weak table
+    /// entries, the interner).
+    pub weak_edges: Vec<usize>,
+}
+
+/// Every live object in a `Lua`, taken by [`crate::Lua::heap_snapshot`].
+///
+/// The snapshot may include garbage the collector hasn't reclaimed yet;
+/// such objects have no [`HeapSnapshot::retaining_path`].
+#[derive(Debug, Clone)]
+pub struct HeapSnapshot {
+    roots: Vec<usize>,
+    nodes: Vec<HeapNode>,
+    index: HashMap<usize, usize>,
+    /// The objects with a strong edge to each object, each listed once.
+    retainers: HashMap<usize, Vec<usize>>,
+}
+
+impl HeapSnapshot {
+    pub(crate) fn new(graph: HeapGraph) -> Self {
+        let nodes: Vec<HeapNode> = graph
+            .objects
+            .into_iter()
+            .map(|object| HeapNode {
+                id: object.id,
+                kind: MemoryKind::of_id(object.kind),
+                type_name: object.type_name,
+                size: object.size,
+                edges: object.edges,
+                weak_edges: object.weak_edges,
+            })
+            .collect();
+        let index = nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+        let mut retainers: HashMap<usize, Vec<usize>> = HashMap::new();
+        for node in &nodes {
+            for &edge in &node.edges {
+                let list = retainers.entry(edge).or_default();
+                // A node's edges are all visited together, so a repeat of
+                // the same edge is always the last entry.
+                if list.last() != Some(&node.id) {
+                    list.push(node.id);
+                }
+            }
+        }
+        HeapSnapshot {
+            roots: graph.roots,
+            nodes,
+            index,
+            retainers,
+        }
+    }
+
+    /// The id of the object behind `value`, or `None` for values that
+    /// aren't heap objects (nil, booleans, numbers).
+    pub fn id_of(value: Value<'_>) -> Option<usize> {
+        match value.kind() {
+            ValueKind::String => value.get_string().map(|s| heap::object_id(s.inner())),
+            _ => value.weak_object().map(heap::object_id),
+        }
+    }
+
+    /// Objects the runtime's root (globals, registry, stashed values, the
+    /// main thread) points to directly.
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    pub fn nodes(&self) -> &[HeapNode] {
+        &self.nodes
+    }
+
+    pub fn get(&self, id: usize) -> Option<&HeapNode> {
+        self.index.get(&id).map(|&i| &self.nodes[i])
+    }
+
+    /// Total size of the objects' own allocations.
+    pub fn total_size(&self) -> usize {
+        self.nodes.iter().map(|n| n.size).sum()
+    }
+
+    /// The objects holding a strong reference to `id`.
+    pub fn retainers(&self, id: usize) -> &[usize] {
+        self.retainers.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// A shortest chain of strong references from a root to `id`, starting
+    /// with the root and ending with `id`. `None` if `id` is garbage (or
+    /// not in the snapshot).
+    pub fn retaining_path(&self, id: usize) -> Option<Vec<usize>> {
+        let mut parent: HashMap<usize, Option<usize>> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for &root in &self.roots {
+            if parent.insert(root, None).is_none() {
+                queue.push_back(root);
+            }
+        }
+        while let Some(current) = queue.pop_front() {
+            if current == id {
+                let mut path = vec![id];
+                let mut at = id;
+                while let Some(&Some(prev)) = parent.get(&at) {
+                    path.push(prev);
+                    at = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let Some(node) = self.get(current) else {
+                continue;
+            };
+            for &next in &node.edges {
+                if let Entry::Vacant(entry) = parent.entry(next) {
+                    entry.insert(Some(current));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// The snapshot as a JSON document:
+    ///
+    /// ```json
+    /// {"roots": [id, ...],
+    ///  "nodes": [{"id": 1, "kind": "table", "type": "...", "size": 96,
+    ///             "edges": [id, ...], "weak_edges": [id, ...]}, ...]}
+    /// ```
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        out.push_str("{\"roots\":");
+        push_ids(&mut out, &self.roots);
+        out.push_str(",\"nodes\":[");
+        for (i, node) in self.nodes.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(
+                out,
+                "{{\"id\":{},\"kind\":\"{}\",\"type\":",
+                node.id,
+                node.kind.name()
+            );
+            push_json_string(&mut out, node.type_name);
+            let _ = write!(out, ",\"size\":{},\"edges\":", node.size);
+            push_ids(&mut out, &node.edges);
+            out.push_str(",\"weak_edges\":");
+            push_ids(&mut out, &node.weak_edges);
+            out.push('}');
+        }
+        out.push_str("]}");
+        out
+    }
+
+    /// Write [`HeapSnapshot::to_json`] to `w`.
+    pub fn write_json<W: io::Write>(&self, mut w: W) -> io::Result<()> {
+        w.write_all(self.to_json().as_bytes())
+    }
+}
+
+fn push_ids(out: &mut String, ids: &[usize]) {
+    out.push('[');
+    for (i, id) in ids.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let _ = write!(out, "{id}");
+    }
+    out.push(']');
+}
+
+fn push_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
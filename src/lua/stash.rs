@@ -102,10 +102,13 @@ impl Fetchable for StashedExecutor {
 /// `'static`-erased handle to a Lua [`Value`]. Primitive variants store
 /// their data inline (no allocation); Gc-pointer variants pin the inner
 /// `Gc` directly via the `DynamicRootSet` (no extra `Gc<Value>` wrapper).
+/// Integers too wide to live inline in a `Value` keep their existing box,
+/// so fetching never allocates.
 pub enum StashedValue {
     Nil,
     Boolean(bool),
     Integer(i64),
+    BoxedInteger(DynamicRoot<Rootable![i64]>),
     Float(f64),
     String(DynamicRoot<Rootable![StringData]>),
     Table(DynamicRoot<Rootable![RefLock<TableState<'_>>]>),
@@ -120,7 +123,10 @@ impl<'gc> Stashable<'gc> for Value<'gc> {
         match self.kind() {
             ValueKind::Nil => StashedValue::Nil,
             ValueKind::Boolean => StashedValue::Boolean(self.get_boolean().unwrap()),
-            ValueKind::Integer => StashedValue::Integer(self.get_integer().unwrap()),
+            ValueKind::Integer => match self.get_boxed_integer() {
+                Some(boxed) => StashedValue::BoxedInteger(roots.stash::<Rootable![i64]>(mc, boxed)),
+                None => StashedValue::Integer(self.get_integer().unwrap()),
+            },
             ValueKind::Float => StashedValue::Float(self.get_float().unwrap()),
             ValueKind::String => StashedValue::String(
                 roots.stash::<Rootable![StringData]>(mc, self.get_string().unwrap().inner()),
@@ -157,7 +163,12 @@ impl Fetchable for StashedValue {
         match self {
             StashedValue::Nil => Value::nil(),
             StashedValue::Boolean(b) => Value::boolean(*b),
-            StashedValue::Integer(i) => Value::integer(*i),
+            StashedValue::Integer(i) => {
+                Value::inline_integer(*i).expect("wide integers are stashed boxed")
+            }
+            StashedValue::BoxedInteger(r) => {
+                Value::from_boxed_integer(roots.fetch::<Rootable![i64]>(r))
+            }
             StashedValue::Float(f) => Value::float(*f),
             StashedValue::String(r) => Value::string(LuaString::from_inner(
                 roots.fetch::<Rootable![StringData]>(r),
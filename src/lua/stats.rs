@@ -0,0 +1,93 @@
+//! Live memory broken down by what it is used for, as reported by
+//! `Lua::memory_usage` and `collectgarbage("stats")`.
+
+use crate::dmm::metrics::{AllocationKind, Metrics};
+
+/// What a piece of heap memory belongs to. Every allocation the runtime
+/// makes is accounted to exactly one kind.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum MemoryKind {
+    /// Table objects and their hash parts.
+    Table,
+    /// The array parts of tables.
+    TableArray,
+    /// Table shapes, their transition tables and metatable caches.
+    Shape,
+    /// Strings and the interner.
+    String,
+    Prototype,
+    /// Closures, function objects and upvalues.
+    Closure,
+    /// Coroutines and their value stacks.
+    Thread,
+    Userdata,
+    /// Runtime bookkeeping: executors, finalizer queues, native sequences
+    /// and the like.
+    Other,
+}
+
+impl MemoryKind {
+    pub const ALL: [MemoryKind; 9] = [
+        MemoryKind::Table,
+        MemoryKind::TableArray,
+        MemoryKind::Shape,
+        MemoryKind::String,
+        MemoryKind::Prototype,
+        MemoryKind::Closure,
+        MemoryKind::Thread,
+        MemoryKind::Userdata,
+        MemoryKind::Other,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MemoryKind::Table => "table",
+            MemoryKind::TableArray => "table_array",
+            MemoryKind::Shape => "shape",
+            MemoryKind::String => "string",
+            MemoryKind::Prototype => "prototype",
+            MemoryKind::Closure => "closure",
+            MemoryKind::Thread => "thread",
+            MemoryKind::Userdata => "userdata",
+            MemoryKind::Other => "other",
+        }
+    }
+
+    /// The arena-level kind allocations of this kind are accounted to.
+    pub(crate) const fn id(self) -> AllocationKind {
+        match self {
+            MemoryKind::Other => AllocationKind::OTHER,
+            kind => AllocationKind::new(kind as usize + 1),
+        }
+    }
+
+    /// The kind whose allocations are accounted to `id`.
+    pub(crate) fn of_id(id: AllocationKind) -> MemoryKind {
+        MemoryKind::ALL
+            .into_iter()
+            .find(|kind| kind.id() == id)
+            .unwrap_or(MemoryKind::Other)
+    }
+}
+
+/// Live memory of one [`MemoryKind`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// Number of live garbage-collected objects. Memory that only ever
+    /// hangs off another object, such as a table's array part, counts
+    /// bytes but no objects.
+    pub count: usize,
+    /// Bytes allocated, both for the objects themselves and for the
+    /// buffers they own.
+    pub bytes: usize,
+}
+
+impl MemoryUsage {
+    pub(crate) fn of(metrics: &Metrics, kind: MemoryKind) -> Self {
+        let stats = metrics.allocation_stats(kind.id());
+        MemoryUsage {
+            count: stats.gc_count,
+            bytes: stats.total_bytes(),
+        }
+    }
+}
@@ -124,9 +124,9 @@ impl<'cache, 'source> State<'cache, 'source> {
         last_span
     }
 
-    pub fn finish(self) -> (GreenNode, Vec<ariadne::Report<'static, Span>>) {
-        let tree = Sink::new(self.cache, &self.tokens, self.events, self.source).finish();
-        (tree, self.reports)
+    pub fn finish(self) -> (GreenNode, LineMap, Vec<ariadne::Report<'static, Span>>) {
+        let (tree, lines) = Sink::new(self.cache, &self.tokens, self.events, self.source).finish();
+        (tree, lines, self.reports)
     }
 }
 
@@ -392,12 +392,37 @@ impl Display for Span {
     }
 }
 
+/// Source lines of a syntax tree. The tree leaves out whitespace and
+/// comments, so offsets into it don't match offsets into the source; this
+/// maps the former to the 1-based line the text at that offset is on.
+#[derive(Debug, Default)]
+pub struct LineMap {
+    /// `(tree offset, line)` of the first token on each line that has one,
+    /// in increasing order.
+    starts: Vec<(u32, u32)>,
+}
+
+impl LineMap {
+    /// Line of the token at `offset` in the tree.
+    pub fn line(&self, offset: u32) -> u32 {
+        let idx = self.starts.partition_point(|&(start, _)| start <= offset);
+        idx.checked_sub(1).map_or(1, |idx| self.starts[idx].1)
+    }
+}
+
 struct Sink<'cache, 'source> {
     builder: GreenNodeBuilder<'cache, 'static, SyntaxKind>,
     tokens: &'source [(SyntaxKind, Span)],
     cursor: usize,
     events: Vec<Event>,
     source: &'source str,
+    lines: LineMap,
+    /// Length of the tree built so far.
+    offset: u32,
+    /// Line of the source at `scanned`.
+    line: u32,
+    /// Source offset up to which newlines have been counted.
+    scanned: usize,
 }
 
 impl<'cache, 'source> Sink<'cache, 'source> {
@@ -413,15 +438,31 @@ impl<'cache, 'source> Sink<'cache, 'source> {
             cursor: 0,
             events,
             source,
+            lines: LineMap::default(),
+            offset: 0,
+            line: 1,
+            scanned: 0,
         }
     }
 
-    fn token(&mut self, kind: SyntaxKind, text: &str) {
+    fn token(&mut self, kind: SyntaxKind, span: Span) {
+        let start = (span.start() as usize).max(self.scanned);
+        let newlines = self.source.as_bytes()[self.scanned..start]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count();
+        self.scanned = start;
+        if newlines > 0 || self.lines.starts.is_empty() {
+            self.line += newlines as u32;
+            self.lines.starts.push((self.offset, self.line));
+        }
+        let text = &self.source[span];
+        self.offset += text.len() as u32;
         self.cursor += 1;
         self.builder.token(kind, text);
     }
 
-    fn finish(mut self) -> GreenNode {
+    fn finish(mut self) -> (GreenNode, LineMap) {
         let mut preceded_nodes = Vec::new();
         for idx in 0..self.events.len() {
             match mem::take(&mut self.events[idx]) {
@@ -459,11 +500,11 @@ impl<'cache, 'source> Sink<'cache, 'source> {
                 }
 
                 Event::Token { kind, span } => {
-                    self.token(kind, &self.source[span]);
+                    self.token(kind, span);
                 }
             }
         }
 
-        self.builder.finish().0
+        (self.builder.finish().0, self.lines)
     }
 }
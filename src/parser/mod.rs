@@ -8,13 +8,13 @@ use std::ops::{Deref, DerefMut};
 
 use cstree::build::NodeCache;
 use kind::T;
-use machinery::{Span, State};
+use machinery::{LineMap, Span, State};
 use syntax::SyntaxNode;
 
 pub fn parse(
     cache: &mut NodeCache<'static>,
     source: &str,
-) -> (SyntaxNode, Vec<ariadne::Report<'static, Span>>) {
+) -> (SyntaxNode, LineMap, Vec<ariadne::Report<'static, Span>>) {
     Parser::new(cache, source).run()
 }
 
@@ -35,10 +35,10 @@ impl<'cache, 'source> Parser<'cache, 'source> {
         marker.complete(self);
     }
 
-    fn run(mut self) -> (SyntaxNode, Vec<ariadne::Report<'static, Span>>) {
+    fn run(mut self) -> (SyntaxNode, LineMap, Vec<ariadne::Report<'static, Span>>) {
         self.root();
-        let (root, reports) = self.state.finish();
-        (SyntaxNode::new_root(root), reports)
+        let (root, lines, reports) = self.state.finish();
+        (SyntaxNode::new_root(root), lines, reports)
     }
 }
 
@@ -73,7 +73,7 @@ mod tests {
                 fn [<test_parse_ $name>]() {
                     let mut cache = NodeCache::new();
                     let source = fs::read_to_string($path).unwrap();
-                    let (syntax_tree, reports) = parse(&mut cache, &source);
+                    let (syntax_tree, _, reports) = parse(&mut cache, &source);
                     let syntax_tree = syntax_tree.debug(cache.interner(), true);
                     assert!(reports.is_empty());
                     assert_snapshot!(syntax_tree);
@@ -130,7 +130,7 @@ mod tests {
             "foo @ bar",
         ] {
             let mut cache = NodeCache::new();
-            let (_tree, reports) = parse(&mut cache, src);
+            let (_tree, _, reports) = parse(&mut cache, src);
             assert!(!reports.is_empty(), "expected a parse error for {src:?}");
         }
     }
@@ -94,6 +94,29 @@ impl Stmt {
             _ => Expr::cast(node).map(Self::Expr)?,
         })
     }
+
+    /// Offset of the statement's first token in the tree; see
+    /// [`LineMap`](super::machinery::LineMap).
+    pub fn offset(&self) -> Option<u32> {
+        let node = match self {
+            Self::Label(s) => &s.0,
+            Self::Goto(s) => &s.0,
+            Self::Decl(s) => &s.0,
+            Self::Global(s) => &s.0,
+            Self::Assign(s) => &s.0,
+            Self::Func(s) => &s.0,
+            Self::Expr(e) => return e.offset(),
+            Self::Break(s) => &s.0,
+            Self::Return(s) => &s.0,
+            Self::Do(s) => &s.0,
+            Self::While(s) => &s.0,
+            Self::Repeat(s) => &s.0,
+            Self::If(s) => &s.0,
+            Self::ForNum(s) => &s.0,
+            Self::ForGen(s) => &s.0,
+        };
+        Some(node.text_range().start().into())
+    }
 }
 
 ast_node!(Label, T![label]);
@@ -149,6 +172,34 @@ impl Expr {
             _ => return None,
         })
     }
+
+    /// Offset in the tree of the token an expression's own instructions are
+    /// attributed to: the operator of a binary operation or index (as in
+    /// reference Lua, `a\n.b` faults on the `.b` line), otherwise the first
+    /// token. `None` for expressions without a node of their own; see
+    /// [`LineMap`](super::machinery::LineMap).
+    pub fn offset(&self) -> Option<u32> {
+        let node = match self {
+            Self::Method(e) => &e.0,
+            Self::Ident(e) => &e.0,
+            Self::Literal(e) => &e.0,
+            Self::Func(e) => &e.0,
+            Self::Table(e) => &e.0,
+            Self::PrefixOp(e) => &e.0,
+            Self::BinaryOp(e) => return operator_offset(&e.0),
+            Self::FuncCall(e) => &e.0,
+            Self::Index(e) => return operator_offset(&e.0),
+            Self::VarArg => return None,
+            Self::Paren(e) => return e.offset(),
+        };
+        Some(node.text_range().start().into())
+    }
+}
+
+/// Offset of the token following an infix node's first operand.
+fn operator_offset(node: &SyntaxNode) -> Option<u32> {
+    let operator = node.children_with_tokens().nth(1)?;
+    Some(operator.text_range().start().into())
 }
 
 ast_node!(MethodCall, T![method_call]);
@@ -53,8 +53,8 @@ use std::task::{self, Poll, RawWaker, RawWakerVTable, Waker};
 use crate::dmm::{Collect, DynamicRootSet, Mutation, Trace};
 use crate::env::error::Error;
 use crate::env::function::Stack;
-use crate::env::value::Value;
-use crate::env::{Function, Thread};
+use crate::env::thread::ValueStack;
+use crate::env::{Function, Thread, Value};
 use crate::lua::Context;
 use crate::lua::stash::{Fetchable, Stashable, StashedError, StashedFunction, StashedThread};
 use crate::vm::sequence::{BoxSequence, Execution, Sequence, SequencePoll};
@@ -326,9 +326,9 @@ where
                 );
                 match res {
                     Ok(SequenceReturn::Return) => Ok(SequencePoll::Return),
-                    Ok(SequenceReturn::Call(function)) => {
-                        Ok(SequencePoll::TailCall(function.fetch(roots_local)))
-                    }
+                    Ok(SequenceReturn::Call(function)) => Ok(SequencePoll::TailCall(
+                        Value::function(function.fetch(roots_local)),
+                    )),
                     Ok(SequenceReturn::Yield) => Ok(SequencePoll::TailYield),
                     Ok(SequenceReturn::Resume(thread)) => {
                         Ok(SequencePoll::TailResume(thread.fetch(roots_local)))
@@ -339,9 +339,10 @@ where
             Poll::Pending => Ok(
                 match next_op.expect("`await` of a future other than AsyncSequence methods") {
                     SequenceOp::Pending => SequencePoll::Pending,
-                    SequenceOp::Call { function, bottom } => {
-                        SequencePoll::Call { function, bottom }
-                    }
+                    SequenceOp::Call { function, bottom } => SequencePoll::Call {
+                        function: Value::function(function),
+                        bottom,
+                    },
                     SequenceOp::Yield { bottom } => SequencePoll::Yield { bottom },
                     SequenceOp::Resume { thread, bottom } => {
                         SequencePoll::Resume { thread, bottom }
@@ -405,7 +406,7 @@ struct Shared<'gc, 'a> {
     exec: Execution<'gc, 'a>,
     /// Live mutable view of the underlying value-stack vec; used to
     /// reconstruct a `Stack<'gc, '_>` per `enter` call.
-    stack_buf: &'a mut Vec<Value<'gc>>,
+    stack_buf: &'a mut ValueStack<'gc>,
     stack_bottom: usize,
     error: Option<Error<'gc>>,
     next_op: &'a mut Option<SequenceOp<'gc>>,
@@ -0,0 +1,160 @@
+//! To-be-closed variables.
+//!
+//! `TBC` registers a variable's stack slot on its thread. Leaving the
+//! variable's scope — the end of its block, a `break` or `goto` out of it,
+//! `return`, an error unwinding its frame or `coroutine.close` — takes the
+//! pending values off the thread and hands them to a [`CloseSequence`],
+//! which the executor drives like any other native sequence. Closing
+//! methods therefore run as ordinary calls: they may call back into Lua,
+//! yield, or raise.
+
+use std::pin::Pin;
+
+use crate::dmm::{Collect, Trace};
+use crate::env::function::Stack;
+use crate::env::thread::ThreadState;
+use crate::env::{Error, Value};
+use crate::lua::Context;
+use crate::vm::sequence::{Execution, Sequence, SequencePoll, seq_trace_pointers};
+
+/// The `__close` metamethod of `v`, or nil.
+pub(crate) fn close_metamethod<'gc>(ctx: Context<'gc>, v: Value<'gc>) -> Value<'gc> {
+    let mt = if let Some(t) = v.get_table() {
+        t.metatable()
+    } else if let Some(u) = v.get_userdata() {
+        u.metatable()
+    } else {
+        None
+    };
+    mt.map_or(Value::nil(), |mt| {
+        mt.raw_get(Value::string(ctx.symbols().mm_close))
+    })
+}
+
+/// Whether a closing method `mm` can be called: a function, or a table
+/// with `__call`. Where that chain leads is checked when it's called.
+fn is_callable<'gc>(ctx: Context<'gc>, mm: Value<'gc>) -> bool {
+    mm.get_function().is_some()
+        || mm
+            .get_table()
+            .is_some_and(|t| !t.get_metamethod(ctx.symbols().mm_call).is_nil())
+}
+
+/// Whether a to-be-closed variable lives at or above stack index `start`.
+/// Slots are registered in increasing order, since a frame's variables sit
+/// above its caller's, so only the last one needs checking.
+#[inline(always)]
+pub(crate) fn has_pending(thread: &ThreadState<'_>, start: usize) -> bool {
+    thread.tbc_slots.last().is_some_and(|&slot| slot >= start)
+}
+
+/// Unregister the to-be-closed variables at or above stack index `start`
+/// and return their values in declaration order.
+pub(crate) fn take_pending<'gc>(thread: &mut ThreadState<'gc>, start: usize) -> Vec<Value<'gc>> {
+    let split = thread.tbc_slots.partition_point(|&slot| slot < start);
+    let stack = &thread.stack;
+    thread
+        .tbc_slots
+        .drain(split..)
+        .map(|slot| stack[slot])
+        .collect()
+}
+
+/// Calls `__close(value, err)` on each pending value, the last declared
+/// first. `err` is the error being propagated, if any; an error raised by a
+/// closing method replaces it, and is what the remaining ones see.
+///
+/// Once every value is closed the sequence raises the final error, or else
+/// returns the `keep` values at the bottom of its window: the results of
+/// the `return` that left the scope, or nothing for a block exit.
+#[derive(Collect)]
+#[collect(internal, no_drop)]
+pub(crate) struct CloseSequence<'gc> {
+    /// Values still to be closed; the last is closed next.
+    pending: Vec<Value<'gc>>,
+    err: Option<Error<'gc>>,
+    keep: usize,
+    /// Report the outcome as `true` or `false, err` instead of raising, as
+    /// `coroutine.close` does.
+    report: bool,
+}
+
+impl<'gc> CloseSequence<'gc> {
+    pub(crate) fn new(pending: Vec<Value<'gc>>, keep: usize) -> Self {
+        CloseSequence {
+            pending,
+            err: None,
+            keep,
+            report: false,
+        }
+    }
+
+    /// The sequence `coroutine.close` returns for a coroutine's pending
+    /// variables.
+    pub(crate) fn reporting(pending: Vec<Value<'gc>>) -> Self {
+        CloseSequence {
+            report: true,
+            ..Self::new(pending, 0)
+        }
+    }
+
+    fn close_next(
+        &mut self,
+        ctx: Context<'gc>,
+        mut stack: Stack<'gc, '_>,
+    ) -> Result<SequencePoll<'gc>, Error<'gc>> {
+        // Drop whatever the previous closing method returned.
+        stack.truncate(self.keep);
+        while let Some(v) = self.pending.pop() {
+            let mm = close_metamethod(ctx, v);
+            if !is_callable(ctx, mm) {
+                let msg = format!(
+                    "attempt to call a {} value (metamethod 'close')",
+                    mm.type_name()
+                );
+                self.err = Some(Error::from_str(ctx, &msg));
+                continue;
+            }
+            stack.push(v);
+            stack.push(self.err.map_or(Value::nil(), Error::value));
+            return Ok(SequencePoll::Call {
+                function: mm,
+                bottom: self.keep,
+            });
+        }
+        match (self.err, self.report) {
+            (None, false) => {}
+            (Some(err), false) => return Err(err),
+            (None, true) => stack.replace(&[Value::boolean(true)]),
+            (Some(err), true) => stack.replace(&[Value::boolean(false), err.value()]),
+        }
+        Ok(SequencePoll::Return)
+    }
+}
+
+impl<'gc> Sequence<'gc> for CloseSequence<'gc> {
+    fn trace_pointers(&self, cc: &mut dyn Trace<'gc>) {
+        seq_trace_pointers!(self, cc);
+    }
+
+    fn poll(
+        self: Pin<&mut Self>,
+        ctx: Context<'gc>,
+        _exec: Execution<'gc, '_>,
+        stack: Stack<'gc, '_>,
+    ) -> Result<SequencePoll<'gc>, Error<'gc>> {
+        self.get_mut().close_next(ctx, stack)
+    }
+
+    fn error(
+        self: Pin<&mut Self>,
+        ctx: Context<'gc>,
+        _exec: Execution<'gc, '_>,
+        err: Error<'gc>,
+        stack: Stack<'gc, '_>,
+    ) -> Result<SequencePoll<'gc>, Error<'gc>> {
+        let this = self.get_mut();
+        this.err = Some(err);
+        this.close_next(ctx, stack)
+    }
+}
@@ -1,18 +1,26 @@
-use crate::dmm::{Gc, Mutation, RefLock};
+use std::cell::Cell;
+
+use crate::builtin::util::{check_memory, raw_eq};
+use crate::dmm::{Gc, Lock, Mutation, RefLock};
 use crate::env::function::{
-    Function, FunctionKind, InlineCache, LuaClosure, NativeClosure, NativeContext, Stack, Upvalue,
-    UpvalueState,
+    Function, FunctionKind, IcEntry, IndexLink, InlineCache, LuaClosure, NativeClosure,
+    NativeContext, Stack, Upvalue, UpvalueState,
 };
 use crate::env::shape::{MetamethodBits, Shape};
 use crate::env::string::LuaString;
-use crate::env::table::Table;
+use crate::env::table::{Table, TableState};
 use crate::env::thread::{
-    CallSite, Frame, LuaFrame, PendingAction, Thread, ThreadState, ThreadStatus,
+    CallSite, Frame, HookCursor, HookEvents, LuaFrame, PendingAction, Thread, ThreadState,
+    ThreadStatus,
 };
 use crate::env::value::{Value, ValueKind};
+use crate::env::{Hook, HookMask};
 use crate::instruction::{Instruction, UpValueDescriptor};
 use crate::lua::Context;
-use crate::vm::num::{self, op_arith, op_bit};
+use crate::lua::executor::{CALL_FUEL, NATIVE_CALL_FUEL};
+use crate::vm::close::{self, CloseSequence};
+use crate::vm::num::{self, float_arith, int_arith, op_arith, op_bit};
+use crate::vm::sequence::{BoxSequence, CallbackAction};
 
 static HANDLERS: &[Handler] = &[
     op_move,
@@ -24,6 +32,8 @@ static HANDLERS: &[Handler] = &[
     op_settabup,
     op_gettable,
     op_settable,
+    op_geti,
+    op_seti,
     op_getfield,
     op_setfield,
     op_self,
@@ -40,6 +50,19 @@ static HANDLERS: &[Handler] = &[
     op_bxor,
     op_shl,
     op_shr,
+    op_addi,
+    op_addk,
+    op_subk,
+    op_mulk,
+    op_modk,
+    op_powk,
+    op_divk,
+    op_idivk,
+    op_bandk,
+    op_bork,
+    op_bxork,
+    op_shri,
+    op_shli,
     op_unm,
     op_bnot,
     op_not,
@@ -51,6 +74,12 @@ static HANDLERS: &[Handler] = &[
     op_eq,
     op_lt,
     op_le,
+    op_eqk,
+    op_eqi,
+    op_lti,
+    op_lei,
+    op_gti,
+    op_gei,
     op_test,
     op_testset,
     op_call,
@@ -69,8 +98,25 @@ static HANDLERS: &[Handler] = &[
     op_errnnil,
     op_nop,
     op_stop,
+    op_add_ii,
+    op_add_ff,
+    op_sub_ii,
+    op_sub_ff,
+    op_mul_ii,
+    op_mul_ff,
+    op_lt_ii,
+    op_lt_ff,
+    op_le_ii,
+    op_le_ff,
+    op_forloop_i,
+    op_forloop_f,
+    op_gettable_array,
 ];
 
+/// Dispatch table of a thread with a debug hook installed: every opcode
+/// goes through [`op_hook`] before its own handler.
+static HOOK_HANDLERS: [Handler; HANDLERS.len()] = [op_hook; HANDLERS.len()];
+
 #[derive(Debug)]
 pub(crate) struct Error {
     pub pc: usize,
@@ -125,7 +171,7 @@ macro_rules! helpers {
                     #[cfg(debug_assertions)]
                     {
                         let frame = $thread.top_lua_unchecked();
-                        debug_assert!($ip.offset_from_unsigned(frame.closure.proto.code.as_ptr()) < frame.closure.proto.code.len());
+                        debug_assert!($ip.offset_from_unsigned(frame.closure.proto.code_ptr()) < frame.closure.proto.code.len());
                     }
                     let _ = $instruction;
                     let instruction = *$ip;
@@ -150,16 +196,18 @@ macro_rules! helpers {
 
         #[allow(unused_macros)]
         macro_rules! raise {
-            () => {{
-                become impl_error($instruction, $ctx, $thread, $registers, $ip, $handlers);
+            ($$err:expr) => {{
+                let __err: crate::env::Error<'gc> = $$err;
+                let _ = $registers;
+                return raise_error($ctx, $thread, $ip, __err);
             }};
         }
 
         #[allow(unused_macros)]
         macro_rules! check {
-            ($$cond:expr) => {{
+            ($$cond:expr, $$err:expr) => {{
                 if std::hint::unlikely(!$$cond) {
-                    raise!();
+                    raise!($$err);
                 }
             }};
         }
@@ -199,6 +247,21 @@ macro_rules! helpers {
             }};
         }
 
+        /// Collector and fuel safe point, placed on backward jumps and
+        /// calls. Charges `cost` fuel; when the step's fuel is spent or
+        /// enough allocation debt has built up, persist the current frame's
+        /// pc and return to the executor, which yields `StepResult::Pending`
+        /// so the host can collect outside the mutation context or move on.
+        #[allow(unused_macros)]
+        macro_rules! safepoint {
+            ($$cost:expr) => {{
+                if std::hint::unlikely(safepoint_due($ctx, $$cost)) {
+                    save_pc($thread, $ip);
+                    return Ok(());
+                }
+            }};
+        }
+
         #[allow(unused_macros)]
         macro_rules! skip {
             () => {{
@@ -206,6 +269,31 @@ macro_rules! helpers {
             }};
         }
 
+        /// Rewrite the instruction being executed in place to a quickened
+        /// form, unless it has despecialized too often already. Must run
+        /// before the handler moves `ip`.
+        #[allow(unused_macros)]
+        macro_rules! quicken {
+            ($$new:expr) => {{
+                if despecializations($thread, $ip).get() < MAX_DESPECIALIZATIONS {
+                    unsafe { rewrite_instruction($ip.sub(1), $$new) };
+                }
+            }};
+        }
+
+        /// The guard of a quickened instruction failed: rewrite it back to
+        /// its `generic` form and run that handler in its place, which may
+        /// quicken it again for the operands it sees (up to
+        /// `MAX_DESPECIALIZATIONS` times).
+        #[allow(unused_macros)]
+        macro_rules! despecialize {
+            ($$handler:ident, $$generic:expr) => {{
+                let __generic: Instruction = $$generic;
+                despecialize($thread, $ip, __generic);
+                become $$handler(__generic, $ctx, $thread, $registers, $ip, $handlers);
+            }};
+        }
+
         /// Schedule a metamethod (or iterator) call and dispatch into it.
         /// A Lua target dispatches into a fresh frame whose `op_return` resumes
         /// the continuation; a native target runs inline — synchronously the
@@ -249,7 +337,11 @@ macro_rules! helpers {
                     // Native target suspended (or errored): the executor will
                     // resume / unwind from the installed frame state.
                     MetaDispatch::Suspended => return Ok(()),
-                    MetaDispatch::Unresolvable => raise!(),
+                    MetaDispatch::Unresolvable => raise!(type_error($ctx, "call", $$meta, "")),
+                    MetaDispatch::CallChainTooLong => raise!(call_chain_error($ctx)),
+                    MetaDispatch::Overflow(msg) => {
+                        raise!(crate::env::Error::from_str($ctx, msg))
+                    }
                 }
             }};
         }
@@ -359,7 +451,7 @@ macro_rules! table_get_slow_body {
                 };
                 invoke_metamethod!(__mm_func, &[__mm_recv, __k], __cont);
             }
-            IndexChain::Exhausted => raise!(),
+            IndexChain::Exhausted => raise!(chain_error($ctx, "__index")),
         }
     }};
 }
@@ -378,7 +470,7 @@ macro_rules! userdata_get_slow_body {
         let __dst_reg: u8 = $dst;
 
         match userdata_index_chain(__u, __recv, __k, $ctx.symbols().mm_index) {
-            Err(()) => raise!(),
+            Err(()) => raise!(type_error($ctx, "index", __recv, "")),
             Ok(IndexChain::Resolved(__rv)) => {
                 *reg!(mut __dst_reg) = __rv;
                 dispatch!();
@@ -394,7 +486,7 @@ macro_rules! userdata_get_slow_body {
                 };
                 invoke_metamethod!(__mm_func, &[__mm_recv, __k], __cont);
             }
-            Ok(IndexChain::Exhausted) => raise!(),
+            Ok(IndexChain::Exhausted) => raise!(chain_error($ctx, "__index")),
         }
     }};
 }
@@ -412,7 +504,9 @@ macro_rules! table_set_slow_body {
 
         match walk_newindex_chain(__t, __k, $ctx.symbols().mm_newindex) {
             NewIndexChain::RawSet(__target) => {
-                __target.raw_set($ctx, __k, __new_val);
+                if let Err(__err) = __target.try_raw_set($ctx, __k, __new_val) {
+                    return raise_memory_error($thread, $ip, __err);
+                }
                 dispatch!();
             }
             NewIndexChain::Invoke {
@@ -426,7 +520,7 @@ macro_rules! table_set_slow_body {
                 };
                 invoke_metamethod!(__mm_func, &[__mm_recv, __k, __new_val], __cont);
             }
-            NewIndexChain::Exhausted => raise!(),
+            NewIndexChain::Exhausted => raise!(chain_error($ctx, "__newindex")),
         }
     }};
 }
@@ -437,7 +531,7 @@ macro_rules! table_set_slow_body {
 
 /// Read the IC entry for the current call site. The handler must have
 /// validated `ic_idx` came from a `GETFIELD`/`SETFIELD`/`GETTABUP`/
-/// `SETTABUP` instruction whose prototype was assembled with a matching
+/// `SETTABUP`/`SELF` instruction whose prototype was assembled with a matching
 /// `ic_table` length.
 #[inline(always)]
 fn read_ic<'gc>(thread: &ThreadState<'gc>, ic_idx: u16) -> InlineCache<'gc> {
@@ -448,19 +542,19 @@ fn read_ic<'gc>(thread: &ThreadState<'gc>, ic_idx: u16) -> InlineCache<'gc> {
     unsafe { proto.ic_table.get_unchecked(ic_idx as usize) }.get()
 }
 
-/// Refill the IC entry. Called by slow paths after they've done a full
-/// shape lookup; subsequent same-shape accesses skip the slow path.
+/// Record a shape and slot in the IC entry, widening it as needed (a
+/// megamorphic entry is left alone). Called by slow paths after they've
+/// done a full shape lookup; subsequent same-shape accesses skip the slow
+/// path.
 #[inline(always)]
-fn fill_ic<'gc>(
-    ctx: Context<'gc>,
-    thread: &ThreadState<'gc>,
-    ic_idx: u16,
-    shape: Shape<'gc>,
-    slot: u32,
-) {
+fn fill_ic<'gc>(ctx: Context<'gc>, thread: &ThreadState<'gc>, ic_idx: u16, entry: IcEntry<'gc>) {
     let proto_gc = unsafe { thread.top_lua_unchecked().closure.proto };
-    let value = InlineCache::Mono { shape, slot };
     if let Some(slot_lock) = proto_gc.ic_table.get(ic_idx as usize) {
+        let cache = slot_lock.get();
+        if matches!(cache, InlineCache::Mega) {
+            return;
+        }
+        let value = cache.with(entry);
         // We're adopting a fresh `Shape` Gc pointer through this slot
         // (transitively reachable from the parent `Prototype`), so emit
         // the backward barrier on the Prototype manually before writing
@@ -477,12 +571,36 @@ fn fill_ic<'gc>(
 /// handled downstream by `Shape::has_mm` — see `InlineCache`.
 #[inline(always)]
 fn ic_check<'gc>(cache: InlineCache<'gc>, live_shape: Shape<'gc>) -> Option<u32> {
-    if let InlineCache::Mono { shape, slot } = cache
-        && Shape::ptr_eq(live_shape, shape)
+    cache.lookup(live_shape)
+}
+
+/// Value a GETFIELD / SELF cache entry yields for the receiver in
+/// `t_state`: one of its own properties, or a property of the table its
+/// metatable's `__index` names, behind the guards `IndexLink` documents.
+/// `None` on a miss, a failed guard, or a nil the slow path must resolve.
+#[inline(always)]
+fn ic_get<'gc>(cache: InlineCache<'gc>, t_state: &TableState<'gc>) -> Option<Value<'gc>> {
+    let entry = cache.entry(t_state.shape())?;
+    if entry.slot == InlineCache::ABSENT_SLOT {
+        return None;
+    }
+    let Some(link) = entry.via else {
+        let v = unsafe { t_state.property_at(entry.slot) };
+        return (!(v.is_nil() && t_state.shape().has_mm(MetamethodBits::INDEX))).then_some(v);
+    };
+    let mt_state = t_state.metatable()?.inner().borrow();
+    if !Shape::ptr_eq(mt_state.shape(), link.meta_shape)
+        || unsafe { mt_state.property_at(link.index_slot) } != Value::table(link.holder)
     {
-        return Some(slot);
+        return None;
     }
-    None
+    drop(mt_state);
+    let holder = link.holder.inner().borrow();
+    if !Shape::ptr_eq(holder.shape(), link.holder_shape) {
+        return None;
+    }
+    let v = unsafe { holder.property_at(entry.slot) };
+    (!v.is_nil()).then_some(v)
 }
 
 /// Fill the IC entry from the table's *current* shape + slot for the
@@ -499,7 +617,7 @@ fn fill_ic_for_constant_key<'gc>(
     t: Table<'gc>,
     k: Value<'gc>,
 ) {
-    // GETFIELD/SETFIELD/GETTABUP/SETTABUP only carry constant string keys.
+    // GETFIELD/SETFIELD/GETTABUP/SETTABUP/SELF only carry constant string keys.
     debug_assert!(
         k.get_string().is_some(),
         "IC fill on non-string key — compiler invariant violation"
@@ -507,11 +625,137 @@ fn fill_ic_for_constant_key<'gc>(
     let Some(key_str) = k.get_string() else {
         return;
     };
+    // A megamorphic site is never refilled; skip the shape lookup too.
+    if matches!(read_ic(thread, ic_idx), InlineCache::Mega) {
+        return;
+    }
     let state = t.inner().borrow();
     let shape = state.shape();
     let slot = shape.find_slot(key_str).unwrap_or(InlineCache::ABSENT_SLOT);
     drop(state);
-    fill_ic(ctx, thread, ic_idx, shape, slot);
+    fill_ic(
+        ctx,
+        thread,
+        ic_idx,
+        IcEntry {
+            shape,
+            slot,
+            via: None,
+        },
+    );
+}
+
+/// [`fill_ic_for_constant_key`] for a GETFIELD / SELF site, which may also
+/// cache a key the table lacks but finds one `__index` table away. Every
+/// table involved must be in fast mode, so the cached slots stay valid
+/// for as long as the shapes match.
+fn fill_ic_for_get<'gc>(
+    ctx: Context<'gc>,
+    thread: &ThreadState<'gc>,
+    ic_idx: u16,
+    t: Table<'gc>,
+    k: Value<'gc>,
+) {
+    // A megamorphic site is never refilled; don't allocate an index link.
+    if matches!(read_ic(thread, ic_idx), InlineCache::Mega) {
+        return;
+    }
+    if let Some(entry) = index_link_entry(ctx, t, k) {
+        fill_ic(ctx, thread, ic_idx, entry);
+        return;
+    }
+    fill_ic_for_constant_key(ctx, thread, ic_idx, t, k);
+}
+
+/// The cache entry for string key `k` of `t` if `t` lacks it and its
+/// metatable's `__index` is a table holding it.
+fn index_link_entry<'gc>(ctx: Context<'gc>, t: Table<'gc>, k: Value<'gc>) -> Option<IcEntry<'gc>> {
+    let key = k.get_string()?;
+    let state = t.inner().borrow();
+    let shape = state.shape();
+    if shape.is_dict() || shape.find_slot(key).is_some() || !shape.has_mm(MetamethodBits::INDEX) {
+        return None;
+    }
+    let mt_state = state.metatable()?.inner().borrow();
+    let meta_shape = mt_state.shape();
+    if meta_shape.is_dict() {
+        return None;
+    }
+    let index_slot = meta_shape.find_slot(ctx.symbols().mm_index)?;
+    let holder = unsafe { mt_state.property_at(index_slot) }.get_table()?;
+    let holder_shape = holder.inner().borrow().shape();
+    if holder_shape.is_dict() {
+        return None;
+    }
+    let slot = holder_shape.find_slot(key)?;
+    let link = IndexLink {
+        meta_shape,
+        index_slot,
+        holder,
+        holder_shape,
+    };
+    Some(IcEntry {
+        shape,
+        slot,
+        via: Some(Gc::new(ctx.mutation(), link)),
+    })
+}
+
+// ---------------------------------------------------------------------------
+// Quickening
+// ---------------------------------------------------------------------------
+
+/// Times an instruction may fall back from a quickened form before it's
+/// left generic for good.
+const MAX_DESPECIALIZATIONS: u8 = 4;
+
+/// The despecialization count of the instruction before `ip`, which must
+/// point into the running prototype's `code`.
+#[inline(always)]
+fn despecializations<'a>(thread: &'a ThreadState<'_>, ip: *const Instruction) -> &'a Cell<u8> {
+    let proto = unsafe { &thread.top_lua_unchecked().closure.proto };
+    let pc = unsafe { ip.offset_from_unsigned(proto.code_ptr()) } - 1;
+    &proto.despecializations[pc]
+}
+
+/// Rewrite the quickened instruction before `ip` to its `generic` form
+/// and count the fallback. Out of line so the quickened handlers' fast
+/// paths don't need a stack frame.
+#[cold]
+#[inline(never)]
+fn despecialize(thread: &ThreadState<'_>, ip: *const Instruction, generic: Instruction) {
+    let count = despecializations(thread, ip);
+    count.set(count.get() + 1);
+    unsafe { rewrite_instruction(ip.sub(1), generic) };
+}
+
+/// Store `instruction` over the one at `at`, which must point into the
+/// running prototype's `code`.
+#[inline(always)]
+unsafe fn rewrite_instruction(at: *const Instruction, instruction: Instruction) {
+    // SAFETY: `code` is a slice of `Lock<Instruction>`, so `at` points at
+    // one. Instructions hold no `Gc` pointers, so writing past the lock
+    // needs no barrier. Handlers copy their instruction before running, so
+    // rewriting one that's still executing further up the stack is fine.
+    unsafe { (*at.cast::<Lock<Instruction>>()).as_cell().set(instruction) }
+}
+
+/// Whether the collector has accrued enough debt that execution should
+/// stop at the next safe point and let the host pay it down. Shared by the
+/// interpreter's `safepoint!` and the executor's driver loop so the two
+/// agree on when `Executor::step` returns `Pending`.
+#[inline(always)]
+pub(crate) fn collection_due(ctx: Context<'_>) -> bool {
+    ctx.gc_control().collection_due(ctx.mutation().metrics())
+}
+
+/// Charge `cost` fuel and tell whether the interpreter should stop at this
+/// safe point: the step's fuel is spent or a collection is due.
+#[inline(always)]
+fn safepoint_due(ctx: Context<'_>, cost: i64) -> bool {
+    let fuel = ctx.fuel();
+    fuel.consume(cost);
+    fuel.is_exhausted() || collection_due(ctx)
 }
 
 /// Drive the VM on `thread` until the top-level frame returns.
@@ -526,12 +770,16 @@ pub(crate) fn run_thread<'gc>(ctx: Context<'gc>, thread: Thread<'gc>) -> Result<
         let frame = ts
             .top_lua()
             .expect("run_thread requires a seeded Lua frame");
-        let code_ptr = frame.closure.proto.code.as_ptr();
+        let code_ptr = frame.closure.proto.code_ptr();
         let ip = unsafe { code_ptr.add(frame.pc) };
         (ip, frame.base)
     };
     let registers = unsafe { ts.stack.as_mut_ptr().add(base) };
-    let handlers = HANDLERS.as_ptr() as *const ();
+    let handlers = if ts.hook.get().is_some() {
+        HOOK_HANDLERS.as_ptr() as *const ()
+    } else {
+        HANDLERS.as_ptr() as *const ()
+    };
     op_nop(Instruction::NOP, ctx, &mut ts, registers, ip, handlers)
 }
 
@@ -539,18 +787,236 @@ pub(crate) fn run_thread<'gc>(ctx: Context<'gc>, thread: Thread<'gc>) -> Result<
 // Error
 // ---------------------------------------------------------------------------
 
+/// Unwind with `err` as a Lua error, exactly as a failing native call
+/// does: persist the faulting frame's pc and push `Frame::Error` for the
+/// executor's unwinder, which hands the error to the nearest `pcall` or
+/// ends the thread with it.
 #[cold]
 #[inline(never)]
-extern "rust-preserve-none" fn impl_error<'gc>(
-    _instruction: Instruction,
-    _ctx: Context<'gc>,
+fn raise_error<'gc>(
+    ctx: Context<'gc>,
     thread: &mut ThreadState<'gc>,
-    _registers: Registers<'gc, '_>,
     ip: *const Instruction,
-    _handlers: *const (),
+    err: crate::env::Error<'gc>,
 ) -> Result<(), Box<Error>> {
-    // See #44: compute proper PC from current frame's prototype code base.
-    Err(Box::new(Error { pc: 0 }))
+    save_pc(thread, ip);
+    // Faults are raised at level 1: the message names the faulting line.
+    let err = match thread.top_lua() {
+        Some(frame) => err.with_location(ctx, &frame.closure.proto.location(frame.pc - 1)),
+        None => err,
+    };
+    thread.frames.push(Frame::Error(err));
+    Ok(())
+}
+
+/// Raise a "not enough memory" error from [`check_memory`] or a fallible
+/// table write. Lua reports these without the faulting line.
+#[cold]
+fn raise_memory_error<'gc>(
+    thread: &mut ThreadState<'gc>,
+    ip: *const Instruction,
+    err: crate::env::Error<'gc>,
+) -> Result<(), Box<Error>> {
+    save_pc(thread, ip);
+    thread.frames.push(Frame::Error(err));
+    Ok(())
+}
+
+/// Persist `ip` as the top Lua frame's pc, so the frame can be resumed and
+/// reports the line it's at while control is elsewhere.
+#[inline(always)]
+fn save_pc(thread: &mut ThreadState<'_>, ip: *const Instruction) {
+    if let Some(frame) = thread.top_lua_mut() {
+        let code_start = frame.closure.proto.code_ptr();
+        frame.pc = unsafe { ip.offset_from_unsigned(code_start) };
+    }
+}
+
+/// `attempt to <action> a <type> value`, the message of every fault on a
+/// value of the wrong type. `info` describes where the value came from
+/// (see [`varinfo`]).
+#[cold]
+fn type_error<'gc>(
+    ctx: Context<'gc>,
+    action: &str,
+    value: Value<'gc>,
+    info: &str,
+) -> crate::env::Error<'gc> {
+    let msg = format!("attempt to {action} a {} value{info}", value.type_name());
+    crate::env::Error::from_str(ctx, &msg)
+}
+
+/// ` (local 'x')`-style description of what register `reg` holds for the
+/// instruction being executed, or nothing if it's an anonymous temporary.
+#[cold]
+fn varinfo(thread: &ThreadState<'_>, ip: *const Instruction, reg: u8) -> String {
+    let Some(frame) = thread.top_lua() else {
+        return String::new();
+    };
+    let proto = frame.closure.proto;
+    let pc = unsafe { ip.offset_from_unsigned(proto.code_ptr()) } - 1;
+    proto
+        .describe_register(pc, reg)
+        .map_or_else(String::new, |d| format!(" ({d})"))
+}
+
+/// ` (upvalue 'x')` for upvalue `idx` of the running function.
+#[cold]
+fn upvalinfo(thread: &ThreadState<'_>, idx: u8) -> String {
+    thread
+        .top_lua()
+        .and_then(|f| f.closure.proto.upvalue_names.get(idx as usize))
+        .map_or_else(String::new, |name| format!(" (upvalue '{name}')"))
+}
+
+/// Operand registers of a binary or unary arithmetic instruction; a unary
+/// one's operand is both, as is the register operand of one with a constant
+/// or immediate operand (which, being a number, is never blamed).
+fn operand_registers(instruction: Instruction) -> (u8, u8) {
+    use Instruction::*;
+    match instruction {
+        ADD { lhs, rhs, .. }
+        | SUB { lhs, rhs, .. }
+        | MUL { lhs, rhs, .. }
+        | MOD { lhs, rhs, .. }
+        | POW { lhs, rhs, .. }
+        | DIV { lhs, rhs, .. }
+        | IDIV { lhs, rhs, .. }
+        | BAND { lhs, rhs, .. }
+        | BOR { lhs, rhs, .. }
+        | BXOR { lhs, rhs, .. }
+        | SHL { lhs, rhs, .. }
+        | SHR { lhs, rhs, .. }
+        | CONCAT { lhs, rhs, .. } => (lhs, rhs),
+        ADDI { lhs, .. }
+        | ADDK { lhs, .. }
+        | SUBK { lhs, .. }
+        | MULK { lhs, .. }
+        | MODK { lhs, .. }
+        | POWK { lhs, .. }
+        | DIVK { lhs, .. }
+        | IDIVK { lhs, .. }
+        | BANDK { lhs, .. }
+        | BORK { lhs, .. }
+        | BXORK { lhs, .. }
+        | SHRI { lhs, .. } => (lhs, lhs),
+        UNM { src, .. } | BNOT { src, .. } | SHLI { rhs: src, .. } => (src, src),
+        _ => unreachable!("not an arithmetic instruction"),
+    }
+}
+
+/// [`type_error`] blaming the first operand if `first`, else the second.
+#[cold]
+fn operand_error<'gc>(
+    ctx: Context<'gc>,
+    thread: &ThreadState<'gc>,
+    ip: *const Instruction,
+    instruction: Instruction,
+    action: &str,
+    (a, b): (Value<'gc>, Value<'gc>),
+    first: bool,
+) -> crate::env::Error<'gc> {
+    let (lhs, rhs) = operand_registers(instruction);
+    let (value, reg) = if first { (a, lhs) } else { (b, rhs) };
+    type_error(ctx, action, value, &varinfo(thread, ip, reg))
+}
+
+/// Error of an arithmetic instruction neither operands nor metamethods
+/// could satisfy. Blames the first non-number operand; two numbers can
+/// only fail as an integer `//` or `%` by zero.
+#[cold]
+fn arith_error<'gc>(
+    ctx: Context<'gc>,
+    thread: &ThreadState<'gc>,
+    ip: *const Instruction,
+    instruction: Instruction,
+    a: Value<'gc>,
+    b: Value<'gc>,
+) -> crate::env::Error<'gc> {
+    if a.is_number() && b.is_number() {
+        let msg = match instruction {
+            Instruction::IDIV { .. } | Instruction::IDIVK { .. } => "attempt to perform 'n//0'",
+            _ => "attempt to perform 'n%0'",
+        };
+        return crate::env::Error::from_str(ctx, msg);
+    }
+    let action = "perform arithmetic on";
+    operand_error(ctx, thread, ip, instruction, action, (a, b), !a.is_number())
+}
+
+/// Error of a bitwise instruction. Two numbers can only fail when one of
+/// them is a float with no integer value.
+#[cold]
+fn bitwise_error<'gc>(
+    ctx: Context<'gc>,
+    thread: &ThreadState<'gc>,
+    ip: *const Instruction,
+    instruction: Instruction,
+    a: Value<'gc>,
+    b: Value<'gc>,
+) -> crate::env::Error<'gc> {
+    if a.is_number() && b.is_number() {
+        return crate::env::Error::from_str(ctx, "number has no integer representation");
+    }
+    let action = "perform bitwise operation on";
+    operand_error(ctx, thread, ip, instruction, action, (a, b), !a.is_number())
+}
+
+#[cold]
+fn concat_error<'gc>(
+    ctx: Context<'gc>,
+    thread: &ThreadState<'gc>,
+    ip: *const Instruction,
+    instruction: Instruction,
+    a: Value<'gc>,
+    b: Value<'gc>,
+) -> crate::env::Error<'gc> {
+    let first = !(a.is_number() || a.get_string().is_some());
+    operand_error(ctx, thread, ip, instruction, "concatenate", (a, b), first)
+}
+
+#[cold]
+fn compare_error<'gc>(ctx: Context<'gc>, a: Value<'gc>, b: Value<'gc>) -> crate::env::Error<'gc> {
+    let (ta, tb) = (a.type_name(), b.type_name());
+    let msg = if ta == tb {
+        format!("attempt to compare two {ta} values")
+    } else {
+        format!("attempt to compare {ta} with {tb}")
+    };
+    crate::env::Error::from_str(ctx, &msg)
+}
+
+/// An `__index` / `__newindex` chain ran past `MAX_TAG_LOOP` tables.
+#[cold]
+fn chain_error<'gc>(ctx: Context<'gc>, event: &str) -> crate::env::Error<'gc> {
+    let msg = format!("'{event}' chain too long; possible loop");
+    crate::env::Error::from_str(ctx, &msg)
+}
+
+/// A `__call` chain ran past `MAX_CALL_CHAIN` hops.
+#[cold]
+pub(crate) fn call_chain_error<'gc>(ctx: Context<'gc>) -> crate::env::Error<'gc> {
+    crate::env::Error::from_str(ctx, "'__call' chain too long")
+}
+
+/// `variable 'x' got a non-closable value`, raised by `TBC`.
+#[cold]
+fn tbc_error<'gc>(
+    ctx: Context<'gc>,
+    thread: &ThreadState<'gc>,
+    ip: *const Instruction,
+    reg: u8,
+) -> crate::env::Error<'gc> {
+    let name = thread.top_lua().and_then(|frame| {
+        let proto = frame.closure.proto;
+        let pc = unsafe { ip.offset_from_unsigned(proto.code_ptr()) } - 1;
+        proto.local_name(reg, pc).map(str::to_owned)
+    });
+    let msg = format!(
+        "variable '{}' got a non-closable value",
+        name.as_deref().unwrap_or("?")
+    );
+    crate::env::Error::from_str(ctx, &msg)
 }
 
 // ---------------------------------------------------------------------------
@@ -667,7 +1133,7 @@ extern "rust-preserve-none" fn op_gettabup<'gc>(
     let t_val = read_upvalue(thread, uv);
 
     let Some(t) = t_val.get_table() else {
-        raise!();
+        raise!(type_error(ctx, "index", t_val, &upvalinfo(thread, idx)));
     };
 
     let cache = read_ic(thread, ic_idx);
@@ -705,7 +1171,7 @@ extern "rust-preserve-none" fn gettabup_slow<'gc>(
     let uv = upvalue!(idx);
     let t_val = read_upvalue(thread, uv);
     let Some(t) = t_val.get_table() else {
-        raise!();
+        raise!(type_error(ctx, "index", t_val, &upvalinfo(thread, idx)));
     };
     let k = constant!(key);
     fill_ic_for_constant_key(ctx, thread, ic_idx, t, k);
@@ -733,7 +1199,7 @@ extern "rust-preserve-none" fn op_settabup<'gc>(
     let t_val = read_upvalue(thread, uv);
 
     let Some(t) = t_val.get_table() else {
-        raise!();
+        raise!(type_error(ctx, "index", t_val, &upvalinfo(thread, idx)));
     };
 
     let v = reg!(src);
@@ -775,7 +1241,7 @@ extern "rust-preserve-none" fn settabup_slow<'gc>(
     let uv = upvalue!(idx);
     let t_val = read_upvalue(thread, uv);
     let Some(t) = t_val.get_table() else {
-        raise!();
+        raise!(type_error(ctx, "index", t_val, &upvalinfo(thread, idx)));
     };
     let k = constant!(key);
     let v = reg!(src);
@@ -806,6 +1272,9 @@ extern "rust-preserve-none" fn op_gettable<'gc>(
     };
 
     let k = reg!(key);
+    if k.get_integer().is_some_and(|i| i >= 1) {
+        quicken!(Instruction::GETTABLE_ARRAY { dst, table, key });
+    }
     let (v, need_index) = {
         let t_state = t.inner().borrow();
         let v = t_state.raw_get(k);
@@ -838,11 +1307,44 @@ extern "rust-preserve-none" fn gettable_slow<'gc>(
         table_get_slow_body!(ctx, thread, registers, ip, handlers, t, k, dst);
     }
     let Some(u) = recv.get_userdata() else {
-        raise!();
+        raise!(type_error(ctx, "index", recv, &varinfo(thread, ip, table)));
     };
     userdata_get_slow_body!(ctx, thread, registers, ip, handlers, u, recv, k, dst);
 }
 
+/// `GETTABLE` of a table by a positive integer key, read straight from the
+/// array part.
+#[inline(never)]
+extern "rust-preserve-none" fn op_gettable_array<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    registers: Registers<'gc, '_>,
+    ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    let (dst, table, key) = args!(Instruction::GETTABLE_ARRAY { dst, table, key });
+    let generic = Instruction::GETTABLE { dst, table, key };
+    let (Some(t), Some(i @ 1..)) = (reg!(table).get_table(), reg!(key).get_integer()) else {
+        despecialize!(op_gettable, generic);
+    };
+
+    let (v, need_index) = {
+        let t_state = t.inner().borrow();
+        let v = t_state.array_get(i as usize);
+        let need = v.is_nil() && t_state.shape().has_mm(MetamethodBits::INDEX);
+        (v, need)
+    };
+
+    if need_index {
+        become gettable_slow(generic, ctx, thread, registers, ip, handlers);
+    }
+
+    *reg!(mut dst) = v;
+    dispatch!();
+}
+
 /// R[table][R[key]] = R[src]
 #[inline(never)]
 extern "rust-preserve-none" fn op_settable<'gc>(
@@ -857,7 +1359,12 @@ extern "rust-preserve-none" fn op_settable<'gc>(
     let (src, table, key) = args!(Instruction::SETTABLE { src, table, key });
 
     let Some(t) = reg!(table).get_table() else {
-        raise!();
+        raise!(type_error(
+            ctx,
+            "index",
+            reg!(table),
+            &varinfo(thread, ip, table)
+        ));
     };
 
     let k = reg!(key);
@@ -872,7 +1379,9 @@ extern "rust-preserve-none" fn op_settable<'gc>(
     }
 
     let mut t_state = t.inner().borrow_mut(ctx.mutation());
-    t_state.raw_set(ctx, k, v);
+    if let Err(err) = t_state.try_raw_set(ctx, k, v) {
+        return raise_memory_error(thread, ip, err);
+    }
     dispatch!()
 }
 
@@ -888,13 +1397,136 @@ extern "rust-preserve-none" fn settable_slow<'gc>(
     helpers!(instruction, ctx, thread, registers, ip, handlers);
     let (src, table, key) = args!(Instruction::SETTABLE { src, table, key });
     let Some(t) = reg!(table).get_table() else {
-        raise!();
+        raise!(type_error(
+            ctx,
+            "index",
+            reg!(table),
+            &varinfo(thread, ip, table)
+        ));
     };
     let k = reg!(key);
     let v = reg!(src);
     table_set_slow_body!(ctx, thread, registers, ip, handlers, t, k, v);
 }
 
+/// R[dst] = R[table][idx]
+#[inline(never)]
+extern "rust-preserve-none" fn op_geti<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    registers: Registers<'gc, '_>,
+    ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    let (dst, table, idx) = args!(Instruction::GETI { dst, table, idx });
+
+    let Some(t) = reg!(table).get_table() else {
+        become geti_slow(instruction, ctx, thread, registers, ip, handlers);
+    };
+
+    let (v, need_index) = {
+        let t_state = t.inner().borrow();
+        let v = t_state.raw_get(Value::small_integer(idx as i64));
+        let need = v.is_nil() && t_state.shape().has_mm(MetamethodBits::INDEX);
+        (v, need)
+    };
+
+    if need_index {
+        become geti_slow(instruction, ctx, thread, registers, ip, handlers);
+    }
+
+    *reg!(mut dst) = v;
+    dispatch!();
+}
+
+#[inline(never)]
+extern "rust-preserve-none" fn geti_slow<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    mut registers: Registers<'gc, '_>,
+    mut ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    let (dst, table, idx) = args!(Instruction::GETI { dst, table, idx });
+    let recv = reg!(table);
+    let k = Value::small_integer(idx as i64);
+    if let Some(t) = recv.get_table() {
+        table_get_slow_body!(ctx, thread, registers, ip, handlers, t, k, dst);
+    }
+    let Some(u) = recv.get_userdata() else {
+        raise!(type_error(ctx, "index", recv, &varinfo(thread, ip, table)));
+    };
+    userdata_get_slow_body!(ctx, thread, registers, ip, handlers, u, recv, k, dst);
+}
+
+/// R[table][idx] = R[src]
+#[inline(never)]
+extern "rust-preserve-none" fn op_seti<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    registers: Registers<'gc, '_>,
+    ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    let (src, table, idx) = args!(Instruction::SETI { src, table, idx });
+
+    let Some(t) = reg!(table).get_table() else {
+        raise!(type_error(
+            ctx,
+            "index",
+            reg!(table),
+            &varinfo(thread, ip, table)
+        ));
+    };
+
+    let k = Value::small_integer(idx as i64);
+    let v = reg!(src);
+    let needs_newindex = {
+        let t_state = t.inner().borrow();
+        t_state.shape().has_mm(MetamethodBits::NEWINDEX) && t_state.raw_get(k).is_nil()
+    };
+
+    if needs_newindex {
+        become seti_slow(instruction, ctx, thread, registers, ip, handlers);
+    }
+
+    let mut t_state = t.inner().borrow_mut(ctx.mutation());
+    if let Err(err) = t_state.try_raw_set(ctx, k, v) {
+        return raise_memory_error(thread, ip, err);
+    }
+    dispatch!()
+}
+
+#[inline(never)]
+extern "rust-preserve-none" fn seti_slow<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    mut registers: Registers<'gc, '_>,
+    mut ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    let (src, table, idx) = args!(Instruction::SETI { src, table, idx });
+    let Some(t) = reg!(table).get_table() else {
+        raise!(type_error(
+            ctx,
+            "index",
+            reg!(table),
+            &varinfo(thread, ip, table)
+        ));
+    };
+    let k = Value::small_integer(idx as i64);
+    let v = reg!(src);
+    table_set_slow_body!(ctx, thread, registers, ip, handlers, t, k, v);
+}
+
 /// R[dst] = R[table][K[key_idx]]
 #[inline(never)]
 extern "rust-preserve-none" fn op_getfield<'gc>(
@@ -920,15 +1552,10 @@ extern "rust-preserve-none" fn op_getfield<'gc>(
 
     let cache = read_ic(thread, ic_idx);
     let t_state = t.inner().borrow();
-    if let Some(slot) = ic_check(cache, t_state.shape()) {
-        if slot != InlineCache::ABSENT_SLOT {
-            let v = unsafe { t_state.property_at(slot) };
-            if !(v.is_nil() && t_state.shape().has_mm(MetamethodBits::INDEX)) {
-                drop(t_state);
-                *reg!(mut dst) = v;
-                dispatch!();
-            }
-        }
+    if let Some(v) = ic_get(cache, &t_state) {
+        drop(t_state);
+        *reg!(mut dst) = v;
+        dispatch!();
     }
     drop(t_state);
     become getfield_slow(instruction, ctx, thread, registers, ip, handlers);
@@ -953,11 +1580,11 @@ extern "rust-preserve-none" fn getfield_slow<'gc>(
     let recv = reg!(table);
     let k = constant!(key_idx);
     if let Some(t) = recv.get_table() {
-        fill_ic_for_constant_key(ctx, thread, ic_idx, t, k);
+        fill_ic_for_get(ctx, thread, ic_idx, t, k);
         table_get_slow_body!(ctx, thread, registers, ip, handlers, t, k, dst);
     }
     let Some(u) = recv.get_userdata() else {
-        raise!();
+        raise!(type_error(ctx, "index", recv, &varinfo(thread, ip, table)));
     };
     userdata_get_slow_body!(ctx, thread, registers, ip, handlers, u, recv, k, dst);
 }
@@ -981,7 +1608,12 @@ extern "rust-preserve-none" fn op_setfield<'gc>(
     });
 
     let Some(t) = reg!(table).get_table() else {
-        raise!();
+        raise!(type_error(
+            ctx,
+            "index",
+            reg!(table),
+            &varinfo(thread, ip, table)
+        ));
     };
 
     let v = reg!(src);
@@ -1020,7 +1652,12 @@ extern "rust-preserve-none" fn setfield_slow<'gc>(
         key_idx
     });
     let Some(t) = reg!(table).get_table() else {
-        raise!();
+        raise!(type_error(
+            ctx,
+            "index",
+            reg!(table),
+            &varinfo(thread, ip, table)
+        ));
     };
     let k = constant!(key_idx);
     let v = reg!(src);
@@ -1045,9 +1682,10 @@ extern "rust-preserve-none" fn op_self<'gc>(
     handlers: *const (),
 ) -> Result<(), Box<Error>> {
     helpers!(instruction, ctx, thread, registers, ip, handlers);
-    let (dst, object, key_idx) = args!(Instruction::SELF {
+    let (dst, object, ic_idx, _key_idx) = args!(Instruction::SELF {
         dst,
         object,
+        ic_idx,
         key_idx
     });
 
@@ -1057,21 +1695,16 @@ extern "rust-preserve-none" fn op_self<'gc>(
         become op_self_nontable(instruction, ctx, thread, registers, ip, handlers);
     };
 
-    let key = constant!(key_idx);
-    let (method, need_index) = {
-        let recv_state = recv.inner().borrow();
-        let v = recv_state.raw_get(key);
-        let need = v.is_nil() && recv_state.shape().has_mm(MetamethodBits::INDEX);
-        (v, need)
-    };
-
-    if need_index {
-        become op_self_slow(instruction, ctx, thread, registers, ip, handlers);
+    let cache = read_ic(thread, ic_idx);
+    let recv_state = recv.inner().borrow();
+    if let Some(method) = ic_get(cache, &recv_state) {
+        drop(recv_state);
+        *reg!(mut dst) = method;
+        *reg!(mut (dst + 1)) = recv_val;
+        dispatch!();
     }
-
-    *reg!(mut dst) = method;
-    *reg!(mut (dst + 1)) = recv_val;
-    dispatch!();
+    drop(recv_state);
+    become op_self_slow(instruction, ctx, thread, registers, ip, handlers);
 }
 
 #[inline(never)]
@@ -1084,20 +1717,38 @@ extern "rust-preserve-none" fn op_self_slow<'gc>(
     handlers: *const (),
 ) -> Result<(), Box<Error>> {
     helpers!(instruction, ctx, thread, registers, ip, handlers);
-    let (dst, object, key_idx) = args!(Instruction::SELF {
+    let (dst, object, ic_idx, key_idx) = args!(Instruction::SELF {
         dst,
         object,
+        ic_idx,
         key_idx
     });
 
     let recv_val = reg!(object);
     let Some(recv) = recv_val.get_table() else {
-        raise!();
+        raise!(type_error(
+            ctx,
+            "index",
+            recv_val,
+            &varinfo(thread, ip, object)
+        ));
     };
     let key = constant!(key_idx);
+    fill_ic_for_get(ctx, thread, ic_idx, recv, key);
 
-    // Reachable only when raw_get returned nil and the INDEX bit is set,
-    // so we go straight to the chain walk.
+    let (method, need_index) = {
+        let recv_state = recv.inner().borrow();
+        let v = recv_state.raw_get(key);
+        let need = v.is_nil() && recv_state.shape().has_mm(MetamethodBits::INDEX);
+        (v, need)
+    };
+    if !need_index {
+        *reg!(mut dst) = method;
+        *reg!(mut (dst + 1)) = recv_val;
+        dispatch!();
+    }
+
+    // The INDEX bit guarantees a metatable.
     let mt = unsafe { recv.metatable().unwrap_unchecked() };
     match walk_index_chain(recv_val, mt, key, ctx.symbols().mm_index) {
         IndexChain::Resolved(method) => {
@@ -1116,7 +1767,7 @@ extern "rust-preserve-none" fn op_self_slow<'gc>(
             };
             invoke_metamethod!(func, &[receiver, key], cont);
         }
-        IndexChain::Exhausted => raise!(),
+        IndexChain::Exhausted => raise!(chain_error(ctx, "__index")),
     }
 }
 
@@ -1136,15 +1787,21 @@ extern "rust-preserve-none" fn op_self_nontable<'gc>(
     handlers: *const (),
 ) -> Result<(), Box<Error>> {
     helpers!(instruction, ctx, thread, registers, ip, handlers);
-    let (dst, object, key_idx) = args!(Instruction::SELF {
+    let (dst, object, _ic_idx, key_idx) = args!(Instruction::SELF {
         dst,
         object,
+        ic_idx,
         key_idx
     });
 
     let recv_val = reg!(object);
     let Some(u) = recv_val.get_userdata() else {
-        raise!();
+        raise!(type_error(
+            ctx,
+            "index",
+            recv_val,
+            &varinfo(thread, ip, object)
+        ));
     };
     let key = constant!(key_idx);
 
@@ -1168,7 +1825,7 @@ extern "rust-preserve-none" fn op_self_nontable<'gc>(
             };
             invoke_metamethod!(func, &[receiver, key], cont);
         }
-        IndexChain::Exhausted => raise!(),
+        IndexChain::Exhausted => raise!(chain_error(ctx, "__index")),
     }
 }
 
@@ -1192,8 +1849,220 @@ extern "rust-preserve-none" fn op_newtable<'gc>(
 // Arithmetic and bitwise (register-register)
 // ---------------------------------------------------------------------------
 
-macro_rules! binop_handler {
-    ($fn_name:ident, $instr:ident, $op:ident, $num_kind:ty, $mm:ident) => {
+/// Register-register arithmetic. With `quicken` forms given, the handler
+/// rewrites itself to the integer or float form for two operands of that
+/// kind.
+macro_rules! binop_handler {
+    ($fn_name:ident, $instr:ident, $op:ident, $num_kind:ty, $mm:ident, $fault:ident
+     $(, quicken($ii:ident, $ff:ident))?) => {
+        #[inline(never)]
+        extern "rust-preserve-none" fn $fn_name<'gc>(
+            instruction: Instruction,
+            ctx: Context<'gc>,
+            thread: &mut ThreadState<'gc>,
+            mut registers: Registers<'gc, '_>,
+            mut ip: *const Instruction,
+            handlers: *const (),
+        ) -> Result<(), Box<Error>> {
+            helpers!(instruction, ctx, thread, registers, ip, handlers);
+            let (dst, lhs, rhs) = args!(Instruction::$instr { dst, lhs, rhs });
+            let (a, b) = (reg!(lhs), reg!(rhs));
+            $(
+                if a.get_inline_integer().is_some() && b.get_inline_integer().is_some() {
+                    quicken!(Instruction::$ii { dst, lhs, rhs });
+                } else if a.get_float().is_some() && b.get_float().is_some() {
+                    quicken!(Instruction::$ff { dst, lhs, rhs });
+                }
+            )?
+            if let Some(v) = $op::<$num_kind>(ctx, a, b) {
+                *reg!(mut dst) = v;
+                dispatch!();
+            }
+            let meta_fn = binop_metamethod(a, b, ctx.symbols().$mm);
+            if meta_fn.is_nil() {
+                raise!($fault(ctx, thread, ip, instruction, a, b));
+            }
+            let cont = Continuation {
+                payload: ContinuationPayload::StoreResult { dst },
+                results_base: 0,
+                nret: 0,
+            };
+            invoke_metamethod!(meta_fn, &[a, b], cont);
+        }
+    };
+}
+
+binop_handler!(
+    op_add,
+    ADD,
+    op_arith,
+    num::Add,
+    mm_add,
+    arith_error,
+    quicken(ADD_II, ADD_FF)
+);
+binop_handler!(
+    op_sub,
+    SUB,
+    op_arith,
+    num::Sub,
+    mm_sub,
+    arith_error,
+    quicken(SUB_II, SUB_FF)
+);
+binop_handler!(
+    op_mul,
+    MUL,
+    op_arith,
+    num::Mul,
+    mm_mul,
+    arith_error,
+    quicken(MUL_II, MUL_FF)
+);
+binop_handler!(op_mod, MOD, op_arith, num::Mod, mm_mod, arith_error);
+binop_handler!(op_pow, POW, op_arith, num::Pow, mm_pow, arith_error);
+binop_handler!(op_div, DIV, op_arith, num::Div, mm_div, arith_error);
+binop_handler!(op_idiv, IDIV, op_arith, num::IDiv, mm_idiv, arith_error);
+binop_handler!(op_band, BAND, op_bit, num::BAnd, mm_band, bitwise_error);
+binop_handler!(op_bor, BOR, op_bit, num::BOr, mm_bor, bitwise_error);
+binop_handler!(op_bxor, BXOR, op_bit, num::BXor, mm_bxor, bitwise_error);
+binop_handler!(op_shl, SHL, op_bit, num::Shl, mm_shl, bitwise_error);
+binop_handler!(op_shr, SHR, op_bit, num::Shr, mm_shr, bitwise_error);
+
+/// Quickened register-register arithmetic: `$apply` the operation to two
+/// operands of the kind `$get` reads, or give the instruction back to its
+/// generic handler.
+macro_rules! quickened_binop_handler {
+    ($fn_name:ident, $instr:ident, $get:ident, $apply:ident, $num_kind:ty,
+     $generic:ident, $generic_fn:ident) => {
+        #[inline(never)]
+        extern "rust-preserve-none" fn $fn_name<'gc>(
+            instruction: Instruction,
+            ctx: Context<'gc>,
+            thread: &mut ThreadState<'gc>,
+            registers: Registers<'gc, '_>,
+            ip: *const Instruction,
+            handlers: *const (),
+        ) -> Result<(), Box<Error>> {
+            helpers!(instruction, ctx, thread, registers, ip, handlers);
+            let (dst, lhs, rhs) = args!(Instruction::$instr { dst, lhs, rhs });
+            if let (Some(x), Some(y)) = (reg!(lhs).$get(), reg!(rhs).$get()) {
+                *reg!(mut dst) = $apply::<$num_kind>(ctx, x, y);
+                dispatch!();
+            }
+            despecialize!($generic_fn, Instruction::$generic { dst, lhs, rhs });
+        }
+    };
+}
+
+quickened_binop_handler!(
+    op_add_ii,
+    ADD_II,
+    get_inline_integer,
+    int_arith,
+    num::Add,
+    ADD,
+    op_add
+);
+quickened_binop_handler!(
+    op_add_ff,
+    ADD_FF,
+    get_float,
+    float_arith,
+    num::Add,
+    ADD,
+    op_add
+);
+quickened_binop_handler!(
+    op_sub_ii,
+    SUB_II,
+    get_inline_integer,
+    int_arith,
+    num::Sub,
+    SUB,
+    op_sub
+);
+quickened_binop_handler!(
+    op_sub_ff,
+    SUB_FF,
+    get_float,
+    float_arith,
+    num::Sub,
+    SUB,
+    op_sub
+);
+quickened_binop_handler!(
+    op_mul_ii,
+    MUL_II,
+    get_inline_integer,
+    int_arith,
+    num::Mul,
+    MUL,
+    op_mul
+);
+quickened_binop_handler!(
+    op_mul_ff,
+    MUL_FF,
+    get_float,
+    float_arith,
+    num::Mul,
+    MUL,
+    op_mul
+);
+
+// ---------------------------------------------------------------------------
+// Arithmetic and bitwise (constant and immediate operands)
+// ---------------------------------------------------------------------------
+
+macro_rules! binop_k_handler {
+    ($fn_name:ident, $instr:ident, $op:ident, $num_kind:ty, $mm:ident, $fault:ident) => {
+        #[inline(never)]
+        extern "rust-preserve-none" fn $fn_name<'gc>(
+            instruction: Instruction,
+            ctx: Context<'gc>,
+            thread: &mut ThreadState<'gc>,
+            mut registers: Registers<'gc, '_>,
+            mut ip: *const Instruction,
+            handlers: *const (),
+        ) -> Result<(), Box<Error>> {
+            helpers!(instruction, ctx, thread, registers, ip, handlers);
+            let (dst, lhs, key) = args!(Instruction::$instr { dst, lhs, key });
+            let (a, b) = (reg!(lhs), constant!(key));
+            if let Some(v) = $op::<$num_kind>(ctx, a, b) {
+                *reg!(mut dst) = v;
+                dispatch!();
+            }
+            let meta_fn = binop_metamethod(a, b, ctx.symbols().$mm);
+            if meta_fn.is_nil() {
+                raise!($fault(ctx, thread, ip, instruction, a, b));
+            }
+            let cont = Continuation {
+                payload: ContinuationPayload::StoreResult { dst },
+                results_base: 0,
+                nret: 0,
+            };
+            invoke_metamethod!(meta_fn, &[a, b], cont);
+        }
+    };
+}
+
+binop_k_handler!(op_addk, ADDK, op_arith, num::Add, mm_add, arith_error);
+binop_k_handler!(op_subk, SUBK, op_arith, num::Sub, mm_sub, arith_error);
+binop_k_handler!(op_mulk, MULK, op_arith, num::Mul, mm_mul, arith_error);
+binop_k_handler!(op_modk, MODK, op_arith, num::Mod, mm_mod, arith_error);
+binop_k_handler!(op_powk, POWK, op_arith, num::Pow, mm_pow, arith_error);
+binop_k_handler!(op_divk, DIVK, op_arith, num::Div, mm_div, arith_error);
+binop_k_handler!(op_idivk, IDIVK, op_arith, num::IDiv, mm_idiv, arith_error);
+binop_k_handler!(op_bandk, BANDK, op_bit, num::BAnd, mm_band, bitwise_error);
+binop_k_handler!(op_bork, BORK, op_bit, num::BOr, mm_bor, bitwise_error);
+binop_k_handler!(op_bxork, BXORK, op_bit, num::BXor, mm_bxor, bitwise_error);
+
+/// Immediate-operand arithmetic: `R[dst] = R[operand] op imm`, or
+/// `imm op R[operand]` when `imm_first`. Metamethods see the operands in
+/// source order.
+macro_rules! binop_imm_handler {
+    ($fn_name:ident, $instr:ident, $operand:ident, $imm_first:literal,
+     $op:ident, $num_kind:ty, $mm:ident, $fault:ident) => {
         #[inline(never)]
         extern "rust-preserve-none" fn $fn_name<'gc>(
             instruction: Instruction,
@@ -1204,15 +2073,19 @@ macro_rules! binop_handler {
             handlers: *const (),
         ) -> Result<(), Box<Error>> {
             helpers!(instruction, ctx, thread, registers, ip, handlers);
-            let (dst, lhs, rhs) = args!(Instruction::$instr { dst, lhs, rhs });
-            let (a, b) = (reg!(lhs), reg!(rhs));
-            if let Some(v) = $op::<$num_kind>(a, b) {
+            let (dst, $operand, imm) = args!(Instruction::$instr { dst, $operand, imm });
+            let (a, b) = if $imm_first {
+                (Value::small_integer(imm as i64), reg!($operand))
+            } else {
+                (reg!($operand), Value::small_integer(imm as i64))
+            };
+            if let Some(v) = $op::<$num_kind>(ctx, a, b) {
                 *reg!(mut dst) = v;
                 dispatch!();
             }
             let meta_fn = binop_metamethod(a, b, ctx.symbols().$mm);
             if meta_fn.is_nil() {
-                raise!();
+                raise!($fault(ctx, thread, ip, instruction, a, b));
             }
             let cont = Continuation {
                 payload: ContinuationPayload::StoreResult { dst },
@@ -1224,18 +2097,36 @@ macro_rules! binop_handler {
     };
 }
 
-binop_handler!(op_add, ADD, op_arith, num::Add, mm_add);
-binop_handler!(op_sub, SUB, op_arith, num::Sub, mm_sub);
-binop_handler!(op_mul, MUL, op_arith, num::Mul, mm_mul);
-binop_handler!(op_mod, MOD, op_arith, num::Mod, mm_mod);
-binop_handler!(op_pow, POW, op_arith, num::Pow, mm_pow);
-binop_handler!(op_div, DIV, op_arith, num::Div, mm_div);
-binop_handler!(op_idiv, IDIV, op_arith, num::IDiv, mm_idiv);
-binop_handler!(op_band, BAND, op_bit, num::BAnd, mm_band);
-binop_handler!(op_bor, BOR, op_bit, num::BOr, mm_bor);
-binop_handler!(op_bxor, BXOR, op_bit, num::BXor, mm_bxor);
-binop_handler!(op_shl, SHL, op_bit, num::Shl, mm_shl);
-binop_handler!(op_shr, SHR, op_bit, num::Shr, mm_shr);
+binop_imm_handler!(
+    op_addi,
+    ADDI,
+    lhs,
+    false,
+    op_arith,
+    num::Add,
+    mm_add,
+    arith_error
+);
+binop_imm_handler!(
+    op_shri,
+    SHRI,
+    lhs,
+    false,
+    op_bit,
+    num::Shr,
+    mm_shr,
+    bitwise_error
+);
+binop_imm_handler!(
+    op_shli,
+    SHLI,
+    rhs,
+    true,
+    op_bit,
+    num::Shl,
+    mm_shl,
+    bitwise_error
+);
 
 // ---------------------------------------------------------------------------
 // Unary operations
@@ -1255,7 +2146,7 @@ extern "rust-preserve-none" fn op_unm<'gc>(
     let (dst, src) = args!(Instruction::UNM { dst, src });
     let val = reg!(src);
     if let Some(i) = val.get_integer() {
-        *reg!(mut dst) = Value::integer(i.wrapping_neg());
+        *reg!(mut dst) = Value::integer(ctx, i.wrapping_neg());
         dispatch!();
     }
     if let Some(f) = val.get_float() {
@@ -1264,7 +2155,7 @@ extern "rust-preserve-none" fn op_unm<'gc>(
     }
     let meta_fn = unop_metamethod(val, ctx.symbols().mm_unm);
     if meta_fn.is_nil() {
-        raise!();
+        raise!(arith_error(ctx, thread, ip, instruction, val, val));
     }
     let cont = Continuation {
         payload: ContinuationPayload::StoreResult { dst },
@@ -1288,13 +2179,16 @@ extern "rust-preserve-none" fn op_bnot<'gc>(
     helpers!(instruction, ctx, thread, registers, ip, handlers);
     let (dst, src) = args!(Instruction::BNOT { dst, src });
     let val = reg!(src);
-    if let Some(i) = val.get_integer() {
-        *reg!(mut dst) = Value::integer(!i);
+    if let Some(i) = val
+        .get_integer()
+        .or_else(|| val.get_float().and_then(num::exact_float_to_int))
+    {
+        *reg!(mut dst) = Value::integer(ctx, !i);
         dispatch!();
     }
     let meta_fn = unop_metamethod(val, ctx.symbols().mm_bnot);
     if meta_fn.is_nil() {
-        raise!();
+        raise!(bitwise_error(ctx, thread, ip, instruction, val, val));
     }
     let cont = Continuation {
         payload: ContinuationPayload::StoreResult { dst },
@@ -1337,7 +2231,7 @@ extern "rust-preserve-none" fn op_len<'gc>(
 
     // Strings never consult __len; return byte length directly.
     if let Some(s) = val.get_string() {
-        *reg!(mut dst) = Value::integer(s.len() as i64);
+        *reg!(mut dst) = Value::small_integer(s.len() as i64);
         dispatch!();
     }
 
@@ -1345,12 +2239,17 @@ extern "rust-preserve-none" fn op_len<'gc>(
     let meta_fn = if let Some(t) = val.get_table() {
         let mm = t.get_metamethod(ctx.symbols().mm_len);
         if mm.is_nil() {
-            *reg!(mut dst) = Value::integer(t.raw_len() as i64);
+            *reg!(mut dst) = Value::small_integer(t.raw_len() as i64);
             dispatch!();
         }
         mm
     } else {
-        raise!()
+        raise!(type_error(
+            ctx,
+            "get length of",
+            val,
+            &varinfo(thread, ip, src)
+        ))
     };
 
     let cont = Continuation {
@@ -1376,14 +2275,22 @@ extern "rust-preserve-none" fn op_concat<'gc>(
     let a = reg!(lhs);
     let b = reg!(rhs);
     // Fast path: both coerce to strings/numbers.
-    let mut buf = Vec::new();
-    if num::coerce_to_str(&mut buf, a) && num::coerce_to_str(&mut buf, b) {
+    if let (Some(a_len), Some(b_len)) = (num::coerced_len(a), num::coerced_len(b)) {
+        // The result is built in `buf` and then copied into the string, so
+        // both have to fit under the memory limit.
+        let len = a_len + b_len;
+        if let Err(err) = check_memory(ctx, 2 * len) {
+            return raise_memory_error(thread, ip, err);
+        }
+        let mut buf = Vec::with_capacity(len);
+        num::coerce_to_str(&mut buf, a);
+        num::coerce_to_str(&mut buf, b);
         *reg!(mut dst) = Value::string(LuaString::new(ctx, &buf));
         dispatch!();
     }
     let meta_fn = binop_metamethod(a, b, ctx.symbols().mm_concat);
     if meta_fn.is_nil() {
-        raise!();
+        raise!(concat_error(ctx, thread, ip, instruction, a, b));
     }
     let cont = Continuation {
         payload: ContinuationPayload::StoreResult { dst },
@@ -1397,7 +2304,7 @@ extern "rust-preserve-none" fn op_concat<'gc>(
 // Upvalue / resource management
 // ---------------------------------------------------------------------------
 
-/// Close all upvalues >= R[start].
+/// Close all upvalues and to-be-closed variables >= R[start].
 #[inline(never)]
 extern "rust-preserve-none" fn op_close<'gc>(
     instruction: Instruction,
@@ -1412,7 +2319,10 @@ extern "rust-preserve-none" fn op_close<'gc>(
     let base = thread.top_lua().map_or(0, |f| f.base);
     let start_idx = base + start as usize;
     close_upvalues(ctx.mutation(), thread, start_idx);
-    close_tbc_vars(ctx.mutation(), thread, start_idx);
+    if close::has_pending(thread, start_idx) {
+        schedule_close(ctx, thread, start_idx, ip);
+        return Ok(());
+    }
     dispatch!();
 }
 
@@ -1428,6 +2338,15 @@ extern "rust-preserve-none" fn op_tbc<'gc>(
 ) -> Result<(), Box<Error>> {
     helpers!(instruction, ctx, thread, registers, ip, handlers);
     let val = args!(Instruction::TBC { val });
+    let v = reg!(val);
+    // `nil` and `false` are accepted and ignored.
+    if v.is_falsy() {
+        dispatch!();
+    }
+    check!(
+        !close::close_metamethod(ctx, v).is_nil(),
+        tbc_error(ctx, thread, ip, val)
+    );
     let base = thread.top_lua().map_or(0, |f| f.base);
     thread.tbc_slots.push(base + val as usize);
     dispatch!();
@@ -1450,6 +2369,9 @@ extern "rust-preserve-none" fn op_jmp<'gc>(
     helpers!(instruction, ctx, thread, registers, ip, handlers);
     let offset = args!(Instruction::JMP { offset });
     ip = unsafe { ip.offset(offset as isize) };
+    if offset < 0 {
+        safepoint!(offset.unsigned_abs() as i64);
+    }
     dispatch!();
 }
 
@@ -1516,6 +2438,11 @@ extern "rust-preserve-none" fn op_lt<'gc>(
     let (lhs, rhs, inverted) = args!(Instruction::LT { lhs, rhs, inverted });
     let a = reg!(lhs);
     let b = reg!(rhs);
+    if a.get_inline_integer().is_some() && b.get_inline_integer().is_some() {
+        quicken!(Instruction::LT_II { lhs, rhs, inverted });
+    } else if a.get_float().is_some() && b.get_float().is_some() {
+        quicken!(Instruction::LT_FF { lhs, rhs, inverted });
+    }
     let primitive = if let (Some(x), Some(y)) = (a.get_integer(), b.get_integer()) {
         Some(x < y)
     } else if let (Some(x), Some(y)) = (a.get_float(), b.get_float()) {
@@ -1537,7 +2464,7 @@ extern "rust-preserve-none" fn op_lt<'gc>(
     }
     let meta_fn = binop_metamethod(a, b, ctx.symbols().mm_lt);
     if meta_fn.is_nil() {
-        raise!();
+        raise!(compare_error(ctx, a, b));
     }
     let cont = Continuation {
         payload: ContinuationPayload::CondJump {
@@ -1564,6 +2491,11 @@ extern "rust-preserve-none" fn op_le<'gc>(
     let (lhs, rhs, inverted) = args!(Instruction::LE { lhs, rhs, inverted });
     let a = reg!(lhs);
     let b = reg!(rhs);
+    if a.get_inline_integer().is_some() && b.get_inline_integer().is_some() {
+        quicken!(Instruction::LE_II { lhs, rhs, inverted });
+    } else if a.get_float().is_some() && b.get_float().is_some() {
+        quicken!(Instruction::LE_FF { lhs, rhs, inverted });
+    }
     let primitive = if let (Some(x), Some(y)) = (a.get_integer(), b.get_integer()) {
         Some(x <= y)
     } else if let (Some(x), Some(y)) = (a.get_float(), b.get_float()) {
@@ -1585,7 +2517,7 @@ extern "rust-preserve-none" fn op_le<'gc>(
     }
     let meta_fn = binop_metamethod(a, b, ctx.symbols().mm_le);
     if meta_fn.is_nil() {
-        raise!();
+        raise!(compare_error(ctx, a, b));
     }
     let cont = Continuation {
         payload: ContinuationPayload::CondJump {
@@ -1598,6 +2530,129 @@ extern "rust-preserve-none" fn op_le<'gc>(
     invoke_metamethod!(meta_fn, &[a, b], cont);
 }
 
+/// Quickened order comparison of two operands of the kind `$get` reads,
+/// or else the generic handler's.
+macro_rules! quickened_compare_handler {
+    ($fn_name:ident, $instr:ident, $get:ident, $cmp:tt, $generic:ident, $generic_fn:ident) => {
+        #[inline(never)]
+        extern "rust-preserve-none" fn $fn_name<'gc>(
+            instruction: Instruction,
+            ctx: Context<'gc>,
+            thread: &mut ThreadState<'gc>,
+            registers: Registers<'gc, '_>,
+            mut ip: *const Instruction,
+            handlers: *const (),
+        ) -> Result<(), Box<Error>> {
+            helpers!(instruction, ctx, thread, registers, ip, handlers);
+            let (lhs, rhs, inverted) = args!(Instruction::$instr { lhs, rhs, inverted });
+            if let (Some(x), Some(y)) = (reg!(lhs).$get(), reg!(rhs).$get()) {
+                if (x $cmp y) != inverted {
+                    skip!();
+                }
+                dispatch!();
+            }
+            despecialize!($generic_fn, Instruction::$generic { lhs, rhs, inverted });
+        }
+    };
+}
+
+quickened_compare_handler!(op_lt_ii, LT_II, get_inline_integer, <, LT, op_lt);
+quickened_compare_handler!(op_lt_ff, LT_FF, get_float, <, LT, op_lt);
+quickened_compare_handler!(op_le_ii, LE_II, get_inline_integer, <=, LE, op_le);
+quickened_compare_handler!(op_le_ff, LE_FF, get_float, <=, LE, op_le);
+
+/// if (R[lhs] == K[key]) != inverted then skip next instruction
+///
+/// Constants are never tables or userdata, so `__eq` cannot apply.
+#[inline(never)]
+extern "rust-preserve-none" fn op_eqk<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    registers: Registers<'gc, '_>,
+    mut ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    let (lhs, key, inverted) = args!(Instruction::EQK { lhs, key, inverted });
+    if raw_eq(reg!(lhs), constant!(key)) != inverted {
+        skip!();
+    }
+    dispatch!();
+}
+
+/// if (R[lhs] == imm) != inverted then skip next instruction
+#[inline(never)]
+extern "rust-preserve-none" fn op_eqi<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    registers: Registers<'gc, '_>,
+    mut ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    let (lhs, imm, inverted) = args!(Instruction::EQI { lhs, imm, inverted });
+    if raw_eq(reg!(lhs), Value::small_integer(imm as i64)) != inverted {
+        skip!();
+    }
+    dispatch!();
+}
+
+/// Order comparison against an immediate. `GTI` / `GEI` are evaluated as
+/// `imm < R` / `imm <= R` so a metamethod sees the operands in source order.
+macro_rules! compare_imm_handler {
+    ($fn_name:ident, $instr:ident, $cmp:tt, $imm_first:literal, $mm:ident) => {
+        #[inline(never)]
+        extern "rust-preserve-none" fn $fn_name<'gc>(
+            instruction: Instruction,
+            ctx: Context<'gc>,
+            thread: &mut ThreadState<'gc>,
+            mut registers: Registers<'gc, '_>,
+            mut ip: *const Instruction,
+            handlers: *const (),
+        ) -> Result<(), Box<Error>> {
+            helpers!(instruction, ctx, thread, registers, ip, handlers);
+            let (lhs, imm, inverted) = args!(Instruction::$instr { lhs, imm, inverted });
+            let v = reg!(lhs);
+            let primitive = if let Some(x) = v.get_integer() {
+                Some(x $cmp imm as i64)
+            } else {
+                v.get_float().map(|x| x $cmp imm as f64)
+            };
+            if let Some(r) = primitive {
+                if r != inverted {
+                    skip!();
+                }
+                dispatch!();
+            }
+            let (a, b) = if $imm_first {
+                (Value::small_integer(imm as i64), v)
+            } else {
+                (v, Value::small_integer(imm as i64))
+            };
+            let meta_fn = binop_metamethod(a, b, ctx.symbols().$mm);
+            if meta_fn.is_nil() {
+                raise!(compare_error(ctx, a, b));
+            }
+            let cont = Continuation {
+                payload: ContinuationPayload::CondJump {
+                    offset: 1,
+                    inverted,
+                },
+                results_base: 0,
+                nret: 0,
+            };
+            invoke_metamethod!(meta_fn, &[a, b], cont);
+        }
+    };
+}
+
+compare_imm_handler!(op_lti, LTI, <, false, mm_lt);
+compare_imm_handler!(op_lei, LEI, <=, false, mm_le);
+compare_imm_handler!(op_gti, GTI, >, true, mm_lt);
+compare_imm_handler!(op_gei, GEI, >=, true, mm_le);
+
 /// if (not R[src]) == inverted then skip next instruction
 #[inline(never)]
 extern "rust-preserve-none" fn op_test<'gc>(
@@ -1662,18 +2717,27 @@ extern "rust-preserve-none" fn op_call<'gc>(
     });
     let base = thread.top_lua().map_or(0, |f| f.base);
     let func_idx = base + func as usize;
-    let Some((target, nargs)) = resolve_call_chain(ctx, thread, func_idx, nargs) else {
-        raise!();
+    let callee = thread.stack[func_idx];
+    let (target, nargs) = match resolve_call_chain(ctx, thread, func_idx, nargs) {
+        Ok(resolved) => resolved,
+        Err(CallChainError::NotCallable) => {
+            raise!(type_error(ctx, "call", callee, &varinfo(thread, ip, func)))
+        }
+        Err(CallChainError::TooLong) => raise!(call_chain_error(ctx)),
     };
 
     match target {
         CallTarget::Lua(closure) => {
             let new_base = func_idx + 1;
+            let needed = new_base + closure.proto.max_stack_size as usize;
+            let depth = thread.frames.len() + 1;
+            if let Err(msg) = ctx.stack_limits().check(thread, depth, needed) {
+                raise!(crate::env::Error::from_str(ctx, msg));
+            }
             if let Some(frame) = thread.top_lua_mut() {
-                let code_start = frame.closure.proto.code.as_ptr();
+                let code_start = frame.closure.proto.code_ptr();
                 frame.pc = unsafe { ip.offset_from_unsigned(code_start) };
             }
-            let needed = new_base + closure.proto.max_stack_size as usize;
             if thread.stack.len() < needed {
                 thread.stack.resize(needed, Value::nil());
             }
@@ -1700,8 +2764,9 @@ extern "rust-preserve-none" fn op_call<'gc>(
                 num_extras,
                 continuation: None,
             });
-            ip = closure.proto.code.as_ptr();
+            ip = closure.proto.code_ptr();
             registers = unsafe { thread.stack.as_mut_ptr().add(new_base) };
+            safepoint!(CALL_FUEL);
             dispatch!();
         }
         CallTarget::Native(nc) => {
@@ -1711,18 +2776,16 @@ extern "rust-preserve-none" fn op_call<'gc>(
             } else {
                 nargs as usize - 1
             };
+            // Persist the caller's pc before the call: the native may ask
+            // where it was called from (`error`), and if it fails, re-entry
+            // after a catch resumes from here.
+            save_pc(thread, ip);
             let action = match invoke_native(ctx, thread, nc, args_base, argc) {
                 Ok(a) => a,
                 Err(err) => {
                     // Push Frame::Error so the executor's unwinder finds
                     // the nearest catching `Frame::Sequence` (e.g. the
-                    // PCallSequence under coroutine.resume). Persist
-                    // caller's pc first so re-entry would work if anything
-                    // catches and resumes.
-                    if let Some(frame) = thread.top_lua_mut() {
-                        let code_start = frame.closure.proto.code.as_ptr();
-                        frame.pc = unsafe { ip.offset_from_unsigned(code_start) };
-                    }
+                    // PCallSequence under coroutine.resume).
                     thread.frames.push(Frame::Error(err));
                     return Ok(());
                 }
@@ -1765,13 +2828,9 @@ extern "rust-preserve-none" fn op_call<'gc>(
                     dispatch!();
                 }
                 action => {
-                    // Suspension path: persist caller's pc, stash the
-                    // action on the thread for the executor to translate
-                    // into frame ops, then exit the dispatch chain.
-                    if let Some(frame) = thread.top_lua_mut() {
-                        let code_start = frame.closure.proto.code.as_ptr();
-                        frame.pc = unsafe { ip.offset_from_unsigned(code_start) };
-                    }
+                    // Suspension path (caller's pc is already persisted):
+                    // stash the action on the thread for the executor to
+                    // translate into frame ops, then exit the dispatch chain.
                     thread.pending_action = Some(PendingAction {
                         action,
                         call_site: CallSite {
@@ -1802,8 +2861,13 @@ extern "rust-preserve-none" fn op_tailcall<'gc>(
     let (func, nargs) = args!(Instruction::TAILCALL { func, args });
     let base = thread.top_lua().map_or(0, |f| f.base);
     let func_idx = base + func as usize;
-    let Some((target, nargs)) = resolve_call_chain(ctx, thread, func_idx, nargs) else {
-        raise!();
+    let callee = thread.stack[func_idx];
+    let (target, nargs) = match resolve_call_chain(ctx, thread, func_idx, nargs) {
+        Ok(resolved) => resolved,
+        Err(CallChainError::NotCallable) => {
+            raise!(type_error(ctx, "call", callee, &varinfo(thread, ip, func)))
+        }
+        Err(CallChainError::TooLong) => raise!(call_chain_error(ctx)),
     };
 
     match target {
@@ -1817,6 +2881,11 @@ extern "rust-preserve-none" fn op_tailcall<'gc>(
             };
             let caller_func_idx = cur_base - 1 - cur_num_extras;
             let new_base = caller_func_idx + 1;
+            let needed = new_base + closure.proto.max_stack_size as usize;
+            let depth = thread.frames.len();
+            if let Err(msg) = ctx.stack_limits().check(thread, depth, needed) {
+                raise!(crate::env::Error::from_str(ctx, msg));
+            }
             // Close upvalues before overwriting these slots with args, so an
             // open upvalue keeps referencing the local, not the arg value.
             close_upvalues(ctx.mutation(), thread, new_base);
@@ -1842,15 +2911,15 @@ extern "rust-preserve-none" fn op_tailcall<'gc>(
             } else {
                 0
             };
-            let needed = new_base + closure.proto.max_stack_size as usize;
             if thread.stack.len() < needed {
                 thread.stack.resize(needed, Value::nil());
             }
             for i in nargs..num_params {
                 thread.stack[new_base + i] = Value::nil();
             }
-            ip = closure.proto.code.as_ptr();
+            ip = closure.proto.code_ptr();
             registers = unsafe { thread.stack.as_mut_ptr().add(new_base) };
+            safepoint!(CALL_FUEL);
             dispatch!();
         }
         CallTarget::Native(nc) => {
@@ -1860,6 +2929,9 @@ extern "rust-preserve-none" fn op_tailcall<'gc>(
             } else {
                 nargs as usize - 1
             };
+            // The tailcalling frame stays until the native returns, so it is
+            // what the native sees as its caller.
+            save_pc(thread, ip);
             let action = match invoke_native(ctx, thread, nc, args_base, argc) {
                 Ok(a) => a,
                 Err(err) => {
@@ -1869,7 +2941,6 @@ extern "rust-preserve-none" fn op_tailcall<'gc>(
                     // executor's unwinder.
                     let cur_base = thread.top_lua().unwrap().base;
                     close_upvalues(ctx.mutation(), thread, cur_base);
-                    close_tbc_vars(ctx.mutation(), thread, cur_base);
                     thread.frames.pop();
                     thread.frames.push(Frame::Error(err));
                     return Ok(());
@@ -1902,7 +2973,6 @@ extern "rust-preserve-none" fn op_tailcall<'gc>(
                         (f.base, f.num_results)
                     };
                     close_upvalues(ctx.mutation(), thread, cur_base);
-                    close_tbc_vars(ctx.mutation(), thread, cur_base);
                     thread.frames.pop();
                     thread.pending_action = Some(PendingAction {
                         action,
@@ -1941,6 +3011,11 @@ extern "rust-preserve-none" fn op_return<'gc>(
     } else {
         count as usize - 1
     };
+    if std::hint::unlikely(close::has_pending(thread, cur_base)) {
+        save_pc(thread, ip);
+        return_closing(ctx, thread, values_base, nret);
+        return Ok(());
+    }
 
     match frame_return(ctx.mutation(), thread, values_base, nret) {
         FrameReturn::Continuation => {
@@ -2021,14 +3096,26 @@ extern "rust-preserve-none" fn op_forloop<'gc>(
     if let (Some(i), Some(lim), Some(s)) =
         (cur.get_integer(), lim_v.get_integer(), step.get_integer())
     {
+        if cur.get_inline_integer().is_some()
+            && lim_v.get_inline_integer().is_some()
+            && step.get_inline_integer().is_some()
+        {
+            quicken!(Instruction::FORLOOP_I { base, offset });
+        }
         let next = i.wrapping_add(s);
         let cont = if s > 0 { next <= lim } else { next >= lim };
         if cont {
-            *reg!(mut base) = Value::integer(next);
-            *reg!(mut base + 3) = Value::integer(next);
+            // Box a wide counter once; both registers can share it.
+            let next = Value::integer(ctx, next);
+            *reg!(mut base) = next;
+            *reg!(mut base + 3) = next;
             ip = unsafe { ip.offset(offset as isize) };
+            safepoint!(offset.unsigned_abs() as i64);
         }
     } else {
+        if cur.get_float().is_some() && lim_v.get_float().is_some() && step.get_float().is_some() {
+            quicken!(Instruction::FORLOOP_F { base, offset });
+        }
         let i = to_number(cur).unwrap_or(0.0);
         let lim = to_number(lim_v).unwrap_or(0.0);
         let s = to_number(step).unwrap_or(0.0);
@@ -2038,12 +3125,74 @@ extern "rust-preserve-none" fn op_forloop<'gc>(
             *reg!(mut base) = Value::float(next);
             *reg!(mut base + 3) = Value::float(next);
             ip = unsafe { ip.offset(offset as isize) };
+            safepoint!(offset.unsigned_abs() as i64);
         }
     }
 
     dispatch!();
 }
 
+/// `FORLOOP` over inline integer control values.
+#[inline(never)]
+extern "rust-preserve-none" fn op_forloop_i<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    registers: Registers<'gc, '_>,
+    mut ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    let (base, offset) = args!(Instruction::FORLOOP_I { base, offset });
+    let (Some(i), Some(lim), Some(s)) = (
+        reg!(base).get_inline_integer(),
+        reg!(base + 1).get_inline_integer(),
+        reg!(base + 2).get_inline_integer(),
+    ) else {
+        despecialize!(op_forloop, Instruction::FORLOOP { base, offset });
+    };
+    let next = i.wrapping_add(s);
+    let cont = if s > 0 { next <= lim } else { next >= lim };
+    if cont {
+        let next = Value::integer(ctx, next);
+        *reg!(mut base) = next;
+        *reg!(mut base + 3) = next;
+        ip = unsafe { ip.offset(offset as isize) };
+        safepoint!(offset.unsigned_abs() as i64);
+    }
+    dispatch!();
+}
+
+/// `FORLOOP` over float control values.
+#[inline(never)]
+extern "rust-preserve-none" fn op_forloop_f<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    registers: Registers<'gc, '_>,
+    mut ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    let (base, offset) = args!(Instruction::FORLOOP_F { base, offset });
+    let (Some(i), Some(lim), Some(s)) = (
+        reg!(base).get_float(),
+        reg!(base + 1).get_float(),
+        reg!(base + 2).get_float(),
+    ) else {
+        despecialize!(op_forloop, Instruction::FORLOOP { base, offset });
+    };
+    let next = i + s;
+    let cont = if s > 0.0 { next <= lim } else { next >= lim };
+    if cont {
+        *reg!(mut base) = Value::float(next);
+        *reg!(mut base + 3) = Value::float(next);
+        ip = unsafe { ip.offset(offset as isize) };
+        safepoint!(offset.unsigned_abs() as i64);
+    }
+    dispatch!();
+}
+
 // ---------------------------------------------------------------------------
 // Generic for loop
 // ---------------------------------------------------------------------------
@@ -2104,6 +3253,7 @@ extern "rust-preserve-none" fn op_tforloop<'gc>(
     if !first.is_nil() {
         *reg!(mut base + 2) = first;
         ip = unsafe { ip.offset(offset as isize) };
+        safepoint!(offset.unsigned_abs() as i64);
     }
     dispatch!();
 }
@@ -2129,7 +3279,12 @@ extern "rust-preserve-none" fn op_setlist<'gc>(
         offset
     });
     let Some(t) = reg!(table).get_table() else {
-        raise!();
+        raise!(type_error(
+            ctx,
+            "index",
+            reg!(table),
+            &varinfo(thread, ip, table)
+        ));
     };
     // `count == 0` is MULTRET: element count comes from `thread.top`. It can
     // exceed u8 (a `VARARG count=0` spread), so index the stack by usize.
@@ -2146,8 +3301,10 @@ extern "rust-preserve-none" fn op_setlist<'gc>(
     let off = offset as i64;
     for i in 1..=n {
         let val = thread.stack[elements_start + i - 1];
-        let key = Value::integer(off + i as i64);
-        t.raw_set(ctx, key, val);
+        let key = Value::integer(ctx, off + i as i64);
+        if let Err(err) = t.try_raw_set(ctx, key, val) {
+            return raise_memory_error(thread, ip, err);
+        }
     }
     if count == 0 {
         // A MULTRET spread leaves `thread.stack` truncated to `thread.top` by
@@ -2186,34 +3343,29 @@ extern "rust-preserve-none" fn op_closure<'gc>(
     let proto = parent_closure.proto.prototypes[proto_idx as usize];
 
     let thread_handle = thread.thread_handle.expect("thread must have a handle");
-    let mut upvalues_vec = Vec::with_capacity(proto.upvalue_desc.len());
-    for desc in proto.upvalue_desc.iter() {
-        let uv = match desc {
-            UpValueDescriptor::ParentLocal(idx) => {
-                let stack_idx = base + *idx as usize;
-                // Check if there's already an open upvalue for this stack slot
-                let existing = thread.open_upvalues.iter().find(|uv| {
-                    matches!(&*uv.borrow(), UpvalueState::Open { index, .. } if *index == stack_idx)
-                });
-                if let Some(uv) = existing {
-                    *uv
-                } else {
-                    let uv: Upvalue<'gc> = Gc::new(
-                        ctx.mutation(),
-                        RefLock::new(UpvalueState::Open {
-                            thread: thread_handle,
-                            index: stack_idx,
-                        }),
-                    );
-                    thread.open_upvalues.push(uv);
-                    uv
-                }
+    let upvalues = proto.upvalue_desc.iter().map(|desc| match desc {
+        UpValueDescriptor::ParentLocal(idx) => {
+            let stack_idx = base + *idx as usize;
+            // Check if there's already an open upvalue for this stack slot
+            let existing = thread.open_upvalues.iter().find(|uv| {
+                matches!(&*uv.borrow(), UpvalueState::Open { index, .. } if *index == stack_idx)
+            });
+            if let Some(uv) = existing {
+                *uv
+            } else {
+                let uv: Upvalue<'gc> = Gc::new(
+                    ctx.mutation(),
+                    RefLock::new(UpvalueState::Open {
+                        thread: thread_handle,
+                        index: stack_idx,
+                    }),
+                );
+                thread.open_upvalues.push(uv);
+                uv
             }
-            UpValueDescriptor::ParentUpvalue(idx) => parent_closure.upvalues[*idx as usize],
-        };
-        upvalues_vec.push(uv);
-    }
-    let upvalues: Box<[Upvalue<'gc>]> = upvalues_vec.into_boxed_slice();
+        }
+        UpValueDescriptor::ParentUpvalue(idx) => parent_closure.upvalues[*idx as usize],
+    });
 
     let func = Function::new_lua(ctx.mutation(), proto, upvalues);
     *reg!(mut dst) = Value::function(func);
@@ -2273,7 +3425,7 @@ extern "rust-preserve-none" fn op_vararg<'gc>(
         }
         let t = table.inner().borrow();
         for i in 0..wanted {
-            thread.stack[target + i] = t.raw_get(Value::integer(i as i64 + 1));
+            thread.stack[target + i] = t.raw_get(Value::small_integer(i as i64 + 1));
         }
         if count == 0 {
             thread.top = new_top;
@@ -2345,7 +3497,7 @@ extern "rust-preserve-none" fn op_varargget<'gc>(
         }
     } else if let Some(s) = key_val.get_string() {
         if s.as_bytes() == b"n" {
-            Value::integer(num_extras as i64)
+            Value::small_integer(num_extras as i64)
         } else {
             Value::nil()
         }
@@ -2408,12 +3560,12 @@ extern "rust-preserve-none" fn op_varargprep<'gc>(
         thread.stack[new_base + num_params] = Value::table(table);
         for i in 0..num_extras {
             let v = thread.stack[extras_start + i];
-            table.raw_set(ctx, Value::integer(i as i64 + 1), v);
+            table.raw_set(ctx, Value::small_integer(i as i64 + 1), v);
         }
         table.raw_set(
             ctx,
             Value::string(LuaString::new(ctx, b"n")),
-            Value::integer(num_extras as i64),
+            Value::small_integer(num_extras as i64),
         );
     }
     dispatch!();
@@ -2430,9 +3582,15 @@ extern "rust-preserve-none" fn op_errnnil<'gc>(
     handlers: *const (),
 ) -> Result<(), Box<Error>> {
     helpers!(instruction, ctx, thread, registers, ip, handlers);
-    // TODO: surface the name (see #28).
-    let (src, _name_key) = args!(Instruction::ERRNNIL { src, name_key });
-    check!(reg!(src).is_nil());
+    let (src, name_key) = args!(Instruction::ERRNNIL { src, name_key });
+    check!(reg!(src).is_nil(), {
+        let name = constant!(name_key).get_string().unwrap();
+        let msg = format!(
+            "global '{}' already defined",
+            String::from_utf8_lossy(name.as_bytes())
+        );
+        crate::env::Error::from_str(ctx, &msg)
+    });
     dispatch!();
 }
 
@@ -2468,6 +3626,164 @@ extern "rust-preserve-none" fn op_stop<'gc>(
     Ok(())
 }
 
+// ---------------------------------------------------------------------------
+// Debug hooks
+// ---------------------------------------------------------------------------
+
+/// Entry of every opcode while the thread has a debug hook: reports the
+/// events due at this instruction, then runs its own handler. The hook is
+/// called like a metamethod whose continuation re-dispatches this
+/// instruction, one event per call; the thread's [`HookCursor`] recognises
+/// the re-dispatch and moves on to the next event.
+#[inline(never)]
+extern "rust-preserve-none" fn op_hook<'gc>(
+    instruction: Instruction,
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    mut registers: Registers<'gc, '_>,
+    mut ip: *const Instruction,
+    handlers: *const (),
+) -> Result<(), Box<Error>> {
+    helpers!(instruction, ctx, thread, registers, ip, handlers);
+    if let Some(hook) = thread.hook.get()
+        && let Some(event) = next_hook_event(thread, instruction, ip, hook)
+    {
+        let name = match event {
+            HookEvents::CALL => "call",
+            HookEvents::TAIL_CALL => "tail call",
+            HookEvents::COUNT => "count",
+            HookEvents::LINE => "line",
+            _ => "return",
+        };
+        let mut args = [
+            Value::string(LuaString::new(ctx, name.as_bytes())),
+            Value::nil(),
+        ];
+        let nargs = if event == HookEvents::LINE {
+            let frame = unsafe { thread.top_lua_unchecked() };
+            let pc = unsafe { ip.offset_from_unsigned(frame.closure.proto.code_ptr()) } - 1;
+            args[1] = Value::small_integer(frame.closure.proto.line(pc) as i64);
+            2
+        } else {
+            1
+        };
+        // Resume at this instruction once the hook returns.
+        ip = unsafe { ip.sub(1) };
+        invoke_metamethod!(
+            Value::function(hook.function),
+            &args[..nargs],
+            Continuation {
+                payload: ContinuationPayload::IgnoreResult,
+                results_base: 0,
+                nret: 0,
+            }
+        );
+    }
+    let handler = HANDLERS[instruction.discriminant() as usize];
+    become handler(instruction, ctx, thread, registers, ip, handlers);
+}
+
+/// Advance the thread's [`HookCursor`] to the instruction before `ip` and
+/// take the next event `hook` wants reported there, if any.
+fn next_hook_event(
+    thread: &mut ThreadState<'_>,
+    instruction: Instruction,
+    ip: *const Instruction,
+    hook: Hook<'_>,
+) -> Option<HookEvents> {
+    let depth = thread.frames.len();
+    let proto = unsafe { thread.top_lua_unchecked() }.closure.proto;
+    let pc = unsafe { ip.offset_from_unsigned(proto.code_ptr()) } - 1;
+    let cursor = &mut thread.hook_cursor;
+
+    if cursor.running.is_some_and(|running| depth > running) {
+        // Inside the hook function: its own code isn't hooked.
+        return None;
+    }
+    // Back at the hooked instruction after a hook call returned (rather
+    // than unwound), with the rest of its events still pending.
+    let resumed = cursor
+        .running
+        .take()
+        .is_some_and(|running| running == depth && cursor.pc == pc);
+
+    if thread.hook.take_changed() {
+        *cursor = HookCursor {
+            depth,
+            pc,
+            countdown: hook.count,
+            ..HookCursor::default()
+        };
+        if resumed {
+            return None;
+        }
+        // Installing the hook isn't itself a call or a new line, unless
+        // the frame hasn't started yet.
+        match pc.checked_sub(1) {
+            Some(prev) => cursor.pc = prev,
+            None => cursor.depth -= 1,
+        }
+    }
+
+    if !resumed {
+        let mut events = HookEvents::empty();
+        let same_frame = depth == cursor.depth && !cursor.returned;
+        if depth > cursor.depth || (depth == cursor.depth && cursor.returned) {
+            events |= HookEvents::CALL;
+        } else if same_frame && cursor.tailcall && pc == 0 {
+            events |= HookEvents::TAIL_CALL;
+        }
+        if hook.count > 0 {
+            cursor.countdown = cursor.countdown.saturating_sub(1);
+            if cursor.countdown == 0 {
+                cursor.countdown = hook.count;
+                events |= HookEvents::COUNT;
+            }
+        }
+        let line = proto.line(pc);
+        let new_line = if !events.intersects(HookEvents::CALL | HookEvents::TAIL_CALL) {
+            if same_frame {
+                pc <= cursor.pc || line != proto.line(cursor.pc)
+            } else {
+                // Returned into this frame: compare with the line of the
+                // call it returned from.
+                pc.checked_sub(1)
+                    .is_none_or(|call| line != proto.line(call))
+            }
+        } else {
+            true
+        };
+        if new_line {
+            events |= HookEvents::LINE;
+        }
+        let returning = matches!(instruction, Instruction::RETURN { .. });
+        if returning {
+            events |= HookEvents::RETURN;
+        }
+
+        let mut wanted = HookEvents::COUNT;
+        if hook.mask.contains(HookMask::CALL) {
+            wanted |= HookEvents::CALL | HookEvents::TAIL_CALL;
+        }
+        if hook.mask.contains(HookMask::RETURN) {
+            wanted |= HookEvents::RETURN;
+        }
+        if hook.mask.contains(HookMask::LINE) {
+            wanted |= HookEvents::LINE;
+        }
+        cursor.depth = depth;
+        cursor.pc = pc;
+        cursor.tailcall = matches!(instruction, Instruction::TAILCALL { .. });
+        cursor.returned = returning;
+        cursor.pending = events & wanted;
+    }
+
+    let event = cursor.pending.iter().next()?;
+    cursor.pending.remove(event);
+    cursor.running = Some(depth);
+    Some(event)
+}
+
 // ---------------------------------------------------------------------------
 // Helpers
 // ---------------------------------------------------------------------------
@@ -2482,20 +3798,78 @@ fn to_number(v: Value) -> Option<f64> {
     None
 }
 
-/// Close all TBC variables at stack indices >= `start_idx`.
-/// Removes them from the tracking list; __close invocation is pending (see #45).
-pub(crate) fn close_tbc_vars<'gc>(
-    _mc: &Mutation<'gc>,
+/// Close the to-be-closed variables at stack indices >= `start_idx` through
+/// a [`CloseSequence`] run by the executor. Like a native metamethod that
+/// suspends, it's staged above the frame's register window and lands with
+/// an `IgnoreResult` continuation, so the frame resumes at `ip` once every
+/// variable is closed.
+#[cold]
+#[inline(never)]
+fn schedule_close<'gc>(
+    ctx: Context<'gc>,
     thread: &mut ThreadState<'gc>,
     start_idx: usize,
+    ip: *const Instruction,
 ) {
-    thread.tbc_slots.retain(|&slot| {
-        if slot >= start_idx {
-            // See #45: invoke __close metamethod on thread.stack[slot].
-            false
-        } else {
-            true
-        }
+    save_pc(thread, ip);
+    let pending = close::take_pending(thread, start_idx);
+    let bottom = {
+        let frame = thread.top_lua().unwrap();
+        frame.base + frame.closure.proto.max_stack_size as usize
+    };
+    thread.stack.resize(bottom, Value::nil());
+    let seq = BoxSequence::new(ctx.mutation(), CloseSequence::new(pending, 0));
+    thread.pending_action = Some(PendingAction {
+        action: CallbackAction::Sequence(seq),
+        call_site: CallSite {
+            bottom,
+            func_idx: bottom,
+            returns: 0,
+            cont: Some(Continuation {
+                payload: ContinuationPayload::IgnoreResult,
+                results_base: 0,
+                nret: 0,
+            }),
+        },
+    });
+}
+
+/// `RETURN` from a frame that still has to-be-closed variables. The frame
+/// is popped and a [`CloseSequence`] takes its place, holding the `nret`
+/// results at `stack[values_base..]`; once the variables are closed it
+/// returns them to the frame's caller, the way a suspending native that
+/// was tail-called does.
+#[cold]
+#[inline(never)]
+fn return_closing<'gc>(
+    ctx: Context<'gc>,
+    thread: &mut ThreadState<'gc>,
+    values_base: usize,
+    nret: usize,
+) {
+    let (cur_base, num_results, num_extras, continuation) = {
+        let f = thread.top_lua().unwrap();
+        (f.base, f.num_results, f.num_extras as usize, f.continuation)
+    };
+    let pending = close::take_pending(thread, cur_base);
+    close_upvalues(ctx.mutation(), thread, cur_base);
+    thread.frames.pop();
+    thread.stack.truncate(values_base + nret);
+    // A caller that isn't a Lua frame takes every result, as in `frame_return`.
+    let returns = if thread.top_lua().is_some() {
+        num_results
+    } else {
+        0
+    };
+    let seq = BoxSequence::new(ctx.mutation(), CloseSequence::new(pending, nret));
+    thread.pending_action = Some(PendingAction {
+        action: CallbackAction::Sequence(seq),
+        call_site: CallSite {
+            bottom: values_base,
+            func_idx: cur_base - 1 - num_extras,
+            returns,
+            cont: continuation,
+        },
     });
 }
 
@@ -2557,13 +3931,14 @@ pub(crate) fn invoke_native<'gc>(
     } else if thread.stack.len() < end {
         thread.stack.resize(end, Value::nil());
     }
+    ctx.fuel().consume(NATIVE_CALL_FUEL);
     let current_thread = thread
         .thread_handle
         .expect("ThreadState missing back-reference");
     let nctx = NativeContext {
         ctx,
         upvalues: &nc.upvalues,
-        exec: crate::vm::sequence::Execution::new(current_thread),
+        exec: crate::vm::sequence::Execution::new(current_thread, &thread.frames, &thread.hook),
     };
     let stack = Stack::new(&mut thread.stack, args_base);
     (nc.function)(nctx, stack)
@@ -2618,8 +3993,11 @@ pub(crate) fn frame_return<'gc>(
     // The func slot sits at `cur_base - 1 - num_extras`: VARARGPREP shifted
     // base past the extras at `[cur_base - num_extras .. cur_base]` (0 for
     // non-vararg frames).
+    debug_assert!(
+        !close::has_pending(thread, cur_base),
+        "to-be-closed variables must be closed before the frame returns"
+    );
     close_upvalues(mc, thread, cur_base);
-    close_tbc_vars(mc, thread, cur_base);
     thread.frames.pop();
 
     let dst_start = cur_base - 1 - num_extras;
@@ -2670,7 +4048,7 @@ pub(crate) fn frame_return<'gc>(
 
     let caller = thread.top_lua().unwrap();
     let new_base = caller.base;
-    let new_ip = unsafe { caller.closure.proto.code.as_ptr().add(caller.pc) };
+    let new_ip = unsafe { caller.closure.proto.code_ptr().add(caller.pc) };
     FrameReturn::Caller { new_base, new_ip }
 }
 
@@ -2709,8 +4087,8 @@ pub(crate) fn close_upvalues<'gc>(
 // Metamethod invocation / continuations
 // ---------------------------------------------------------------------------
 
-/// Maximum depth of `__index` / `__newindex` / `__call` chains before we
-/// give up and raise (matches Lua 5.4's `MAXTAGLOOP`).
+/// Maximum depth of `__index` / `__newindex` chains before we give up and
+/// raise (matches Lua 5.4's `MAXTAGLOOP`).
 const MAX_TAG_LOOP: usize = 2000;
 
 /// Result of walking an `__index` chain.
@@ -2872,54 +4250,82 @@ pub(crate) enum MetaDispatch {
     /// Target is not callable (or a suspending comparison metamethod, which we
     /// don't support). The caller raises.
     Unresolvable,
+    /// The metamethod's `__call` chain ran past `MAX_CALL_CHAIN` hops. The
+    /// caller raises.
+    CallChainTooLong,
+    /// Calling the metamethod would overflow the thread's stack limits. The
+    /// caller raises the message.
+    Overflow(&'static str),
+}
+
+/// Why [`resolve_call_chain`] couldn't find a callable target.
+pub(crate) enum CallChainError {
+    /// A value in the chain is neither a function nor has `__call`.
+    NotCallable,
+    /// More than [`MAX_CALL_CHAIN`] `__call` hops.
+    TooLong,
 }
 
+/// Maximum number of `__call` hops for a single call (Lua 5.5's `MAX_CCMT`).
+/// Each hop prepends an argument, so a loop fails here rather than growing
+/// the stack without bound.
+const MAX_CALL_CHAIN: usize = 15;
+
 /// Walk the `__call` chain at `thread.stack[func_idx]` until we hit a
 /// callable target, shifting args right by one on each hop to prepend the
 /// current callee as the first argument (Lua 5.5 `tryfuncTM` behavior).
-/// Returns the resolved target and the (possibly adjusted) `nargs`, or
-/// `None` if the chain is unresolvable: non-callable value, variadic call
-/// with `__call` (see #46), or `MAX_TAG_LOOP` exhaustion. Callers raise on
-/// `None`.
+/// Returns the resolved target and the (possibly adjusted) `nargs`.
+///
+/// `nargs` follows `CALL`'s convention: the argument count plus one, or 0
+/// for a MULTRET call whose arguments end at `thread.top`. A MULTRET call
+/// stays MULTRET with `thread.top` bumped per hop; a fixed one whose count
+/// no longer fits the encoding is converted to MULTRET.
 #[inline]
-fn resolve_call_chain<'gc>(
+pub(crate) fn resolve_call_chain<'gc>(
     ctx: Context<'gc>,
     thread: &mut ThreadState<'gc>,
     func_idx: usize,
     mut nargs: u8,
-) -> Option<(CallTarget<'gc>, u8)> {
-    for _ in 0..MAX_TAG_LOOP {
+) -> Result<(CallTarget<'gc>, u8), CallChainError> {
+    for hop in 0..=MAX_CALL_CHAIN {
         let func_val = thread.stack[func_idx];
         if let Some(f) = func_val.get_function() {
             return match f.inner().as_ref() {
-                FunctionKind::Lua(c) => Some((CallTarget::Lua(*c), nargs)),
-                FunctionKind::Native(nc) => Some((CallTarget::Native(nc), nargs)),
+                FunctionKind::Lua(c) => Ok((CallTarget::Lua(*c), nargs)),
+                FunctionKind::Native(nc) => Ok((CallTarget::Native(nc), nargs)),
             };
         }
         let mm = match func_val.get_table() {
             Some(t) => t.get_metamethod(ctx.symbols().mm_call),
-            None => return None,
+            None => return Err(CallChainError::NotCallable),
         };
         if mm.is_nil() {
-            return None;
+            return Err(CallChainError::NotCallable);
         }
-        if nargs == 0 {
-            // See #46: variadic + __call not yet supported.
-            return None;
-        }
-        let actual_args = nargs as usize - 1;
-        let end = func_idx + 2 + actual_args;
-        if thread.stack.len() < end {
-            thread.stack.resize(end, Value::nil());
+        if hop == MAX_CALL_CHAIN {
+            return Err(CallChainError::TooLong);
         }
-        for i in (0..actual_args).rev() {
-            thread.stack[func_idx + 2 + i] = thread.stack[func_idx + 1 + i];
+        let args_end = if nargs == 0 {
+            thread.top
+        } else {
+            func_idx + nargs as usize
+        };
+        if thread.stack.len() <= args_end {
+            thread.stack.resize(args_end + 1, Value::nil());
         }
+        thread
+            .stack
+            .copy_within(func_idx + 1..args_end, func_idx + 2);
         thread.stack[func_idx + 1] = func_val;
         thread.stack[func_idx] = mm;
-        nargs += 1;
+        if nargs == 0 || nargs == u8::MAX {
+            thread.top = args_end + 1;
+            nargs = 0;
+        } else {
+            nargs += 1;
+        }
     }
-    None
+    unreachable!("the last hop returns")
 }
 
 /// Look up a binary metamethod on `lhs` first, then `rhs`. Only checks
@@ -2979,10 +4385,7 @@ fn schedule_meta_call<'gc>(
     // Save caller's pc; no decisions here depend on knowing the final target.
     // The native suspend path relies on this so the executor re-enters the
     // caller frame at the instruction following the one that scheduled us.
-    if let Some(frame) = thread.top_lua_mut() {
-        let code_start = frame.closure.proto.code.as_ptr();
-        frame.pc = unsafe { caller_ip.offset_from_unsigned(code_start) };
-    }
+    save_pc(thread, caller_ip);
 
     // schedule_meta_call is only reachable from inside a handler via
     // invoke_metamethod!, so an active caller frame is always present.
@@ -3008,10 +4411,16 @@ fn schedule_meta_call<'gc>(
     // function slot), so `args.len() + 1`.
     debug_assert!(args.len() < u8::MAX as usize);
     let nargs = (args.len() + 1) as u8;
-    let Some((target, final_nargs)) = resolve_call_chain(ctx, thread, scratch_func, nargs) else {
-        return MetaDispatch::Unresolvable;
+    let (target, final_nargs) = match resolve_call_chain(ctx, thread, scratch_func, nargs) {
+        Ok(resolved) => resolved,
+        Err(CallChainError::NotCallable) => return MetaDispatch::Unresolvable,
+        Err(CallChainError::TooLong) => return MetaDispatch::CallChainTooLong,
+    };
+    let actual_args = if final_nargs == 0 {
+        thread.top - new_base
+    } else {
+        final_nargs as usize - 1
     };
-    let actual_args = final_nargs as usize - 1;
 
     let closure = match target {
         CallTarget::Lua(c) => c,
@@ -3030,6 +4439,10 @@ fn schedule_meta_call<'gc>(
 
     // Grow stack to fit the resolved closure's full frame.
     let needed = new_base + closure.proto.max_stack_size as usize;
+    let depth = thread.frames.len() + 1;
+    if let Err(msg) = ctx.stack_limits().check(thread, depth, needed) {
+        return MetaDispatch::Overflow(msg);
+    }
     if thread.stack.len() < needed {
         thread.stack.resize(needed, Value::nil());
     }
@@ -3056,7 +4469,7 @@ fn schedule_meta_call<'gc>(
         continuation: Some(cont),
     });
 
-    let new_ip = closure.proto.code.as_ptr();
+    let new_ip = closure.proto.code_ptr();
     MetaDispatch::Lua { new_ip, new_base }
 }
 
@@ -3148,12 +4561,11 @@ macro_rules! finalize_return {
         let __cur_base = $thread.top_lua().unwrap().base;
 
         close_upvalues($ctx.mutation(), $thread, __cur_base);
-        close_tbc_vars($ctx.mutation(), $thread, __cur_base);
         $thread.frames.pop();
 
         let __caller_base = {
             let caller = $thread.top_lua().unwrap();
-            $ip = unsafe { caller.closure.proto.code.as_ptr().add(caller.pc) };
+            $ip = unsafe { caller.closure.proto.code_ptr().add(caller.pc) };
             caller.base
         };
         $registers = unsafe { $thread.stack.as_mut_ptr().add(__caller_base) };
@@ -1,4 +1,5 @@
 pub mod async_sequence;
+pub(crate) mod close;
 pub(crate) mod interp;
 pub(crate) mod num;
 pub mod sequence;
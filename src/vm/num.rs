@@ -1,4 +1,5 @@
 use crate::env::Value;
+use crate::lua::Context;
 
 pub fn exact_float_to_int(f: f64) -> Option<i64> {
     if !f.is_finite() {
@@ -21,8 +22,20 @@ pub fn exact_float_to_int(f: f64) -> Option<i64> {
 }
 
 #[inline(always)]
-pub fn op_arith<'gc, Op: ArithOp>(lhs: Value, rhs: Value) -> Option<Value<'gc>> {
-    if let (Some(li), Some(ri)) = (lhs.get_integer(), rhs.get_integer()) {
+pub fn op_arith<'gc, Op: ArithOp>(
+    ctx: Context<'gc>,
+    lhs: Value<'gc>,
+    rhs: Value<'gc>,
+) -> Option<Value<'gc>> {
+    // Floats are the cheapest kind to test for, so they go first.
+    if let (Some(lf), Some(rf)) = (lhs.get_float(), rhs.get_float()) {
+        return Some(Op::float(lf, rf));
+    }
+
+    // Inline integers take one tag check each. Keeping boxed integers and
+    // mixed operands out of line keeps this path small enough that the
+    // handlers it's inlined into stay cheap to enter.
+    if let (Some(li), Some(ri)) = (lhs.get_inline_integer(), rhs.get_inline_integer()) {
         // Lua raises on integer `//`/`%` by zero; without this guard the
         // `wrapping_div`/`wrapping_rem` in `Op::int` would panic. Returning
         // `None` takes the handler's error path (integers carry no metamethod),
@@ -31,7 +44,24 @@ pub fn op_arith<'gc, Op: ArithOp>(lhs: Value, rhs: Value) -> Option<Value<'gc>>
         if Op::INT_ZERO_DIVISOR_RAISES && ri == 0 {
             return None;
         }
-        return Some(Op::int(li, ri));
+        return Some(Op::int(ctx, li, ri));
+    }
+
+    op_arith_slow::<Op>(ctx, lhs, rhs)
+}
+
+#[inline(never)]
+fn op_arith_slow<'gc, Op: ArithOp>(
+    ctx: Context<'gc>,
+    lhs: Value<'gc>,
+    rhs: Value<'gc>,
+) -> Option<Value<'gc>> {
+    if let (Some(li), Some(ri)) = (lhs.get_integer(), rhs.get_integer()) {
+        // Zero divisors raise here too, as above.
+        if Op::INT_ZERO_DIVISOR_RAISES && ri == 0 {
+            return None;
+        }
+        return Some(Op::int(ctx, li, ri));
     }
 
     let lhs = if let Some(v) = lhs.get_integer() {
@@ -53,12 +83,27 @@ pub fn op_arith<'gc, Op: ArithOp>(lhs: Value, rhs: Value) -> Option<Value<'gc>>
     Some(Op::float(lhs, rhs))
 }
 
+/// `Op` on two integers, for quickened instructions that have already
+/// checked their operands. Not for `//` and `%`, which need a zero check.
+#[inline(always)]
+pub fn int_arith<'gc, Op: ArithOp>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
+    debug_assert!(!Op::INT_ZERO_DIVISOR_RAISES);
+    Op::int(ctx, lhs, rhs)
+}
+
+/// `Op` on two floats, for quickened instructions that have already
+/// checked their operands.
+#[inline(always)]
+pub fn float_arith<'gc, Op: ArithOp>(_ctx: Context<'gc>, lhs: f64, rhs: f64) -> Value<'gc> {
+    Op::float(lhs, rhs)
+}
+
 pub trait ArithOp {
     /// `//` and `%` set this so `op_arith` raises on an integer zero divisor
     /// instead of computing (and panicking in `wrapping_div`/`wrapping_rem`).
     const INT_ZERO_DIVISOR_RAISES: bool = false;
 
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc>;
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc>;
     fn float<'gc>(lhs: f64, rhs: f64) -> Value<'gc>;
 }
 
@@ -66,8 +111,8 @@ pub struct Add;
 
 impl ArithOp for Add {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
-        Value::integer(lhs.wrapping_add(rhs))
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
+        Value::integer(ctx, lhs.wrapping_add(rhs))
     }
 
     #[inline(always)]
@@ -80,8 +125,8 @@ pub struct Sub;
 
 impl ArithOp for Sub {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
-        Value::integer(lhs.wrapping_sub(rhs))
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
+        Value::integer(ctx, lhs.wrapping_sub(rhs))
     }
 
     #[inline(always)]
@@ -94,8 +139,8 @@ pub struct Mul;
 
 impl ArithOp for Mul {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
-        Value::integer(lhs.wrapping_mul(rhs))
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
+        Value::integer(ctx, lhs.wrapping_mul(rhs))
     }
 
     #[inline(always)]
@@ -110,7 +155,7 @@ impl ArithOp for Mod {
     const INT_ZERO_DIVISOR_RAISES: bool = true;
 
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
         let r = lhs.wrapping_rem(rhs);
         let adjusted = if r != 0 && (r ^ rhs) < 0 {
             r.wrapping_add(rhs)
@@ -118,7 +163,7 @@ impl ArithOp for Mod {
             r
         };
 
-        Value::integer(adjusted)
+        Value::integer(ctx, adjusted)
     }
 
     #[inline(always)]
@@ -138,7 +183,7 @@ pub struct Pow;
 
 impl ArithOp for Pow {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
+    fn int<'gc>(_ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
         Value::float((lhs as f64).powf(rhs as f64))
     }
 
@@ -152,7 +197,7 @@ pub struct Div;
 
 impl ArithOp for Div {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
+    fn int<'gc>(_ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
         Value::float((lhs as f64) / (rhs as f64))
     }
 
@@ -168,7 +213,7 @@ impl ArithOp for IDiv {
     const INT_ZERO_DIVISOR_RAISES: bool = true;
 
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
         let q = lhs.wrapping_div(rhs);
         let r = lhs.wrapping_rem(rhs);
         let adjusted = if r != 0 && (lhs ^ rhs) < 0 {
@@ -177,7 +222,7 @@ impl ArithOp for IDiv {
             q
         };
 
-        Value::integer(adjusted)
+        Value::integer(ctx, adjusted)
     }
 
     #[inline(always)]
@@ -189,11 +234,15 @@ impl ArithOp for IDiv {
 }
 
 #[inline(always)]
-pub fn op_bit<'gc, Op: BitOp>(lhs: Value, rhs: Value) -> Option<Value<'gc>> {
+pub fn op_bit<'gc, Op: BitOp>(
+    ctx: Context<'gc>,
+    lhs: Value<'gc>,
+    rhs: Value<'gc>,
+) -> Option<Value<'gc>> {
     let lhs = if let Some(v) = lhs.get_integer() {
         v
     } else if let Some(v) = lhs.get_float() {
-        exact_float_to_int(v).unwrap()
+        exact_float_to_int(v)?
     } else {
         return None;
     };
@@ -201,24 +250,24 @@ pub fn op_bit<'gc, Op: BitOp>(lhs: Value, rhs: Value) -> Option<Value<'gc>> {
     let rhs = if let Some(v) = rhs.get_integer() {
         v
     } else if let Some(v) = rhs.get_float() {
-        exact_float_to_int(v).unwrap()
+        exact_float_to_int(v)?
     } else {
         return None;
     };
 
-    Some(Op::int(lhs, rhs))
+    Some(Op::int(ctx, lhs, rhs))
 }
 
 pub trait BitOp {
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc>;
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc>;
 }
 
 pub struct BAnd;
 
 impl BitOp for BAnd {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
-        Value::integer(lhs & rhs)
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
+        Value::integer(ctx, lhs & rhs)
     }
 }
 
@@ -226,8 +275,8 @@ pub struct BOr;
 
 impl BitOp for BOr {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
-        Value::integer(lhs | rhs)
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
+        Value::integer(ctx, lhs | rhs)
     }
 }
 
@@ -235,8 +284,8 @@ pub struct BXor;
 
 impl BitOp for BXor {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
-        Value::integer(lhs ^ rhs)
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
+        Value::integer(ctx, lhs ^ rhs)
     }
 }
 
@@ -244,8 +293,8 @@ pub struct Shl;
 
 impl BitOp for Shl {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
-        Value::integer(lhs.wrapping_shl(rhs as u32))
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
+        Value::integer(ctx, lhs.wrapping_shl(rhs as u32))
     }
 }
 
@@ -253,8 +302,8 @@ pub struct Shr;
 
 impl BitOp for Shr {
     #[inline(always)]
-    fn int<'gc>(lhs: i64, rhs: i64) -> Value<'gc> {
-        Value::integer(lhs.wrapping_shr(rhs as u32))
+    fn int<'gc>(ctx: Context<'gc>, lhs: i64, rhs: i64) -> Value<'gc> {
+        Value::integer(ctx, lhs.wrapping_shr(rhs as u32))
     }
 }
 
@@ -267,6 +316,21 @@ pub fn write_float(dst: &mut Vec<u8>, f: f64) {
     crate::builtin::util::push_float(dst, f);
 }
 
+/// Longest text a number converts to (Lua's `MAXNUMBER2STR`).
+const MAX_NUMBER_TEXT: usize = 44;
+
+/// The most bytes `coerce_to_str` can append for `val`, or `None` if it
+/// appends nothing.
+pub fn coerced_len(val: Value) -> Option<usize> {
+    if let Some(s) = val.get_string() {
+        Some(s.len())
+    } else if val.get_integer().is_some() || val.get_float().is_some() {
+        Some(MAX_NUMBER_TEXT)
+    } else {
+        None
+    }
+}
+
 pub fn coerce_to_str(buf: &mut Vec<u8>, val: Value) -> bool {
     if let Some(s) = val.get_string() {
         buf.extend_from_slice(s.as_bytes());
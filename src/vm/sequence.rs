@@ -15,7 +15,8 @@ use std::pin::Pin;
 use crate::dmm::{Collect, Gc, GcWeak, MetricsAlloc, Mutation, Trace};
 use crate::env::error::Error;
 use crate::env::function::Stack;
-use crate::env::{Function, Thread};
+use crate::env::thread::{Frame, HookSlot};
+use crate::env::{Hook, Thread, Value};
 
 /// What a [`Sequence::poll`] (or `error`) call requests of the executor next.
 #[derive(Collect)]
@@ -26,12 +27,10 @@ pub enum SequencePoll<'gc> {
     Pending,
     /// Sequence is finished. Values at `bottom..` are its return values.
     Return,
-    /// Call `function`; on completion, this sequence is polled again with the
-    /// returned values placed at `bottom..` in its window.
-    Call {
-        function: Function<'gc>,
-        bottom: usize,
-    },
+    /// Call `function`, following its `__call` chain if it isn't a function;
+    /// on completion, this sequence is polled again with the returned values
+    /// placed at `bottom..` in its window.
+    Call { function: Value<'gc>, bottom: usize },
     /// Yield values at `bottom..` to the resumer. On resumption, this
     /// sequence is polled again with the resume-args at `bottom..`.
     Yield { bottom: usize },
@@ -41,7 +40,7 @@ pub enum SequencePoll<'gc> {
     Resume { thread: Thread<'gc>, bottom: usize },
     /// Tail call; this sequence is consumed and the call's results go to the
     /// sequence's caller, not back to this sequence.
-    TailCall(Function<'gc>),
+    TailCall(Value<'gc>),
     /// Tail yield; sequence consumed.
     TailYield,
     /// Tail resume; sequence consumed.
@@ -57,11 +56,12 @@ pub enum CallbackAction<'gc> {
     /// Become a multi-step sequence. The pushed sequence will be polled
     /// repeatedly until it completes / yields / resumes.
     Sequence(BoxSequence<'gc>),
-    /// Call `function`; on its return, optionally hand control to a follow-up
-    /// sequence. Without `then`, the callback's caller receives the call's
-    /// results directly.
+    /// Call `function`, following its `__call` chain if it isn't a function;
+    /// on its return, optionally hand control to a follow-up sequence.
+    /// Without `then`, the callback's caller receives the call's results
+    /// directly.
     Call {
-        function: Function<'gc>,
+        function: Value<'gc>,
         then: Option<BoxSequence<'gc>>,
     },
     /// Yield to the resumer. Optional follow-up sequence runs on
@@ -76,28 +76,61 @@ pub enum CallbackAction<'gc> {
 }
 
 /// Read-only view of the executor that is passed to a native callback or
-/// sequence poll. Carries the currently-running thread; richer fields
-/// (full `&[Thread<'gc>]` thread stack, fuel handle) aren't implemented
-/// yet.
+/// sequence poll. Carries the currently-running thread and its call frames;
+/// richer fields (full `&[Thread<'gc>]` thread stack, fuel handle) aren't
+/// implemented yet.
 #[derive(Clone, Copy)]
 pub struct Execution<'gc, 'a> {
     current_thread: Thread<'gc>,
-    _marker: std::marker::PhantomData<&'a Thread<'gc>>,
+    /// Frames of `current_thread` below the running native, innermost last.
+    /// Natives run without a frame of their own, so the last one is the
+    /// caller.
+    frames: &'a [Frame<'gc>],
+    hook: &'a HookSlot<'gc>,
 }
 
 impl<'gc, 'a> Execution<'gc, 'a> {
-    pub fn new(current_thread: Thread<'gc>) -> Self {
+    pub fn new(
+        current_thread: Thread<'gc>,
+        frames: &'a [Frame<'gc>],
+        hook: &'a HookSlot<'gc>,
+    ) -> Self {
         Execution {
             current_thread,
-            _marker: std::marker::PhantomData,
+            frames,
+            hook,
         }
     }
 
+    /// `chunkid:line:` of the call `level` frames up from the running
+    /// native (1 is its caller), as `luaL_where` reports it. `None` when that
+    /// frame isn't running Lua code, or there are fewer frames than `level`.
+    pub fn location(self, level: usize) -> Option<String> {
+        let frame = self.frames.iter().rev().nth(level.checked_sub(1)?)?;
+        let Frame::Lua(frame) = frame else {
+            return None;
+        };
+        // A suspended frame's pc is one past the instruction it's executing.
+        Some(frame.closure.proto.location(frame.pc.checked_sub(1)?))
+    }
+
     /// Thread the native callback / sequence is running on top of.
     pub fn current_thread(self) -> Thread<'gc> {
         self.current_thread
     }
 
+    /// The running thread's debug hook.
+    pub fn hook(self) -> Option<Hook<'gc>> {
+        self.hook.get()
+    }
+
+    /// Install a debug hook on the running thread, or remove it with
+    /// `None`. It takes effect once control next passes through the
+    /// executor, which a native can force by returning a `Sequence`.
+    pub fn set_hook(self, mc: &Mutation<'gc>, hook: Option<Hook<'gc>>) {
+        self.hook.set(mc, self.current_thread, hook);
+    }
+
     /// Whether the running thread is the executor's main (entry) thread.
     pub fn is_main(self, ctx: crate::lua::Context<'gc>) -> bool {
         self.current_thread.ptr_eq(ctx.main_thread())
@@ -0,0 +1,168 @@
+//! `Lua::new_in` places the whole heap in the given allocator, and hands
+//! every byte back to it when the runtime is dropped.
+
+#![feature(allocator_api)]
+
+use std::alloc::{AllocError, Allocator, GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ptr::NonNull;
+use std::rc::Rc;
+
+use tcvm::env::userdata::Userdata;
+use tcvm::env::{LuaString, Value};
+use tcvm::{Executor, LoadError, Lua};
+
+/// Forwards to the system allocator, bypassing the global one, keeping
+/// count of what is live.
+#[derive(Clone, Default)]
+struct Counting(Rc<Counts>);
+
+#[derive(Default)]
+struct Counts {
+    live_bytes: Cell<usize>,
+    allocations: Cell<usize>,
+}
+
+unsafe impl Allocator for Counting {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = System.allocate(layout)?;
+        self.0.live_bytes.update(|b| b + layout.size());
+        self.0.allocations.update(|n| n + 1);
+        Ok(ptr)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.0.live_bytes.update(|b| b - layout.size());
+        unsafe { System.deallocate(ptr, layout) }
+    }
+}
+
+/// The system allocator, keeping count of the bytes each thread has live
+/// in it, so tests see anything a runtime keeps outside its own allocator.
+struct GlobalCounting;
+
+thread_local! {
+    static GLOBAL_LIVE: Cell<isize> = const { Cell::new(0) };
+}
+
+fn record(delta: isize) {
+    let _ = GLOBAL_LIVE.try_with(|live| live.update(|b| b + delta));
+}
+
+fn global_live() -> isize {
+    GLOBAL_LIVE.with(Cell::get)
+}
+
+unsafe impl GlobalAlloc for GlobalCounting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            record(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        record(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new.is_null() {
+            record(new_size as isize - layout.size() as isize);
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static GLOBAL: GlobalCounting = GlobalCounting;
+
+fn eval<R>(lua: &mut Lua, src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("allocator"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex).expect("run")
+}
+
+#[test]
+fn heap_lives_in_the_given_allocator() {
+    let alloc = Counting::default();
+    let mut lua = Lua::new_in(alloc.clone());
+    lua.load_all();
+    let n: i64 = eval(
+        &mut lua,
+        "local t = {} \
+         for i = 1, 1000 do t[i] = {name = 'item' .. i, i} end \
+         local co = coroutine.wrap(function(...) return select('#', ...) end) \
+         return co(table.unpack(t, 1, 200))",
+    );
+    assert_eq!(n, 200);
+    assert!(alloc.0.allocations.get() > 1000);
+    assert_eq!(
+        alloc.0.live_bytes.get(),
+        lua.gc_metrics().total_allocation()
+    );
+}
+
+#[test]
+fn dropping_the_runtime_returns_everything() {
+    let alloc = Counting::default();
+    {
+        let mut lua = Lua::new_in(alloc.clone());
+        lua.load_all();
+        eval::<()>(
+            &mut lua,
+            "keep = {} for i = 1, 100 do keep[i] = string.rep('x', i) end \
+             setmetatable({}, {__gc = function() end})",
+        );
+        assert!(alloc.0.live_bytes.get() > 0);
+    }
+    assert_eq!(alloc.0.live_bytes.get(), 0);
+}
+
+#[test]
+fn runtime_keeps_nothing_in_the_global_allocator() {
+    let alloc = Counting::default();
+    let mut lua = Lua::new_in(alloc.clone());
+    lua.load_all();
+    let before = global_live();
+    // Leave one of everything the heap holds reachable: prototypes with
+    // locals and upvalues, closures, a coroutine suspended mid-call with
+    // open upvalues and a to-be-closed variable, and userdata.
+    eval::<()>(
+        &mut lua,
+        "local function counter(start) \
+           local n = start \
+           return function(step) n = n + step return n end \
+         end \
+         keep = {counter(1), counter(2), string.gmatch('a b c', '%a')} \
+         co = coroutine.wrap(function(...) \
+           local open <close> = setmetatable({}, {__close = function() end}) \
+           local x = select('#', ...) \
+           local function get() return x end \
+           coroutine.yield(get) \
+           return pcall(coroutine.yield, get) \
+         end) \
+         co(1, 2, 3) \
+         co()",
+    );
+    lua.enter(|ctx| {
+        let ud = Userdata::new(ctx.mutation(), [0u64; 8], 4);
+        let key = Value::string(LuaString::new(ctx, b"ud"));
+        ctx.globals().raw_set(ctx, key, Value::userdata(ud));
+    });
+    lua.gc_collect();
+    assert_eq!(global_live() - before, 0);
+    assert_eq!(
+        alloc.0.live_bytes.get(),
+        lua.gc_metrics().total_allocation()
+    );
+}
@@ -21,7 +21,7 @@ fn async_pending_then_return() {
         let seq = async_sequence(nctx.ctx.mutation(), |_locals, mut seq| async move {
             seq.pending().await;
             seq.enter(|_ctx, _locals, _exec, mut stack| {
-                stack.replace(&[Value::integer(7)]);
+                stack.replace(&[Value::small_integer(7)]);
             });
             Ok(SequenceReturn::Return)
         });
@@ -30,7 +30,7 @@ fn async_pending_then_return() {
 
     let ex = lua
         .try_enter(|ctx| -> Result<_, LoadError> {
-            let make_fn = Function::new_native(ctx.mutation(), make as NativeFn, Box::new([]));
+            let make_fn = Function::new_native(ctx.mutation(), make as NativeFn, []);
             let key = Value::string(LuaString::new(ctx, b"makeseq"));
             ctx.globals().raw_set(ctx, key, Value::function(make_fn));
             let chunk = ctx.load("return makeseq()", Some("async_pending"))?;
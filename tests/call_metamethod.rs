@@ -0,0 +1,139 @@
+//! Calling a table with a `__call` metamethod, from every call shape:
+//! fixed and variadic arguments, tail calls, chains of callable tables,
+//! generic-for iterators and metamethods. A chain longer than Lua 5.5's
+//! limit raises instead of looping.
+
+use tcvm::env::LuaString;
+use tcvm::{Executor, LoadError, Lua, RuntimeError};
+
+/// Shared prelude: `C` returns its argument count followed by the
+/// arguments, `C2` is a table whose `__call` is `C`, and `chain(n)` nests
+/// `n` callable tables around a function returning its argument count.
+const PRELUDE: &str = r#"
+local function count(...) return select('#', ...), ... end
+local C = setmetatable({}, {__call = function(self, ...) return count(...) end})
+local C2 = setmetatable({}, {__call = C})
+local function chain(n)
+  local f = function(...) return select('#', ...) end
+  for _ = 1, n do f = setmetatable({}, {__call = f}) end
+  return f
+end
+"#;
+
+/// Run `PRELUDE .. src` and return the string it returns.
+fn run(src: &str) -> Result<String, RuntimeError> {
+    let mut lua = Lua::new();
+    lua.load_all();
+    let src = format!("{PRELUDE}{src}");
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(&src, Some("=call"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.finish(&ex)?;
+    lua.try_enter(|ctx| -> Result<_, RuntimeError> {
+        let s = ctx.fetch(&ex).take_result::<LuaString>(ctx)?;
+        Ok(String::from_utf8_lossy(s.as_bytes()).into_owned())
+    })
+}
+
+#[test]
+fn variadic_arguments() {
+    let out = run(r#"
+        local function fwd(...) local r = {C(...)} return table.concat(r, ",") end
+        local function spread(...) return select('#', C2(...)) end
+        return fwd(1, 2, 3) .. " " .. fwd() .. " " .. spread("a", "b")
+    "#)
+    .unwrap();
+    // `C2(...)` passes `C2` on to `C`, which counts it too.
+    assert_eq!(out, "3,1,2,3 0 4");
+}
+
+#[test]
+fn tail_calls() {
+    let out = run(r#"
+        local function fixed(x) return C(x, "y") end
+        local function va(...) return C(0, ...) end
+        return table.concat({fixed("x")}, ",") .. " " .. table.concat({va(8, 9)}, ",")
+    "#)
+    .unwrap();
+    assert_eq!(out, "2,x,y 3,0,8,9");
+}
+
+#[test]
+fn iterators_and_metamethods() {
+    let out = run(r#"
+        local it = setmetatable({}, {__call = function(_, _, i)
+          if i < 3 then return i + 1 end
+        end})
+        local seen = {}
+        for i in it, nil, 0 do seen[#seen + 1] = i end
+        local v = setmetatable({}, {__add = C2, __concat = C})
+        return table.concat(seen, ",") .. " " .. (v + 1) .. " " .. (v .. "s")
+    "#)
+    .unwrap();
+    // `v + 1` → `C2(v, 1)` → `C(C2, v, 1)`; `v .. "s"` → `C(v, "s")`.
+    assert_eq!(out, "1,2,3 3 2");
+}
+
+#[test]
+fn chain_length_limit() {
+    let out = run(r#"
+        local ok, n = pcall(function() return chain(15)(1) end)
+        local _, e = pcall(function() return chain(16)() end)
+        local _, emm = pcall(function() return -setmetatable({}, {__unm = chain(16)}) end)
+        return tostring(ok) .. " " .. n .. " | " .. e .. " | " .. emm
+    "#)
+    .unwrap();
+    // Fifteen hops prepend fifteen callees to the one argument.
+    let (head, errors) = out.split_once(" | ").unwrap();
+    assert_eq!(head, "true 16");
+    for e in errors.split(" | ") {
+        assert!(e.ends_with("'__call' chain too long"), "{out}");
+    }
+}
+
+#[test]
+fn hop_past_the_argument_encoding_limit() {
+    // 254 arguments is the most a `CALL` encodes; the hop adds a 255th.
+    // Each call sits at R0 of its function so the arguments fit.
+    let args: Vec<String> = (1..=254).map(|i| i.to_string()).collect();
+    let args = args.join(",");
+    let out = run(&format!(
+        r#"
+        local function call() local n = C({args}) return n end
+        local function tail() return C({args}) end
+        return call() .. " " .. select(254, tail())
+    "#
+    ))
+    .unwrap();
+    assert_eq!(out, "254 253");
+}
+
+#[test]
+fn native_call_through() {
+    let out = run(r#"
+        local ok, n, a = pcall(C, "y")
+        local ok2, n2 = pcall(chain(15), 1)
+        local ok3, e3 = pcall(chain(16))
+        local ok4, e4 = pcall(setmetatable({}, {}))
+        local closed
+        do
+          local x <close> = setmetatable({}, {__close = setmetatable({}, {
+            __call = function(_, v) closed = v end,
+          })})
+          closed = "open"
+        end
+        return table.concat({tostring(ok), n, a, tostring(ok2), n2, tostring(ok3), e3,
+                             tostring(ok4), e4, type(closed)}, " ")
+    "#)
+    .unwrap();
+    // The pcall'd callable table is `C`'s first argument; `__close` gets
+    // the closing method's table ahead of the variable.
+    assert_eq!(
+        out,
+        "true 1 y true 16 false '__call' chain too long \
+         false attempt to call a table value table"
+    );
+}
@@ -0,0 +1,134 @@
+//! `collectgarbage` drives the real collector: `"collect"` and `"step"`
+//! run collection work from inside a script, `"stop"` / `"restart"` pause
+//! the pacer, and the tuning options report the mode they replace.
+
+use tcvm::{Executor, LoadError, Lua, RuntimeError};
+
+fn try_eval<R>(lua: &mut Lua, src: &str) -> Result<R, RuntimeError>
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("collectgarbage"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex)
+}
+
+fn eval<R>(lua: &mut Lua, src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    try_eval(lua, src).expect("run")
+}
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+#[test]
+fn collect_runs_a_full_cycle_mid_script() {
+    let mut lua = new_lua();
+    assert!(eval::<bool>(
+        &mut lua,
+        "local probe = setmetatable({}, {__mode = 'v'}) \
+         probe[1] = {} \
+         local kept = {} \
+         probe[2] = kept \
+         local r = collectgarbage() \
+         return r == 0 and probe[1] == nil and probe[2] == kept",
+    ));
+}
+
+#[test]
+fn collect_runs_pending_finalizers() {
+    let mut lua = new_lua();
+    assert!(eval::<bool>(
+        &mut lua,
+        "ran = false \
+         local function make() \
+           setmetatable({}, {__gc = function() ran = true end}) \
+         end \
+         make() \
+         collectgarbage('collect') \
+         return ran",
+    ));
+}
+
+#[test]
+fn step_reports_cycle_completion() {
+    let mut lua = new_lua();
+    let cycles = lua.gc_metrics().total_cycles();
+    assert!(eval::<bool>(
+        &mut lua,
+        "for i = 1, 1000 do \
+           if collectgarbage('step', 64) then return true end \
+         end \
+         return false",
+    ));
+    assert!(lua.gc_metrics().total_cycles() > cycles);
+}
+
+#[test]
+fn stop_pauses_automatic_collection() {
+    let mut lua = new_lua();
+    assert!(eval::<bool>(
+        &mut lua,
+        "collectgarbage('stop') return not collectgarbage('isrunning')"
+    ));
+    let cycles = lua.gc_metrics().total_cycles();
+    eval::<()>(&mut lua, "for i = 1, 20000 do local t = {i} end");
+    assert_eq!(lua.gc_metrics().total_cycles(), cycles);
+
+    assert!(eval::<bool>(
+        &mut lua,
+        "collectgarbage('restart') return collectgarbage('isrunning')"
+    ));
+    eval::<()>(&mut lua, "for i = 1, 20000 do local t = {i} end");
+    assert!(lua.gc_metrics().total_cycles() > cycles);
+}
+
+#[test]
+fn explicit_collection_works_while_stopped() {
+    let mut lua = new_lua();
+    assert!(eval::<bool>(
+        &mut lua,
+        "collectgarbage('stop') \
+         local probe = setmetatable({}, {__mode = 'v'}) \
+         probe[1] = {} \
+         collectgarbage() \
+         return probe[1] == nil",
+    ));
+}
+
+#[test]
+fn mode_options_return_the_previous_mode() {
+    let mut lua = new_lua();
+    assert!(eval::<bool>(
+        &mut lua,
+        "local a = collectgarbage('generational', 20, 100) \
+         local b = collectgarbage('incremental', 200, 200, 12) \
+         local c = collectgarbage('incremental') \
+         return a == 'incremental' and b == 'generational' and c == 'incremental'",
+    ));
+}
+
+#[test]
+fn incremental_parameters_still_collect() {
+    let mut lua = new_lua();
+    let ex_src = "collectgarbage('incremental', 100, 400, 8) \
+                  for i = 1, 50000 do local t = {i} end";
+    let cycles = lua.gc_metrics().total_cycles();
+    eval::<()>(&mut lua, ex_src);
+    assert!(lua.gc_metrics().total_cycles() > cycles);
+}
+
+#[test]
+fn invalid_option_is_an_error() {
+    let mut lua = new_lua();
+    assert!(try_eval::<()>(&mut lua, "collectgarbage('bogus')").is_err());
+}
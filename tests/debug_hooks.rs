@@ -0,0 +1,125 @@
+//! `debug.sethook` / `debug.gethook`: per-thread call, return, line and
+//! count hooks, which may be Lua or host functions and may yield or raise.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tcvm::env::{Error, Function, Hook, HookMask, LuaString, NativeContext, Stack};
+use tcvm::vm::sequence::CallbackAction;
+use tcvm::{Executor, LoadError, Lua, RuntimeError};
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+/// Run `src` and return the string it returns.
+fn run(lua: &mut Lua, src: &str) -> String {
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("=hooks"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.finish(&ex).expect("execute");
+    lua.try_enter(|ctx| -> Result<_, RuntimeError> {
+        let s = ctx.fetch(&ex).take_result::<LuaString>(ctx)?;
+        Ok(String::from_utf8_lossy(s.as_bytes()).into_owned())
+    })
+    .expect("take_result")
+}
+
+#[test]
+fn line_hooks_report_each_new_line() {
+    let src = "local lines = {}\n\
+               debug.sethook(function(event, line) lines[#lines + 1] = line end, 'l')\n\
+               local a = 1\n\
+               for i = 1, 2 do\n\
+                 a = a + i\n\
+               end\n\
+               debug.sethook()\n\
+               return table.concat(lines, ',')";
+    assert_eq!(run(&mut new_lua(), src), "3,4,5,4,5,4,7");
+}
+
+#[test]
+fn call_and_return_hooks_see_lua_calls() {
+    let src = "local events = {}\n\
+               local function f() return 1 end\n\
+               local function g() return f() end\n\
+               debug.sethook(function(event) events[#events + 1] = event end, 'cr')\n\
+               f()\n\
+               g()\n\
+               local hook, mask, count = debug.gethook()\n\
+               debug.sethook()\n\
+               assert(debug.gethook() == nil)\n\
+               return table.concat(events, ' ') .. ' | ' .. mask .. count";
+    assert_eq!(
+        run(&mut new_lua(), src),
+        "call return call tail call return | cr0"
+    );
+}
+
+#[test]
+fn count_hooks_can_abort_runaway_code() {
+    let src = "local calls = 0\n\
+               debug.sethook(function(event)\n\
+                 calls = calls + 1\n\
+                 if calls == 10 then error('watchdog: ' .. event) end\n\
+               end, '', 100)\n\
+               local ok, err = pcall(function() while true do end end)\n\
+               debug.sethook()\n\
+               return tostring(ok) .. ' ' .. err";
+    assert_eq!(run(&mut new_lua(), src), "false hooks:4: watchdog: count");
+}
+
+#[test]
+fn hooks_are_per_thread_and_can_yield() {
+    let src = "local co = coroutine.create(function()\n\
+                 local s = 0\n\
+                 for i = 1, 2 do s = s + i end\n\
+                 return s\n\
+               end)\n\
+               debug.sethook(co, function(event, line) coroutine.yield(line) end, 'l')\n\
+               assert(debug.gethook() == nil)\n\
+               assert(debug.gethook(co) ~= nil)\n\
+               local out = {}\n\
+               repeat\n\
+                 local ok, v = coroutine.resume(co)\n\
+                 out[#out + 1] = v\n\
+               until coroutine.status(co) == 'dead'\n\
+               return table.concat(out, ',')";
+    assert_eq!(run(&mut new_lua(), src), "2,3,3,4,3");
+}
+
+static HOST_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn count_calls<'gc>(
+    _ctx: NativeContext<'gc, '_>,
+    _stack: Stack<'gc, '_>,
+) -> Result<CallbackAction<'gc>, Error<'gc>> {
+    HOST_CALLS.fetch_add(1, Ordering::Relaxed);
+    Ok(CallbackAction::Return)
+}
+
+#[test]
+fn host_functions_can_be_installed_as_hooks() {
+    let mut lua = new_lua();
+    lua.enter(|ctx| {
+        let function = Function::new_native(ctx.mutation(), count_calls, []);
+        ctx.main_thread().set_hook(
+            ctx.mutation(),
+            Some(Hook {
+                function,
+                mask: HookMask::CALL,
+                count: 0,
+            }),
+        );
+    });
+    let src = "local function f() end\n\
+               for i = 1, 5 do f() end\n\
+               return 'done'";
+    assert_eq!(run(&mut lua, src), "done");
+    // The chunk itself, then `f` five times.
+    assert_eq!(HOST_CALLS.load(Ordering::Relaxed), 6);
+}
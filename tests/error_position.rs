@@ -0,0 +1,85 @@
+//! Runtime faults and `error(msg)` are prefixed with `chunkname:line:` of
+//! the position they were raised at, the chunk name being the one given to
+//! `Context::load` in reference Lua's `=`/`@`/source conventions.
+
+use tcvm::{Executor, LoadError, Lua, RuntimeError};
+
+/// The message `src`, loaded as `name`, fails with.
+fn error_message(src: &str, name: Option<&str>) -> String {
+    let mut lua = Lua::new();
+    lua.load_all();
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, name)?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    let err = lua.execute::<()>(&ex).expect_err("should fail");
+    let RuntimeError::Lua(stashed) = err else {
+        panic!("expected RuntimeError::Lua, got {err:?}");
+    };
+    lua.enter(|ctx| {
+        let value = ctx.fetch(&stashed).value();
+        let s = value.get_string().expect("string");
+        String::from_utf8_lossy(s.as_bytes()).into_owned()
+    })
+}
+
+#[test]
+fn faults_report_the_line_they_happen_on() {
+    let src = "local t = {}\n\
+               local function get(k)\n\
+                 return t[k]\n\
+                   .field\n\
+               end\n\
+               return get(1)";
+    assert_eq!(
+        error_message(src, Some("=lines")),
+        "lines:4: attempt to index a nil value"
+    );
+    assert_eq!(
+        error_message("local x = 1\n\n\nx()", Some("=lines")),
+        "lines:4: attempt to call a number value (local 'x')"
+    );
+}
+
+#[test]
+fn error_levels_pick_the_reported_frame() {
+    let src = "local function check(v)\n\
+                 if not v then error('bad value', 2) end\n\
+               end\n\
+               local function fail() error('failed') end\n\
+               local ok, e1 = pcall(fail)\n\
+               local ok, e2 = pcall(function() check(false) end)\n\
+               local ok, e3 = pcall(function() error('plain', 0) end)\n\
+               local ok, e4 = pcall(error, 'direct')\n\
+               local ok, e5 = pcall(function() error({}) end)\n\
+               error(table.concat({e1, e2, e3, e4, type(e5)}, '|'))";
+    assert_eq!(
+        error_message(src, Some("=levels")),
+        "levels:10: levels:4: failed|levels:6: bad value|plain|direct|table"
+    );
+}
+
+#[test]
+fn chunk_names_follow_reference_lua() {
+    assert_eq!(
+        error_message("error('x')", Some("@scripts/init.lua")),
+        "scripts/init.lua:1: x"
+    );
+    let long = format!("@{}/init.lua", "dir".repeat(30));
+    let tail = &long[long.len() - 56..];
+    assert_eq!(
+        error_message("error('x')", Some(&long)),
+        format!("...{tail}:1: x")
+    );
+    assert_eq!(
+        error_message("error('x')", Some("chunk")),
+        "[string \"chunk\"]:1: x"
+    );
+    // Without a name the source itself is used, cut at its first line.
+    assert_eq!(
+        error_message("local a = nil\nerror('x')", None),
+        "[string \"local a = nil...\"]:2: x"
+    );
+}
@@ -0,0 +1,199 @@
+//! `__gc` finalizers: objects whose metatable had `__gc` when it was set
+//! get their finalizer called once they become unreachable, most recently
+//! marked first, and every still-marked object is finalized when the
+//! `Lua` is dropped.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tcvm::env::{Error, Function, LuaString, NativeContext, NativeFn, Stack, Userdata, Value};
+use tcvm::vm::sequence::CallbackAction;
+use tcvm::{Executor, LoadError, Lua};
+
+fn eval<R>(lua: &mut Lua, src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("finalizers"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex).expect("run")
+}
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+#[test]
+fn unreachable_table_is_finalized() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        "log = {} \
+         do \
+           local t = setmetatable({}, {__gc = function(o) log[#log + 1] = o.name end}) \
+           t.name = 'a' \
+         end",
+    );
+    lua.gc_collect();
+    assert!(eval::<bool>(&mut lua, "return #log == 1 and log[1] == 'a'"));
+}
+
+#[test]
+fn gc_field_added_after_setmetatable_is_ignored() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        "ran = false \
+         local mt = {} \
+         setmetatable({}, mt) \
+         mt.__gc = function() ran = true end",
+    );
+    lua.gc_collect();
+    assert!(!eval::<bool>(&mut lua, "return ran"));
+}
+
+#[test]
+fn finalizers_run_in_reverse_marking_order() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        // Keep an automatic cycle from finalizing some of these early.
+        "collectgarbage('stop') \
+         log = {} \
+         local mt = {__gc = function(o) log[#log + 1] = o.name end} \
+         for _, name in ipairs({'a', 'b', 'c'}) do \
+           local t = {name = name} \
+           setmetatable(t, mt) \
+         end \
+         collectgarbage('restart')",
+    );
+    lua.gc_collect();
+    assert!(eval::<bool>(
+        &mut lua,
+        "return #log == 3 and log[1] == 'c' and log[2] == 'b' and log[3] == 'a'",
+    ));
+}
+
+#[test]
+fn resurrected_object_is_finalized_once() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        "count = 0 \
+         do \
+           local t = setmetatable({name = 'x'}, {__gc = function(o) \
+             count = count + 1 \
+             saved = o \
+           end}) \
+         end",
+    );
+    lua.gc_collect();
+    assert!(eval::<bool>(
+        &mut lua,
+        "return count == 1 and saved.name == 'x'"
+    ));
+    eval::<()>(&mut lua, "saved = nil");
+    lua.gc_collect();
+    assert_eq!(eval::<i64>(&mut lua, "return count"), 1);
+}
+
+#[test]
+fn weak_values_are_cleared_before_finalizer_runs() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        "weak = setmetatable({}, {__mode = 'v'}) \
+         seen = nil \
+         do \
+           local t = setmetatable({}, {__gc = function(o) seen = weak[1] end}) \
+           weak[1] = t \
+         end",
+    );
+    lua.gc_collect();
+    assert!(eval::<bool>(
+        &mut lua,
+        "return seen == nil and weak[1] == nil"
+    ));
+}
+
+#[test]
+fn finalizer_errors_are_discarded() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        "ran = false \
+         do \
+           setmetatable({}, {__gc = function() ran = true end}) \
+           setmetatable({}, {__gc = function() error('boom') end}) \
+         end",
+    );
+    lua.gc_collect();
+    assert!(eval::<bool>(&mut lua, "return ran"));
+}
+
+#[test]
+fn finalizers_run_while_a_script_executes() {
+    let mut lua = new_lua();
+    let finalized: i64 = eval(
+        &mut lua,
+        "local count = 0 \
+         local mt = {__gc = function() count = count + 1 end} \
+         for i = 1, 20000 do setmetatable({}, mt) end \
+         return count",
+    );
+    assert!(finalized > 0, "no finalizer ran during execution");
+}
+
+#[test]
+fn unreachable_userdata_is_finalized() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        "finalized = nil \
+         ud_mt = {__gc = function(u) finalized = u end}",
+    );
+    lua.enter(|ctx| {
+        let key = Value::string(LuaString::new(ctx, b"ud_mt"));
+        let mt = ctx.globals().raw_get(key).get_table().expect("ud_mt");
+        let ud = Userdata::new(ctx.mutation(), 7u32, 0);
+        ud.set_metatable(ctx, Some(mt));
+    });
+    lua.gc_collect();
+    assert!(eval::<bool>(
+        &mut lua,
+        "return type(finalized) == 'userdata'"
+    ));
+}
+
+static DROP_FINALIZED: AtomicUsize = AtomicUsize::new(0);
+
+fn count_finalized<'gc>(
+    _nctx: NativeContext<'gc, '_>,
+    _stack: Stack<'gc, '_>,
+) -> Result<CallbackAction<'gc>, Error<'gc>> {
+    DROP_FINALIZED.fetch_add(1, Ordering::SeqCst);
+    Ok(CallbackAction::Return)
+}
+
+#[test]
+fn dropping_lua_finalizes_reachable_objects() {
+    let mut lua = new_lua();
+    lua.enter(|ctx| {
+        let f = Function::new_native(ctx.mutation(), count_finalized as NativeFn, []);
+        let key = Value::string(LuaString::new(ctx, b"count_finalized"));
+        ctx.globals().raw_set(ctx, key, Value::function(f));
+    });
+    eval::<()>(
+        &mut lua,
+        "kept = setmetatable({}, {__gc = count_finalized}) \
+         also_kept = setmetatable({}, {__gc = count_finalized})",
+    );
+    assert_eq!(DROP_FINALIZED.load(Ordering::SeqCst), 0);
+    drop(lua);
+    assert_eq!(DROP_FINALIZED.load(Ordering::SeqCst), 2);
+}
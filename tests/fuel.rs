@@ -0,0 +1,96 @@
+//! `Executor::step_with_fuel` returns `Pending` once its instruction budget
+//! is spent, so scripts can be run cooperatively on one thread without a
+//! runaway loop starving the others.
+
+use tcvm::env::Thread;
+use tcvm::{Executor, LoadError, Lua, RuntimeError, StashedExecutor, StepResult};
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+/// Start `src` on a thread of its own, so several can be in flight.
+fn start(lua: &mut Lua, src: &str) -> StashedExecutor {
+    lua.try_enter(|ctx| -> Result<_, LoadError> {
+        let chunk = ctx.load(src, Some("=fuel"))?;
+        let thread = Thread::new(ctx);
+        Ok(ctx.stash(Executor::start_on(ctx, thread, chunk, ())))
+    })
+    .expect("load")
+}
+
+/// Step `ex` once with `fuel`; `true` once it has finished.
+fn step(lua: &mut Lua, ex: &StashedExecutor, fuel: u64) -> bool {
+    lua.try_enter(|ctx| -> Result<_, RuntimeError> {
+        Ok(match ctx.fetch(ex).step_with_fuel(ctx, fuel)? {
+            StepResult::Done => true,
+            StepResult::Yielded(_) => panic!("unexpected Yielded"),
+            StepResult::Pending => false,
+        })
+    })
+    .expect("step")
+}
+
+#[test]
+fn runaway_loops_return_pending() {
+    let mut lua = new_lua();
+    for src in [
+        "while true do end",
+        "local n = 0 repeat n = n + 1 until false",
+        "while true do tostring(1) end",
+        "local function f() return f() end f()",
+        "for i = 1, math.maxinteger do end",
+    ] {
+        let ex = start(&mut lua, src);
+        for _ in 0..10 {
+            assert!(!step(&mut lua, &ex, 1000), "{src:?} finished");
+        }
+    }
+}
+
+#[test]
+fn work_is_spread_over_steps_and_completes() {
+    let mut lua = new_lua();
+    let ex = start(
+        &mut lua,
+        "local function add(a, b) return a + b end \
+         local sum = 0 \
+         for i = 1, 10000 do sum = add(sum, i) end \
+         return sum",
+    );
+    let mut steps = 1;
+    while !step(&mut lua, &ex, 1000) {
+        steps += 1;
+        assert!(steps < 1000, "guard against runaway loop");
+    }
+    assert!(steps > 10, "finished in {steps} steps");
+    let sum: i64 = lua
+        .try_enter(|ctx| ctx.fetch(&ex).take_result::<i64>(ctx))
+        .expect("take_result");
+    assert_eq!(sum, 10000 * 10001 / 2);
+}
+
+#[test]
+fn cooperative_scripts_all_make_progress() {
+    let mut lua = new_lua();
+    let spinner = start(&mut lua, "while true do end");
+    let worker = start(
+        &mut lua,
+        "local t = {} for i = 1, 5000 do t[i] = i * 2 end return #t",
+    );
+    let mut rounds = 0;
+    loop {
+        assert!(!step(&mut lua, &spinner, 500));
+        if step(&mut lua, &worker, 500) {
+            break;
+        }
+        rounds += 1;
+        assert!(rounds < 1000, "worker starved");
+    }
+    let len: i64 = lua
+        .try_enter(|ctx| ctx.fetch(&worker).take_result::<i64>(ctx))
+        .expect("take_result");
+    assert_eq!(len, 5000);
+}
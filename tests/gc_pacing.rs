@@ -0,0 +1,87 @@
+//! The executor pays GC debt between steps: a script that churns through
+//! garbage in a single long-running loop must not grow the heap without
+//! bound, and `Lua::gc_collect` / `Lua::gc_step` give the host explicit
+//! control over the collector.
+
+use tcvm::env::{Error, Function, LuaString, NativeContext, NativeFn, Stack, Value};
+use tcvm::vm::sequence::CallbackAction;
+use tcvm::{Executor, LoadError, Lua};
+
+/// `heap()` — current total arena allocation in bytes, read from inside the
+/// running script.
+fn heap<'gc>(
+    nctx: NativeContext<'gc, '_>,
+    mut stack: Stack<'gc, '_>,
+) -> Result<CallbackAction<'gc>, Error<'gc>> {
+    let bytes = nctx.ctx.mutation().metrics().total_allocation();
+    stack.replace(&[Value::small_integer(bytes as i64)]);
+    Ok(CallbackAction::Return)
+}
+
+fn start(lua: &mut Lua, src: &str) -> tcvm::StashedExecutor {
+    lua.try_enter(|ctx| -> Result<_, LoadError> {
+        let f = Function::new_native(ctx.mutation(), heap as NativeFn, []);
+        let key = Value::string(LuaString::new(ctx, b"heap"));
+        ctx.globals().raw_set(ctx, key, Value::function(f));
+        let chunk = ctx.load(src, Some("gc_pacing"))?;
+        Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+    })
+    .expect("load")
+}
+
+/// One `finish` call runs a loop allocating 100k short-lived tables. Without
+/// debt payment at the executor's safe points every one of them would stay
+/// allocated until the script returned.
+#[test]
+fn long_running_loop_heap_stays_bounded() {
+    let mut lua = Lua::new();
+    let ex = start(
+        &mut lua,
+        "local peak = 0 \
+         for i = 1, 100000 do \
+           local t = {i, i + 1, i + 2} \
+           if i % 1000 == 0 then \
+             local h = heap() \
+             if h > peak then peak = h end \
+           end \
+         end \
+         return peak",
+    );
+    let cycles_before = lua.gc_metrics().total_cycles();
+    let peak: i64 = lua.execute(&ex).expect("run");
+    assert!(
+        lua.gc_metrics().total_cycles() > cycles_before,
+        "collector never completed a cycle during execution"
+    );
+    // 100k tables are several megabytes; a bounded heap stays far below.
+    assert!(peak < 1 << 20, "heap grew to {peak} bytes");
+}
+
+/// Garbage left behind by a finished script is reclaimed by an explicit
+/// full collection.
+#[test]
+fn gc_collect_reclaims_garbage() {
+    let mut lua = Lua::new();
+    lua.gc_collect();
+    let baseline = lua.gc_metrics().total_gc_count();
+
+    lua.enter(|ctx| {
+        for _ in 0..1000 {
+            tcvm::env::Table::new(ctx);
+        }
+    });
+    lua.gc_collect();
+    assert!(
+        lua.gc_metrics().total_gc_count() <= baseline,
+        "unreachable tables survived a full collection"
+    );
+}
+
+/// Stepping with a large enough work budget eventually reports that a
+/// cycle finished.
+#[test]
+fn gc_step_reports_cycle_completion() {
+    let mut lua = Lua::new();
+    let finished = (0..1000).any(|_| lua.gc_step(64 * 1024));
+    assert!(finished, "gc_step never finished a cycle");
+}
@@ -0,0 +1,182 @@
+//! Generational collection: objects that survive a cycle are promoted and
+//! left alone by minor cycles, young objects stored into old ones are kept
+//! alive through write barriers, and a full collection still reclaims old
+//! garbage.
+
+use tcvm::dmm::metrics::{CollectionMode, Pacing};
+use tcvm::{Executor, LoadError, Lua};
+
+fn eval<R>(lua: &mut Lua, src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("generational"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex).expect("run")
+}
+
+fn generational_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    eval::<()>(&mut lua, "collectgarbage('generational')");
+    lua
+}
+
+/// Run cycles through `collectgarbage("step")` until one finishes.
+const FINISH_MINOR: &str = "repeat until collectgarbage('step', 64)";
+
+#[test]
+fn collectgarbage_switches_the_pacing_mode() {
+    let mut lua = generational_lua();
+    assert!(matches!(
+        lua.gc_metrics().pacing().mode,
+        CollectionMode::Generational { .. }
+    ));
+    eval::<()>(&mut lua, "collectgarbage('incremental')");
+    assert_eq!(lua.gc_metrics().pacing().mode, CollectionMode::Incremental);
+}
+
+#[test]
+fn minor_cycles_keep_old_garbage_until_a_full_collection() {
+    let mut lua = generational_lua();
+    eval::<()>(
+        &mut lua,
+        "probe = setmetatable({}, {__mode = 'v'}) \
+         old = {} \
+         probe[1] = old",
+    );
+    // Promote `old`, then drop the only strong reference to it.
+    lua.gc_collect();
+    eval::<()>(&mut lua, "old = nil");
+
+    assert!(eval::<bool>(
+        &mut lua,
+        &format!("{FINISH_MINOR} return probe[1] ~= nil")
+    ));
+    lua.gc_collect();
+    assert!(eval::<bool>(&mut lua, "return probe[1] == nil"));
+}
+
+#[test]
+fn minor_cycles_collect_young_garbage() {
+    let mut lua = generational_lua();
+    lua.gc_collect();
+    assert!(eval::<bool>(
+        &mut lua,
+        &format!(
+            "local probe = setmetatable({{}}, {{__mode = 'v'}}) \
+             probe[1] = {{}} \
+             {FINISH_MINOR} \
+             return probe[1] == nil"
+        ),
+    ));
+}
+
+#[test]
+fn young_objects_stored_in_old_ones_survive_minor_cycles() {
+    let mut lua = generational_lua();
+    eval::<()>(&mut lua, "keep = {}");
+    lua.gc_collect();
+    let cycles = lua.gc_metrics().total_cycles();
+    // Every table stored into `keep` is young, and only reachable through
+    // an object the minor cycles don't trace.
+    assert!(eval::<bool>(
+        &mut lua,
+        "for i = 1, 50000 do \
+           local t = {value = i} \
+           keep[i % 64] = t \
+           keep[i % 64 + 64] = {t} \
+         end \
+         for i = 49937, 50000 do \
+           local t = keep[i % 64] \
+           if t.value ~= i or keep[i % 64 + 64][1] ~= t then return false end \
+         end \
+         return true",
+    ));
+    assert!(lua.gc_metrics().total_cycles() > cycles);
+}
+
+#[test]
+fn weak_entries_in_old_tables_are_cleared_by_minor_cycles() {
+    let mut lua = generational_lua();
+    eval::<()>(&mut lua, "weak = setmetatable({}, {__mode = 'v'})");
+    lua.gc_collect();
+    assert!(eval::<bool>(
+        &mut lua,
+        &format!(
+            "local kept = {{}} \
+             weak[1] = {{}} \
+             weak[2] = kept \
+             {FINISH_MINOR} \
+             return weak[1] == nil and weak[2] == kept"
+        ),
+    ));
+}
+
+#[test]
+fn heap_stays_bounded_in_generational_mode() {
+    let mut lua = generational_lua();
+    let peak: i64 = eval(
+        &mut lua,
+        "local peak = 0 \
+         for i = 1, 100000 do \
+           local t = {i, i + 1, i + 2} \
+           if i % 1000 == 0 then \
+             local kb = collectgarbage('count') \
+             if kb > peak then peak = kb end \
+           end \
+         end \
+         return math.floor(peak)",
+    );
+    assert!(peak < 1024, "heap grew to {peak} KB");
+}
+
+#[test]
+fn pacing_selects_generational_mode_from_the_host() {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua.gc_metrics().set_pacing(Pacing {
+        mode: CollectionMode::Generational { major_factor: 1.0 },
+        ..Pacing::DEFAULT
+    });
+    eval::<()>(&mut lua, "keep = {}");
+    lua.gc_collect();
+    assert!(eval::<bool>(
+        &mut lua,
+        "for i = 1, 20000 do keep[i % 16] = {i} end \
+         return keep[20000 % 16][1] == 20000",
+    ));
+}
+
+#[test]
+fn young_objects_stored_while_a_major_cycle_demotes_survive() {
+    let mut lua = generational_lua();
+    // A major factor of 1% makes nearly every cycle a major one, each
+    // starting by demoting the whole old generation a step at a time.
+    eval::<()>(
+        &mut lua,
+        "collectgarbage('generational', 20, 1) \
+         old = {} \
+         for i = 1, 20000 do old[i] = {n = i} end",
+    );
+    lua.gc_collect();
+    let cycles = lua.gc_metrics().total_cycles();
+    assert!(eval::<bool>(
+        &mut lua,
+        "for i = 1, 200000 do \
+           local t = old[i % 20000 + 1] \
+           t.child = {v = i} \
+           local garbage = {i, i} \
+         end \
+         for k = 1, 20000 do \
+           local t = old[k] \
+           if t.n ~= k or t.child.v % 20000 ~= k - 1 then return false end \
+         end \
+         return true",
+    ));
+    assert!(lua.gc_metrics().total_cycles() > cycles + 1);
+}
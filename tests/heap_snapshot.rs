@@ -0,0 +1,147 @@
+//! `Lua::heap_snapshot` lists every live object with its kind, size and
+//! references, can explain what keeps an object alive, and exports as JSON.
+
+use std::collections::HashSet;
+
+use tcvm::env::{LuaString, Userdata, Value};
+use tcvm::{Executor, HeapSnapshot, LoadError, Lua, MemoryKind};
+
+fn eval<R>(lua: &mut Lua, src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("heap_snapshot"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex).expect("run")
+}
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+/// Snapshot id of the global `name`, and of the globals table.
+fn global_ids(lua: &mut Lua, name: &str) -> (usize, usize) {
+    lua.enter(|ctx| {
+        let key = Value::string(LuaString::new(ctx, name.as_bytes()));
+        let value = ctx.globals().raw_get(key);
+        (
+            HeapSnapshot::id_of(value).expect("global is an object"),
+            HeapSnapshot::id_of(Value::table(ctx.globals())).unwrap(),
+        )
+    })
+}
+
+#[test]
+fn retaining_path_leads_through_the_holder() {
+    let mut lua = new_lua();
+    eval::<()>(&mut lua, "cache = {entries = {{}}} leak = cache.entries[1]");
+    let (leak, globals) = global_ids(&mut lua, "leak");
+    let (cache, _) = global_ids(&mut lua, "cache");
+    let snapshot = lua.heap_snapshot();
+
+    assert_eq!(snapshot.get(leak).unwrap().kind, MemoryKind::Table);
+    let path = snapshot.retaining_path(leak).expect("leak is reachable");
+    assert_eq!(path.last(), Some(&leak));
+    assert!(snapshot.roots().contains(&path[0]));
+    assert!(path.contains(&globals));
+
+    let retainers = snapshot.retainers(leak);
+    assert!(retainers.contains(&globals));
+    let entries = retainers
+        .iter()
+        .copied()
+        .find(|&id| snapshot.retainers(id).contains(&cache))
+        .expect("cache.entries retains leak");
+    assert_eq!(snapshot.get(entries).unwrap().kind, MemoryKind::Table);
+}
+
+#[test]
+fn objects_are_classified_by_kind() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        "local up = {} \
+         f = function() return up end \
+         co = coroutine.create(f) \
+         s = 'a' .. 'string' \
+         t = {x = 1}",
+    );
+    lua.enter(|ctx| {
+        let ud = Userdata::new(ctx.mutation(), 1u32, 0);
+        let key = Value::string(LuaString::new(ctx, b"ud"));
+        ctx.globals().raw_set(ctx, key, Value::userdata(ud));
+    });
+    let snapshot = lua.heap_snapshot();
+    let kinds: HashSet<MemoryKind> = snapshot.nodes().iter().map(|n| n.kind).collect();
+    // Array parts aren't objects of their own.
+    for kind in MemoryKind::ALL
+        .into_iter()
+        .filter(|&k| k != MemoryKind::TableArray)
+    {
+        assert!(kinds.contains(&kind), "no {} in the snapshot", kind.name());
+    }
+    let (s, _) = global_ids(&mut lua, "s");
+    assert_eq!(snapshot.get(s).unwrap().kind, MemoryKind::String);
+}
+
+#[test]
+fn edges_point_at_objects_in_the_snapshot() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        "t = {} for i = 1, 100 do t[i] = {tostring(i)} end",
+    );
+    let snapshot = lua.heap_snapshot();
+    for node in snapshot.nodes() {
+        assert!(node.size > 0);
+        for edge in node.edges.iter().chain(&node.weak_edges) {
+            assert!(snapshot.get(*edge).is_some(), "dangling edge from {node:?}");
+        }
+    }
+    let total = snapshot.total_size();
+    assert!(total <= lua.gc_metrics().total_gc_allocation());
+}
+
+#[test]
+fn weakly_held_objects_have_no_retaining_path() {
+    let mut lua = new_lua();
+    // Start from a fresh cycle so the collector is still sleeping when the
+    // snapshot is taken and the weakly held table hasn't been swept yet.
+    lua.gc_collect();
+    eval::<()>(
+        &mut lua,
+        "probe = setmetatable({}, {__mode = 'v'}) probe[1] = {}",
+    );
+    let (probe, _) = global_ids(&mut lua, "probe");
+    let held = lua.enter(|ctx| {
+        let key = Value::string(LuaString::new(ctx, b"probe"));
+        let probe = ctx.globals().raw_get(key).get_table().unwrap();
+        HeapSnapshot::id_of(probe.raw_get(Value::small_integer(1))).unwrap()
+    });
+    let snapshot = lua.heap_snapshot();
+    assert!(snapshot.get(probe).unwrap().weak_edges.contains(&held));
+    assert!(snapshot.retainers(held).is_empty());
+    assert!(snapshot.retaining_path(held).is_none());
+}
+
+#[test]
+fn json_export_lists_every_node() {
+    let mut lua = new_lua();
+    eval::<()>(&mut lua, "t = {}");
+    let snapshot = lua.heap_snapshot();
+    let json = snapshot.to_json();
+    assert!(json.starts_with("{\"roots\":["));
+    assert!(json.ends_with("]}"));
+    assert_eq!(json.matches("\"kind\":").count(), snapshot.nodes().len());
+    assert!(json.contains("\"kind\":\"table\""));
+
+    let mut written = Vec::new();
+    snapshot.write_json(&mut written).unwrap();
+    assert_eq!(written, json.as_bytes());
+}
@@ -23,7 +23,7 @@ fn host_resume_round_trip() {
     let mut lua = Lua::new();
     let ex = lua
         .try_enter(|ctx| -> Result<_, LoadError> {
-            let y = Function::new_native(ctx.mutation(), yielder as NativeFn, Box::new([]));
+            let y = Function::new_native(ctx.mutation(), yielder as NativeFn, []);
             let key = Value::string(LuaString::new(ctx, b"yielder"));
             ctx.globals().raw_set(ctx, key, Value::function(y));
             let chunk = ctx.load(
@@ -75,7 +75,7 @@ fn host_resume_two_cycles() {
     let mut lua = Lua::new();
     let ex = lua
         .try_enter(|ctx| -> Result<_, LoadError> {
-            let y = Function::new_native(ctx.mutation(), yielder as NativeFn, Box::new([]));
+            let y = Function::new_native(ctx.mutation(), yielder as NativeFn, []);
             let key = Value::string(LuaString::new(ctx, b"yielder"));
             ctx.globals().raw_set(ctx, key, Value::function(y));
             let chunk = ctx.load(
@@ -0,0 +1,111 @@
+//! Constant and immediate operand forms (`ADDI`, `ADDK`..., `SHRI`/`SHLI`,
+//! `EQK`/`EQI`, `LTI`..., `GETI`/`SETI`) behave exactly like their
+//! register-operand counterparts, metamethods and error messages included.
+
+use tcvm::env::LuaString;
+use tcvm::{Executor, LoadError, Lua, RuntimeError};
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+/// Run `src` and return the string it returns.
+fn run(src: &str) -> String {
+    let mut lua = new_lua();
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("=imm"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.finish(&ex).expect("execute");
+    lua.try_enter(|ctx| -> Result<_, RuntimeError> {
+        let s = ctx.fetch(&ex).take_result::<LuaString>(ctx)?;
+        Ok(String::from_utf8_lossy(s.as_bytes()).into_owned())
+    })
+    .expect("take_result")
+}
+
+#[test]
+fn arithmetic_with_constant_operands() {
+    let src = "local x, f = 7, 2.5\n\
+               return table.concat({\n\
+                 x + 1, x + 100000, f + 1, x - 1, x * 2, x % 3, x ^ 2, x / 2,\n\
+                 x // 2, f // 1, x & 3, x | 8, x ~ 1, x >> 1, 1 << x, x - 0.5,\n\
+               }, ',')";
+    assert_eq!(
+        run(src),
+        "8,100007,3.5,6,14,1,49.0,3.5,3,2.0,3,15,6,3,128,6.5"
+    );
+}
+
+#[test]
+fn constant_operands_reach_metamethods_in_source_order() {
+    let src = "local function tag(e)\n\
+                 return function(a, b) return e .. '(' .. type(a) .. ',' .. type(b) .. ')' end\n\
+               end\n\
+               local mt = {}\n\
+               for _, e in ipairs({'add', 'sub', 'shl', 'shr', 'band', 'lt', 'le'}) do\n\
+                 mt['__' .. e] = tag(e)\n\
+               end\n\
+               local t = setmetatable({}, mt)\n\
+               local lt, gt = t < 1, 1 < t\n\
+               return table.concat({\n\
+                 t + 1, t + 1.5, t - 1, 1 << t, t >> 1, t & 0xff,\n\
+                 tostring(lt), tostring(gt), tostring(t >= 2),\n\
+               }, ' ')";
+    assert_eq!(
+        run(src),
+        "add(table,number) add(table,number) sub(table,number) shl(number,table) \
+         shr(table,number) band(table,number) true true true"
+    );
+}
+
+#[test]
+fn comparisons_against_constants() {
+    let src = "local i, f, s, n = 5, 5.0, 'str', nil\n\
+               local r = {}\n\
+               for _, v in ipairs({\n\
+                 i == 5, f == 5, i ~= 5, f == 5.5, s == 'str', s ~= 'str', n == nil,\n\
+                 i == 'str', s == 5, i < 5, i <= 5, 5 < i, 4 < f, i > 4, i >= 6,\n\
+                 -1 < i, 0/0 < 1,\n\
+               }) do r[#r + 1] = tostring(v) end\n\
+               return table.concat(r, ',')";
+    assert_eq!(
+        run(src),
+        "true,true,false,false,true,false,true,false,false,false,true,false,true,\
+         true,false,true,false"
+    );
+    let src = "local ok, err = pcall(function() local t = {} return t < 1 end)\n\
+               local ok2, err2 = pcall(function() local t = {} return 1 <= t end)\n\
+               return err .. ' | ' .. err2";
+    assert_eq!(
+        run(src),
+        "imm:1: attempt to compare table with number | \
+         imm:2: attempt to compare number with table"
+    );
+}
+
+#[test]
+fn integer_keys_index_and_assign() {
+    let src = "local t = {[0] = 'zero', 'one'}\n\
+               t[2] = 'two'\n\
+               t[65535] = 'max'\n\
+               t[65536] = 'big'\n\
+               local logged = {}\n\
+               local p = setmetatable({}, {\n\
+                 __index = function(_, k) return 'idx' .. k end,\n\
+                 __newindex = function(_, k, v) logged[#logged + 1] = k .. '=' .. v end,\n\
+               })\n\
+               p[3] = 'x'\n\
+               local ok, err = pcall(function() local n return n[1] end)\n\
+               return table.concat({\n\
+                 t[0], t[1], t[2], t[65535], t[65536], p[7], logged[1], err,\n\
+               }, ' ')";
+    assert_eq!(
+        run(src),
+        "zero one two max big idx7 3=x imm:11: attempt to index a nil value (local 'n')"
+    );
+}
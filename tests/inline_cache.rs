@@ -0,0 +1,136 @@
+//! Field-access inline caches stay correct at polymorphic and megamorphic
+//! sites: a site that sees a few shapes caches each of them, and one that
+//! sees more stops refilling instead of thrashing. Method lookups through
+//! one `__index` table are cached too, and notice every change that could
+//! alter their result.
+
+use tcvm::env::LuaString;
+use tcvm::env::function::InlineCache;
+use tcvm::{Executor, Lua, StashedFunction};
+
+/// Run `src` and return its integer result, along with the chunk so its
+/// caches can be inspected.
+fn run(lua: &mut Lua, src: &str) -> (i64, StashedFunction) {
+    let (chunk, ex) = lua.enter(|ctx| {
+        let chunk = ctx.load(src, Some("=ic")).expect("load");
+        (ctx.stash(chunk), ctx.stash(Executor::start(ctx, chunk, ())))
+    });
+    (lua.execute(&ex).expect("run"), chunk)
+}
+
+/// The state of the chunk's last cache site.
+fn last_site(lua: &mut Lua, chunk: &StashedFunction) -> String {
+    lua.enter(|ctx| {
+        let closure = ctx.fetch(chunk).as_lua().expect("lua chunk");
+        match closure.proto.ic_table.last().expect("cache site").get() {
+            InlineCache::Empty => "empty".to_owned(),
+            InlineCache::Mono(entry) if entry.via.is_some() => "mono via __index".to_owned(),
+            InlineCache::Mono(_) => "mono".to_owned(),
+            InlineCache::Poly { len, .. } => format!("poly({len})"),
+            InlineCache::Mega => "mega".to_owned(),
+        }
+    })
+}
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+#[test]
+fn sites_seeing_a_few_shapes_cache_each() {
+    let mut lua = new_lua();
+    let src = "local objs = {{x = 1}, {y = 0, x = 2}, {z = 0, x = 3}}\n\
+               local sum = 0\n\
+               for round = 1, 10 do\n\
+                 for i = 1, #objs do sum = sum + objs[i].x end\n\
+               end\n\
+               return sum";
+    let (sum, chunk) = run(&mut lua, src);
+    assert_eq!(sum, 60);
+    assert_eq!(last_site(&mut lua, &chunk), "poly(3)");
+}
+
+#[test]
+fn sites_seeing_many_shapes_go_megamorphic() {
+    let mut lua = new_lua();
+    let src = "local objs = {{x = 1}, {a = 0, x = 2}, {b = 0, x = 3}, {c = 0, x = 4},\n\
+                             {d = 0, x = 5}, {e = 0, x = 6}}\n\
+               for round = 1, 10 do\n\
+                 for i = 1, #objs do local o = objs[i] o.x = o.x + 1 end\n\
+               end\n\
+               local sum = 0\n\
+               for i = 1, #objs do sum = sum + objs[i].x end\n\
+               return sum";
+    let (sum, chunk) = run(&mut lua, src);
+    assert_eq!(sum, 81);
+    assert_eq!(last_site(&mut lua, &chunk), "mega");
+}
+
+#[test]
+fn cached_globals_follow_environment_changes() {
+    let mut lua = new_lua();
+    let src = "local function get() return counter end\n\
+               local total = 0\n\
+               for i = 1, 20 do\n\
+                 counter = i\n\
+                 if i % 5 == 0 then _ENV['extra' .. i] = true end\n\
+                 total = total + get()\n\
+               end\n\
+               return total";
+    let (total, _) = run(&mut lua, src);
+    assert_eq!(total, 210);
+}
+
+#[test]
+fn methods_found_through_index_are_cached() {
+    let mut lua = new_lua();
+    let src = "local Point = {}\n\
+               Point.__index = Point\n\
+               Point.norm1 = function(self) return self.x + self.y end\n\
+               local sum = 0\n\
+               for i = 1, 10 do\n\
+                 local p = setmetatable({x = i, y = 1}, Point)\n\
+                 sum = sum + p:norm1()\n\
+               end\n\
+               return sum";
+    let (sum, chunk) = run(&mut lua, src);
+    assert_eq!(sum, 65);
+    assert_eq!(last_site(&mut lua, &chunk), "mono via __index");
+}
+
+#[test]
+fn cached_index_lookups_see_every_change() {
+    let mut lua = new_lua();
+    let src = "local Base = {}\n\
+               Base.__index = Base\n\
+               Base.get = function() return 1 end\n\
+               local Other = {get = function() return 100 end}\n\
+               local obj = setmetatable({}, Base)\n\
+               local function call(o) return o:get() + (o.get and 0) end\n\
+               local out = {}\n\
+               local function step() for _ = 1, 3 do out[#out + 1] = call(obj) end end\n\
+               step()\n\
+               Base.get = function() return 2 end step()\n\
+               Base.extra = true step()\n\
+               obj.get = function() return 3 end step()\n\
+               obj.get = nil step()\n\
+               Base.__index = Other step()\n\
+               Base.__index = function() return function() return 4 end end step()\n\
+               setmetatable(obj, {__index = Base}) step()\n\
+               return table.concat(out, ',')";
+    let ex = lua.enter(|ctx| {
+        let chunk = ctx.load(src, Some("=ic")).expect("load");
+        ctx.stash(Executor::start(ctx, chunk, ()))
+    });
+    lua.finish(&ex).expect("run");
+    let out = lua.enter(|ctx| {
+        let s = ctx
+            .fetch(&ex)
+            .take_result::<LuaString>(ctx)
+            .expect("result");
+        String::from_utf8_lossy(s.as_bytes()).into_owned()
+    });
+    assert_eq!(out, "1,1,1,2,2,2,2,2,2,3,3,3,2,2,2,100,100,100,4,4,4,2,2,2");
+}
@@ -21,7 +21,7 @@ fn main_thread_yield_surfaces_as_main_yielded() {
     let mut lua = Lua::new();
     let ex = lua
         .try_enter(|ctx| -> Result<_, LoadError> {
-            let y = Function::new_native(ctx.mutation(), yielder as NativeFn, Box::new([]));
+            let y = Function::new_native(ctx.mutation(), yielder as NativeFn, []);
             let key = Value::string(LuaString::new(ctx, b"yielder"));
             ctx.globals().raw_set(ctx, key, Value::function(y));
             let chunk = ctx.load("return yielder()", Some("main_yield"))?;
@@ -0,0 +1,296 @@
+//! `Lua::set_memory_limit`: a script that grows the heap past the limit
+//! gets a "not enough memory" error it can catch with `pcall`, whether the
+//! memory goes to one large string or to many small allocations, and the
+//! runtime stays usable afterwards. Large allocations are refused before
+//! they're made, so the process never actually holds more than the limit.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use tcvm::env::LuaString;
+use tcvm::{Executor, LoadError, Lua, RuntimeError, StepResult};
+
+const LIMIT: usize = 1024 * 1024;
+
+/// The system allocator, keeping count of the bytes each thread has live
+/// and the most it has had, so tests see everything a script allocates
+/// whether or not the runtime accounts for it.
+struct Counting;
+
+thread_local! {
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+fn record(delta: isize) {
+    let _ = LIVE.try_with(|live| {
+        let now = live.get() + delta;
+        live.set(now);
+        let _ = PEAK.try_with(|peak| peak.set(peak.get().max(now)));
+    });
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            record(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            record(layout.size() as isize);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        record(-(layout.size() as isize));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new.is_null() {
+            // Count the old and new blocks as both live for a moment, as
+            // they are when the data has to be moved.
+            record(new_size as isize);
+            record(-(layout.size() as isize));
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+fn try_eval<R>(lua: &mut Lua, src: &str) -> Result<R, RuntimeError>
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("memory_limit"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex)
+}
+
+fn eval<R>(lua: &mut Lua, src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    try_eval(lua, src).expect("run")
+}
+
+/// Run `src` one `Executor::step` at a time and return the string it
+/// returns, failing if it hasn't finished after `max_steps` steps.
+fn step_to_completion(lua: &mut Lua, src: &str, max_steps: usize) -> String {
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("memory_limit"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    for _ in 0..max_steps {
+        let done = lua
+            .try_enter(|ctx| -> Result<_, RuntimeError> {
+                Ok(match ctx.fetch(&ex).step(ctx)? {
+                    StepResult::Done => true,
+                    StepResult::Yielded(_) => panic!("unexpected Yielded"),
+                    StepResult::Pending => false,
+                })
+            })
+            .expect("step");
+        if done {
+            return lua
+                .try_enter(|ctx| -> Result<_, RuntimeError> {
+                    let s = ctx.fetch(&ex).take_result::<LuaString>(ctx)?;
+                    Ok(String::from_utf8_lossy(s.as_bytes()).into_owned())
+                })
+                .expect("take_result");
+        }
+    }
+    panic!("still running after {max_steps} steps");
+}
+
+/// A runtime allowed `LIMIT` bytes on top of what the standard library
+/// already uses.
+fn limited_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua.gc_collect();
+    let baseline = lua.gc_metrics().total_allocation();
+    lua.set_memory_limit(Some(baseline + LIMIT));
+    lua
+}
+
+#[test]
+fn oversized_string_rep_is_catchable() {
+    let mut lua = limited_lua();
+    assert!(eval::<bool>(
+        &mut lua,
+        "local ok, err = pcall(string.rep, 'x', 1 << 30) \
+         return not ok and err == 'not enough memory'",
+    ));
+}
+
+#[test]
+fn table_growth_past_the_limit_is_catchable() {
+    let mut lua = limited_lua();
+    assert!(eval::<bool>(
+        &mut lua,
+        "local ok, err = pcall(function() \
+           local t = {} \
+           for i = 1, 100000000 do t[i] = {} end \
+         end) \
+         return not ok and err == 'not enough memory'",
+    ));
+}
+
+#[test]
+fn string_concatenation_past_the_limit_is_catchable() {
+    let mut lua = limited_lua();
+    assert!(eval::<bool>(
+        &mut lua,
+        "local ok, err = pcall(function() \
+           local s = 'x' \
+           while true do s = s .. s end \
+         end) \
+         return not ok and err == 'not enough memory'",
+    ));
+}
+
+#[test]
+fn script_continues_once_the_memory_is_released() {
+    let mut lua = limited_lua();
+    let n: i64 = eval(
+        &mut lua,
+        "local ok = pcall(function() \
+           local t = {} \
+           for i = 1, 100000000 do t[i] = {} end \
+         end) \
+         assert(not ok) \
+         local t = {} \
+         for i = 1, 1000 do t[i] = {i} end \
+         return #t",
+    );
+    assert_eq!(n, 1000);
+    lua.gc_collect();
+    assert!(!lua.gc_metrics().exceeds_memory_limit(0));
+}
+
+#[test]
+fn native_results_past_the_limit_are_refused() {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua.set_memory_limit(Some(2_000_000));
+    let out = step_to_completion(
+        &mut lua,
+        r#"
+        local big = string.rep("x", 600000)
+        local ok1, e1 = pcall(function() return string.format("%s%s%s", big, big, big) end)
+        local ok2, e2 = pcall(string.format, "%s%s%s", big, big, big)
+        local ok3, e3 = pcall(string.gsub, big, "x", "yyyy")
+        local ok4, e4 = pcall(string.gsub, big, "x", function() return "yyyy" end)
+        return table.concat({tostring(ok1), e1, tostring(ok2), e2, tostring(ok3), e3,
+                             tostring(ok4), e4}, " ")
+    "#,
+        100_000,
+    );
+    assert_eq!(
+        out,
+        "false not enough memory false not enough memory false not enough memory \
+         false not enough memory"
+    );
+}
+
+#[test]
+fn staying_over_the_limit_does_not_stall_the_script() {
+    let mut lua = limited_lua();
+    // Many small allocations take the heap over the limit, and they're
+    // still live after the error is caught: the loop after the `pcall`
+    // allocates nothing and has to run while the heap stays over.
+    let out = step_to_completion(
+        &mut lua,
+        "local head \
+         local ok, err = pcall(function() for i = 1, 1e9 do head = {head} end end) \
+         local sum = 0 \
+         for i = 1, 1000000 do sum = sum + i end \
+         head = nil \
+         local n = 0 \
+         for i = 1, 1000 do n = n + #tostring(i) end \
+         return tostring(ok) .. ' ' .. err .. ' ' .. sum .. ' ' .. n",
+        100_000,
+    );
+    assert_eq!(out, "false not enough memory 500000500000 2893");
+}
+
+#[test]
+fn garbage_does_not_count_against_the_limit() {
+    let mut lua = limited_lua();
+    // Ten times the limit in total, but never more than a few KB live.
+    eval::<()>(
+        &mut lua,
+        "for i = 1, 100 do local s = string.rep('x', 100000, tostring(i)) end",
+    );
+}
+
+#[test]
+fn uncaught_error_reaches_the_host() {
+    let mut lua = limited_lua();
+    let err = try_eval::<()>(&mut lua, "local s = string.rep('x', 1 << 30)")
+        .expect_err("should run out of memory");
+    let RuntimeError::Lua(stashed) = err else {
+        panic!("expected RuntimeError::Lua, got {err:?}");
+    };
+    let msg = lua.enter(|ctx| {
+        let s = ctx.fetch(&stashed).value().get_string().expect("string");
+        String::from_utf8_lossy(s.as_bytes()).into_owned()
+    });
+    assert_eq!(msg, "not enough memory");
+}
+
+#[test]
+fn lifting_the_limit_allows_large_allocations() {
+    let mut lua = limited_lua();
+    lua.set_memory_limit(None);
+    assert_eq!(
+        eval::<i64>(&mut lua, "return #string.rep('x', 4 * 1024 * 1024)"),
+        4 * 1024 * 1024
+    );
+}
+
+#[test]
+fn peak_usage_stays_under_the_limit() {
+    for body in [
+        "local s = 'x' while true do s = s .. s end",
+        "local t = {} for i = 1, 1 << 40 do t[i] = i end",
+        "local t = {} for i = 1, 1 << 40 do t[i + 0.5] = i end",
+        "local t = {'x'} while true do t[1] = table.concat(t, '', 1, 1) .. t[1] end",
+        "local t = {'x'} while true do t[2] = t[1] t[1] = table.concat(t) end",
+    ] {
+        let mut lua = limited_lua();
+        let before = LIVE.with(Cell::get);
+        PEAK.with(|peak| peak.set(before));
+        assert!(
+            eval::<bool>(
+                &mut lua,
+                &format!(
+                    "local ok, err = pcall(function() {body} end) \
+                     return not ok and err == 'not enough memory'"
+                ),
+            ),
+            "{body}"
+        );
+        let peak = PEAK.with(Cell::get) - before;
+        assert!(
+            peak <= LIMIT as isize,
+            "`{body}` peaked at {peak} bytes over the {LIMIT} allowed"
+        );
+    }
+}
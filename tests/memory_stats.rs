@@ -0,0 +1,116 @@
+//! `Lua::memory_usage` and `collectgarbage("stats")` break live memory
+//! down by kind, and the kinds add up to the runtime's total.
+
+use tcvm::{Executor, LoadError, Lua, MemoryKind};
+
+fn eval<R>(lua: &mut Lua, src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("memory_stats"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex).expect("run")
+}
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua.gc_collect();
+    lua
+}
+
+#[test]
+fn kinds_add_up_to_the_total() {
+    let mut lua = new_lua();
+    eval::<()>(
+        &mut lua,
+        "t = {} for i = 1, 100 do t[i] = {name = tostring(i), i} end \
+         co = coroutine.create(function() end)",
+    );
+    let sum: usize = MemoryKind::ALL
+        .iter()
+        .map(|&kind| lua.memory_usage(kind).bytes)
+        .sum();
+    assert_eq!(sum, lua.gc_metrics().total_allocation());
+    let count: usize = MemoryKind::ALL
+        .iter()
+        .map(|&kind| lua.memory_usage(kind).count)
+        .sum();
+    assert_eq!(count, lua.gc_metrics().total_gc_count());
+}
+
+#[test]
+fn tables_and_their_array_parts_are_counted_separately() {
+    let mut lua = new_lua();
+    let tables = lua.memory_usage(MemoryKind::Table);
+    let arrays = lua.memory_usage(MemoryKind::TableArray);
+    eval::<()>(
+        &mut lua,
+        "keep = {} for i = 1, 1000 do keep[i] = {1, 2, 3, 4, 5, 6, 7, 8} end",
+    );
+    let grown_tables = lua.memory_usage(MemoryKind::Table);
+    let grown_arrays = lua.memory_usage(MemoryKind::TableArray);
+    assert!(grown_tables.count >= tables.count + 1001);
+    assert!(grown_arrays.bytes >= arrays.bytes + 1000 * 8 * 8);
+    assert_eq!(grown_arrays.count, 0);
+
+    eval::<()>(&mut lua, "keep = nil");
+    lua.gc_collect();
+    assert!(lua.memory_usage(MemoryKind::Table).count < grown_tables.count - 1000);
+    assert!(lua.memory_usage(MemoryKind::TableArray).bytes < grown_arrays.bytes);
+}
+
+#[test]
+fn distinct_layouts_grow_the_shape_tree() {
+    let mut lua = new_lua();
+    let before = lua.memory_usage(MemoryKind::Shape);
+    eval::<()>(
+        &mut lua,
+        "keep = {} for i = 1, 500 do keep[i] = {['k' .. i] = true} end",
+    );
+    let after = lua.memory_usage(MemoryKind::Shape);
+    assert!(after.count >= before.count + 500);
+    assert!(after.bytes > before.bytes);
+}
+
+#[test]
+fn strings_closures_and_threads_are_attributed() {
+    let mut lua = new_lua();
+    let strings = lua.memory_usage(MemoryKind::String);
+    let closures = lua.memory_usage(MemoryKind::Closure);
+    let threads = lua.memory_usage(MemoryKind::Thread);
+    eval::<()>(
+        &mut lua,
+        "s = string.rep('x', 100000) \
+         fs = {} for i = 1, 100 do fs[i] = function() return i end end \
+         co = coroutine.create(function() coroutine.yield() end) \
+         coroutine.resume(co)",
+    );
+    assert!(lua.memory_usage(MemoryKind::String).bytes >= strings.bytes + 100000);
+    assert!(lua.memory_usage(MemoryKind::Closure).count >= closures.count + 100);
+    let grown_threads = lua.memory_usage(MemoryKind::Thread);
+    assert!(grown_threads.count > threads.count);
+    assert!(grown_threads.bytes > threads.bytes);
+}
+
+#[test]
+fn collectgarbage_stats_reports_every_kind() {
+    let mut lua = new_lua();
+    assert!(eval::<bool>(
+        &mut lua,
+        "local stats = collectgarbage('stats') \
+         for _, kind in ipairs({'table', 'table_array', 'shape', 'string', \
+                                'prototype', 'closure', 'thread', 'userdata', 'other'}) do \
+           local entry = stats[kind] \
+           if math.type(entry.count) ~= 'integer' or math.type(entry.bytes) ~= 'integer' then \
+             return false \
+           end \
+         end \
+         return stats.table.count > 0 and stats.string.bytes > 0 \
+            and stats.prototype.count > 0",
+    ));
+}
@@ -21,12 +21,12 @@ impl<'gc> Sequence<'gc> for AddOneSequence {
 
     fn poll(
         self: Pin<&mut Self>,
-        _ctx: Context<'gc>,
+        ctx: Context<'gc>,
         _exec: Execution<'gc, '_>,
         mut stack: Stack<'gc, '_>,
     ) -> Result<SequencePoll<'gc>, Error<'gc>> {
         let v = stack.get(0).get_integer().unwrap_or(0);
-        stack.replace(&[Value::integer(v + 1)]);
+        stack.replace(&[Value::integer(ctx, v + 1)]);
         Ok(SequencePoll::Return)
     }
 }
@@ -46,7 +46,7 @@ fn bumper<'gc>(
     stack.replace(&[]);
     let then = BoxSequence::new(nctx.ctx.mutation(), AddOneSequence);
     Ok(CallbackAction::Call {
-        function: f,
+        function: Value::function(f),
         then: Some(then),
     })
 }
@@ -57,7 +57,7 @@ fn native_calls_lua_then_post_processes() {
 
     let ex = lua
         .try_enter(|ctx| -> Result<_, LoadError> {
-            let bumper_fn = Function::new_native(ctx.mutation(), bumper as NativeFn, Box::new([]));
+            let bumper_fn = Function::new_native(ctx.mutation(), bumper as NativeFn, []);
             let key = Value::string(LuaString::new(ctx, b"bumper"));
             ctx.globals().raw_set(ctx, key, Value::function(bumper_fn));
             let chunk = ctx.load(
@@ -0,0 +1,63 @@
+//! `pcall` runs a function in protected mode: its results come back after
+//! `true`, and an error it raises comes back as `false` plus the error
+//! value instead of unwinding further.
+
+use tcvm::{Executor, LoadError, Lua};
+
+fn eval<R>(src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let mut lua = Lua::new();
+    lua.load_all();
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("pcall"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex).expect("run")
+}
+
+#[test]
+fn success_prepends_true() {
+    assert!(eval::<bool>(
+        "local ok, a, b = pcall(function(x, y) return x + y, x * y end, 3, 4) \
+         return ok == true and a == 7 and b == 12",
+    ));
+}
+
+#[test]
+fn error_value_is_returned() {
+    assert!(eval::<bool>(
+        "local e = {} \
+         local ok, err = pcall(function() error(e) end) \
+         return ok == false and err == e",
+    ));
+}
+
+#[test]
+fn native_errors_are_caught() {
+    assert!(eval::<bool>(
+        "local ok, err = pcall(string.rep) \
+         return not ok and type(err) == 'string'",
+    ));
+}
+
+#[test]
+fn calling_a_non_function_is_caught() {
+    assert!(eval::<bool>(
+        "local ok, err = pcall(42) \
+         return not ok and err == 'attempt to call a number value'",
+    ));
+}
+
+#[test]
+fn nested_pcall_catches_innermost() {
+    assert!(eval::<bool>(
+        "local ok, inner_ok, err = pcall(function() \
+           return pcall(error, 'inner') \
+         end) \
+         return ok and not inner_ok and err == 'inner'",
+    ));
+}
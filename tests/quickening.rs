@@ -0,0 +1,212 @@
+//! Quickening: arithmetic, comparisons, numeric `for` loops and integer
+//! table reads rewrite themselves to type-specialized forms after seeing
+//! their operands, and back when the operands change. Results and error
+//! messages stay the same whichever form a site is in.
+
+use tcvm::env::{LuaString, Prototype};
+use tcvm::{Executor, Lua};
+
+/// Run `src`, returning the string it returns and the opcode names left in
+/// its first nested function once it has run.
+fn run(src: &str) -> (String, Vec<String>) {
+    let mut lua = Lua::new();
+    lua.load_all();
+    let (chunk, ex) = lua.enter(|ctx| {
+        let chunk = ctx.load(src, Some("=quick")).expect("load");
+        (ctx.stash(chunk), ctx.stash(Executor::start(ctx, chunk, ())))
+    });
+    lua.finish(&ex).expect("run");
+    lua.enter(|ctx| {
+        let out = ctx
+            .fetch(&ex)
+            .take_result::<LuaString>(ctx)
+            .expect("string result");
+        let closure = ctx.fetch(&chunk).as_lua().expect("lua chunk");
+        (
+            String::from_utf8_lossy(out.as_bytes()).into_owned(),
+            opcodes(&closure.proto.prototypes[0]),
+        )
+    })
+}
+
+fn opcodes(proto: &Prototype<'_>) -> Vec<String> {
+    proto
+        .code
+        .iter()
+        .map(|i| {
+            let i = format!("{:?}", i.get());
+            i.split_whitespace().next().unwrap().to_owned()
+        })
+        .collect()
+}
+
+fn has(ops: &[String], op: &str) -> bool {
+    ops.iter().any(|o| o == op)
+}
+
+/// Shared prelude: `f` does every quickened arithmetic and comparison on
+/// its two arguments.
+const ARITH: &str = r#"
+local function f(a, b)
+  local lt, le = a < b, a <= b
+  return table.concat({tostring(a + b), tostring(a - b), tostring(a * b),
+                       tostring(lt), tostring(le)}, " ")
+end
+"#;
+
+#[test]
+fn integer_operands_quicken() {
+    let (out, ops) = run(&format!("{ARITH} return f(7, 3)"));
+    assert_eq!(out, "10 4 21 false false");
+    for op in ["ADD_II", "SUB_II", "MUL_II", "LT_II", "LE_II"] {
+        assert!(has(&ops, op), "{op} missing from {ops:?}");
+    }
+}
+
+#[test]
+fn float_operands_quicken() {
+    let (out, ops) = run(&format!("{ARITH} return f(0.5, 2.5)"));
+    assert_eq!(out, "3.0 -2.0 1.25 true true");
+    for op in ["ADD_FF", "SUB_FF", "MUL_FF", "LT_FF", "LE_FF"] {
+        assert!(has(&ops, op), "{op} missing from {ops:?}");
+    }
+}
+
+#[test]
+fn changing_operands_despecialize() {
+    let (out, ops) = run(&format!(
+        r#"{ARITH}
+        local v = setmetatable({{}}, {{
+          __add = function() return "add" end,
+          __sub = function() return "sub" end,
+          __mul = function() return "mul" end,
+          __lt = function() return true end,
+          __le = function() return false end,
+        }})
+        return table.concat({{f(2, 3), f(2.0, 0.5), f(1, 0.5), f(v, v), f(1 << 50, 1 << 50),
+                             f(math.maxinteger, 1), f(1, 2)}}, " | ")
+    "#
+    ));
+    assert_eq!(
+        out,
+        "5 -1 6 true true | 2.5 1.5 1.0 false false | 1.5 0.5 0.5 false false | \
+         add sub mul true false | 2251799813685248 0 0 false true | \
+         -9223372036854775808 9223372036854775806 9223372036854775807 false false | \
+         3 -1 2 true true"
+    );
+    // Back on integers at the end.
+    assert!(has(&ops, "ADD_II") && !has(&ops, "ADD_FF"), "{ops:?}");
+    assert!(!has(&ops, "ADD"), "{ops:?}");
+}
+
+#[test]
+fn mixed_operands_stay_generic() {
+    let (out, ops) = run(&format!("{ARITH} return f(1, 2) .. ' | ' .. f(1, 2.5)"));
+    assert_eq!(out, "3 -1 2 true true | 3.5 -1.5 2.5 true true");
+    for op in ["ADD", "SUB", "MUL", "LT", "LE"] {
+        assert!(has(&ops, op), "{op} missing from {ops:?}");
+    }
+}
+
+#[test]
+fn wide_integers_stay_generic() {
+    let (out, ops) = run(&format!(
+        "{ARITH} return f(1 << 50, 3) .. ' | ' .. f(1 << 50, 3)"
+    ));
+    assert_eq!(
+        out,
+        "1125899906842627 1125899906842621 3377699720527872 false false | \
+         1125899906842627 1125899906842621 3377699720527872 false false"
+    );
+    for op in ["ADD", "SUB", "MUL", "LT", "LE"] {
+        assert!(has(&ops, op), "{op} missing from {ops:?}");
+    }
+
+    let (out, ops) = run(
+        "local function sum(a, b) local t = 0 for i = a, b do t = t + i end return t end \
+         return sum(1 << 50, (1 << 50) + 3) .. ' ' .. sum(1 << 50, (1 << 50) + 3)",
+    );
+    assert_eq!(out, "4503599627370502 4503599627370502");
+    assert!(has(&ops, "FORLOOP") && !has(&ops, "FORLOOP_I"), "{ops:?}");
+}
+
+#[test]
+fn for_loops_follow_their_control_values() {
+    let src = r#"
+        local function sum(a, b, step)
+          local t = 0
+          for i = a, b, step do t = t + i end
+          return t
+        end
+        local r = {sum(1, 10, 1), sum(0.5, 2, 0.5), sum(10, 1, -3), sum(1, 2.5, 1)}
+        return table.concat(r, " ")
+    "#;
+    let (out, ops) = run(src);
+    assert_eq!(out, "55 5.0 22 3.0");
+    assert!(!has(&ops, "FORLOOP_I"), "{ops:?}");
+
+    let (out, ops) = run(&src.replace("sum(1, 2.5, 1)", "sum(3, 1, -1)"));
+    assert_eq!(out, "55 5.0 22 6");
+    assert!(has(&ops, "FORLOOP_I"), "{ops:?}");
+
+    let (_, ops) = run(&src.replace("sum(1, 2.5, 1)", "sum(0.0, 1.0, 0.25)"));
+    assert!(has(&ops, "FORLOOP_F"), "{ops:?}");
+}
+
+#[test]
+fn integer_table_reads() {
+    let src = r#"
+        local function get(t, k) return t[k] end
+        local arr = {10, 20, 30}
+        local proto = setmetatable({}, {__index = arr})
+        local computed = setmetatable({1}, {__index = function(_, k) return k * 2 end})
+        local r = {get(arr, 2), tostring(get(arr, 4)), get({x = 1}, "x"), tostring(get(arr, 0)),
+                   get(proto, 3), get(computed, 1), get(computed, 5), get(arr, 1.0)}
+        return table.concat(r, " ")
+    "#;
+    let (out, ops) = run(src);
+    assert_eq!(out, "20 nil 1 nil 30 1 10 10");
+    assert!(
+        has(&ops, "GETTABLE") && !has(&ops, "GETTABLE_ARRAY"),
+        "{ops:?}"
+    );
+
+    let (_, ops) = run(&src.replace("get(arr, 1.0)", "get(arr, 3)"));
+    assert!(has(&ops, "GETTABLE_ARRAY"), "{ops:?}");
+}
+
+#[test]
+fn errors_after_quickening_name_their_operands() {
+    let src = r#"
+        local function f(t, k, a) return t[k] + a end
+        f({1}, 1, 2)
+        f({1}, 1, 2)
+        local ok1, e1 = pcall(f, {1}, 1, nil)
+        local ok2, e2 = pcall(f, nil, 1, 2)
+        return e1 .. " | " .. e2
+    "#;
+    let (out, _) = run(src);
+    assert_eq!(
+        out,
+        "quick:2: attempt to perform arithmetic on a nil value (local 'a') | \
+         quick:2: attempt to index a nil value (local 't')"
+    );
+}
+
+#[test]
+fn alternating_operands_settle_on_generic() {
+    let (out, ops) = run(&format!(
+        r#"{ARITH}
+        local r
+        for i = 1, 20 do r = f(i, 2) .. " | " .. f(i + 0.5, 2.0) end
+        return r
+    "#
+    ));
+    assert_eq!(out, "22 18 40 false false | 22.5 18.5 41.0 false false");
+    for op in ["ADD", "SUB", "MUL", "LT", "LE"] {
+        assert!(has(&ops, op), "{op} missing from {ops:?}");
+    }
+    for op in ["ADD_II", "ADD_FF", "LT_II", "LT_FF"] {
+        assert!(!has(&ops, op), "{op} left in {ops:?}");
+    }
+}
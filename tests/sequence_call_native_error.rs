@@ -46,7 +46,7 @@ impl<'gc> Sequence<'gc> for CallBoomerSeq<'gc> {
             self.called = true;
             stack.replace(&[]);
             Ok(SequencePoll::Call {
-                function: self.boomer,
+                function: Value::function(self.boomer),
                 bottom: 0,
             })
         } else {
@@ -59,7 +59,7 @@ fn factory<'gc>(
     nctx: NativeContext<'gc, '_>,
     _stack: Stack<'gc, '_>,
 ) -> Result<CallbackAction<'gc>, Error<'gc>> {
-    let boomer_fn = Function::new_native(nctx.ctx.mutation(), boomer as NativeFn, Box::new([]));
+    let boomer_fn = Function::new_native(nctx.ctx.mutation(), boomer as NativeFn, []);
     let seq = BoxSequence::new(
         nctx.ctx.mutation(),
         CallBoomerSeq {
@@ -76,7 +76,7 @@ fn native_error_in_sequence_call_caught_by_pcallseq() {
     lua.load_all();
     let ex = lua
         .try_enter(|ctx| -> Result<_, LoadError> {
-            let f = Function::new_native(ctx.mutation(), factory as NativeFn, Box::new([]));
+            let f = Function::new_native(ctx.mutation(), factory as NativeFn, []);
             let key = Value::string(LuaString::new(ctx, b"factory"));
             ctx.globals().raw_set(ctx, key, Value::function(f));
             let chunk = ctx.load(
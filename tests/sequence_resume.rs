@@ -32,7 +32,7 @@ impl<'gc> Sequence<'gc> for ResumeAndAddOne<'gc> {
 
     fn poll(
         mut self: Pin<&mut Self>,
-        _ctx: Context<'gc>,
+        ctx: Context<'gc>,
         _exec: Execution<'gc, '_>,
         mut stack: Stack<'gc, '_>,
     ) -> Result<SequencePoll<'gc>, Error<'gc>> {
@@ -49,7 +49,7 @@ impl<'gc> Sequence<'gc> for ResumeAndAddOne<'gc> {
                 .get(0)
                 .get_integer()
                 .expect("target should return an integer");
-            stack.replace(&[Value::integer(v + 1)]);
+            stack.replace(&[Value::integer(ctx, v + 1)]);
             Ok(SequencePoll::Return)
         }
     }
@@ -79,7 +79,7 @@ fn sequence_resume_then_post_processes() {
     lua.load_all();
     let ex = lua
         .try_enter(|ctx| -> Result<_, LoadError> {
-            let f = Function::new_native(ctx.mutation(), bumpr as NativeFn, Box::new([]));
+            let f = Function::new_native(ctx.mutation(), bumpr as NativeFn, []);
             let key = Value::string(LuaString::new(ctx, b"bumpr"));
             ctx.globals().raw_set(ctx, key, Value::function(f));
             let chunk = ctx.load(
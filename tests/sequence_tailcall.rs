@@ -32,8 +32,8 @@ impl<'gc> Sequence<'gc> for TailCallSeq {
             .get(0)
             .get_function()
             .expect("stack[0] should be a function");
-        stack.replace(&[Value::integer(41)]);
-        Ok(SequencePoll::TailCall(target))
+        stack.replace(&[Value::small_integer(41)]);
+        Ok(SequencePoll::TailCall(Value::function(target)))
     }
 }
 
@@ -52,8 +52,7 @@ fn sequence_tailcall_lands_at_original_func_idx() {
 
     let ex = lua
         .try_enter(|ctx| -> Result<_, LoadError> {
-            let forward_fn =
-                Function::new_native(ctx.mutation(), forward as NativeFn, Box::new([]));
+            let forward_fn = Function::new_native(ctx.mutation(), forward as NativeFn, []);
             let key = Value::string(LuaString::new(ctx, b"forward"));
             ctx.globals().raw_set(ctx, key, Value::function(forward_fn));
             let chunk = ctx.load(
@@ -0,0 +1,84 @@
+//! Table shapes are only kept alive by the tables using them: layouts no
+//! live table has anymore are pruned from the transition tree, and
+//! `Lua::shape_count` reports what is left.
+
+use tcvm::{Executor, LoadError, Lua, MemoryKind};
+
+fn eval<R>(lua: &mut Lua, src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("shape_tree"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex).expect("run")
+}
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua.gc_collect();
+    lua
+}
+
+#[test]
+fn dynamic_keys_do_not_grow_the_tree_forever() {
+    let mut lua = new_lua();
+    let shapes = lua.shape_count();
+    let bytes = lua.memory_usage(MemoryKind::Shape).bytes;
+    for round in 0..5 {
+        eval::<()>(
+            &mut lua,
+            &format!(
+                "for i = 1, 2000 do \
+                   local record = {{id = i}} \
+                   record['field_{round}_' .. i] = true \
+                   record.tail = i \
+                 end"
+            ),
+        );
+        lua.gc_collect();
+        assert!(
+            lua.shape_count() < shapes + 16,
+            "round {round}: {} shapes, started with {shapes}",
+            lua.shape_count()
+        );
+    }
+    assert!(lua.memory_usage(MemoryKind::Shape).bytes < bytes + 4096);
+}
+
+#[test]
+fn shapes_of_live_tables_stay_in_the_tree() {
+    let mut lua = new_lua();
+    let shapes = lua.shape_count();
+    eval::<()>(
+        &mut lua,
+        "keep = {} \
+         for i = 1, 300 do keep[i] = {['k' .. i] = i} end \
+         mt = {} \
+         for i = 1, 100 do setmetatable(keep[i], mt) end",
+    );
+    lua.gc_collect();
+    assert!(lua.shape_count() >= shapes + 400);
+
+    // Tables built after the collection with the same layouts reuse the
+    // surviving shapes and read back correctly.
+    let sum: i64 = eval(
+        &mut lua,
+        "local sum = 0 \
+         for i = 1, 300 do \
+           local t = {['k' .. i] = i} \
+           if i <= 100 then setmetatable(t, mt) end \
+           sum = sum + t['k' .. i] + keep[i]['k' .. i] \
+         end \
+         return sum",
+    );
+    assert_eq!(sum, 2 * 300 * 301 / 2);
+
+    eval::<()>(&mut lua, "keep = nil mt = nil");
+    lua.gc_collect();
+    assert!(lua.shape_count() < shapes + 16);
+}
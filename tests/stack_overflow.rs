@@ -0,0 +1,167 @@
+//! `Lua::set_stack_limits`: runaway recursion — through Lua calls,
+//! metamethods, natives calling back into Lua, or nested coroutines — gets
+//! a "stack overflow" error `pcall` can catch instead of exhausting memory,
+//! and the runtime stays usable afterwards.
+
+use tcvm::env::LuaString;
+use tcvm::{Executor, LoadError, Lua, RuntimeError, StackLimits};
+
+fn lua_with(limits: StackLimits) -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua.set_stack_limits(limits);
+    lua
+}
+
+/// Run `src` and return the string it returns.
+fn run(lua: &mut Lua, src: &str) -> Result<String, RuntimeError> {
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("=overflow"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.finish(&ex)?;
+    lua.try_enter(|ctx| -> Result<_, RuntimeError> {
+        let s = ctx.fetch(&ex).take_result::<LuaString>(ctx)?;
+        Ok(String::from_utf8_lossy(s.as_bytes()).into_owned())
+    })
+}
+
+#[test]
+fn unbounded_recursion_is_catchable() {
+    let mut lua = lua_with(StackLimits::DEFAULT);
+    let out = run(
+        &mut lua,
+        r#"
+        local function f() return 1 + f() end
+        local ok, e = pcall(f)
+        local ok2, e2 = pcall(f)
+        return tostring(ok) .. " " .. e .. " | " .. tostring(ok2) .. " " .. e2
+    "#,
+    )
+    .unwrap();
+    assert_eq!(
+        out,
+        "false overflow:2: stack overflow | false overflow:2: stack overflow"
+    );
+}
+
+#[test]
+fn call_depth_is_configurable() {
+    let limits = StackLimits {
+        max_call_depth: 100,
+        ..StackLimits::DEFAULT
+    };
+    let mut lua = lua_with(limits);
+    assert_eq!(lua.stack_limits(), limits);
+    let out = run(
+        &mut lua,
+        r#"
+        local function depth(n) if n == 0 then return 0 end return 1 + depth(n - 1) end
+        local function tail(n) if n == 0 then return "tail" end return tail(n - 1) end
+        local ok, e = pcall(depth, 200)
+        return depth(90) .. " " .. tail(1000) .. " " .. e
+    "#,
+    )
+    .unwrap();
+    assert_eq!(out, "90 tail overflow:2: stack overflow");
+}
+
+#[test]
+fn stack_size_is_configurable() {
+    let mut lua = lua_with(StackLimits {
+        max_stack_size: 1000,
+        ..StackLimits::DEFAULT
+    });
+    // Each level of `wide` holds a few dozen registers, so the slot limit
+    // is reached long before the call depth limit.
+    let locals: Vec<String> = (0..40).map(|i| format!("l{i}")).collect();
+    let src = format!(
+        r#"
+        local function wide(n)
+          local {} = n
+          if n == 0 then return "bottom" end
+          return (wide(n - 1))
+        end
+        local shallow = wide(5)
+        local ok, e = pcall(wide, 100)
+        return shallow .. " " .. tostring(ok) .. " " .. e
+    "#,
+        locals.join(", ")
+    );
+    let out = run(&mut lua, &src).unwrap();
+    assert_eq!(out, "bottom false overflow:5: stack overflow");
+}
+
+#[test]
+fn metamethod_recursion_overflows() {
+    let mut lua = lua_with(StackLimits::DEFAULT);
+    let out = run(
+        &mut lua,
+        r#"
+        local t = setmetatable({}, {__index = function(t, k) return t[k] end})
+        local ok, e = pcall(function() return t.x end)
+        return e
+    "#,
+    )
+    .unwrap();
+    assert_eq!(out, "overflow:2: stack overflow");
+}
+
+#[test]
+fn native_reentry_has_its_own_limit() {
+    let mut lua = lua_with(StackLimits {
+        max_native_depth: 10,
+        ..StackLimits::DEFAULT
+    });
+    let out = run(
+        &mut lua,
+        r#"
+        local levels = 0
+        local function nest() levels = levels + 1 return pcall(nest) end
+        local r = {nest()}
+        local depth = 0
+        local function wrap() depth = depth + 1 return coroutine.wrap(wrap)() end
+        local ok, e = pcall(wrap)
+        return levels .. " " .. r[#r] .. " | " .. depth .. " " .. e
+    "#,
+    )
+    .unwrap();
+    // Ten nested `pcall`s fit, so `nest` runs eleven times. Each `wrap`
+    // nests two levels: the wrapper waiting on the resume, and the resume.
+    assert_eq!(out, "11 C stack overflow | 5 C stack overflow");
+}
+
+#[test]
+fn error_handling_gets_headroom() {
+    let mut lua = lua_with(StackLimits {
+        max_call_depth: 100,
+        ..StackLimits::DEFAULT
+    });
+    let out = run(
+        &mut lua,
+        r#"
+        local function rec() return 1 + rec() end
+        local seen = {}
+        local mt = {__close = function()
+          local _, e = pcall(rec)
+          seen[#seen + 1] = e
+        end}
+        local function f()
+          local x <close> = setmetatable({}, mt)
+          return 1 + f()
+        end
+        local _, e = pcall(f)
+        return e .. " | " .. seen[1] .. " | " .. seen[2]
+    "#,
+    )
+    .unwrap();
+    // The innermost `__close` runs past the limit, in the headroom, so
+    // overflowing again there is an error in error handling. The next one
+    // runs back under the limit, where `pcall` itself overflows.
+    assert_eq!(
+        out,
+        "overflow:10: stack overflow | overflow:2: error in error handling | stack overflow"
+    );
+}
@@ -0,0 +1,86 @@
+//! Thread stacks are traced precisely: registers of frames that have
+//! returned don't keep their values alive, and the memory of a stack that
+//! grew during deep recursion is given back once it unwinds.
+
+use tcvm::{Executor, LoadError, Lua, MemoryKind};
+
+fn eval<R>(lua: &mut Lua, src: &str) -> R
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("thread_stack"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex).expect("run")
+}
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+#[test]
+fn dead_registers_do_not_keep_values_alive() {
+    let mut lua = new_lua();
+    // No native calls after `rec` returns, so nothing but the collector
+    // touches the stack slots its frames left behind. The outermost calls'
+    // registers overlap the chunk's own window, so they record nothing.
+    let alive: i64 = eval(
+        &mut lua,
+        "probe = setmetatable({}, {__mode = 'v'}) \
+         local function rec(n) \
+           local t = {} \
+           if n <= 100 then probe[n] = t end \
+           if n > 1 then rec(n - 1) end \
+         end \
+         rec(110) \
+         for i = 1, 200000 do local x = {} end \
+         local alive = 0 \
+         for i = 1, 100 do if probe[i] ~= nil then alive = alive + 1 end end \
+         return alive",
+    );
+    assert_eq!(alive, 0);
+}
+
+#[test]
+fn live_registers_survive_collection() {
+    let mut lua = new_lua();
+    let sum: i64 = eval(
+        &mut lua,
+        "local function rec(n) \
+           local t = {n} \
+           if n > 1 then \
+             for i = 1, 2000 do local x = {} end \
+             return t[1] + rec(n - 1) \
+           end \
+           return t[1] \
+         end \
+         return rec(200)",
+    );
+    assert_eq!(sum, 200 * 201 / 2);
+}
+
+#[test]
+fn stack_shrinks_after_deep_recursion() {
+    let mut lua = new_lua();
+    let (deep, after): (i64, i64) = eval(
+        &mut lua,
+        "local deep \
+         local function rec(n) \
+           if n == 0 then deep = collectgarbage('stats').thread.bytes return end \
+           rec(n - 1) \
+         end \
+         rec(50000) \
+         for i = 1, 200000 do local x = {} end \
+         return deep, collectgarbage('stats').thread.bytes",
+    );
+    assert!(deep > 1024 * 1024, "deep stack used only {deep} bytes");
+    assert!(after < deep / 10, "stack kept {after} of {deep} bytes");
+
+    lua.gc_collect();
+    assert!(lua.memory_usage(MemoryKind::Thread).bytes < 64 * 1024);
+}
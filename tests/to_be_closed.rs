@@ -0,0 +1,228 @@
+//! `local x <close>` calls `__close(x, err)` whenever the variable goes out
+//! of scope: block exit, `break`/`goto`, `return`, error unwinding and
+//! `coroutine.close`. Each chunk logs the closes it observes and returns
+//! the log as a string.
+
+use tcvm::env::LuaString;
+use tcvm::{Executor, LoadError, Lua, RuntimeError};
+
+/// Shared prelude: `closer(name)` returns a value whose `__close` appends
+/// `name:err` to `log`; `flush()` returns the log and clears it.
+const PRELUDE: &str = r#"
+local log = {}
+local function closer(name)
+  return setmetatable({}, {__close = function(_, e)
+    log[#log + 1] = name .. ":" .. tostring(e)
+  end})
+end
+local function flush()
+  local s = table.concat(log, " ")
+  log = {}
+  return s
+end
+"#;
+
+/// Run `PRELUDE .. src` and return the string it returns.
+fn run(src: &str) -> Result<String, RuntimeError> {
+    let mut lua = Lua::new();
+    lua.load_all();
+    let src = format!("{PRELUDE}{src}");
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(&src, Some("=tbc"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.finish(&ex)?;
+    lua.try_enter(|ctx| -> Result<_, RuntimeError> {
+        let s = ctx.fetch(&ex).take_result::<LuaString>(ctx)?;
+        Ok(String::from_utf8_lossy(s.as_bytes()).into_owned())
+    })
+}
+
+#[test]
+fn block_exit_closes_in_reverse_order() {
+    let out = run(r#"
+        do
+          local a <close> = closer("a")
+          local b <close> = closer("b")
+          local c <close> = nil
+          local d <close> = false
+          log[#log + 1] = "body"
+        end
+        return flush()
+    "#)
+    .unwrap();
+    assert_eq!(out, "body b:nil a:nil");
+}
+
+#[test]
+fn loops_close_every_iteration() {
+    let out = run(r#"
+        for i = 1, 2 do local x <close> = closer("f" .. i) end
+        for _, v in ipairs({"p", "q"}) do local x <close> = closer(v) end
+        local n = 0
+        while n < 2 do
+          n = n + 1
+          local x <close> = closer("w" .. n)
+        end
+        repeat local x <close> = closer("r") until true
+        return flush()
+    "#)
+    .unwrap();
+    assert_eq!(out, "f1:nil f2:nil p:nil q:nil w1:nil w2:nil r:nil");
+}
+
+#[test]
+fn break_and_goto_close_the_scopes_they_leave() {
+    let out = run(r#"
+        for i = 1, 3 do
+          local x <close> = closer("b" .. i)
+          if i == 2 then break end
+        end
+        local k = 0
+        ::again::
+        k = k + 1
+        do
+          local g <close> = closer("g" .. k)
+          if k < 2 then goto again end
+          do
+            local inner <close> = closer("inner")
+            goto out
+          end
+        end
+        ::out::
+        return flush()
+    "#)
+    .unwrap();
+    assert_eq!(out, "b1:nil b2:nil g1:nil inner:nil g2:nil");
+}
+
+#[test]
+fn return_closes_after_evaluating_results() {
+    let out = run(r#"
+        local function f()
+          local x <close> = closer("f")
+          return #log, "b", "c"
+        end
+        local function g()
+          local y <close> = closer("g")
+          return f()
+        end
+        local r = table.concat({g()}, ",")
+        return r .. " " .. select('#', g()) .. " " .. flush()
+    "#)
+    .unwrap();
+    assert_eq!(out, "0,b,c 3 f:nil g:nil f:nil g:nil");
+}
+
+#[test]
+fn error_unwinding_passes_the_error() {
+    let out = run(r#"
+        local ok, e = pcall(function()
+          local x <close> = closer("x")
+          error("boom", 0)
+        end)
+        return tostring(ok) .. " " .. e .. " " .. flush()
+    "#)
+    .unwrap();
+    assert_eq!(out, "false boom x:boom");
+}
+
+#[test]
+fn error_in_close_replaces_the_original() {
+    let out = run(r#"
+        local ok, e = pcall(function()
+          local x <close> = closer("x")
+          local y <close> = setmetatable({}, {__close = function(_, e)
+            error("close failed after " .. e, 0)
+          end})
+          error("boom", 0)
+        end)
+        local ok2, e2 = pcall(function()
+          local z <close> = setmetatable({}, {__close = function()
+            error("on exit", 0)
+          end})
+        end)
+        return e .. " | " .. flush() .. " | " .. e2
+    "#)
+    .unwrap();
+    assert_eq!(
+        out,
+        "close failed after boom | x:close failed after boom | on exit"
+    );
+}
+
+#[test]
+fn non_closable_value_is_an_error() {
+    let out = run(r#"
+        local ok, e = pcall(function() local v <close> = 42 end)
+        return e
+    "#)
+    .unwrap();
+    assert!(
+        out.ends_with("variable 'v' got a non-closable value"),
+        "{out}"
+    );
+}
+
+#[test]
+fn close_methods_can_yield() {
+    let out = run(r#"
+        local co = coroutine.wrap(function()
+          do
+            local x <close> = setmetatable({}, {__close = function()
+              coroutine.yield("closing")
+            end})
+          end
+          return "done"
+        end)
+        return co() .. " " .. co()
+    "#)
+    .unwrap();
+    assert_eq!(out, "closing done");
+}
+
+#[test]
+fn coroutine_close_closes_pending_variables() {
+    let out = run(r#"
+        local co = coroutine.create(function()
+          local x <close> = closer("co")
+          coroutine.yield()
+        end)
+        coroutine.resume(co)
+        local ok = coroutine.close(co)
+        local bad = coroutine.create(function()
+          local x <close> = setmetatable({}, {__close = function()
+            error("bad", 0)
+          end})
+          coroutine.yield()
+        end)
+        coroutine.resume(bad)
+        local ok2, e = coroutine.close(bad)
+        return tostring(ok) .. " " .. flush() .. " " .. tostring(ok2) .. " " .. e
+    "#)
+    .unwrap();
+    assert_eq!(out, "true co:nil false bad");
+}
+
+#[test]
+fn close_variable_closes_a_file() {
+    let mut p = std::env::temp_dir();
+    p.push(format!("tcvm_tbc_{}.txt", std::process::id()));
+    let path = p.to_string_lossy().into_owned();
+    let out = run(&format!(
+        r#"
+        local h
+        do
+          local f <close> = assert(io.open({path:?}, "w"))
+          f:write("x")
+          h = f
+        end
+        return io.type(h)
+    "#
+    ))
+    .unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(out, "closed file");
+}
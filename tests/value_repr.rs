@@ -0,0 +1,101 @@
+//! `Value` is a single NaN-boxed word. Integers too wide to store inline
+//! are boxed, and must stay indistinguishable from narrow ones: they
+//! round-trip exactly, compare and hash by value, and survive collection.
+//! `Value::small_integer` never allocates and only takes integers that fit inline.
+
+use tcvm::env::{LuaString, Value};
+use tcvm::{Executor, LoadError, Lua, RuntimeError};
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+/// Run `src` and return the string it returns.
+fn run(lua: &mut Lua, src: &str) -> String {
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("=repr"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.finish(&ex).expect("execute");
+    lua.try_enter(|ctx| -> Result<_, RuntimeError> {
+        let s = ctx.fetch(&ex).take_result::<LuaString>(ctx)?;
+        Ok(String::from_utf8_lossy(s.as_bytes()).into_owned())
+    })
+    .expect("take_result")
+}
+
+#[test]
+fn values_are_one_word() {
+    assert_eq!(size_of::<Value<'static>>(), 8);
+}
+
+#[test]
+fn host_values_round_trip() {
+    let mut lua = new_lua();
+    let wide = [
+        0,
+        -1,
+        (1 << 47) - 1,
+        1 << 47,
+        -(1 << 47),
+        -(1 << 47) - 1,
+        i64::MAX,
+        i64::MIN,
+    ];
+    let stashed = lua.enter(|ctx| {
+        for i in wide {
+            assert_eq!(Value::integer(ctx, i).get_integer(), Some(i));
+            assert!(Value::integer(ctx, i) == Value::integer(ctx, i));
+        }
+        for f in [0.0, -0.0, 1.5, f64::INFINITY, f64::NEG_INFINITY, f64::MIN] {
+            let v = Value::float(f);
+            assert_eq!(v.get_float().map(f64::to_bits), Some(f.to_bits()));
+            assert!(v.get_integer().is_none());
+        }
+        assert!(Value::float(f64::NAN).get_float().unwrap().is_nan());
+        assert!(Value::float(-f64::NAN).get_float().unwrap().is_nan());
+        assert_eq!(Value::boolean(false).get_boolean(), Some(false));
+        assert!(Value::boolean(false).is_falsy() && Value::nil().is_falsy());
+        assert!(!Value::integer(ctx, 0).is_falsy());
+        for i in [0, -1, (1 << 47) - 1, -(1 << 47)] {
+            assert!(Value::small_integer(i) == Value::integer(ctx, i));
+        }
+        ctx.stash(Value::integer(ctx, i64::MIN + 7))
+    });
+    lua.gc_collect();
+    lua.enter(|ctx| {
+        assert_eq!(ctx.fetch(&stashed).get_integer(), Some(i64::MIN + 7));
+    });
+}
+
+#[test]
+#[should_panic(expected = "inline range")]
+fn inline_integers_must_fit() {
+    let _ = Value::small_integer(1 << 47);
+}
+
+#[test]
+fn wide_integers_behave_like_narrow_ones() {
+    let src = "local big = math.maxinteger\n\
+               local t = {[math.mininteger] = 'min', [-(1 << 62)] = 'shifted'}\n\
+               local k = -(1 << 61) * 2\n\
+               local h = 0\n\
+               for i = 1, 100 do h = h * 31 + i end\n\
+               collectgarbage()\n\
+               return table.concat({\n\
+                 big, tostring(big + 1 == math.mininteger), t[-big - 1], t[k],\n\
+                 math.type(big), big // 1000, big % 1000, -big, h,\n\
+                 string.format('%x', -1), 2^53 | 0, 1 << 47, tostring((1 << 47) - 1 == 0x7fffffffffff),\n\
+                 tostring(big - 1 < big), tostring(math.mininteger < -(1 << 47)),\n\
+               }, ',')";
+    assert_eq!(
+        run(&mut new_lua(), src),
+        "9223372036854775807,true,min,shifted,integer,9223372036854775,807,\
+         -9223372036854775807,-5745203550453931406,ffffffffffffffff,9007199254740992,\
+         140737488355328,true,true,true"
+    );
+}
@@ -0,0 +1,192 @@
+//! Faults the interpreter detects — arithmetic on nil, indexing a number,
+//! calling a non-function and the like — are raised as Lua errors with
+//! the standard messages, prefixed with the faulting position and naming
+//! the offending variable where it has one: `pcall` catches them, and
+//! uncaught ones reach the host as `RuntimeError::Lua`.
+
+use tcvm::{Executor, LoadError, Lua, RuntimeError};
+
+fn new_lua() -> Lua {
+    let mut lua = Lua::new();
+    lua.load_all();
+    lua
+}
+
+fn try_eval<R>(lua: &mut Lua, src: &str) -> Result<R, RuntimeError>
+where
+    R: for<'gc> tcvm::FromMultiValue<'gc>,
+{
+    let ex = lua
+        .try_enter(|ctx| -> Result<_, LoadError> {
+            let chunk = ctx.load(src, Some("=vm_errors"))?;
+            Ok(ctx.stash(Executor::start(ctx, chunk, ())))
+        })
+        .expect("load");
+    lua.execute(&ex)
+}
+
+/// The message of the error `src` fails with.
+fn error_message(src: &str) -> String {
+    let mut lua = new_lua();
+    let err = try_eval::<()>(&mut lua, src).expect_err("should fail");
+    let RuntimeError::Lua(stashed) = err else {
+        panic!("expected RuntimeError::Lua, got {err:?}");
+    };
+    lua.enter(|ctx| {
+        let s = ctx.fetch(&stashed).value().get_string().expect("string");
+        String::from_utf8_lossy(s.as_bytes()).into_owned()
+    })
+}
+
+#[test]
+fn type_errors_have_lua_messages() {
+    for (src, msg) in [
+        (
+            "local x return x + 1",
+            "attempt to perform arithmetic on a nil value (local 'x')",
+        ),
+        (
+            "local x = 1 return x .. {}",
+            "attempt to concatenate a table value",
+        ),
+        (
+            "local x = 1 return -{}",
+            "attempt to perform arithmetic on a table value",
+        ),
+        (
+            "local x = 5 return x.field",
+            "attempt to index a number value (local 'x')",
+        ),
+        (
+            "local x = true x[1] = 2",
+            "attempt to index a boolean value (local 'x')",
+        ),
+        (
+            "local t = {} t:method()",
+            "attempt to call a nil value (method 'method')",
+        ),
+        (
+            "local f = 3 f()",
+            "attempt to call a number value (local 'f')",
+        ),
+        ("return 1 < {}", "attempt to compare number with table"),
+        ("return {} <= {}", "attempt to compare two table values"),
+        ("return #5", "attempt to get length of a number value"),
+        (
+            "local x = 'a' return x | 1",
+            "attempt to perform bitwise operation on a string value (local 'x')",
+        ),
+        (
+            "local x = 1.5 return x & 1",
+            "number has no integer representation",
+        ),
+        ("local x = 0 return 1 // x", "attempt to perform 'n//0'"),
+        ("local x = 0 return 1 % x", "attempt to perform 'n%0'"),
+    ] {
+        assert_eq!(
+            error_message(src),
+            format!("vm_errors:1: {msg}"),
+            "for {src:?}"
+        );
+    }
+}
+
+#[test]
+fn pcall_catches_vm_faults() {
+    let mut lua = new_lua();
+    let (ok, matched): (bool, bool) = try_eval(
+        &mut lua,
+        "local ok, err = pcall(function() local t = nil return t.x end) \
+         return ok, err == 'vm_errors:1: attempt to index a nil value (local \\'t\\')'",
+    )
+    .expect("run");
+    assert!(!ok);
+    assert!(matched);
+}
+
+#[test]
+fn execution_continues_after_a_caught_fault() {
+    let mut lua = new_lua();
+    let n: i64 = try_eval(
+        &mut lua,
+        "local caught = 0 \
+         local function risky(i) \
+           local x = i % 2 == 0 and i or nil \
+           return x * 2 \
+         end \
+         local sum = 0 \
+         for i = 1, 10 do \
+           local ok, v = pcall(risky, i) \
+           if ok then sum = sum + v else caught = caught + 1 end \
+         end \
+         return sum * 100 + caught",
+    )
+    .expect("run");
+    assert_eq!(n, 60 * 100 + 5);
+}
+
+#[test]
+fn faults_in_metamethods_and_coroutines_unwind_to_pcall() {
+    let mut lua = new_lua();
+    let ok: bool = try_eval(
+        &mut lua,
+        "local mt = {__index = function(t, k) return k + nil end} \
+         local ok1, e1 = pcall(function() return setmetatable({}, mt).x end) \
+         local co = coroutine.create(function() local f coroutine.yield() f() end) \
+         coroutine.resume(co) \
+         local ok2, e2 = coroutine.resume(co) \
+         return not ok1 and e1 == 'vm_errors:1: attempt to perform arithmetic on a string value (local \\'k\\')' \
+            and not ok2 and e2 == 'vm_errors:1: attempt to call a nil value (local \\'f\\')'",
+    )
+    .expect("run");
+    assert!(ok);
+}
+
+#[test]
+fn redefining_a_declared_global_names_it() {
+    assert_eq!(
+        error_message("x = 1 global function x() end"),
+        "vm_errors:1: global 'x' already defined"
+    );
+}
+
+#[test]
+fn faulting_operands_are_named_by_origin() {
+    for (src, msg) in [
+        (
+            "return undefined_fn()",
+            "attempt to call a nil value (global 'undefined_fn')",
+        ),
+        (
+            "local cfg = {} return cfg.name.first",
+            "attempt to index a nil value (field 'name')",
+        ),
+        (
+            "local u local function f() return u.x end f()",
+            "attempt to index a nil value (upvalue 'u')",
+        ),
+        (
+            "local t = {} return t.a .. 'x'",
+            "attempt to concatenate a nil value (field 'a')",
+        ),
+        (
+            "local x = 1 return x + nothing",
+            "attempt to perform arithmetic on a nil value (global 'nothing')",
+        ),
+        (
+            "return ('abc')()",
+            "attempt to call a string value (constant 'abc')",
+        ),
+        // Temporaries of unknown origin carry no description.
+        (
+            "local f = function() end f()()",
+            "attempt to call a nil value",
+        ),
+    ] {
+        assert_eq!(
+            error_message(src),
+            format!("vm_errors:1: {msg}"),
+            "for {src:?}"
+        );
+    }
+}
@@ -27,13 +27,13 @@ impl<'gc> Sequence<'gc> for YieldThenAddOne {
 
     fn poll(
         mut self: Pin<&mut Self>,
-        _ctx: Context<'gc>,
+        ctx: Context<'gc>,
         _exec: Execution<'gc, '_>,
         mut stack: Stack<'gc, '_>,
     ) -> Result<SequencePoll<'gc>, Error<'gc>> {
         if !self.yielded {
             self.yielded = true;
-            stack.replace(&[Value::integer(42)]);
+            stack.replace(&[Value::small_integer(42)]);
             // Yield with the bottom relative to the sequence's window.
             // bottom = 0 means stack[seq.bottom..] (the 42) is what
             // gets yielded.
@@ -44,7 +44,7 @@ impl<'gc> Sequence<'gc> for YieldThenAddOne {
                 .get(0)
                 .get_integer()
                 .expect("resume-arg should be integer");
-            stack.replace(&[Value::integer(v + 1)]);
+            stack.replace(&[Value::integer(ctx, v + 1)]);
             Ok(SequencePoll::Return)
         }
     }
@@ -66,7 +66,7 @@ fn coroutine_yields_from_sequence_then_resumes() {
     lua.load_all();
     let ex = lua
         .try_enter(|ctx| -> Result<_, LoadError> {
-            let f = Function::new_native(ctx.mutation(), yielding_seq as NativeFn, Box::new([]));
+            let f = Function::new_native(ctx.mutation(), yielding_seq as NativeFn, []);
             let key = Value::string(LuaString::new(ctx, b"yseq"));
             ctx.globals().raw_set(ctx, key, Value::function(f));
             // Inside a coroutine, call yseq() which yields 42; the